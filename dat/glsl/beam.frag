@@ -1,4 +1,4 @@
-#ifdef HAS_GL_ARB_shader_subroutine
+#if HAS_GL_ARB_shader_subroutine
 #extension GL_ARB_shader_subroutine : require
 
 subroutine vec4 beam_func_prototype( vec4 colour, vec2 pos_tex, vec2 dim );
@@ -284,7 +284,7 @@ void main(void) {
    pos_tex.y = 2.0 * pos.y - 1.0;
    pos_px = pos * dimensions;
 
-#ifdef HAS_GL_ARB_shader_subroutine
+#if HAS_GL_ARB_shader_subroutine
    /* Use subroutines */
    colour_out = beam_func( colour, pos_tex, pos_px );
 #else /* HAS_GL_ARB_shader_subroutine */
@@ -1,4 +1,4 @@
-#ifdef HAS_GL_ARB_shader_subroutine
+#if HAS_GL_ARB_shader_subroutine
 #extension GL_ARB_shader_subroutine : require
 
 subroutine vec4 jump_func_prototype (void);
@@ -129,7 +129,7 @@ vec4 jump_wind (void)
 
 void main(void)
 {
-#ifdef HAS_GL_ARB_shader_subroutine
+#if HAS_GL_ARB_shader_subroutine
    // Use subroutines
    colour_out = jump_func();
 #else /* HAS_GL_ARB_shader_subroutine */
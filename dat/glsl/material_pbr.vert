@@ -25,6 +25,8 @@ out InterfBlock {
    vec2 tex1;
    vec3 shadow[MAX_LIGHTS];
 } OUT;
+/* Has to match exactly in the depth pre-pass, which uses a different program. */
+invariant gl_Position;
 
 void main (void)
 {
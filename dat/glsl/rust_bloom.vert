@@ -0,0 +1,7 @@
+layout(location = 0) in vec2 vertex;
+out vec2 tex_coord;
+
+void main(void) {
+   tex_coord = vertex;
+   gl_Position = vec4( vertex * 2.0 - 1.0, 0.0, 1.0 );
+}
@@ -0,0 +1,23 @@
+layout(std140) uniform BloomData {
+   vec2 direction;
+   float threshold;
+   float intensity;
+};
+
+uniform sampler2D sampler;
+
+in vec2 tex_coord;
+layout(location = 0) out vec4 colour_out;
+
+/* 9-tap Gaussian, only half is stored as it is symmetric. */
+const float WEIGHTS[5] = float[]( 0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216 );
+
+void main(void) {
+   vec3 colour = texture( sampler, tex_coord ).rgb * WEIGHTS[0];
+   for (int i=1; i<5; i++) {
+      vec2 off = direction * float(i);
+      colour += texture( sampler, tex_coord + off ).rgb * WEIGHTS[i];
+      colour += texture( sampler, tex_coord - off ).rgb * WEIGHTS[i];
+   }
+   colour_out = vec4( colour, 1.0 );
+}
@@ -0,0 +1,17 @@
+layout(std140) uniform BloomData {
+   vec2 direction;
+   float threshold;
+   float intensity;
+};
+
+uniform sampler2D sampler;
+uniform sampler2D bloom;
+
+in vec2 tex_coord;
+layout(location = 0) out vec4 colour_out;
+
+void main(void) {
+   vec4 scene = texture( sampler, tex_coord );
+   vec3 glow = texture( bloom, tex_coord ).rgb * intensity;
+   colour_out = vec4( scene.rgb + glow, scene.a );
+}
@@ -0,0 +1,18 @@
+layout(std140) uniform BloomData {
+   vec2 direction;
+   float threshold;
+   float intensity;
+};
+
+uniform sampler2D sampler;
+
+in vec2 tex_coord;
+layout(location = 0) out vec4 colour_out;
+
+void main(void) {
+   vec3 colour = texture( sampler, tex_coord ).rgb;
+   float luma = dot( colour, vec3( 0.2126, 0.7152, 0.0722 ) );
+   /* Scale by how far above the threshold it is to avoid a hard edge. */
+   float w = max( luma - threshold, 0.0 ) / max( luma, 1e-4 );
+   colour_out = vec4( colour * w, 1.0 );
+}
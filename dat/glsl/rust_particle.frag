@@ -0,0 +1,12 @@
+in vec2 fragpos;
+in vec4 colour;
+layout(location = 0) out vec4 colour_out;
+
+void main(void) {
+   float a = smoothstep( 0.0, 1.0, 1.0-length( fragpos ) );
+   if (a <= 0.0) {
+      discard;
+      return;
+   }
+   colour_out = vec4( colour.rgb, colour.a * a );
+}
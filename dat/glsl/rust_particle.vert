@@ -0,0 +1,17 @@
+layout(std140) uniform ParticleData {
+   mat3 transform;
+};
+
+layout(location = 0) in vec2 vertex;
+layout(location = 1) in vec4 data;
+layout(location = 2) in vec4 colourin;
+out vec2 fragpos;
+out vec4 colour;
+
+void main(void) {
+   fragpos = vertex;
+   /* Fade out as the particle dies. */
+   colour = vec4( colourin.rgb, colourin.a * data.w );
+   vec3 pos = vec3( data.xy + vertex * data.z, 1.0 );
+   gl_Position = vec4( (transform * pos).xy, 0.0, 1.0 );
+}
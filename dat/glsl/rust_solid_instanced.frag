@@ -0,0 +1,6 @@
+flat in vec4 colour_rect;
+layout(location = 0) out vec4 colour_out;
+
+void main(void) {
+   colour_out = colour_rect;
+}
@@ -0,0 +1,15 @@
+layout(location = 0) in vec2 vertex;
+/* Per instance. */
+layout(location = 1) in vec3 transform_x;
+layout(location = 2) in vec3 transform_y;
+layout(location = 3) in vec3 transform_z;
+layout(location = 4) in vec4 colour;
+
+flat out vec4 colour_rect;
+
+void main(void) {
+   mat3 transform = mat3( transform_x, transform_y, transform_z );
+   vec3 pos = vec3( vertex, 1.0 );
+   gl_Position = vec4( (transform * pos).xy, 0.0, 1.0 );
+   colour_rect = colour;
+}
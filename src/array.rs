@@ -1,8 +1,12 @@
 use anyhow::Result;
 use std::ffi::CString;
+use std::marker::PhantomData;
 use std::os::raw::{c_char, c_void};
 use std::sync::atomic::AtomicPtr;
 
+use crate::gettext::gettext;
+use crate::warn;
+
 /// Wrapper to convert C arrays to Vec
 #[allow(dead_code)]
 pub fn to_vec<T: Clone>(array: *mut T) -> Result<Vec<T>> {
@@ -87,3 +91,66 @@ impl Drop for ArrayCString {
         }
     }
 }
+
+/// Iterator over intrusive C linked lists, where each node points to the next one. Iteration
+/// stops at the first null pointer, or after `limit` nodes in case the list is corrupt or has a
+/// cycle.
+///
+/// ```ignore
+/// for item in unsafe { CList::new(head, |n| n.next) } { ... }
+/// ```
+#[allow(dead_code)]
+pub struct CList<'a, T, F> {
+    cur: *const T,
+    next: F,
+    limit: usize,
+    _marker: PhantomData<&'a T>,
+}
+#[allow(dead_code)]
+impl<'a, T, F: Fn(&'a T) -> *const T> CList<'a, T, F> {
+    /// Default maximum number of nodes to visit.
+    pub const LIMIT: usize = 1 << 20;
+
+    /// Iterates from `head`, visiting at most [`CList::LIMIT`] nodes.
+    ///
+    /// # Safety
+    ///
+    /// `head` and every pointer returned by `next` must be null or point to a valid node that
+    /// stays alive and unmodified for `'a`.
+    pub unsafe fn new(head: *const T, next: F) -> Self {
+        unsafe { Self::with_limit(head, next, Self::LIMIT) }
+    }
+
+    /// Iterates from `head`, visiting at most `limit` nodes.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`CList::new`].
+    pub unsafe fn with_limit(head: *const T, next: F, limit: usize) -> Self {
+        CList {
+            cur: head,
+            next,
+            limit,
+            _marker: PhantomData,
+        }
+    }
+}
+impl<'a, T: 'a, F: Fn(&'a T) -> *const T> Iterator for CList<'a, T, F> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        if self.cur.is_null() {
+            return None;
+        }
+        if self.limit == 0 {
+            warn!("linked list exceeds maximum length, stopping iteration");
+            self.cur = std::ptr::null();
+            return None;
+        }
+        self.limit -= 1;
+        // Valid as promised to the constructor
+        let node = unsafe { &*self.cur };
+        self.cur = (self.next)(node);
+        Some(node)
+    }
+}
+impl<'a, T: 'a, F: Fn(&'a T) -> *const T> std::iter::FusedIterator for CList<'a, T, F> {}
@@ -0,0 +1,240 @@
+use anyhow::Result;
+use std::collections::{BinaryHeap, VecDeque};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::context::{Context, ProgressStyle};
+use crate::ffi::ffi_guard;
+use crate::log::warn_err;
+use crate::warn;
+
+/// Priority of an asset request. Higher priorities are picked up by the workers first.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+/// Work to be done on the main thread with the OpenGL context, usually uploading data.
+pub type Upload = Box<dyn FnOnce(&Context) -> Result<()> + Send>;
+/// Work to be done on a worker thread, returning the upload to run on the main thread, if any.
+pub type Job = Box<dyn FnOnce() -> Result<Option<Upload>> + Send>;
+
+struct Request {
+    priority: Priority,
+    seq: u64,
+    job: Job,
+}
+impl PartialEq for Request {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+impl Eq for Request {}
+impl Ord for Request {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // Highest priority first, then first come first served
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+impl PartialOrd for Request {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Coordinates asynchronous asset loading. Requests are run on a bounded worker pool in
+/// priority order, and the resulting GPU uploads are queued so that the main thread can process
+/// them with a time budget in [`AssetLoader::drain`].
+pub struct AssetLoader {
+    pool: rayon::ThreadPool,
+    requests: Arc<Mutex<BinaryHeap<Request>>>,
+    uploads: Arc<Mutex<VecDeque<Upload>>>,
+    seq: AtomicU64,
+    total: AtomicUsize,
+    done: Arc<AtomicUsize>,
+    /// Notified by the workers whenever an upload is queued or a request is done.
+    signal: Arc<(Mutex<()>, Condvar)>,
+}
+
+impl AssetLoader {
+    pub fn new(workers: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|i| format!("naev-assets-{i}"))
+            .build()?;
+        Ok(AssetLoader {
+            pool,
+            requests: Arc::new(Mutex::new(BinaryHeap::new())),
+            uploads: Arc::new(Mutex::new(VecDeque::new())),
+            seq: AtomicU64::new(0),
+            total: AtomicUsize::new(0),
+            done: Arc::new(AtomicUsize::new(0)),
+            signal: Arc::new((Mutex::new(()), Condvar::new())),
+        })
+    }
+
+    /// Queues a request. The job runs on a worker thread, and the upload it returns runs on the
+    /// main thread during [`AssetLoader::drain`].
+    pub fn request(&self, priority: Priority, job: Job) {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+        self.requests
+            .lock()
+            .unwrap()
+            .push(Request { priority, seq, job });
+
+        // Each spawned task picks the highest priority request available when it runs, not
+        // necessarily the one that was just pushed.
+        let requests = self.requests.clone();
+        let uploads = self.uploads.clone();
+        let done = self.done.clone();
+        let signal = self.signal.clone();
+        self.pool.spawn(move || {
+            let req = match requests.lock().unwrap().pop() {
+                Some(req) => req,
+                None => return,
+            };
+            // A panicking job still has to be counted, or the loader would never be idle again
+            match std::panic::catch_unwind(AssertUnwindSafe(req.job)) {
+                Ok(Ok(Some(upload))) => uploads.lock().unwrap().push_back(upload),
+                Ok(Ok(None)) => {
+                    done.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(Err(e)) => {
+                    warn_err(e.context("asset request failed"));
+                    done.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(_) => {
+                    warn!("asset request panicked");
+                    done.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            let (lock, cvar) = &*signal;
+            let _guard = lock.lock().unwrap();
+            cvar.notify_all();
+        });
+    }
+
+    /// Runs pending uploads on the main thread until there are none left or `budget` is spent.
+    /// At least one upload is processed per call so that loading always makes progress. Returns
+    /// the number of uploads processed.
+    pub fn drain(&self, ctx: &Context, budget: Duration) -> usize {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "AssetLoader::drain called outside of the main thread"
+        );
+        let start = Instant::now();
+        let mut n = 0;
+        loop {
+            // Don't hold the lock while uploading, so workers can keep pushing
+            let upload = match self.uploads.lock().unwrap().pop_front() {
+                Some(upload) => upload,
+                None => break,
+            };
+            if let Err(e) = upload(ctx) {
+                warn_err(e.context("asset upload failed"));
+            }
+            self.done.fetch_add(1, Ordering::Relaxed);
+            n += 1;
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        n
+    }
+
+    /// Fraction of the requests that have been fully processed, in [0, 1].
+    pub fn progress(&self) -> f32 {
+        let total = self.total.load(Ordering::Relaxed);
+        match total {
+            0 => 1.0,
+            _ => self.done.load(Ordering::Relaxed) as f32 / total as f32,
+        }
+    }
+
+    /// Whether all requests have been fully processed.
+    pub fn is_idle(&self) -> bool {
+        self.done.load(Ordering::Relaxed) >= self.total.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until there are uploads to drain or all requests are done, for at most `timeout`.
+    pub fn wait(&self, timeout: Duration) {
+        let (lock, cvar) = &*self.signal;
+        let guard = lock.lock().unwrap();
+        let _ = cvar
+            .wait_timeout_while(guard, timeout, |_| {
+                self.uploads.lock().unwrap().is_empty() && !self.is_idle()
+            })
+            .unwrap();
+    }
+}
+
+/// Global asset loader, created on the first request.
+static ASSETS: OnceLock<AssetLoader> = OnceLock::new();
+
+/// Gets the global asset loader, using half the available cores so the main thread stays
+/// responsive.
+pub fn loader() -> &'static AssetLoader {
+    ASSETS.get_or_init(|| {
+        let workers = std::thread::available_parallelism()
+            .map(|n| (n.get() / 2).max(1))
+            .unwrap_or(1);
+        AssetLoader::new(workers).unwrap()
+    })
+}
+
+/// Runs pending uploads of the global asset loader, see [`AssetLoader::drain`]. Does nothing if
+/// nothing was ever requested.
+pub fn drain(ctx: &Context, budget: Duration) -> usize {
+    match ASSETS.get() {
+        Some(assets) => assets.drain(ctx, budget),
+        None => 0,
+    }
+}
+
+/// Whether the global asset loader has no requests left.
+pub fn is_idle() -> bool {
+    ASSETS.get().is_none_or(AssetLoader::is_idle)
+}
+
+/// Progress of the global asset loader, for the load screen.
+pub fn progress() -> f32 {
+    ASSETS.get().map_or(1.0, AssetLoader::progress)
+}
+
+/// Draws the progress of the asset loader at the bottom of the load screen while it is busy.
+#[unsafe(no_mangle)]
+pub extern "C" fn loadscreen_render_assets() {
+    ffi_guard((), || {
+        let Some(assets) = ASSETS.get().filter(|assets| !assets.is_idle()) else {
+            return;
+        };
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(_) => return,
+        };
+        let (vw, vh) = {
+            let dims = ctx.dimensions.read().unwrap();
+            (dims.view_width, dims.view_height)
+        };
+        let (w, h) = ((0.4 * vw).min(600.0), 8.0);
+        let res = ctx.draw_progress(
+            0.5 * (vw - w),
+            0.05 * vh,
+            w,
+            h,
+            assets.progress(),
+            &ProgressStyle::default(),
+        );
+        if let Err(e) = res {
+            warn_err(e.context("unable to draw asset loading progress"));
+        }
+    })
+}
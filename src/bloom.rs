@@ -0,0 +1,214 @@
+#![allow(dead_code)]
+use anyhow::Result;
+use encase::ShaderType;
+use glow::HasContext;
+use nalgebra::Vector2;
+
+use crate::buffer::{Buffer, BufferBuilder, BufferTarget, BufferUsage, VertexArray};
+use crate::context::Context;
+use crate::render::Uniform;
+use crate::shader::{Shader, ShaderBuilder};
+use crate::texture::{AddressMode, FilterMode, Framebuffer, FramebufferBuilder, Texture};
+
+fn tex(fb: &Framebuffer) -> Result<&Texture> {
+    match &fb.texture {
+        Some(tex) => Ok(tex),
+        None => anyhow::bail!("bloom framebuffer has no texture"),
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, ShaderType)]
+struct BloomUniform {
+    /// Offset between blur samples in texture coordinates, unused when not blurring.
+    direction: Vector2<f32>,
+    threshold: f32,
+    intensity: f32,
+}
+
+/// Bloom post-processing. The pixels brighter than the threshold are extracted to a half
+/// resolution framebuffer, blurred with separable Gaussian passes and added back on top of the
+/// scene.
+///
+/// ```ignore
+/// let mut bloom = Bloom::new(ctx)?;
+/// bloom.intensity = 0.8;
+/// let tex = bloom.apply(ctx, &scene_fb)?;
+/// tex.draw(ctx, 0.0, 0.0, w, h)?;
+/// ```
+pub struct Bloom {
+    /// Luminance above which pixels start to glow.
+    pub threshold: f32,
+    /// Multiplier of the blurred glow when added back to the scene.
+    pub intensity: f32,
+    /// Number of horizontal and vertical blur pass pairs. More gives wider glows.
+    pub iterations: u32,
+    shader_extract: Shader,
+    shader_blur: Shader,
+    shader_composite: Shader,
+    buffer: Buffer,
+    // Framebuffers are kept between frames and only recreated when the scene size changes
+    ping: Option<Framebuffer>,
+    pong: Option<Framebuffer>,
+    output: Option<Framebuffer>,
+}
+
+impl Bloom {
+    pub const DEFAULT_THRESHOLD: f32 = 0.8;
+    pub const DEFAULT_INTENSITY: f32 = 1.0;
+    pub const DEFAULT_ITERATIONS: u32 = 2;
+
+    pub fn new(ctx: &Context) -> Result<Self> {
+        let gl = &ctx.gl;
+        let shader_extract = ShaderBuilder::new(Some("Bloom Extract Shader"))
+            .uniform_buffer("BloomData", 0)
+            .vert_file("rust_bloom.vert")
+            .frag_file("rust_bloom_extract.frag")
+            .sampler("sampler", 0)
+            .build(gl)?;
+        let shader_blur = ShaderBuilder::new(Some("Bloom Blur Shader"))
+            .uniform_buffer("BloomData", 0)
+            .vert_file("rust_bloom.vert")
+            .frag_file("rust_bloom_blur.frag")
+            .sampler("sampler", 0)
+            .build(gl)?;
+        let shader_composite = ShaderBuilder::new(Some("Bloom Composite Shader"))
+            .uniform_buffer("BloomData", 0)
+            .vert_file("rust_bloom.vert")
+            .frag_file("rust_bloom_composite.frag")
+            .sampler("sampler", 0)
+            .sampler("bloom", 1)
+            .build(gl)?;
+        let buffer = BufferBuilder::new(Some("Bloom Buffer"))
+            .target(BufferTarget::Uniform)
+            .usage(BufferUsage::Dynamic)
+            .data(&BloomUniform::default().buffer()?)
+            .build(gl)?;
+
+        Ok(Bloom {
+            threshold: Self::DEFAULT_THRESHOLD,
+            intensity: Self::DEFAULT_INTENSITY,
+            iterations: Self::DEFAULT_ITERATIONS,
+            shader_extract,
+            shader_blur,
+            shader_composite,
+            buffer,
+            ping: None,
+            pong: None,
+            output: None,
+        })
+    }
+
+    /// Applies bloom to the colour texture of `scene`, returning a texture of the same size with
+    /// the result. The returned texture is reused by the next call.
+    pub fn apply(&mut self, ctx: &Context, scene: &Framebuffer) -> Result<Texture> {
+        let scene_tex = match &scene.texture {
+            Some(tex) => tex,
+            None => anyhow::bail!("bloom source framebuffer has no texture"),
+        };
+        let (w, h) = (scene.w, scene.h);
+        let (hw, hh) = ((w / 2).max(1), (h / 2).max(1));
+
+        let half = |name| {
+            FramebufferBuilder::new(Some(name))
+                .width(hw)
+                .height(hh)
+                .filter(FilterMode::Linear)
+                .address_mode(AddressMode::ClampToEdge)
+        };
+        let ping = half("Bloom Ping").build_reusing(ctx, self.ping.take())?;
+        let pong = half("Bloom Pong").build_reusing(ctx, self.pong.take())?;
+        let output = FramebufferBuilder::new(Some("Bloom Output"))
+            .width(w)
+            .height(h)
+            .filter(FilterMode::Linear)
+            .address_mode(AddressMode::ClampToEdge)
+            .build_reusing(ctx, self.output.take())?;
+
+        let state = ctx.capture_state();
+        unsafe {
+            ctx.gl.disable(glow::BLEND);
+        }
+        let res = self.render(ctx, scene_tex, &ping, &pong, &output);
+        Framebuffer::unbind(ctx);
+        ctx.restore_state(&state);
+        ctx.check_errors();
+
+        let tex = output.texture.as_ref().map(|tex| tex.try_clone_gl(&ctx.gl));
+        self.ping = Some(ping);
+        self.pong = Some(pong);
+        self.output = Some(output);
+        res?;
+        match tex {
+            Some(tex) => tex,
+            None => anyhow::bail!("bloom output framebuffer has no texture"),
+        }
+    }
+
+    fn render(
+        &self,
+        ctx: &Context,
+        scene: &Texture,
+        ping: &Framebuffer,
+        pong: &Framebuffer,
+        output: &Framebuffer,
+    ) -> Result<()> {
+        let mut uniform = BloomUniform {
+            direction: Vector2::zeros(),
+            threshold: self.threshold,
+            intensity: self.intensity,
+        };
+
+        // Downsample while keeping only the bright parts
+        self.pass(ctx, &self.shader_extract, ping, &[scene], &uniform)?;
+
+        let (tw, th) = (1.0 / ping.w as f32, 1.0 / ping.h as f32);
+        for _ in 0..self.iterations {
+            uniform.direction = Vector2::new(tw, 0.0);
+            self.pass(ctx, &self.shader_blur, pong, &[tex(ping)?], &uniform)?;
+            uniform.direction = Vector2::new(0.0, th);
+            self.pass(ctx, &self.shader_blur, ping, &[tex(pong)?], &uniform)?;
+        }
+
+        uniform.direction = Vector2::zeros();
+        self.pass(
+            ctx,
+            &self.shader_composite,
+            output,
+            &[scene, tex(ping)?],
+            &uniform,
+        )
+    }
+
+    fn pass(
+        &self,
+        ctx: &Context,
+        shader: &Shader,
+        target: &Framebuffer,
+        inputs: &[&Texture],
+        uniform: &BloomUniform,
+    ) -> Result<()> {
+        let gl = &ctx.gl;
+        target.bind(ctx);
+        unsafe {
+            gl.viewport(0, 0, target.w as i32, target.h as i32);
+        }
+        shader.use_program(gl);
+        for (i, tex) in inputs.iter().enumerate() {
+            tex.bind(ctx, i as u32);
+        }
+        ctx.vao_square.bind(ctx);
+        self.buffer.bind_write_base(ctx, &uniform.buffer()?, 0)?;
+        unsafe {
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+            for i in (0..inputs.len() as u32).rev() {
+                gl.active_texture(glow::TEXTURE0 + i);
+                gl.bind_texture(glow::TEXTURE_2D, None);
+                gl.bind_sampler(i, None);
+            }
+        }
+        VertexArray::unbind(ctx);
+        self.buffer.unbind(ctx);
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use anyhow::Result;
 use glow::*;
 
 use crate::context;
-use crate::context::Context;
+use crate::context::{Context, Feature};
 use crate::{gettext, warn};
 
 pub struct Buffer {
@@ -13,7 +13,13 @@ pub struct Buffer {
     usage: u32,
 }
 impl Buffer {
+    /// Replaces the whole contents of the buffer. This reallocates the storage, use
+    /// [`Buffer::write_range`] when only part of the buffer changes.
     pub fn write(&self, ctx: &Context, data: &[u8]) -> Result<()> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "Buffer::write called outside of the main thread"
+        );
         #[cfg(debug_assertions)]
         if data.len() != self.datalen {
             anyhow::bail!("buffer data length mismatch!");
@@ -26,6 +32,32 @@ impl Buffer {
         }
         Ok(())
     }
+    /// Updates the region of the buffer starting at `offset` bytes, leaving the rest untouched.
+    /// Unlike [`Buffer::write`], the storage is not reallocated.
+    pub fn write_range(&self, ctx: &Context, offset: usize, data: &[u8]) -> Result<()> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "Buffer::write_range called outside of the main thread"
+        );
+        debug_assert!(
+            offset + data.len() <= self.datalen,
+            "Buffer::write_range out of bounds: {} + {} > {}",
+            offset,
+            data.len(),
+            self.datalen
+        );
+        let gl = &ctx.gl;
+        unsafe {
+            gl.bind_buffer(self.target, Some(self.buffer));
+            gl.buffer_sub_data_u8_slice(self.target, offset as i32, data);
+            gl.bind_buffer(self.target, None);
+        }
+        Ok(())
+    }
+    /// Size of the buffer in bytes.
+    pub fn size(&self) -> usize {
+        self.datalen
+    }
     /// Simply binds the buffer to the context
     pub fn bind(&self, ctx: &context::Context) {
         self.bind_gl(&ctx.gl)
@@ -45,8 +77,39 @@ impl Buffer {
             gl.bind_buffer_base(glow::UNIFORM_BUFFER, idx, Some(self.buffer));
         }
     }
+    /// Binds the buffer to a shader storage block binding point
+    pub fn bind_base_ssbo(&self, ctx: &context::Context, binding: u32) {
+        unsafe {
+            ctx.gl
+                .bind_buffer_base(glow::SHADER_STORAGE_BUFFER, binding, Some(self.buffer));
+        }
+    }
+    /// Reads back the contents of the buffer starting at `offset` bytes into `data`, e.g., the
+    /// results a compute shader wrote to a shader storage buffer. This stalls until the GPU is
+    /// done with the buffer.
+    pub fn read(&self, ctx: &Context, offset: usize, data: &mut [u8]) -> Result<()> {
+        if offset + data.len() > self.datalen {
+            anyhow::bail!(
+                "buffer read out of bounds: {} + {} > {}",
+                offset,
+                data.len(),
+                self.datalen
+            );
+        }
+        let gl = &ctx.gl;
+        unsafe {
+            gl.bind_buffer(self.target, Some(self.buffer));
+            gl.get_buffer_sub_data(self.target, offset as i32, data);
+            gl.bind_buffer(self.target, None);
+        }
+        Ok(())
+    }
     /// Simplification for write + binding
     pub fn bind_write_base(&self, ctx: &context::Context, data: &[u8], idx: u32) -> Result<()> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "Buffer::bind_write_base called outside of the main thread"
+        );
         self.bind_write_base_gl(&ctx.gl, data, idx)
     }
     pub fn bind_write_base_gl(&self, gl: &glow::Context, data: &[u8], idx: u32) -> Result<()> {
@@ -83,6 +146,571 @@ impl Drop for Buffer {
     }
 }
 
+/// Packs the uniform blocks of several draws into a single uniform buffer, so that they can be
+/// uploaded with one write and bound as sub-ranges with [`UniformBatch::bind_range`]. Blocks are
+/// padded to `GL_UNIFORM_BUFFER_OFFSET_ALIGNMENT`.
+pub struct UniformBatch {
+    buffer: glow::Buffer,
+    capacity: usize, // in u8
+    size: usize,     // in u8, of a single block
+    stride: usize,   // in u8, size rounded up to the alignment
+    data: Vec<u8>,
+}
+impl UniformBatch {
+    pub fn new(ctx: &Context, name: Option<&str>, size: usize) -> Result<Self> {
+        Self::new_gl(&ctx.gl, name, size)
+    }
+    pub fn new_gl(gl: &glow::Context, name: Option<&str>, size: usize) -> Result<Self> {
+        if size == 0 {
+            anyhow::bail!("uniform batch block size can not be zero");
+        }
+        let align = unsafe { gl.get_parameter_i32(glow::UNIFORM_BUFFER_OFFSET_ALIGNMENT) };
+        let align = (align as usize).max(1);
+        let buffer = unsafe { gl.create_buffer().map_err(|e| anyhow::anyhow!(e))? };
+        unsafe {
+            gl.bind_buffer(glow::UNIFORM_BUFFER, Some(buffer));
+            context::set_label(gl, glow::BUFFER, buffer.0.into(), name);
+            gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+        }
+        Ok(UniformBatch {
+            buffer,
+            capacity: 0,
+            size,
+            stride: size.div_ceil(align) * align,
+            data: Vec::new(),
+        })
+    }
+
+    /// Removes all the blocks, keeping the GPU memory around for reuse.
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    /// Number of blocks in the batch.
+    pub fn len(&self) -> usize {
+        self.data.len() / self.stride
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Adds a block to the batch, returning its index.
+    pub fn push(&mut self, data: &[u8]) -> Result<usize> {
+        if data.len() != self.size {
+            anyhow::bail!("uniform batch data length mismatch!");
+        }
+        let idx = self.len();
+        self.data.extend_from_slice(data);
+        self.data.resize((idx + 1) * self.stride, 0);
+        Ok(idx)
+    }
+
+    /// Uploads all the blocks to the GPU with a single write, growing the buffer if necessary.
+    pub fn upload(&mut self, ctx: &Context) {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "UniformBatch::upload called outside of the main thread"
+        );
+        let gl = &ctx.gl;
+        unsafe {
+            gl.bind_buffer(glow::UNIFORM_BUFFER, Some(self.buffer));
+            if self.data.len() > self.capacity {
+                self.capacity = self.data.len().next_power_of_two();
+                gl.buffer_data_size(
+                    glow::UNIFORM_BUFFER,
+                    self.capacity as i32,
+                    glow::DYNAMIC_DRAW,
+                );
+            }
+            gl.buffer_sub_data_u8_slice(glow::UNIFORM_BUFFER, 0, &self.data);
+            gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+        }
+    }
+
+    /// Binds the block at `idx` to the uniform buffer binding point `binding`. The batch must
+    /// have been uploaded since the block was added.
+    pub fn bind_range(&self, ctx: &Context, idx: usize, binding: u32) {
+        debug_assert!(idx < self.len(), "UniformBatch::bind_range out of range");
+        unsafe {
+            ctx.gl.bind_buffer_range(
+                glow::UNIFORM_BUFFER,
+                binding,
+                Some(self.buffer),
+                (idx * self.stride) as i32,
+                self.size as i32,
+            );
+        }
+    }
+}
+impl Drop for UniformBatch {
+    fn drop(&mut self) {
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteBuffer(self.buffer));
+    }
+}
+
+/// Number of regions of a [`StreamBuffer`], so that the CPU can write into one while the GPU is
+/// still reading from the previous frames.
+const STREAM_REGIONS: usize = 3;
+
+/// Buffer for data that is rewritten every frame, e.g., HUD geometry. When buffer storage is
+/// available, the buffer is persistently mapped and split into a ring of regions: the CPU writes
+/// into the current region with [`StreamBuffer::map_write`], and [`StreamBuffer::advance`] moves
+/// to the next one at the end of the frame, waiting for the GPU to be done with it. Otherwise, it
+/// falls back to orphaning the buffer every frame and uploading with `glBufferSubData`.
+///
+/// ```ignore
+/// let data = stream.map_write(len)?;
+/// data.copy_from_slice(&vertices);
+/// let offset = stream.offset();
+/// stream.flush(ctx);
+/// // ... draw using the data at offset ...
+/// stream.advance(ctx);
+/// ```
+pub struct StreamBuffer {
+    pub buffer: glow::Buffer,
+    target: u32,
+    size: usize,    // in u8, of a single region
+    region: usize,  // current region of the ring
+    cursor: usize,  // in u8, where the next write goes in the current region
+    offset: usize,  // in u8, start of the last write in the current region
+    flushed: usize, // in u8, data already uploaded when orphaning
+    mapping: Option<*mut u8>,
+    fences: [Option<Fence>; STREAM_REGIONS],
+    staging: Vec<u8>,
+}
+impl StreamBuffer {
+    /// Creates a stream buffer where up to `size` bytes can be written every frame.
+    pub fn new(
+        ctx: &Context,
+        name: Option<&str>,
+        target: BufferTarget,
+        size: usize,
+    ) -> Result<Self> {
+        if size == 0 {
+            anyhow::bail!("stream buffer size can not be zero");
+        }
+        let gl = &ctx.gl;
+        let target = target.to_gl();
+        let persistent = ctx.supports(Feature::PersistentMapping)
+            || gl.supported_extensions().contains("GL_ARB_buffer_storage");
+
+        let mut mapping = None;
+        let mut buffer = unsafe { gl.create_buffer().map_err(|e| anyhow::anyhow!(e))? };
+        unsafe {
+            if persistent {
+                let flags = glow::MAP_WRITE_BIT | glow::MAP_PERSISTENT_BIT | glow::MAP_COHERENT_BIT;
+                let total = (size * STREAM_REGIONS) as i32;
+                gl.bind_buffer(target, Some(buffer));
+                gl.buffer_storage(target, total, None, flags);
+                let ptr = gl.map_buffer_range(target, 0, total, flags);
+                if ptr.is_null() {
+                    // Immutable storage can't be respecified, so start over with a new buffer
+                    warn!("unable to map stream buffer, falling back to orphaning");
+                    gl.bind_buffer(target, None);
+                    gl.delete_buffer(buffer);
+                    buffer = gl.create_buffer().map_err(|e| anyhow::anyhow!(e))?;
+                } else {
+                    mapping = Some(ptr);
+                }
+            }
+            gl.bind_buffer(target, Some(buffer));
+            if mapping.is_none() {
+                gl.buffer_data_size(target, size as i32, glow::STREAM_DRAW);
+            }
+            context::set_label(gl, glow::BUFFER, buffer.0.into(), name);
+            gl.bind_buffer(target, None);
+        }
+
+        Ok(StreamBuffer {
+            buffer,
+            target,
+            size,
+            region: 0,
+            cursor: 0,
+            offset: 0,
+            flushed: 0,
+            mapping,
+            fences: Default::default(),
+            staging: match mapping {
+                Some(_) => Vec::new(),
+                None => vec![0; size],
+            },
+        })
+    }
+
+    /// Whether the buffer is persistently mapped, or falling back to orphaning.
+    pub fn is_persistent(&self) -> bool {
+        self.mapping.is_some()
+    }
+
+    /// Maximum number of bytes that can be written every frame.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Reserves `len` bytes in the current region, returning them for writing. The data only
+    /// reaches the GPU after [`StreamBuffer::flush`].
+    pub fn map_write(&mut self, len: usize) -> Result<&mut [u8]> {
+        if self.cursor + len > self.size {
+            anyhow::bail!(
+                "stream buffer overflow: {} + {} > {}",
+                self.cursor,
+                len,
+                self.size
+            );
+        }
+        self.offset = self.cursor;
+        self.cursor += len;
+        Ok(match self.mapping {
+            Some(ptr) => unsafe {
+                let start = self.region * self.size + self.offset;
+                std::slice::from_raw_parts_mut(ptr.add(start), len)
+            },
+            None => &mut self.staging[self.offset..self.cursor],
+        })
+    }
+
+    /// Offset in bytes in the buffer of the data returned by the last
+    /// [`StreamBuffer::map_write`], to be used when drawing.
+    pub fn offset(&self) -> usize {
+        match self.mapping {
+            Some(_) => self.region * self.size + self.offset,
+            None => self.offset,
+        }
+    }
+
+    /// Makes the data written so far visible to the GPU. Persistently mapped buffers are
+    /// coherent, so this only uploads when orphaning.
+    pub fn flush(&mut self, ctx: &Context) {
+        if self.mapping.is_some() || self.flushed >= self.cursor {
+            return;
+        }
+        let gl = &ctx.gl;
+        unsafe {
+            gl.bind_buffer(self.target, Some(self.buffer));
+            gl.buffer_sub_data_u8_slice(
+                self.target,
+                self.flushed as i32,
+                &self.staging[self.flushed..self.cursor],
+            );
+            gl.bind_buffer(self.target, None);
+        }
+        self.flushed = self.cursor;
+    }
+
+    /// Moves on to the next region at the end of a frame. The region is only reused once the GPU
+    /// has finished the commands that were using it.
+    pub fn advance(&mut self, ctx: &Context) {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "StreamBuffer::advance called outside of the main thread"
+        );
+        let gl = &ctx.gl;
+        self.cursor = 0;
+        self.offset = 0;
+        self.flushed = 0;
+        if self.mapping.is_none() {
+            // Orphan so the driver gives us fresh storage instead of stalling
+            unsafe {
+                gl.bind_buffer(self.target, Some(self.buffer));
+                gl.buffer_data_size(self.target, self.size as i32, glow::STREAM_DRAW);
+                gl.bind_buffer(self.target, None);
+            }
+            return;
+        }
+        match Fence::insert(ctx) {
+            Ok(fence) => self.fences[self.region] = Some(fence),
+            Err(e) => {
+                warn!("unable to create stream buffer fence: {}", e);
+            }
+        }
+        self.region = (self.region + 1) % STREAM_REGIONS;
+        if let Some(fence) = self.fences[self.region].take() {
+            if let Err(e) = fence.wait(ctx) {
+                warn!("stream buffer: {}", e);
+            }
+        }
+    }
+}
+impl Drop for StreamBuffer {
+    fn drop(&mut self) {
+        // Deleting the buffer also unmaps it
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteBuffer(self.buffer));
+    }
+}
+
+/// Wrapper around an OpenGL sync object, to find out when the GPU has finished all the commands
+/// issued before it without stalling.
+pub struct Fence {
+    fence: glow::Fence,
+}
+impl Fence {
+    /// Inserts a fence after all the commands issued so far.
+    pub fn insert(ctx: &Context) -> Result<Self> {
+        let fence = unsafe { ctx.gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0) }
+            .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(Fence { fence })
+    }
+
+    /// Whether the GPU has reached the fence, without waiting.
+    pub fn is_signaled(&self, ctx: &Context) -> bool {
+        let res = unsafe {
+            ctx.gl
+                .client_wait_sync(self.fence, glow::SYNC_FLUSH_COMMANDS_BIT, 0)
+        };
+        matches!(res, glow::ALREADY_SIGNALED | glow::CONDITION_SATISFIED)
+    }
+
+    /// Blocks until the GPU has reached the fence.
+    pub fn wait(&self, ctx: &Context) -> Result<()> {
+        loop {
+            let res = unsafe {
+                ctx.gl
+                    .client_wait_sync(self.fence, glow::SYNC_FLUSH_COMMANDS_BIT, 1_000_000)
+            };
+            match res {
+                glow::TIMEOUT_EXPIRED => continue,
+                glow::WAIT_FAILED => anyhow::bail!("waiting on fence failed"),
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+impl Drop for Fence {
+    fn drop(&mut self) {
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteSync(context::SyncHandle(
+                self.fence,
+            )));
+    }
+}
+
+/// Wrapper around a transform feedback object, capturing the outputs of a shader built with
+/// [`ShaderBuilder::feedback_varyings`](crate::shader::ShaderBuilder::feedback_varyings) into
+/// buffers instead of rasterizing them.
+///
+/// ```ignore
+/// let tf = TransformFeedback::new(ctx, Some("Particles"))?;
+/// tf.bind_buffer(ctx, 0, &output);
+/// tf.begin(ctx, glow::POINTS);
+/// gl.draw_arrays(glow::POINTS, 0, n);
+/// tf.end(ctx);
+/// ```
+pub struct TransformFeedback {
+    feedback: glow::TransformFeedback,
+}
+impl TransformFeedback {
+    /// Creates the transform feedback object, which needs OpenGL 4.0 or
+    /// `GL_ARB_transform_feedback2`.
+    pub fn new(ctx: &Context, name: Option<&str>) -> Result<Self> {
+        let gl = &ctx.gl;
+        let version = gl.version();
+        if (version.major, version.minor) < (4, 0)
+            && !gl
+                .supported_extensions()
+                .contains("GL_ARB_transform_feedback2")
+        {
+            anyhow::bail!(
+                "transform feedback objects need OpenGL 4.0, but the context is only {}.{}",
+                version.major,
+                version.minor
+            );
+        }
+        let feedback = unsafe { gl.create_transform_feedback() }.map_err(|e| anyhow::anyhow!(e))?;
+        unsafe {
+            gl.bind_transform_feedback(glow::TRANSFORM_FEEDBACK, Some(feedback));
+            context::set_label(gl, glow::TRANSFORM_FEEDBACK, feedback.0.into(), name);
+            gl.bind_transform_feedback(glow::TRANSFORM_FEEDBACK, None);
+        }
+        Ok(TransformFeedback { feedback })
+    }
+
+    /// Captures the varyings at `index` into `buffer`. Interleaved varyings all go to index 0.
+    pub fn bind_buffer(&self, ctx: &Context, index: u32, buffer: &Buffer) {
+        let gl = &ctx.gl;
+        unsafe {
+            gl.bind_transform_feedback(glow::TRANSFORM_FEEDBACK, Some(self.feedback));
+            gl.bind_buffer_base(glow::TRANSFORM_FEEDBACK_BUFFER, index, Some(buffer.buffer));
+            gl.bind_transform_feedback(glow::TRANSFORM_FEEDBACK, None);
+        }
+    }
+
+    /// Starts capturing. `mode` is the primitive type of the draws, i.e., `glow::POINTS`,
+    /// `glow::LINES` or `glow::TRIANGLES`, and the program has to be in use already.
+    pub fn begin(&self, ctx: &Context, mode: u32) {
+        let gl = &ctx.gl;
+        unsafe {
+            gl.bind_transform_feedback(glow::TRANSFORM_FEEDBACK, Some(self.feedback));
+            gl.begin_transform_feedback(mode);
+        }
+    }
+
+    /// Stops capturing, the buffers can be used once the GPU is done.
+    pub fn end(&self, ctx: &Context) {
+        let gl = &ctx.gl;
+        unsafe {
+            gl.end_transform_feedback();
+            gl.bind_transform_feedback(glow::TRANSFORM_FEEDBACK, None);
+        }
+    }
+}
+impl Drop for TransformFeedback {
+    fn drop(&mut self) {
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteTransformFeedback(self.feedback));
+    }
+}
+
+/// Asynchronous copy of GPU data back to the CPU. The data is copied into a pixel pack buffer
+/// followed by a [`Fence`], so the caller can check [`Readback::is_ready`] over the next frames
+/// and only get the data with [`Readback::finish`] once it won't stall.
+///
+/// ```ignore
+/// let readback = Readback::pixels(ctx, 0, 0, w, h)?;
+/// // ... some frames later ...
+/// if readback.is_ready(ctx) {
+///     let pixels = readback.finish(ctx)?;
+/// }
+/// ```
+pub struct Readback {
+    buffer: glow::Buffer,
+    len: usize, // in u8
+    fence: Fence,
+}
+impl Readback {
+    fn create(ctx: &Context, len: usize) -> Result<glow::Buffer> {
+        if len == 0 {
+            anyhow::bail!("readback can not be empty");
+        }
+        let gl = &ctx.gl;
+        let buffer = unsafe { gl.create_buffer().map_err(|e| anyhow::anyhow!(e))? };
+        unsafe {
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(buffer));
+            gl.buffer_data_size(glow::PIXEL_PACK_BUFFER, len as i32, glow::STREAM_READ);
+            context::set_label(gl, glow::BUFFER, buffer.0.into(), Some("Readback"));
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        }
+        Ok(buffer)
+    }
+
+    /// Starts reading back the RGBA pixels of a region of the bound read framebuffer. The rows
+    /// are bottom to top, as OpenGL stores them.
+    pub fn pixels(ctx: &Context, x: i32, y: i32, w: usize, h: usize) -> Result<Self> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "Readback::pixels called outside of the main thread"
+        );
+        let len = w * h * 4;
+        let buffer = Self::create(ctx, len)?;
+        let gl = &ctx.gl;
+        unsafe {
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(buffer));
+            gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+            gl.read_pixels(
+                x,
+                y,
+                w as i32,
+                h as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::BufferOffset(0),
+            );
+            gl.pixel_store_i32(glow::PACK_ALIGNMENT, 4);
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        }
+        Ok(Readback {
+            buffer,
+            len,
+            fence: Fence::insert(ctx)?,
+        })
+    }
+
+    /// Starts reading back `len` bytes of a buffer starting at `offset` bytes.
+    pub fn buffer(ctx: &Context, src: &Buffer, offset: usize, len: usize) -> Result<Self> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "Readback::buffer called outside of the main thread"
+        );
+        if offset + len > src.datalen {
+            anyhow::bail!(
+                "buffer readback out of bounds: {} + {} > {}",
+                offset,
+                len,
+                src.datalen
+            );
+        }
+        let buffer = Self::create(ctx, len)?;
+        let gl = &ctx.gl;
+        unsafe {
+            gl.bind_buffer(glow::COPY_READ_BUFFER, Some(src.buffer));
+            gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(buffer));
+            gl.copy_buffer_sub_data(
+                glow::COPY_READ_BUFFER,
+                glow::COPY_WRITE_BUFFER,
+                offset as i32,
+                0,
+                len as i32,
+            );
+            gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
+            gl.bind_buffer(glow::COPY_READ_BUFFER, None);
+        }
+        Ok(Readback {
+            buffer,
+            len,
+            fence: Fence::insert(ctx)?,
+        })
+    }
+
+    /// Whether the copy is done, so that [`Readback::finish`] won't stall.
+    pub fn is_ready(&self, ctx: &Context) -> bool {
+        self.fence.is_signaled(ctx)
+    }
+
+    /// Gets the data, waiting for the copy if it is not done yet.
+    pub fn finish(self, ctx: &Context) -> Result<Vec<u8>> {
+        self.fence.wait(ctx)?;
+        let gl = &ctx.gl;
+        let mut data = vec![0; self.len];
+        unsafe {
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(self.buffer));
+            let ptr = gl.map_buffer_range(
+                glow::PIXEL_PACK_BUFFER,
+                0,
+                self.len as i32,
+                glow::MAP_READ_BIT,
+            );
+            if !ptr.is_null() {
+                std::ptr::copy_nonoverlapping(ptr, data.as_mut_ptr(), self.len);
+                gl.unmap_buffer(glow::PIXEL_PACK_BUFFER);
+            }
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+            if ptr.is_null() {
+                anyhow::bail!("unable to map readback buffer");
+            }
+        }
+        Ok(data)
+    }
+}
+impl Drop for Readback {
+    fn drop(&mut self) {
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteBuffer(self.buffer));
+    }
+}
+
 pub enum BufferUsage {
     Stream,
     Dynamic,
@@ -101,12 +729,15 @@ impl BufferUsage {
 pub enum BufferTarget {
     Array,
     Uniform,
+    /// Shader storage buffer, needs OpenGL 4.3 or `GL_ARB_shader_storage_buffer_object`.
+    ShaderStorage,
 }
 impl BufferTarget {
     pub fn to_gl(&self) -> u32 {
         match self {
             Self::Array => glow::ARRAY_BUFFER,
             Self::Uniform => glow::UNIFORM_BUFFER,
+            Self::ShaderStorage => glow::SHADER_STORAGE_BUFFER,
         }
     }
 }
@@ -157,6 +788,20 @@ impl<'a> BufferBuilder<'a> {
         if self.data.is_empty() {
             anyhow::bail!("BufferBuilder has no data");
         }
+        if let BufferTarget::ShaderStorage = self.target {
+            let version = gl.version();
+            if (version.major, version.minor) < (4, 3)
+                && !gl
+                    .supported_extensions()
+                    .contains("GL_ARB_shader_storage_buffer_object")
+            {
+                anyhow::bail!(
+                    "shader storage buffers need OpenGL 4.3, but the context is only {}.{}",
+                    version.major,
+                    version.minor
+                );
+            }
+        }
 
         let buffer = unsafe { gl.create_buffer().map_err(|e| anyhow::anyhow!(e))? };
 
@@ -165,7 +810,7 @@ impl<'a> BufferBuilder<'a> {
         unsafe {
             gl.bind_buffer(target, Some(buffer));
             gl.buffer_data_u8_slice(target, self.data, usage);
-            gl.object_label(glow::BUFFER, buffer.0.into(), self.name);
+            context::set_label(gl, glow::BUFFER, buffer.0.into(), self.name.as_deref());
             gl.bind_buffer(target, None);
         }
 
@@ -217,12 +862,62 @@ impl VertexArray {
     }
 }
 
+/// Format of the data of a vertex attribute.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AttribFormat {
+    pub ty: u32,          // glow::FLOAT and such
+    pub normalized: bool, // Only used for integer types
+}
+impl AttribFormat {
+    pub const FLOAT: Self = AttribFormat {
+        ty: glow::FLOAT,
+        normalized: false,
+    };
+
+    /// Integer data mapped to [0, 1] or [-1, 1] in the shader, e.g., colours as bytes.
+    pub const fn normalized(ty: u32) -> Self {
+        AttribFormat {
+            ty,
+            normalized: true,
+        }
+    }
+
+    /// Integer data read as integers (`int`, `uvec4`, ...) in the shader, e.g., bone indices.
+    pub const fn integer(ty: u32) -> Self {
+        AttribFormat {
+            ty,
+            normalized: false,
+        }
+    }
+
+    /// Whether the attribute is an integer attribute in the shader, and has to be set up with
+    /// `glVertexAttribIPointer`.
+    pub fn is_integer(&self) -> bool {
+        !self.normalized
+            && matches!(
+                self.ty,
+                glow::BYTE
+                    | glow::UNSIGNED_BYTE
+                    | glow::SHORT
+                    | glow::UNSIGNED_SHORT
+                    | glow::INT
+                    | glow::UNSIGNED_INT
+            )
+    }
+}
+impl Default for AttribFormat {
+    fn default() -> Self {
+        Self::FLOAT
+    }
+}
+
 pub struct VertexArrayBuffer<'a> {
-    pub buffer: &'a Buffer, // Buffer
-    pub size: i32,          // in data_type units (1 to 4)
-    pub stride: i32,        // in bytes, 0 indicates tightly packed
-    pub offset: i32,        // in bytes
-    pub divisor: u32,       // 0 indicates per vertex, non-zero is advance per instances
+    pub buffer: &'a Buffer,           // Buffer
+    pub size: i32,                    // in data_type units (1 to 4)
+    pub stride: i32,                  // in bytes, 0 indicates tightly packed
+    pub offset: i32,                  // in bytes
+    pub divisor: u32,                 // 0 indicates per vertex, non-zero is advance per instances
+    pub format: Option<AttribFormat>, // None uses the builder's data type and normalization
 }
 pub struct VertexArrayBuilder<'a> {
     name: Option<String>,
@@ -279,7 +974,12 @@ impl<'a> VertexArrayBuilder<'a> {
         let vertex_array = unsafe { gl.create_vertex_array().map_err(|e| anyhow::anyhow!(e))? };
         unsafe {
             gl.bind_vertex_array(Some(vertex_array));
-            gl.object_label(glow::VERTEX_ARRAY, vertex_array.0.into(), self.name);
+            context::set_label(
+                gl,
+                glow::VERTEX_ARRAY,
+                vertex_array.0.into(),
+                self.name.as_deref(),
+            );
 
             // Bind Vertex Buffers
             for (idx, buffer) in self.buffers.iter().enumerate() {
@@ -290,14 +990,34 @@ impl<'a> VertexArrayBuilder<'a> {
                 }
                 gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer.buffer.buffer));
                 gl.enable_vertex_attrib_array(idx);
-                gl.vertex_attrib_pointer_f32(
-                    idx,
-                    buffer.size,
-                    self.data_type,
-                    self.normalized,
-                    buffer.stride,
-                    buffer.offset,
-                );
+                // Without an explicit format, the data is always converted to floats
+                let (format, integer) = match buffer.format {
+                    Some(format) => (format, format.is_integer()),
+                    None => (
+                        AttribFormat {
+                            ty: self.data_type,
+                            normalized: self.normalized,
+                        },
+                        false,
+                    ),
+                };
+                match integer {
+                    true => gl.vertex_attrib_pointer_i32(
+                        idx,
+                        buffer.size,
+                        format.ty,
+                        buffer.stride,
+                        buffer.offset,
+                    ),
+                    false => gl.vertex_attrib_pointer_f32(
+                        idx,
+                        buffer.size,
+                        format.ty,
+                        format.normalized,
+                        buffer.stride,
+                        buffer.offset,
+                    ),
+                }
                 gl.vertex_attrib_divisor(idx, buffer.divisor);
             }
 
@@ -314,3 +1034,126 @@ impl<'a> VertexArrayBuilder<'a> {
         Ok(VertexArray { vertex_array })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::BarrierFlags;
+    use crate::shader::{ComputeBuilder, ComputeShader, ShaderBuilder};
+    use crate::testing;
+
+    #[test]
+    #[ignore = "needs an OpenGL context"]
+    fn transform_feedback_round_trip() {
+        let res = testing::with_context(|ctx| -> Result<Vec<f32>> {
+            let gl = &ctx.gl;
+            let shader = ShaderBuilder::new(Some("Feedback Test"))
+                .vert_data(
+                    "layout(location = 0) in vec4 vertex;\n\
+                     void main(void) {\n\
+                        gl_Position = vertex * 2.0;\n\
+                     }\n",
+                )
+                .frag_data("void main(void) {}\n")
+                .feedback_varyings(&["gl_Position"], true)
+                .reloadable(false)
+                .build(gl)?;
+            #[rustfmt::skip]
+            let input: [f32; 8] = [
+                1.0, 2.0, 3.0, 4.0,
+                -0.5, 0.25, 0.0, 1.0,
+            ];
+            let vbo = BufferBuilder::new(Some("Feedback Input"))
+                .data_f32(&input)
+                .build(gl)?;
+            let output = BufferBuilder::new(Some("Feedback Output"))
+                .data(&[0; 32])
+                .build(gl)?;
+            let vao = VertexArrayBuilder::new(Some("Feedback Vertex Array"))
+                .buffers(&[VertexArrayBuffer {
+                    buffer: &vbo,
+                    size: 4,
+                    stride: 0,
+                    offset: 0,
+                    divisor: 0,
+                    format: None,
+                }])
+                .build(ctx)?;
+            let tf = TransformFeedback::new(ctx, Some("Feedback Test"))?;
+
+            shader.use_program(gl);
+            vao.bind(ctx);
+            tf.bind_buffer(ctx, 0, &output);
+            unsafe {
+                gl.enable(glow::RASTERIZER_DISCARD);
+            }
+            tf.begin(ctx, glow::POINTS);
+            unsafe {
+                gl.draw_arrays(glow::POINTS, 0, 2);
+            }
+            tf.end(ctx);
+            unsafe {
+                gl.disable(glow::RASTERIZER_DISCARD);
+            }
+            VertexArray::unbind(ctx);
+
+            let mut data = [0; 32];
+            output.read(ctx, 0, &mut data)?;
+            Ok(data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect())
+        });
+        #[rustfmt::skip]
+        assert_eq!(res.unwrap(), [
+            2.0, 4.0, 6.0, 8.0,
+            -1.0, 0.5, 0.0, 2.0,
+        ]);
+    }
+
+    #[test]
+    #[ignore = "needs an OpenGL context"]
+    fn shader_storage_round_trip() {
+        let res = testing::with_context(|ctx| -> Result<(Vec<f32>, Vec<f32>)> {
+            let gl = &ctx.gl;
+            let to_bytes = |v: &[f32]| v.iter().flat_map(|f| f.to_le_bytes()).collect::<Vec<_>>();
+            let from_bytes = |b: &[u8]| {
+                b.chunks_exact(4)
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                    .collect::<Vec<_>>()
+            };
+            let ssbo = BufferBuilder::new(Some("Storage Test"))
+                .target(BufferTarget::ShaderStorage)
+                .usage(BufferUsage::Dynamic)
+                .data(&[0; 16])
+                .build(gl)?;
+
+            // Plain write and read back
+            ssbo.write(ctx, &to_bytes(&[1.0, 2.0, 3.0, 4.0]))?;
+            let mut data = [0; 16];
+            ssbo.read(ctx, 0, &mut data)?;
+            let written = from_bytes(&data);
+
+            // Modified by a compute shader
+            let shader = ComputeBuilder::new(Some("Storage Test"))
+                .comp_data(
+                    "layout(local_size_x = 4) in;\n\
+                     layout(std430, binding = 0) buffer Data {\n\
+                        float values[];\n\
+                     };\n\
+                     void main(void) {\n\
+                        values[gl_GlobalInvocationID.x] *= -2.0;\n\
+                     }\n",
+                )
+                .build(ctx)?;
+            ssbo.bind_base_ssbo(ctx, 0);
+            shader.dispatch(ctx, 1, 1, 1);
+            ComputeShader::barrier(ctx, BarrierFlags::BUFFER_UPDATE);
+            ssbo.read(ctx, 0, &mut data)?;
+            Ok((written, from_bytes(&data)))
+        });
+        let (written, computed) = res.unwrap();
+        assert_eq!(written, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(computed, [-2.0, -4.0, -6.0, -8.0]);
+    }
+}
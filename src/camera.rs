@@ -1,7 +1,8 @@
+use crate::ffi::ffi_guard;
 use crate::physics::angle_diff;
 use crate::vec2::Vec2;
 use anyhow::Result;
-use nalgebra::{Point2, Vector2};
+use nalgebra::{Matrix3, Point2, Vector2};
 use std::os::raw::{c_double, c_int, c_uint};
 use std::sync::{LazyLock, Mutex};
 
@@ -26,11 +27,65 @@ pub struct Camera {
     zoom_override: bool,
     // For pilots
     follow_pilot: c_uint,
+    // For limiting where the camera can go
+    bounds: Option<(Point2<f64>, Point2<f64>)>,
+    clamped: bool,
+    // State before the last update for interpolating
+    prev_pos: Point2<f64>,
+    prev_zoom: f64,
+}
+
+/// Simple 2D camera used by the renderer to go from world to view coordinates.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera2D {
+    pub pos: Vector2<f32>,
+    pub zoom: f32,
+    pub rotation: f32,
+}
+impl Default for Camera2D {
+    fn default() -> Self {
+        Self {
+            pos: Vector2::zeros(),
+            zoom: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+impl Camera2D {
+    /// Gets the view matrix, which places the camera position at the center of a view of
+    /// the given size.
+    #[rustfmt::skip]
+    pub fn view(&self, view_width: f32, view_height: f32) -> Matrix3<f32> {
+        let (s, c) = self.rotation.sin_cos();
+        let z = self.zoom;
+        // translate(center) * scale(zoom) * rotate(-rotation) * translate(-pos)
+        let tx = 0.5 * view_width - z * (c * self.pos.x + s * self.pos.y);
+        let ty = 0.5 * view_height - z * (-s * self.pos.x + c * self.pos.y);
+        Matrix3::new(
+             z * c, z * s, tx,
+            -z * s, z * c, ty,
+             0.0,   0.0,   1.0,
+        )
+    }
+
+    /// Interpolates between two camera states, where alpha of 0 gives self and 1 gives other.
+    /// Position and zoom are interpolated linearly, while rotation takes the shortest path.
+    #[allow(dead_code)]
+    pub fn interpolate(&self, other: &Self, alpha: f32) -> Self {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let drot = angle_diff(self.rotation as f64, other.rotation as f64) as f32;
+        Self {
+            pos: self.pos.lerp(&other.pos, alpha),
+            zoom: self.zoom + (other.zoom - self.zoom) * alpha,
+            rotation: self.rotation + drot * alpha,
+        }
+    }
 }
 
 pub static CAMERA: LazyLock<Mutex<Camera>> = LazyLock::new(|| {
     Mutex::new(Camera {
         zoom: 1.0,
+        prev_zoom: 1.0,
         zoom_speed: unsafe { naevc::conf.zoom_speed },
         ..Default::default()
     })
@@ -39,6 +94,8 @@ pub static CAMERA: LazyLock<Mutex<Camera>> = LazyLock::new(|| {
 impl Camera {
     /// Handles updating the camera at every frame
     pub fn update(&mut self, dt: f64) {
+        self.prev_pos = self.pos;
+        self.prev_zoom = self.zoom;
         let der = self.pos;
         let old = self.old;
         let mut p: *mut naevc::Pilot = std::ptr::null_mut();
@@ -71,6 +128,9 @@ impl Camera {
             self.update_manual_zoom(dt);
         }
 
+        let (w, h) = unsafe { (naevc::gl_screen.w as f64, naevc::gl_screen.h as f64) };
+        self.apply_bounds(w, h);
+
         unsafe {
             if p.is_null() {
                 let dx = dt * (old.x - self.pos.x);
@@ -96,6 +156,70 @@ impl Camera {
         }
     }
 
+    /// Gets the camera state to render with, interpolating between the state before and after
+    /// the last update. An alpha of 1 corresponds to the current simulation state. The
+    /// simulation state itself is left untouched.
+    #[allow(dead_code)]
+    pub fn render_transform(&self, alpha: f32) -> Camera2D {
+        let prev = Camera2D {
+            pos: Vector2::new(self.prev_pos.x as f32, self.prev_pos.y as f32),
+            zoom: self.prev_zoom as f32,
+            rotation: 0.0,
+        };
+        let cur = Camera2D {
+            pos: Vector2::new(self.pos.x as f32, self.pos.y as f32),
+            zoom: self.zoom as f32,
+            rotation: 0.0,
+        };
+        prev.interpolate(&cur, alpha)
+    }
+
+    /// Limits the camera so that the visible area stays within the bounds. Setting the bounds
+    /// to None removes the limit.
+    #[allow(dead_code)]
+    pub fn set_bounds(&mut self, bounds: Option<(Point2<f64>, Point2<f64>)>) {
+        self.bounds = bounds.map(|(min, max)| {
+            (
+                Point2::new(min.x.min(max.x), min.y.min(max.y)),
+                Point2::new(min.x.max(max.x), min.y.max(max.y)),
+            )
+        });
+        self.clamped = false;
+    }
+
+    /// Whether or not the camera was clamped by the bounds in the last update.
+    #[allow(dead_code)]
+    pub fn is_clamped(&self) -> bool {
+        self.clamped
+    }
+
+    /// Clamps a single axis so that [pos-half, pos+half] stays within [min, max]. If the visible
+    /// area is larger than the bounds, it gets centered instead.
+    fn clamp_axis(pos: f64, half: f64, min: f64, max: f64) -> f64 {
+        if 2.0 * half >= max - min {
+            0.5 * (min + max)
+        } else {
+            pos.clamp(min + half, max - half)
+        }
+    }
+
+    /// Applies the bounds to the camera given the screen dimensions.
+    fn apply_bounds(&mut self, screen_w: f64, screen_h: f64) {
+        let Some((min, max)) = self.bounds else {
+            self.clamped = false;
+            return;
+        };
+        let zoom = self.zoom.max(naevc::DOUBLE_TOL);
+        let hw = 0.5 * screen_w / zoom;
+        let hh = 0.5 * screen_h / zoom;
+        let pos = Point2::new(
+            Self::clamp_axis(self.pos.x, hw, min.x, max.x),
+            Self::clamp_axis(self.pos.y, hh, min.y, max.y),
+        );
+        self.clamped = pos != self.pos;
+        self.pos = pos;
+    }
+
     fn update_fly(&mut self, pos: Point2<f64>, dt: f64) {
         let max = self.fly_speed * dt;
         let k = 25. * dt;
@@ -292,131 +416,157 @@ impl Camera {
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_zoomOverride(enable: c_int) {
-    let mut cam = CAMERA.lock().unwrap();
-    cam.zoom_override = enable != 0;
+    ffi_guard((), || {
+        let mut cam = CAMERA.lock().unwrap();
+        cam.zoom_override = enable != 0;
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_setZoom(zoom: c_double) {
-    let mut cam = CAMERA.lock().unwrap();
-    unsafe {
-        cam.zoom = zoom.clamp(naevc::conf.zoom_far, naevc::conf.zoom_near);
-    }
+    ffi_guard((), || {
+        let mut cam = CAMERA.lock().unwrap();
+        unsafe {
+            cam.zoom = zoom.clamp(naevc::conf.zoom_far, naevc::conf.zoom_near);
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_setZoomTarget(zoom: c_double, speed: c_double) {
-    let mut cam = CAMERA.lock().unwrap();
-    unsafe {
-        cam.zoom_target = zoom.clamp(naevc::conf.zoom_far, naevc::conf.zoom_near);
-    }
-    cam.zoom_speed = speed;
+    ffi_guard((), || {
+        let mut cam = CAMERA.lock().unwrap();
+        unsafe {
+            cam.zoom_target = zoom.clamp(naevc::conf.zoom_far, naevc::conf.zoom_near);
+        }
+        cam.zoom_speed = speed;
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_getZoom() -> c_double {
-    let cam = CAMERA.lock().unwrap();
-    cam.zoom as c_double
+    ffi_guard(0.0, || {
+        let cam = CAMERA.lock().unwrap();
+        cam.zoom as c_double
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_getZoomTarget() -> c_double {
-    let cam = CAMERA.lock().unwrap();
-    cam.zoom_target as c_double
+    ffi_guard(0.0, || {
+        let cam = CAMERA.lock().unwrap();
+        cam.zoom_target as c_double
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_getPos(x: *mut c_double, y: *mut c_double) {
-    let cam = CAMERA.lock().unwrap();
-    unsafe {
-        *x = cam.pos.x as c_double;
-        *y = cam.pos.y as c_double;
-    }
+    ffi_guard((), || {
+        let cam = CAMERA.lock().unwrap();
+        unsafe {
+            *x = cam.pos.x as c_double;
+            *y = cam.pos.y as c_double;
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_getDPos(dx: *mut c_double, dy: *mut c_double) {
-    let cam = CAMERA.lock().unwrap();
-    unsafe {
-        *dx = cam.der.x as c_double;
-        *dy = cam.der.y as c_double;
-    }
+    ffi_guard((), || {
+        let cam = CAMERA.lock().unwrap();
+        unsafe {
+            *dx = cam.der.x as c_double;
+            *dy = cam.der.y as c_double;
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_getVel(vx: *mut c_double, vy: *mut c_double) {
-    let cam = CAMERA.lock().unwrap();
-    unsafe {
-        *vx = cam.vel.x as c_double;
-        *vy = cam.vel.y as c_double;
-    }
+    ffi_guard((), || {
+        let cam = CAMERA.lock().unwrap();
+        unsafe {
+            *vx = cam.vel.x as c_double;
+            *vy = cam.vel.y as c_double;
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_vel(vx: c_double, vy: c_double) {
-    let mut cam = CAMERA.lock().unwrap();
-    cam.vel.x = vx;
-    cam.vel.y = vy;
+    ffi_guard((), || {
+        let mut cam = CAMERA.lock().unwrap();
+        cam.vel.x = vx;
+        cam.vel.y = vy;
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_setTargetPilot(follow: c_uint, soft_over: c_int) {
-    let mut cam = CAMERA.lock().unwrap();
-    cam.follow_pilot = follow;
-
-    if soft_over == 0 {
-        if follow != 0 {
-            let p = unsafe { naevc::pilot_get(follow) };
-            let x = unsafe { (*p).solid.pos.x };
-            let y = unsafe { (*p).solid.pos.y };
-            cam.pos.x = x;
-            cam.pos.y = y;
-            cam.old.x = x;
-            cam.old.y = y;
+    ffi_guard((), || {
+        let mut cam = CAMERA.lock().unwrap();
+        cam.follow_pilot = follow;
+
+        if soft_over == 0 {
+            if follow != 0 {
+                let p = unsafe { naevc::pilot_get(follow) };
+                let x = unsafe { (*p).solid.pos.x };
+                let y = unsafe { (*p).solid.pos.y };
+                cam.pos.x = x;
+                cam.pos.y = y;
+                cam.old.x = x;
+                cam.old.y = y;
+            }
+            cam.fly = false;
+        } else {
+            cam.old.x = cam.pos.x;
+            cam.old.y = cam.pos.y;
+            cam.fly = true;
+            cam.fly_speed = soft_over.into();
         }
-        cam.fly = false;
-    } else {
-        cam.old.x = cam.pos.x;
-        cam.old.y = cam.pos.y;
-        cam.fly = true;
-        cam.fly_speed = soft_over.into();
-    }
 
-    unsafe {
-        naevc::sound_updateListener(CAMERA_DIR, cam.pos.x, cam.pos.y, 0., 0.);
-    }
+        unsafe {
+            naevc::sound_updateListener(CAMERA_DIR, cam.pos.x, cam.pos.y, 0., 0.);
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_setTargetPos(x: c_double, y: c_double, soft_over: c_int) {
-    let mut cam = CAMERA.lock().unwrap();
-    cam.follow_pilot = 0;
-    if soft_over == 0 {
-        cam.pos.x = x;
-        cam.pos.y = y;
-        cam.old.x = x;
-        cam.old.y = y;
-        cam.fly = false;
-    } else {
-        cam.target.x = x;
-        cam.target.y = y;
-        cam.old.x = cam.pos.x;
-        cam.old.y = cam.pos.y;
-        cam.fly = true;
-        cam.fly_speed = soft_over.into()
-    };
+    ffi_guard((), || {
+        let mut cam = CAMERA.lock().unwrap();
+        cam.follow_pilot = 0;
+        if soft_over == 0 {
+            cam.pos.x = x;
+            cam.pos.y = y;
+            cam.old.x = x;
+            cam.old.y = y;
+            cam.fly = false;
+        } else {
+            cam.target.x = x;
+            cam.target.y = y;
+            cam.old.x = cam.pos.x;
+            cam.old.y = cam.pos.y;
+            cam.fly = true;
+            cam.fly_speed = soft_over.into()
+        };
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_getTarget() -> c_uint {
-    let cam = CAMERA.lock().unwrap();
-    cam.follow_pilot
+    ffi_guard(0, || {
+        let cam = CAMERA.lock().unwrap();
+        cam.follow_pilot
+    })
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn cam_update(dt: c_double) {
-    let mut cam = CAMERA.lock().unwrap();
-    cam.update(dt);
+    ffi_guard((), || {
+        let mut cam = CAMERA.lock().unwrap();
+        cam.update(dt);
+    })
 }
 
 /// @brief Lua bindings to interact with the Camera.
@@ -529,3 +679,64 @@ pub fn open_camera(lua: &mlua::Lua) -> Result<()> {
     globals.set("camera", api)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(x: f64, y: f64, zoom: f64) -> Camera {
+        Camera {
+            pos: Point2::new(x, y),
+            zoom,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bounds_clamp() {
+        let mut cam = camera(-50.0, 2000.0, 1.0);
+        cam.set_bounds(Some((Point2::new(0.0, 0.0), Point2::new(1000.0, 1000.0))));
+        // Half the screen is visible on each side of the camera
+        cam.apply_bounds(200.0, 100.0);
+        assert_eq!(cam.pos, Point2::new(100.0, 950.0));
+        assert!(cam.is_clamped());
+
+        cam.pos = Point2::new(500.0, 500.0);
+        cam.apply_bounds(200.0, 100.0);
+        assert_eq!(cam.pos, Point2::new(500.0, 500.0));
+        assert!(!cam.is_clamped());
+
+        // Zooming in shows less, so the camera can get closer to the edges
+        cam.zoom = 2.0;
+        cam.pos = Point2::new(10.0, 10.0);
+        cam.apply_bounds(200.0, 100.0);
+        assert_eq!(cam.pos, Point2::new(50.0, 25.0));
+    }
+
+    #[test]
+    fn bounds_center() {
+        // The view is wider than the bounds, but not taller
+        let mut cam = camera(0.0, 0.0, 1.0);
+        cam.set_bounds(Some((Point2::new(0.0, 0.0), Point2::new(100.0, 1000.0))));
+        cam.apply_bounds(200.0, 100.0);
+        assert_eq!(cam.pos, Point2::new(50.0, 50.0));
+        assert!(cam.is_clamped());
+    }
+
+    #[test]
+    fn bounds_set() {
+        // Corners are given in any order
+        let mut cam = camera(-50.0, 2000.0, 1.0);
+        cam.set_bounds(Some((Point2::new(1000.0, 0.0), Point2::new(0.0, 1000.0))));
+        cam.apply_bounds(200.0, 100.0);
+        assert_eq!(cam.pos, Point2::new(100.0, 950.0));
+
+        // Removing the bounds leaves the camera free
+        cam.set_bounds(None);
+        assert!(!cam.is_clamped());
+        cam.pos = Point2::new(-50.0, 2000.0);
+        cam.apply_bounds(200.0, 100.0);
+        assert_eq!(cam.pos, Point2::new(-50.0, 2000.0));
+        assert!(!cam.is_clamped());
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::Result;
+use std::ffi::CString;
+use std::os::raw::{c_double, c_int};
+use std::sync::Mutex;
+
+use crate::context::Context;
+use crate::ffi::ffi_guard;
+use crate::gettext::gettext;
+use crate::log::warn_err;
+use crate::{ndata, warn};
+
+/// Directory in the write dir where capture sequences are stored.
+const CAPTURE_PATH: &str = "captures";
+/// Maximum number of capture sequences, like screenshots.
+const CAPTURE_MAX: u32 = 1000;
+
+/// A frame capture in progress. While active, the game advances by a fixed time step per frame
+/// regardless of how long it takes to render, and every frame is saved as a numbered PNG, so the
+/// result can be assembled into a video at exactly `fps`.
+struct Capture {
+    fps: f64,
+    dir: String,
+    frame: u32,
+    /// Window size before the capture started, if it had to be changed.
+    prev_size: Option<(c_int, c_int)>,
+}
+
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+fn mkdir(path: &str) -> Result<()> {
+    let cpath = CString::new(path)?;
+    match unsafe { naevc::PHYSFS_mkdir(cpath.as_ptr()) } {
+        0 => anyhow::bail!("unable to create directory '{path}'"),
+        _ => Ok(()),
+    }
+}
+
+/// Starts capturing frames at `fps` to a new "captures/captureNNN/" directory in the write dir.
+/// If `size` is given, the window is resized to it for the duration of the capture.
+pub fn start(fps: f64, size: Option<(u32, u32)>) -> Result<()> {
+    if !(fps > 0.0 && fps.is_finite()) {
+        anyhow::bail!("invalid capture framerate '{fps}'");
+    }
+    let mut capture = CAPTURE.lock().unwrap();
+    if capture.is_some() {
+        anyhow::bail!("capture already in progress");
+    }
+
+    mkdir(CAPTURE_PATH)?;
+    let dir = (0..CAPTURE_MAX)
+        .map(|i| format!("{CAPTURE_PATH}/capture{i:03}"))
+        .find(|dir| !ndata::exists(dir))
+        .ok_or_else(|| anyhow::anyhow!("reached the maximum amount of captures"))?;
+    mkdir(&dir)?;
+
+    let prev_size = match size {
+        Some((w, h)) => {
+            if w == 0 || h == 0 {
+                anyhow::bail!("invalid capture resolution '{w}x{h}'");
+            }
+            let ctx = Context::get()?;
+            let (mut pw, mut ph) = (0, 0);
+            unsafe {
+                let window = ctx.window.raw();
+                sdl2::sys::SDL_GetWindowSize(window, &mut pw, &mut ph);
+                sdl2::sys::SDL_SetWindowSize(window, w as c_int, h as c_int);
+            }
+            Some((pw, ph))
+        }
+        None => None,
+    };
+
+    *capture = Some(Capture {
+        fps,
+        dir,
+        frame: 0,
+        prev_size,
+    });
+    Ok(())
+}
+
+/// Stops the current capture, restoring the window size if it was changed. Returns the number of
+/// frames captured.
+pub fn stop() -> Result<u32> {
+    let capture = match CAPTURE.lock().unwrap().take() {
+        Some(capture) => capture,
+        None => anyhow::bail!("no capture in progress"),
+    };
+    if let Some((w, h)) = capture.prev_size {
+        let ctx = Context::get()?;
+        unsafe {
+            sdl2::sys::SDL_SetWindowSize(ctx.window.raw(), w, h);
+        }
+    }
+    Ok(capture.frame)
+}
+
+/// Whether a capture is in progress.
+pub fn is_active() -> bool {
+    CAPTURE.lock().unwrap().is_some()
+}
+
+/// Time step to use for the current frame, overriding the wall-clock time while capturing.
+pub fn dt(real_dt: f64) -> f64 {
+    match &*CAPTURE.lock().unwrap() {
+        Some(capture) => 1.0 / capture.fps,
+        None => real_dt,
+    }
+}
+
+/// Saves the frame that was just rendered, if capturing. Must be called before swapping.
+pub fn frame() -> Result<()> {
+    let mut capture = CAPTURE.lock().unwrap();
+    let capture = match &mut *capture {
+        Some(capture) => capture,
+        None => return Ok(()),
+    };
+    let filename = CString::new(format!("{}/frame{:06}.png", capture.dir, capture.frame))?;
+    unsafe {
+        naevc::gl_screenshot(filename.as_ptr());
+    }
+    capture.frame += 1;
+    Ok(())
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_start(fps: c_double, w: c_int, h: c_int) -> c_int {
+    ffi_guard(-1, || {
+        let size = match w > 0 && h > 0 {
+            true => Some((w as u32, h as u32)),
+            false => None,
+        };
+        match start(fps, size) {
+            Ok(()) => 0,
+            Err(e) => {
+                warn_err(e.context(gettext("unable to start frame capture")));
+                -1
+            }
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_stop() -> c_int {
+    ffi_guard(-1, || match stop() {
+        Ok(n) => n as c_int,
+        Err(e) => {
+            warn_err(e);
+            -1
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_dt(real_dt: c_double) -> c_double {
+    ffi_guard(real_dt, || dt(real_dt))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_frame() {
+    ffi_guard((), || {
+        if let Err(e) = frame() {
+            warn!("unable to capture frame: {}", e);
+            // Don't keep spamming warnings every frame
+            let _ = stop();
+        }
+    })
+}
@@ -36,3 +36,5 @@ void col_hsv2rgb( glColour *c, float h, float s, float v );
 void col_rgb2hsv( float *h, float *s, float *v, float r, float g, float b );
 void col_blend( glColour *blend, const glColour *fg, const glColour *bg,
                 float alpha );
+int  col_daltonizeMatrix( int type, double intensity,
+                          float out[9] ); /* Implemented in Rust. */
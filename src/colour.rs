@@ -0,0 +1,225 @@
+use anyhow::Result;
+use nalgebra::{Matrix3, Vector4};
+use palette::rgb::Srgb;
+use std::ffi::CString;
+use std::os::raw::{c_double, c_float, c_int};
+
+use crate::ffi::ffi_guard;
+
+/// Parses a colour given either as a hex string ("#rrggbb" or "#rrggbbaa") in sRGB, or as the
+/// name of one of the predefined colours. The result is in linear space like `glColour`.
+pub fn parse(s: &str) -> Result<Vector4<f32>> {
+    match s.strip_prefix('#') {
+        Some(hex) => from_hex(hex),
+        None => from_name(s),
+    }
+}
+
+/// Parses a hex colour without the leading '#'.
+pub fn from_hex(hex: &str) -> Result<Vector4<f32>> {
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("invalid hex colour '#{hex}'");
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    let alpha = match hex.len() {
+        8 => channel(6) as f32 / 255.0,
+        _ => 1.0,
+    };
+    let col = Srgb::new(channel(0), channel(2), channel(4))
+        .into_format::<f32>()
+        .into_linear();
+    Ok(Vector4::new(col.red, col.green, col.blue, alpha))
+}
+
+/// Looks up one of the colours defined in colours.gen.h.
+pub fn from_name(name: &str) -> Result<Vector4<f32>> {
+    let cname = CString::new(name)?;
+    let col = unsafe { naevc::col_fromName(cname.as_ptr()) };
+    if col.is_null() {
+        anyhow::bail!("unknown colour '{name}'");
+    }
+    let col = unsafe { &*col };
+    Ok(Vector4::new(col.r, col.g, col.b, col.a))
+}
+
+/// Converts a colour from sRGB to linear space, leaving alpha untouched.
+pub fn srgb_to_linear(col: Vector4<f32>) -> Vector4<f32> {
+    let lin = Srgb::new(col.x, col.y, col.z).into_linear();
+    Vector4::new(lin.red, lin.green, lin.blue, col.w)
+}
+
+/// Colour vision deficiencies that can be corrected, matching the values of
+/// `conf.colourblind_type`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColourBlindness {
+    /// Greatly reduced reds.
+    Protanopia,
+    /// Greatly reduced greens.
+    Deuteranopia,
+    /// Greatly reduced blues.
+    Tritanopia,
+}
+impl ColourBlindness {
+    /// Gets the deficiency from `conf.colourblind_type`. Monochromacy can't be corrected, so it
+    /// and unknown values give `None`.
+    pub fn from_conf(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Protanopia),
+            1 => Some(Self::Deuteranopia),
+            2 => Some(Self::Tritanopia),
+            _ => None,
+        }
+    }
+
+    /// Projection in LMS space removing the information the missing cone type would see.
+    #[rustfmt::skip]
+    fn lms_projection(self) -> Matrix3<f32> {
+        match self {
+            Self::Protanopia => Matrix3::new(
+                0.0, 2.02344, -2.52581,
+                0.0, 1.0,      0.0,
+                0.0, 0.0,      1.0,
+            ),
+            Self::Deuteranopia => Matrix3::new(
+                1.0,      0.0, 0.0,
+                0.494207, 0.0, 1.24827,
+                0.0,      0.0, 1.0,
+            ),
+            Self::Tritanopia => Matrix3::new(
+                1.0,       0.0,      0.0,
+                0.0,       1.0,      0.0,
+                -0.395913, 0.801109, 0.0,
+            ),
+        }
+    }
+
+    /// Matrix taking RGB to what someone with the deficiency would see.
+    pub fn simulate_matrix(self) -> Matrix3<f32> {
+        LMS_TO_RGB * self.lms_projection() * RGB_TO_LMS
+    }
+
+    /// Daltonization matrix. The colour information lost to the deficiency is shifted into the
+    /// channels that can still be told apart, blended with the original by `intensity` in
+    /// [0, 1]. Unlike the old shader, which dropped the red channel and the original colour at
+    /// full intensity, the shifted error is added on top of the original as in the reference.
+    pub fn daltonize_matrix(self, intensity: f32) -> Matrix3<f32> {
+        let error = Matrix3::identity() - self.simulate_matrix();
+        Matrix3::identity() + DALTONIZE_SHIFT * error * intensity.clamp(0.0, 1.0)
+    }
+}
+
+/// RGB to LMS cone response, from <http://www.daltonize.org/search/label/Daltonize>.
+#[rustfmt::skip]
+const RGB_TO_LMS: Matrix3<f32> = Matrix3::new(
+    17.8824,   43.5161,  4.11935,
+    3.45565,   27.1554,  3.86714,
+    0.0299566, 0.184309, 1.46709,
+);
+
+/// Inverse of [`RGB_TO_LMS`].
+#[rustfmt::skip]
+const LMS_TO_RGB: Matrix3<f32> = Matrix3::new(
+    0.0809444479,   -0.130504409,  0.116721066,
+    -0.0102485335,   0.0540193266, -0.113614708,
+    -0.000365296938, -0.00412161469, 0.693511405,
+);
+
+/// How the error is redistributed to the other channels.
+#[rustfmt::skip]
+const DALTONIZE_SHIFT: Matrix3<f32> = Matrix3::new(
+    0.0, 0.0, 0.0,
+    0.7, 1.0, 0.0,
+    0.7, 0.0, 1.0,
+);
+
+/// Writes the column-major daltonization matrix for `conf.colourblind_type` to `out`, ready for
+/// `glUniformMatrix3fv`. Types that can't be corrected give the identity. Returns 0 on success.
+#[unsafe(no_mangle)]
+pub extern "C" fn col_daltonizeMatrix(ty: c_int, intensity: c_double, out: *mut c_float) -> c_int {
+    ffi_guard(-1, || {
+        if out.is_null() {
+            return -1;
+        }
+        let m = match ColourBlindness::from_conf(ty) {
+            Some(cb) => cb.daltonize_matrix(intensity as f32),
+            None => Matrix3::identity(),
+        };
+        let out = unsafe { std::slice::from_raw_parts_mut(out, 9) };
+        out.copy_from_slice(m.as_slice());
+        0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [ColourBlindness; 3] = [
+        ColourBlindness::Protanopia,
+        ColourBlindness::Deuteranopia,
+        ColourBlindness::Tritanopia,
+    ];
+
+    fn assert_matrix_eq(m: &Matrix3<f32>, expected: &Matrix3<f32>, eps: f32) {
+        assert!(
+            (m - expected).abs().max() < eps,
+            "got {m}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn srgb_mid_grey() {
+        // sRGB 0.5 is about 21.4% of the light, not half of it
+        let lin = srgb_to_linear(Vector4::new(0.5, 0.5, 0.5, 0.5));
+        for c in [lin.x, lin.y, lin.z] {
+            assert!((c - 0.214_041).abs() < 1e-5, "got {c}");
+        }
+        assert_eq!(lin.w, 0.5);
+        // The ends stay put
+        let ends = srgb_to_linear(Vector4::new(0.0, 1.0, 0.0, 1.0));
+        assert!((ends - Vector4::new(0.0, 1.0, 0.0, 1.0)).abs().max() < 1e-6);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn daltonize_protanopia() {
+        let expected = Matrix3::new(
+            1.0,      0.0,      0.0,
+            0.50895,  0.49105,  0.0,
+            0.61733,  -0.61732, 1.0,
+        );
+        let m = ColourBlindness::Protanopia.daltonize_matrix(1.0);
+        assert_matrix_eq(&m, &expected, 1e-3);
+    }
+
+    #[test]
+    fn daltonize_intensity() {
+        for cb in ALL {
+            assert_matrix_eq(&cb.daltonize_matrix(0.0), &Matrix3::identity(), 1e-6);
+            // Out of range intensities are clamped
+            assert_matrix_eq(&cb.daltonize_matrix(2.0), &cb.daltonize_matrix(1.0), 1e-6);
+        }
+    }
+
+    #[test]
+    fn daltonize_keeps_greys() {
+        let grey = nalgebra::Vector3::new(0.5, 0.5, 0.5);
+        for cb in ALL {
+            let out = cb.daltonize_matrix(1.0) * grey;
+            assert!(
+                (out - grey).abs().max() < 1e-3,
+                "{cb:?} changed grey to {out}"
+            );
+        }
+    }
+
+    #[test]
+    fn daltonize_matrix_ffi() {
+        let mut out = [0.0; 9];
+        assert_eq!(col_daltonizeMatrix(3, 1.0, out.as_mut_ptr()), 0);
+        assert_eq!(out, [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        // Column-major, so the red contribution to green comes second
+        assert_eq!(col_daltonizeMatrix(0, 1.0, out.as_mut_ptr()), 0);
+        assert!((out[1] - 0.50895).abs() < 1e-3);
+    }
+}
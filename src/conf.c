@@ -240,8 +240,10 @@ void conf_setVideoDefaults( void )
    }
 
    /* OpenGL. */
-   conf.fsaa  = FSAA_DEFAULT;
-   conf.vsync = VSYNC_DEFAULT;
+   conf.fsaa       = FSAA_DEFAULT;
+   conf.vsync      = VSYNC_DEFAULT;
+   conf.anisotropy = ANISOTROPY_DEFAULT;
+   conf.trilinear  = TRILINEAR_DEFAULT;
 
    /* Window. */
    conf.fullscreen = f;
@@ -262,6 +264,7 @@ void conf_setVideoDefaults( void )
    conf.nebu_saturation     = NEBU_SATURATION_DEFAULT;
    conf.jump_brightness     = JUMP_BRIGHTNESS_DEFAULT;
    conf.gamma_correction    = GAMMA_CORRECTION_DEFAULT;
+   conf.render_scale        = RENDER_SCALE_DEFAULT;
    conf.low_memory          = LOW_MEMORY_DEFAULT;
    conf.max_3d_tex_size     = MAX_3D_TEX_SIZE;
 
@@ -343,6 +346,8 @@ int conf_loadConfig( const char *file )
    /* OpenGL. */
    conf_loadInt( L, "fsaa", conf.fsaa );
    conf_loadBool( L, "vsync", conf.vsync );
+   conf_loadFloat( L, "anisotropy", conf.anisotropy );
+   conf_loadBool( L, "trilinear", conf.trilinear );
 
    /* Window. */
    w = h = 0;
@@ -385,6 +390,7 @@ int conf_loadConfig( const char *file )
    conf_loadFloat( L, "nebu_saturation", conf.nebu_saturation );
    conf_loadFloat( L, "jump_brightness", conf.jump_brightness );
    conf_loadFloat( L, "gamma_correction", conf.gamma_correction );
+   conf_loadFloat( L, "render_scale", conf.render_scale );
    conf_loadBool( L, "low_memory", conf.low_memory );
    conf_loadInt( L, "max_3d_tex_size", conf.max_3d_tex_size );
 
@@ -466,7 +472,7 @@ int conf_loadConfig( const char *file )
    /*
     * Keybindings.
     */
-   for ( int i = 0; i <= KST_PASTE; i++ ) {
+   for ( int i = 0; i < KST_END; i++ ) {
       lua_getglobal( L, input_getKeybindBrief( i ) );
 
       /* Use 'none' to differentiate between not instantiated and disabled
@@ -919,6 +925,16 @@ int conf_saveConfig( const char *file )
    conf_saveBool( "vsync", conf.vsync );
    conf_saveEmptyLine();
 
+   conf_saveComment(
+      _( "Anisotropic filtering level to use for mipmapped textures" ) );
+   conf_saveComment( _( "Values of 1 or lower disable anisotropic filtering" ) );
+   conf_saveFloat( "anisotropy", conf.anisotropy );
+   conf_saveEmptyLine();
+
+   conf_saveComment( _( "Whether to blend between mipmap levels" ) );
+   conf_saveBool( "trilinear", conf.trilinear );
+   conf_saveEmptyLine();
+
    /* Window. */
    conf_saveComment( _( "The window size or screen resolution" ) );
    conf_saveComment(
@@ -1023,6 +1039,13 @@ int conf_saveConfig( const char *file )
    conf_saveFloat( "gamma_correction", conf.gamma_correction );
    conf_saveEmptyLine();
 
+   conf_saveComment(
+      _( "Resolution to render the game world at, relative to the window. "
+         "Lower values such as 0.5 improve performance on weak graphics cards, "
+         "while the interface is always rendered at full resolution." ) );
+   conf_saveFloat( "render_scale", conf.render_scale );
+   conf_saveEmptyLine();
+
    conf_saveComment( _( "Enables low memory mode which foregoes using normal "
                         "textures and ambient occlusion. Useful when you want "
                         "to run Naev or more limited hardware." ) );
@@ -1227,7 +1250,7 @@ int conf_saveConfig( const char *file )
    conf_saveEmptyLine();
 
    /* Iterate over the keybinding. */
-   for ( int i = 0; i <= KST_PASTE; i++ ) {
+   for ( int i = 0; i < KST_END; i++ ) {
       SDL_Keycode key;
       KeybindType type;
       const char *typename;
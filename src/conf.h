@@ -46,6 +46,9 @@
    0                    /**< Whether fullscreen uses video modesetting. */
 #define FSAA_DEFAULT 1  /**< Whether to use Full Screen Anti-Aliasing. */
 #define VSYNC_DEFAULT 0 /**< Whether to wait for vertical sync. */
+#define ANISOTROPY_DEFAULT                                                     \
+   16. /**< Anisotropic filtering level to use for mipmapped textures. */
+#define TRILINEAR_DEFAULT 1 /**< Whether to filter between mipmap levels. */
 #define SCALE_FACTOR_DEFAULT 1. /**< Default scale factor. */
 #define NEBULA_SCALE_FACTOR_DEFAULT                                            \
    4.                        /**< Default scale factor for nebula rendering. */
@@ -72,6 +75,7 @@
    0 /**< WHether to give a button to skip puzzles.                            \
       */
 #define GAMMA_CORRECTION_DEFAULT 1.  /**< How much gamma correction to do. */
+#define RENDER_SCALE_DEFAULT 1. /**< Resolution to render the world at. */
 #define JUMP_BRIGHTNESS_DEFAULT 0.8  /**< Default jump brightness.*/
 #define BIG_ICONS_DEFAULT 0          /**< Whether to display BIGGER icons. */
 #define FONT_SIZE_CONSOLE_DEFAULT 10 /**< Default console font size. */
@@ -107,8 +111,10 @@ typedef struct PlayerConf_s {
    char *language; /**< Language to use. */
 
    /* OpenGL properties. */
-   unsigned char fsaa;  /**< Full Scene Anti-Aliasing to use. */
-   int           vsync; /**< Whether or not to use vsync. */
+   unsigned char fsaa;       /**< Full Scene Anti-Aliasing to use. */
+   int           vsync;      /**< Whether or not to use vsync. */
+   double        anisotropy; /**< Anisotropic filtering level for mipmaps. */
+   int           trilinear;  /**< Whether to filter between mipmap levels. */
 
    /* Video options. */
    unsigned int width;        /**< Width of the window to use. */
@@ -134,6 +140,8 @@ typedef struct PlayerConf_s {
    double jump_brightness;    /**< Intensity to fade to/from when jumping. */
    int puzzle_skip; /**< Whether or not to allow skipping puzzles for free. */
    double gamma_correction; /**< How much gamma correction to do. */
+   double render_scale; /**< Resolution to render the world at, relative to the
+                           window. */
    int    low_memory;       /**< Low memory mode. */
    int max_3d_tex_size; /**< How large to make the textures in low memory mode.
                          */
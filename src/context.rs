@@ -1,24 +1,34 @@
 #![allow(dead_code)]
+use crate::ffi::ffi_guard;
 use crate::render::Uniform;
 use anyhow::Result;
 use glow::*;
 use nalgebra::{Matrix3, Matrix4, Point3, Vector3, Vector4};
 use sdl2 as sdl;
 use sdl2::image::ImageRWops;
+use std::ffi::CStr;
+use std::io::Write;
 use std::ops::Deref;
-use std::os::raw::c_double;
-use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock};
+use std::os::raw::{c_char, c_double, c_int};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{
+    mpsc, Arc, Mutex, MutexGuard, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
 use std::thread::ThreadId;
 
 use crate::buffer::{
-    Buffer, BufferBuilder, BufferTarget, BufferUsage, VertexArray, VertexArrayBuffer,
+    Buffer, BufferBuilder, BufferTarget, BufferUsage, UniformBatch, VertexArray, VertexArrayBuffer,
     VertexArrayBuilder,
 };
+use crate::camera::Camera2D;
 use crate::log::warn_err;
-use crate::render::{SolidUniform, TextureScaleUniform, TextureUniform};
+use crate::render::{SolidUniform, TextureScaleUniform, TextureUniform, TimeUniform};
 use crate::shader::{Shader, ShaderBuilder};
-use crate::{debug, warn};
-use crate::{gettext, log, ndata};
+use crate::texture::{
+    FilterMode, Framebuffer, FramebufferBuilder, Sampler, Texture, TextureFormat,
+};
+use crate::{colour, gettext, log, ndata, physfs, render, texture};
+use crate::{debug, info, warn};
 
 fn debug_callback(source: u32, msg_type: u32, id: u32, severity: u32, msg: &str) {
     let s_source = match source {
@@ -64,7 +74,53 @@ fn debug_callback(source: u32, msg_type: u32, id: u32, severity: u32, msg: &str)
     }
 }
 
-#[allow(clippy::enum_variant_names)] // Remove when we add other messages
+/// Name of an error returned by `glGetError`.
+fn gl_error_name(err: u32) -> String {
+    match err {
+        glow::INVALID_ENUM => "GL_INVALID_ENUM".to_string(),
+        glow::INVALID_VALUE => "GL_INVALID_VALUE".to_string(),
+        glow::INVALID_OPERATION => "GL_INVALID_OPERATION".to_string(),
+        glow::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION".to_string(),
+        glow::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY".to_string(),
+        glow::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW".to_string(),
+        glow::STACK_OVERFLOW => "GL_STACK_OVERFLOW".to_string(),
+        _ => format!("0x{err:04x}"),
+    }
+}
+
+/// Whether `KHR_debug` is available, either from OpenGL 4.3 or the extension.
+fn has_khr_debug(gl: &glow::Context) -> bool {
+    let version = gl.version();
+    (version.major, version.minor) >= (4, 3) || gl.supported_extensions().contains("GL_KHR_debug")
+}
+
+/// Whether shader subroutines can be used. They are core since OpenGL 4.0, but the shaders enable
+/// them with `#extension GL_ARB_shader_subroutine`, so the extension has to be listed either way.
+pub fn has_shader_subroutines(gl: &glow::Context) -> bool {
+    gl.supported_extensions()
+        .contains("GL_ARB_shader_subroutine")
+}
+
+/// Labels an OpenGL object, so that debuggers and the debug callback show `name` instead of its
+/// numeric id. `identifier` is the type of object, e.g., `glow::TEXTURE`. Does nothing without
+/// `KHR_debug`.
+pub fn set_label(gl: &glow::Context, identifier: u32, id: u32, name: Option<&str>) {
+    if name.is_some() && has_khr_debug(gl) {
+        unsafe {
+            gl.object_label(identifier, id, name);
+        }
+    }
+}
+
+/// Reply channel for messages that create objects.
+pub type MessageReply<T> = mpsc::Sender<Result<T>>;
+
+/// Sync object handle that can be queued for deletion from any thread. The handle is an opaque
+/// pointer that is only ever used on the main thread.
+#[derive(Clone, Copy)]
+pub struct SyncHandle(pub glow::Fence);
+unsafe impl Send for SyncHandle {}
+
 #[derive(Clone)]
 pub enum Message {
     DeleteBuffer(glow::NativeBuffer),
@@ -73,6 +129,23 @@ pub enum Message {
     DeleteTexture(glow::NativeTexture),
     DeleteSampler(glow::NativeSampler),
     DeleteFramebuffer(glow::NativeFramebuffer),
+    DeleteRenderbuffer(glow::NativeRenderbuffer),
+    DeleteQuery(glow::NativeQuery),
+    DeleteTransformFeedback(glow::NativeTransformFeedback),
+    DeleteSync(SyncHandle),
+    CreateTexture {
+        format: TextureFormat,
+        w: usize,
+        h: usize,
+        data: Vec<u8>,
+        reply: MessageReply<glow::NativeTexture>,
+    },
+    CreateBuffer {
+        target: u32,
+        usage: u32,
+        data: Vec<u8>,
+        reply: MessageReply<glow::NativeBuffer>,
+    },
 }
 impl Message {
     fn execute(self, ctx: &Context) {
@@ -95,17 +168,318 @@ impl Message {
             Self::DeleteFramebuffer(buf) => unsafe {
                 ctx.gl.delete_framebuffer(buf);
             },
+            Self::DeleteRenderbuffer(buf) => unsafe {
+                ctx.gl.delete_renderbuffer(buf);
+            },
+            Self::DeleteQuery(query) => unsafe {
+                ctx.gl.delete_query(query);
+            },
+            Self::DeleteTransformFeedback(tf) => unsafe {
+                ctx.gl.delete_transform_feedback(tf);
+            },
+            Self::DeleteSync(sync) => unsafe {
+                ctx.gl.delete_sync(sync.0);
+            },
+            Self::CreateTexture {
+                format,
+                w,
+                h,
+                data,
+                reply,
+            } => {
+                let res = Self::create_texture(ctx, format, w, h, &data);
+                if let Err(Ok(tex)) = reply.send(res).map_err(|e| e.0) {
+                    // Nobody is waiting anymore, so clean up
+                    unsafe {
+                        ctx.gl.delete_texture(tex);
+                    }
+                }
+            }
+            Self::CreateBuffer {
+                target,
+                usage,
+                data,
+                reply,
+            } => {
+                let res = Self::create_buffer(ctx, target, usage, &data);
+                if let Err(Ok(buf)) = reply.send(res).map_err(|e| e.0) {
+                    unsafe {
+                        ctx.gl.delete_buffer(buf);
+                    }
+                }
+            }
+        }
+    }
+
+    fn create_texture(
+        ctx: &Context,
+        format: TextureFormat,
+        w: usize,
+        h: usize,
+        data: &[u8],
+    ) -> Result<glow::NativeTexture> {
+        let gl = &ctx.gl;
+        let texture = unsafe { gl.create_texture().map_err(|e| anyhow::anyhow!(e)) }?;
+        // The rows are tightly packed, which for 3 channels only matches the default alignment of
+        // 4 when the width happens to be a multiple of 4
+        let packed = matches!(format, TextureFormat::RGB | TextureFormat::SRGB);
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            if packed {
+                gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            }
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                format.to_sized_gl(),
+                w as i32,
+                h as i32,
+                0,
+                format.to_gl(),
+                glow::UNSIGNED_BYTE,
+                glow::PixelUnpackData::Slice(match data.is_empty() {
+                    true => None,
+                    false => Some(data),
+                }),
+            );
+            if packed {
+                gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+        Ok(texture)
+    }
+
+    fn create_buffer(
+        ctx: &Context,
+        target: u32,
+        usage: u32,
+        data: &[u8],
+    ) -> Result<glow::NativeBuffer> {
+        let gl = &ctx.gl;
+        let buffer = unsafe { gl.create_buffer().map_err(|e| anyhow::anyhow!(e)) }?;
+        unsafe {
+            gl.bind_buffer(target, Some(buffer));
+            gl.buffer_data_u8_slice(target, data, usage);
+            gl.bind_buffer(target, None);
         }
+        Ok(buffer)
+    }
+
+    /// Requests a texture to be created on the main thread. The result can be received once
+    /// the main thread processes the message queue, so do not block on it from the main thread.
+    pub fn request_texture(
+        format: TextureFormat,
+        w: usize,
+        h: usize,
+        data: Vec<u8>,
+    ) -> mpsc::Receiver<Result<glow::NativeTexture>> {
+        let (reply, recv) = mpsc::channel();
+        MESSAGE_QUEUE.lock().unwrap().push(Self::CreateTexture {
+            format,
+            w,
+            h,
+            data,
+            reply,
+        });
+        recv
+    }
+
+    /// Requests a buffer to be created on the main thread. The result can be received once the
+    /// main thread processes the message queue, so do not block on it from the main thread.
+    pub fn request_buffer(
+        target: u32,
+        usage: u32,
+        data: Vec<u8>,
+    ) -> mpsc::Receiver<Result<glow::NativeBuffer>> {
+        let (reply, recv) = mpsc::channel();
+        MESSAGE_QUEUE.lock().unwrap().push(Self::CreateBuffer {
+            target,
+            usage,
+            data,
+            reply,
+        });
+        recv
     }
 }
 
 pub static CONTEXT: OnceLock<Context> = OnceLock::new();
 pub static MESSAGE_QUEUE: Mutex<Vec<Message>> = Mutex::new(vec![]);
 
+/// Capabilities of the OpenGL context, from the version that could be created. The game only
+/// requires OpenGL 3.3, so anything needing more has to check for it and fall back.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum FeatureLevel {
+    /// OpenGL 3.3, the minimum supported. 3.2 contexts created as a last resort also end up here.
+    Gl33,
+    /// OpenGL 4.3, adding compute shaders and shader storage buffers.
+    Gl43,
+    /// OpenGL 4.4, adding immutable buffer storage and persistent mapping.
+    Gl44,
+    /// OpenGL 4.6, adding SPIR-V shaders.
+    Gl46,
+}
+impl FeatureLevel {
+    pub fn from_version(major: u8, minor: u8) -> Self {
+        match (major, minor) {
+            (4, 6..) | (5.., _) => Self::Gl46,
+            (4, 4..) => Self::Gl44,
+            (4, 3..) => Self::Gl43,
+            _ => Self::Gl33,
+        }
+    }
+}
+
+/// Optional features that depend on the [`FeatureLevel`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Feature {
+    Compute,
+    StorageBuffers,
+    PersistentMapping,
+    Spirv,
+}
+impl Feature {
+    pub fn min_level(self) -> FeatureLevel {
+        match self {
+            Self::Compute | Self::StorageBuffers => FeatureLevel::Gl43,
+            Self::PersistentMapping => FeatureLevel::Gl44,
+            Self::Spirv => FeatureLevel::Gl46,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Compute => "compute shaders",
+            Self::StorageBuffers => "shader storage buffers",
+            Self::PersistentMapping => "persistently mapped buffers",
+            Self::Spirv => "SPIR-V shaders",
+        }
+    }
+}
+
+/// Set of `glMemoryBarrier` bits, saying how the data written by shaders is going to be read
+/// next. Combine them with `|`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BarrierFlags(u32);
+impl BarrierFlags {
+    pub const VERTEX_ATTRIB_ARRAY: Self = Self(glow::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+    pub const ELEMENT_ARRAY: Self = Self(glow::ELEMENT_ARRAY_BARRIER_BIT);
+    pub const UNIFORM: Self = Self(glow::UNIFORM_BARRIER_BIT);
+    pub const TEXTURE_FETCH: Self = Self(glow::TEXTURE_FETCH_BARRIER_BIT);
+    pub const SHADER_IMAGE_ACCESS: Self = Self(glow::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+    pub const COMMAND: Self = Self(glow::COMMAND_BARRIER_BIT);
+    pub const PIXEL_BUFFER: Self = Self(glow::PIXEL_BUFFER_BARRIER_BIT);
+    pub const TEXTURE_UPDATE: Self = Self(glow::TEXTURE_UPDATE_BARRIER_BIT);
+    pub const BUFFER_UPDATE: Self = Self(glow::BUFFER_UPDATE_BARRIER_BIT);
+    pub const FRAMEBUFFER: Self = Self(glow::FRAMEBUFFER_BARRIER_BIT);
+    pub const TRANSFORM_FEEDBACK: Self = Self(glow::TRANSFORM_FEEDBACK_BARRIER_BIT);
+    pub const ATOMIC_COUNTER: Self = Self(glow::ATOMIC_COUNTER_BARRIER_BIT);
+    pub const SHADER_STORAGE: Self = Self(glow::SHADER_STORAGE_BARRIER_BIT);
+    pub const ALL: Self = Self(glow::ALL_BARRIER_BITS);
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+impl std::ops::BitOr for BarrierFlags {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+impl std::ops::BitOrAssign for BarrierFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0
+    }
+}
+
+/// How buffer swaps are synchronized with the display refresh.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VsyncMode {
+    Off,
+    On,
+    /// Synchronizes, but swaps immediately when a frame is late instead of waiting for the next
+    /// refresh. Not supported by all drivers.
+    Adaptive,
+}
+impl VsyncMode {
+    pub fn from_conf(vsync: i32) -> Self {
+        match vsync {
+            0 => Self::Off,
+            _ => Self::On,
+        }
+    }
+
+    fn from_swap_interval(interval: sdl::video::SwapInterval) -> Self {
+        match interval {
+            sdl::video::SwapInterval::Immediate => Self::Off,
+            sdl::video::SwapInterval::VSync => Self::On,
+            sdl::video::SwapInterval::LateSwapTearing => Self::Adaptive,
+        }
+    }
+
+    fn to_sdl(self) -> sdl::video::SwapInterval {
+        match self {
+            Self::Off => sdl::video::SwapInterval::Immediate,
+            Self::On => sdl::video::SwapInterval::VSync,
+            Self::Adaptive => sdl::video::SwapInterval::LateSwapTearing,
+        }
+    }
+}
+
+/// How the game is fitted into windows that don't match its aspect ratio.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum AspectMode {
+    /// Uses the whole window, changing the visible area to match its aspect ratio.
+    #[default]
+    Stretch,
+    /// Keeps the given aspect ratio (width / height) by adding bars at the sides or top and
+    /// bottom of the window.
+    Letterbox(f32),
+    /// Keeps the given aspect ratio (width / height) by filling the window and cropping what
+    /// doesn't fit.
+    FillCrop(f32),
+}
+impl AspectMode {
+    /// Computes the drawing area inside a window of `w`×`h` real pixels as `[x, y, w, h]`.
+    pub fn viewport(self, w: u32, h: u32) -> [i32; 4] {
+        let (fw, fh) = (w as f32, h as f32);
+        let fit = |aspect: f32, cover: bool| {
+            let wider = fw / fh > aspect;
+            // Letterboxing fits the height of wider windows, while cropping fits the width
+            let (vw, vh) = match wider != cover {
+                true => (fh * aspect, fh),
+                false => (fw, fw / aspect),
+            };
+            let (vw, vh) = (vw.round() as i32, vh.round() as i32);
+            [
+                (w as i32 - vw) / 2,
+                (h as i32 - vh) / 2,
+                vw.max(1),
+                vh.max(1),
+            ]
+        };
+        match self {
+            Self::Letterbox(aspect) if aspect > 0.0 => fit(aspect, false),
+            Self::FillCrop(aspect) if aspect > 0.0 => fit(aspect, true),
+            _ => [0, 0, w as i32, h as i32],
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Dimensions {
     pub window_width: u32,  // In real pixels
     pub window_height: u32, // In real pixels
+    pub viewport: [i32; 4], // Drawing area in the window in real pixels
     pub view_width: f32,    // In scaled pixels
     pub view_height: f32,   // In scaled pixels
     pub view_scale: f32,    // In scaling value
@@ -131,6 +505,51 @@ pub const fn ortho4( left: f32, right: f32, bottom: f32, top: f32, near: f32, fa
     )
 }
 
+/// Orthographic projection for 3D rendering. Same as [`ortho4`], except that with `reverse_z`
+/// the near plane maps to a clip space depth of 1 and the far plane to -1.
+pub fn ortho3d(
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near: f32,
+    far: f32,
+    reverse_z: bool,
+) -> Matrix4<f32> {
+    match reverse_z {
+        true => ortho4(left, right, bottom, top, far, near),
+        false => ortho4(left, right, bottom, top, near, far),
+    }
+}
+
+/// Perspective projection with a vertical field of view `fov` in radians.
+///
+/// The matrices follow the OpenGL convention used by the model shaders: right-handed view space
+/// looking down -Z, and clip space depth in [-1, 1], with near mapping to -1 and far to 1. With
+/// `reverse_z` the depth range is flipped so that far maps to -1 (0 in the default depth range)
+/// and near to 1. Reversed depth needs `GL_GREATER` as the depth function and a depth clear value
+/// of 0, see [`Context::depth_func`] and [`Context::depth_clear`].
+///
+/// This only flips the ordering: the precision gain usually attributed to reverse-Z needs clip
+/// space depth in [0, 1] through `glClipControl`, as the `z * 0.5 + 0.5` remapping of the [-1, 1]
+/// range rounds away the extra precision near 0.
+#[rustfmt::skip]
+pub fn perspective4(fov: f32, aspect: f32, near: f32, far: f32, reverse_z: bool) -> Matrix4<f32> {
+    let (near, far) = match reverse_z {
+        true => (far, near),
+        false => (near, far),
+    };
+    let f = 1.0 / (0.5 * fov).tan();
+    let a = (far + near) / (near - far);
+    let b = (2.0 * far * near) / (near - far);
+    Matrix4::new(
+        f / aspect, 0.0,  0.0, 0.0,
+        0.0,        f,    0.0, 0.0,
+        0.0,        0.0,  a,   b,
+        0.0,        0.0, -1.0, 0.0,
+    )
+}
+
 pub fn look_at4(eye: &Vector3<f32>, target: &Vector3<f32>, up: &Vector3<f32>) -> Matrix4<f32> {
     nalgebra::Isometry3::look_at_rh(&Point3::from(*eye), &Point3::from(*target), up)
         .to_homogeneous()
@@ -138,27 +557,50 @@ pub fn look_at4(eye: &Vector3<f32>, target: &Vector3<f32>, up: &Vector3<f32>) ->
 
 impl Dimensions {
     pub fn new(window: &sdl::video::Window) -> Self {
-        let (draw_width, draw_height) = window.drawable_size();
         let (window_width, window_height) = window.size();
+        let (draw_width, draw_height) = window.drawable_size();
+        Self::from_sizes(
+            window_width,
+            window_height,
+            draw_width,
+            draw_height,
+            AspectMode::Stretch,
+        )
+    }
+
+    /// Computes the dimensions from the logical window size and the real (drawable) size, which
+    /// differ on highdpi displays. The view only covers the drawing area given by `mode`.
+    pub fn from_sizes(
+        window_width: u32,
+        window_height: u32,
+        draw_width: u32,
+        draw_height: u32,
+        mode: AspectMode,
+    ) -> Self {
         let (dwscale, dhscale) = (
             (window_width as f32) / (draw_width as f32),
             (window_height as f32) / (draw_height as f32),
         );
+        let viewport = mode.viewport(draw_width, draw_height);
+        let [vx, vy, draw_width, draw_height] = viewport;
+        let (draw_width, draw_height) = (draw_width as u32, draw_height as u32);
+        // Logical size of the drawing area
+        let (area_width, area_height) = (
+            ((draw_width as f32) * dwscale).round() as u32,
+            ((draw_height as f32) * dhscale).round() as u32,
+        );
         let scalefactor = unsafe { naevc::conf.scalefactor as f32 };
         let scale = f32::max(dwscale, dhscale) / scalefactor;
         let (view_width, view_height, view_scale) = {
-            let (vw, vh) = (
-                (window_width as f32) * scale,
-                (window_height as f32) * scale,
-            );
+            let (vw, vh) = ((area_width as f32) * scale, (area_height as f32) * scale);
             if vw < naevc::RESOLUTION_W_MIN as f32 || vh < naevc::RESOLUTION_H_MIN as f32 {
                 warn!("Screen size is too small, upscaling...");
                 let scalew = naevc::RESOLUTION_W_MIN as f32 / vw;
                 let scaleh = naevc::RESOLUTION_H_MIN as f32 / vh;
                 let scale = scale * f32::max(scalew, scaleh);
                 (
-                    (window_width as f32) * scale,
-                    (window_height as f32) * scale,
+                    (area_width as f32) * scale,
+                    (area_height as f32) * scale,
                     scale,
                 )
             } else {
@@ -171,6 +613,8 @@ impl Dimensions {
         unsafe {
             naevc::gl_screen.rw = draw_width as i32;
             naevc::gl_screen.rh = draw_height as i32;
+            naevc::gl_screen.vx = vx;
+            naevc::gl_screen.vy = vy;
             naevc::gl_screen.dwscale = dwscale as f64;
             naevc::gl_screen.dhscale = dhscale as f64;
             naevc::gl_screen.scale = view_scale as f64;
@@ -187,6 +631,7 @@ impl Dimensions {
         Dimensions {
             window_width,
             window_height,
+            viewport,
             view_width,
             view_height,
             view_scale,
@@ -195,28 +640,247 @@ impl Dimensions {
     }
 }
 
+/// Solid rectangles drawn together with a single instanced draw call. Each rectangle is a
+/// transform and colour in the per instance vertex buffer of [`Context::vao_rect_instanced`].
+///
+/// ```ignore
+/// let mut batch = ctx.rect_batch();
+/// batch.push(10.0, 10.0, 100.0, 20.0, colour);
+/// batch.flush(ctx)?;
+/// ```
+pub struct RectBatch {
+    projection: Matrix3<f32>,
+    instances: Vec<f32>,
+}
+impl RectBatch {
+    /// Floats per instance, the 3 columns of the transform and the colour.
+    const STRIDE: usize = 3 * 3 + 4;
+
+    /// Number of rectangles waiting to be drawn.
+    pub fn len(&self) -> usize {
+        self.instances.len() / Self::STRIDE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Queues a rectangle in screen coordinates with an sRGB colour.
+    pub fn push(&mut self, x: f32, y: f32, w: f32, h: f32, colour: Vector4<f32>) {
+        #[rustfmt::skip]
+        let transform: Matrix3<f32> = self.projection * Matrix3::new(
+             w,  0.0,  x,
+            0.0,  h,   y,
+            0.0, 0.0, 1.0,
+        );
+        self.push_ex(&transform, colour::srgb_to_linear(colour));
+    }
+
+    /// Queues a rectangle with a full transform to clip space and a colour in linear space.
+    pub fn push_ex(&mut self, transform: &Matrix3<f32>, colour: Vector4<f32>) {
+        self.instances.extend_from_slice(transform.as_slice());
+        self.instances.extend_from_slice(colour.as_slice());
+    }
+
+    /// Draws all the queued rectangles and empties the batch.
+    pub fn flush(&mut self, ctx: &Context) -> Result<()> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "RectBatch::flush called outside of the main thread"
+        );
+        if self.instances.is_empty() {
+            return Ok(());
+        }
+        let gl = &ctx.gl;
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                self.instances.as_ptr() as *const u8,
+                std::mem::size_of_val(self.instances.as_slice()),
+            )
+        };
+        ctx.program_solid_instanced.use_program(gl);
+        unsafe {
+            // Orphans the previous contents, so the driver doesn't have to wait on them
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(ctx.vbo_rect_instances.buffer));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, data, glow::STREAM_DRAW);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+        }
+        ctx.vao_rect_instanced.bind(ctx);
+        unsafe {
+            gl.draw_arrays_instanced(glow::TRIANGLE_STRIP, 0, 4, self.len() as i32);
+        }
+        VertexArray::unbind(ctx);
+        self.instances.clear();
+        ctx.check_errors();
+        Ok(())
+    }
+}
+
+/// Appearance of progress bars drawn with [`Context::draw_progress`]. Colours are in sRGB.
+#[derive(Clone, Copy, Debug)]
+pub struct ProgressStyle {
+    pub fg: Vector4<f32>,
+    pub bg: Vector4<f32>,
+    /// Whether the ends of the bar are rounded. The caps overlap the body of the bar, so this
+    /// is best used with opaque colours.
+    pub rounded: bool,
+}
+impl Default for ProgressStyle {
+    fn default() -> Self {
+        ProgressStyle {
+            fg: Vector4::new(0.9, 0.9, 0.9, 1.0),
+            bg: Vector4::new(0.1, 0.1, 0.1, 1.0),
+            rounded: true,
+        }
+    }
+}
+
+/// Time accumulated for [`TimeUniform`], kept in double precision so it doesn't drift.
+#[derive(Clone, Copy, Default)]
+struct FrameTime {
+    game: f64,
+    real: f64,
+}
+
+/// Offscreen target the world is rendered to when using a render scale below 1.
+struct ScaledTarget {
+    fb: Framebuffer,
+    /// Framebuffer that was bound before, which the world gets upscaled to.
+    prev_fbo: u32,
+}
+
+/// Snapshot of the GL state that both the C and Rust rendering code modify, so that Rust code can
+/// defensively wrap calls into C (or the other way around). Only uses cheap `glGet` queries.
+#[derive(Clone, Debug)]
+pub struct RenderState {
+    blend: bool,
+    blend_func: [u32; 4],     // src rgb, dst rgb, src alpha, dst alpha
+    blend_equation: [u32; 2], // rgb, alpha
+    depth_test: bool,
+    depth_func: u32,
+    depth_mask: bool,
+    scissor_test: bool,
+    scissor_box: [i32; 4],
+    program: Option<glow::Program>,
+    vertex_array: Option<glow::VertexArray>,
+    viewport: [i32; 4],
+}
+
+/// OpenGL driver strings, queried once at context creation.
+struct GlInfo {
+    vendor: String,
+    renderer: String,
+    version: String,
+    glsl_version: String,
+}
+impl GlInfo {
+    fn new(gl: &glow::Context) -> Self {
+        unsafe {
+            GlInfo {
+                vendor: gl.get_parameter_string(glow::VENDOR),
+                renderer: gl.get_parameter_string(glow::RENDERER),
+                version: gl.get_parameter_string(glow::VERSION),
+                glsl_version: gl.get_parameter_string(glow::SHADING_LANGUAGE_VERSION),
+            }
+        }
+    }
+}
+
+/// Implementation limits of the OpenGL context, queried once at context creation.
+#[derive(Clone, Copy, Debug)]
+pub struct GlLimits {
+    /// Largest width or height of a texture.
+    pub max_texture_size: u32,
+    /// Number of texture units that can be used from a fragment shader.
+    pub max_texture_image_units: u32,
+    /// Largest size of a uniform block, in bytes.
+    pub max_uniform_block_size: usize,
+    /// Largest number of samples of multisampled framebuffers.
+    pub max_samples: u32,
+    /// Number of vertex attributes that can be used by a vertex array.
+    pub max_vertex_attribs: u32,
+    /// Alignment of the offsets when binding ranges of uniform buffers, in bytes.
+    pub uniform_buffer_offset_alignment: usize,
+    /// Largest anisotropy of texture filtering, 1 if anisotropic filtering is not available.
+    pub max_anisotropy: f32,
+    /// Whether shader subroutines are available.
+    pub shader_subroutines: bool,
+}
+impl GlLimits {
+    fn new(gl: &glow::Context) -> Self {
+        let get = |param| unsafe { gl.get_parameter_i32(param).max(0) };
+        GlLimits {
+            max_texture_size: get(glow::MAX_TEXTURE_SIZE) as u32,
+            max_texture_image_units: get(glow::MAX_TEXTURE_IMAGE_UNITS) as u32,
+            max_uniform_block_size: get(glow::MAX_UNIFORM_BLOCK_SIZE) as usize,
+            max_samples: get(glow::MAX_SAMPLES).max(1) as u32,
+            max_vertex_attribs: get(glow::MAX_VERTEX_ATTRIBS) as u32,
+            uniform_buffer_offset_alignment: get(glow::UNIFORM_BUFFER_OFFSET_ALIGNMENT).max(1)
+                as usize,
+            max_anisotropy: texture::max_anisotropy(gl),
+            shader_subroutines: has_shader_subroutines(gl),
+        }
+    }
+}
+
+/// Guard of a debug group opened with [`Context::debug_scope`], closing it on drop.
+pub struct DebugScope<'a> {
+    ctx: &'a Context,
+}
+impl Drop for DebugScope<'_> {
+    fn drop(&mut self) {
+        self.ctx.pop_debug_group();
+    }
+}
+
 pub struct Context {
     pub sdlvid: sdl::VideoSubsystem,
     pub gl: glow::Context,
     pub window: sdl::video::Window,
     pub gl_context: sdl::video::GLContext,
     main_thread: ThreadId,
+    pub limits: GlLimits,
+    info: GlInfo,
+    feature_level: FeatureLevel,
+    /// Features that have already been warned about being unsupported, as a bitmask.
+    feature_warned: AtomicU32,
+    /// Whether `KHR_debug` debug groups are available.
+    debug_groups: bool,
+    reverse_z: AtomicBool,
+    check_errors: AtomicBool,
+    depth_prepass: AtomicBool,
+    aspect_mode: RwLock<AspectMode>,
+    render_scale: RwLock<f32>,
+    scaled: Mutex<Option<ScaledTarget>>,
     // We should be able to get rid of this mutex when fully moved to Rust
     pub dimensions: RwLock<Dimensions>,
+    camera: RwLock<Camera2D>,
 
     // Useful "globals"
     pub program_texture: Shader,
     pub buffer_texture: Buffer,
+    /// Overrides the sampling of the textures drawn with `program_texture` when set.
+    default_sampler: RwLock<Option<Sampler>>,
     pub program_texture_scale: Shader,
     pub buffer_texture_scale: Buffer,
     pub program_solid: Shader,
     pub buffer_solid: Buffer,
+    /// Solid shader taking the transform and colour per instance, used by [`RectBatch`].
+    pub program_solid_instanced: Shader,
+    pub vbo_rect_instances: Buffer,
+    pub vao_rect_instanced: VertexArray,
+    batch_solid: Mutex<UniformBatch>,
+    buffer_time: Buffer,
+    time: Mutex<FrameTime>,
     pub vbo_square: Buffer,
     pub vao_square: VertexArray,
     pub vbo_center: Buffer,
     pub vao_center: VertexArray,
     pub vbo_triangle: Buffer,
     pub vao_triangle: VertexArray,
+    /// Unit circle, see [`Context::circle_data`].
+    pub vbo_circle: Buffer,
+    pub vao_circle: VertexArray,
 
     // To be phased out when moved to rust
     pub vao_core: glow::VertexArray,
@@ -225,12 +889,55 @@ pub struct Context {
 unsafe impl Sync for Context {}
 unsafe impl Send for Context {}
 
+/// Held while a thread changes which OpenGL context is current, by both [`SafeContext`] and
+/// [`SecondaryContext`], so that one can't make a context current while another thread holds it.
+static MAKE_CURRENT: Mutex<()> = Mutex::new(());
+
+fn lock_make_current() -> MutexGuard<'static, ()> {
+    MAKE_CURRENT.lock().unwrap_or_else(|poisoned| {
+        MAKE_CURRENT.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Context that was current on this thread, restored when dropped. SDL already tracks the current
+/// context per thread, so this only remembers it.
+struct PreviousContext {
+    window: *mut sdl::sys::SDL_Window,
+    context: sdl::sys::SDL_GLContext,
+    _lock: MutexGuard<'static, ()>,
+}
+impl PreviousContext {
+    fn save() -> Self {
+        let lock = lock_make_current();
+        unsafe {
+            PreviousContext {
+                window: sdl::sys::SDL_GL_GetCurrentWindow(),
+                context: sdl::sys::SDL_GL_GetCurrentContext(),
+                _lock: lock,
+            }
+        }
+    }
+}
+impl Drop for PreviousContext {
+    fn drop(&mut self) {
+        // Null makes no context current, which is what was there before
+        if unsafe { sdl::sys::SDL_GL_MakeCurrent(self.window, self.context) } != 0 {
+            warn!(
+                "unable to restore the previous OpenGL context: {}",
+                sdl::get_error()
+            );
+        }
+    }
+}
+
 /// Wrapper for a Context MutexGuard
-pub struct ContextGuard<'sc, 'ctx>(MutexGuard<'sc, &'ctx Context>);
+pub struct ContextGuard<'sc, 'ctx>(MutexGuard<'sc, &'ctx Context>, MutexGuard<'static, ()>);
 impl<'sc, 'ctx> ContextGuard<'sc, 'ctx> {
     fn new(guard: MutexGuard<'sc, &'ctx Context>) -> Self {
+        let lock = lock_make_current();
         guard.window.gl_make_current(&guard.gl_context).unwrap();
-        ContextGuard(guard)
+        ContextGuard(guard, lock)
     }
 }
 impl<'ctx> Deref for ContextGuard<'_, 'ctx> {
@@ -245,6 +952,20 @@ impl Drop for ContextGuard<'_, '_> {
     }
 }
 
+/// Locks the context mutex, recovering it if a thread panicked while holding it. The context
+/// itself is not left in an inconsistent state by a panic elsewhere, so it is better to keep
+/// going than to take down the whole game on every subsequent lock.
+fn lock_context<'sc, 'ctx>(mutex: &'sc Mutex<&'ctx Context>) -> MutexGuard<'sc, &'ctx Context> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            warn!("context mutex was poisoned by a panic, recovering");
+            mutex.clear_poison();
+            poisoned.into_inner()
+        }
+    }
+}
+
 /// Wrapper for thread safe OpenGL context
 #[derive(Clone)]
 pub struct SafeContext<'ctx> {
@@ -252,13 +973,14 @@ pub struct SafeContext<'ctx> {
 }
 impl<'ctx> SafeContext<'ctx> {
     pub fn new(ctx: &'ctx Context) -> Self {
+        let _lock = lock_make_current();
         ctx.sdlvid.gl_release_current_context().unwrap();
         SafeContext {
             ctx: Arc::new(Mutex::new(ctx)),
         }
     }
     pub fn lock(&self) -> ContextGuard<'_, 'ctx> {
-        let guard = self.ctx.lock().unwrap();
+        let guard = lock_context(&self.ctx);
         ContextGuard::new(guard)
     }
     pub fn into_wrap(self) -> ContextWrapper<'ctx> {
@@ -267,7 +989,8 @@ impl<'ctx> SafeContext<'ctx> {
 }
 impl Drop for SafeContext<'_> {
     fn drop(&mut self) {
-        let guard = self.ctx.lock().unwrap();
+        let guard = lock_context(&self.ctx);
+        let _lock = lock_make_current();
         guard.window.gl_make_current(&guard.gl_context).unwrap();
     }
 }
@@ -309,6 +1032,61 @@ impl<'ctx> From<SafeContext<'ctx>> for ContextWrapper<'ctx> {
     }
 }
 
+/// A secondary window with its own OpenGL context that shares objects with the main context.
+/// Note that container objects such as vertex arrays and framebuffers are not shared between
+/// contexts, so anything using them has to create its own.
+pub struct SecondaryContext<'ctx> {
+    main: &'ctx Context,
+    pub window: sdl::video::Window,
+    pub gl_context: sdl::video::GLContext,
+    pub vao_core: glow::VertexArray,
+}
+impl<'ctx> SecondaryContext<'ctx> {
+    /// Makes the secondary context current on this thread until the guard is dropped, when the
+    /// context that was current before is restored. This waits for any thread holding a
+    /// [`SafeContext`] lock, and must not be called while this thread holds one.
+    pub fn lock(&self) -> Result<SecondaryContextGuard<'_, 'ctx>> {
+        let previous = PreviousContext::save();
+        self.window
+            .gl_make_current(&self.gl_context)
+            .map_err(anyhow::Error::msg)?;
+        unsafe {
+            self.main.gl.bind_vertex_array(Some(self.vao_core));
+        }
+        Ok(SecondaryContextGuard {
+            secondary: self,
+            _previous: previous,
+        })
+    }
+
+    pub fn swap_window(&self) {
+        self.window.gl_swap_window();
+    }
+}
+impl Drop for SecondaryContext<'_> {
+    fn drop(&mut self) {
+        // The vertex array belongs to this context, so it has to be current to delete it
+        match self.lock() {
+            Ok(_guard) => unsafe {
+                self.main.gl.delete_vertex_array(self.vao_core);
+            },
+            Err(e) => warn_err(e.context("unable to delete secondary OpenGL context objects")),
+        }
+    }
+}
+
+/// Guard that keeps a SecondaryContext current while alive.
+pub struct SecondaryContextGuard<'sc, 'ctx> {
+    secondary: &'sc SecondaryContext<'ctx>,
+    _previous: PreviousContext,
+}
+impl<'ctx> Deref for SecondaryContextGuard<'_, 'ctx> {
+    type Target = SecondaryContext<'ctx>;
+    fn deref(&self) -> &Self::Target {
+        self.secondary
+    }
+}
+
 impl Context {
     #[rustfmt::skip]
     const DATA_SQUARE: [f32;8] = [ 0., 0.,
@@ -337,11 +1115,36 @@ impl Context {
         -0.25,  0.433_012_7,
         -0.25, -0.433_012_7];
 
+    /// Number of segments of the unit circle.
+    const CIRCLE_SEGMENTS: usize = 64;
+
+    /// Unit circle as its center followed by the points on the circumference, with the first
+    /// repeated at the end. The whole data is a triangle fan, while the points from 1 to
+    /// `CIRCLE_SEGMENTS` are a line loop.
+    fn circle_data() -> Vec<f32> {
+        let mut data = vec![0.0, 0.0];
+        for i in 0..=Self::CIRCLE_SEGMENTS {
+            let a = std::f32::consts::TAU * (i % Self::CIRCLE_SEGMENTS) as f32
+                / Self::CIRCLE_SEGMENTS as f32;
+            data.push(a.cos());
+            data.push(a.sin());
+        }
+        data
+    }
+
+    /// Window icon size to aim for at a scale of 1.
+    const ICON_SIZE: u32 = 64;
+    /// Available window icon resolutions.
+    const ICON_SIZES: [u32; 3] = [32, 64, 128];
+
+    /// Gets the global context, with an error explaining what went wrong if it is not available.
+    /// Code called from C should warn and bail out on the error instead of unwrapping it.
     pub fn get() -> Result<&'static Self> {
-        //CONTEXT.get()?.lock()
         match CONTEXT.get() {
             Some(ctx) => Ok(ctx),
-            None => anyhow::bail!("No context"),
+            None => anyhow::bail!(
+                "OpenGL context is not available, either it failed to initialize or it is being used before initialization"
+            ),
         }
     }
 
@@ -404,25 +1207,66 @@ impl Context {
             Err(e) => anyhow::bail!("Unable to create OpenGL context: {}", e),
         };
 
-        // Try to load the icon.
-        let filename = format!("{}{}", ndata::GFX_PATH, "icon.webp");
-        match ndata::rwops(filename.as_str()) {
-            Ok(rw) => match rw.load() {
-                Ok(icon) => window.set_icon(icon),
-                Err(e) => anyhow::bail!(e),
-            },
-            Err(e) => anyhow::bail!(e),
+        // Try to load the icon, not having one is not fatal
+        if let Some(filename) = Self::find_icon(&window) {
+            match ndata::rwops(filename.as_str()) {
+                Ok(rw) => match rw.load() {
+                    Ok(icon) => window.set_icon(icon),
+                    Err(e) => warn_err(
+                        anyhow::Error::msg(e)
+                            .context(format!("unable to load window icon '{filename}'")),
+                    ),
+                },
+                Err(e) => warn_err(
+                    anyhow::Error::new(e)
+                        .context(format!("unable to open window icon '{filename}'")),
+                ),
+            }
         }
 
         Ok((window, gl_context))
     }
 
+    /// Finds the window icon with the resolution closest to what the window wants, taking into
+    /// account the high DPI scaling. Falls back to the generic icon if no sized ones exist.
+    fn find_icon(window: &sdl::video::Window) -> Option<String> {
+        let (ww, _) = window.size();
+        let (dw, _) = window.drawable_size();
+        let desired = (Self::ICON_SIZE as f32) * (dw as f32) / (ww.max(1) as f32);
+        let mut sizes = Self::ICON_SIZES;
+        sizes.sort_by(|a, b| {
+            let da = (*a as f32 - desired).abs();
+            let db = (*b as f32 - desired).abs();
+            da.total_cmp(&db)
+        });
+        let icon = sizes
+            .iter()
+            .map(|size| format!("{}icon_{}.webp", ndata::GFX_PATH, size))
+            .chain(std::iter::once(format!("{}icon.webp", ndata::GFX_PATH)))
+            .find(|path| ndata::exists(path));
+        if icon.is_none() {
+            warn!("no window icon found, continuing without one");
+        }
+        icon
+    }
+
+    /// Changes the window icon at runtime.
+    pub fn set_icon_from(&self, path: &str) -> Result<()> {
+        let rw = ndata::rwops(path)?;
+        let icon = rw.load().map_err(|e| anyhow::anyhow!(e))?;
+        // Window::set_icon needs a mutable reference, which we can't get from the global context
+        unsafe {
+            sdl::sys::SDL_SetWindowIcon(self.window.raw(), icon.raw());
+        }
+        Ok(())
+    }
+
     pub fn new(sdlvid: sdl::VideoSubsystem) -> Result<&'static Self> {
         let (minimize, fsaa, vsync) = unsafe {
             (
                 naevc::conf.minimize != 0,
                 naevc::conf.fsaa,
-                naevc::conf.vsync != 0,
+                VsyncMode::from_conf(naevc::conf.vsync),
             )
         };
 
@@ -433,38 +1277,82 @@ impl Context {
         let gl_attr = sdlvid.gl_attr();
         gl_attr.set_context_profile(sdl::video::GLProfile::Core);
         gl_attr.set_double_buffer(true);
-        if fsaa > 1 {
-            gl_attr.set_multisample_buffers(1);
-            gl_attr.set_multisample_samples(fsaa);
-        }
         gl_attr.set_framebuffer_srgb_compatible(true);
         gl_attr.set_context_flags().forward_compatible().set();
         #[cfg(debug_assertions)]
         gl_attr.set_context_flags().debug().set();
 
-        let (window, gl_context) = match Self::create_context(&sdlvid, &gl_attr, 4, 6) {
-            Ok(v) => v,
-            _ => match Self::create_context(&sdlvid, &gl_attr, 3, 3) {
-                Ok(v) => {
-                    warn!("Falling back to OpenGL 3.3 context!");
-                    v
+        // Request every feature level from the best down, as asking for a version the driver
+        // doesn't support fails instead of giving the closest one. Unsupported sample counts can
+        // also make context creation fail, so halve them until it works, ending without MSAA.
+        // OpenGL 3.2 is a last resort for old drivers, the shaders are then built as GLSL 1.50.
+        const VERSIONS: [(u8, u8); 5] = [(4, 6), (4, 4), (4, 3), (3, 3), (3, 2)];
+        let mut samples = fsaa;
+        let (window, gl_context) = 'create: loop {
+            match samples > 1 {
+                true => {
+                    gl_attr.set_multisample_buffers(1);
+                    gl_attr.set_multisample_samples(samples);
                 }
-                _ => anyhow::bail!("Failed to create OpenGL context!"),
-            },
+                false => {
+                    gl_attr.set_multisample_buffers(0);
+                    gl_attr.set_multisample_samples(0);
+                }
+            }
+            let mut error = None;
+            for (i, (major, minor)) in VERSIONS.into_iter().enumerate() {
+                match Self::create_context(&sdlvid, &gl_attr, major, minor) {
+                    Ok(v) => {
+                        if i > 0 {
+                            warn!("Falling back to OpenGL {}.{} context!", major, minor);
+                        }
+                        break 'create v;
+                    }
+                    Err(e) => {
+                        debug!("unable to create OpenGL {}.{} context: {}", major, minor, e);
+                        error = Some(e);
+                    }
+                }
+            }
+            if samples > 1 {
+                warn!(
+                    "Unable to create OpenGL context with {}x MSAA, trying {}",
+                    samples,
+                    match samples / 2 > 1 {
+                        true => format!("{}x", samples / 2),
+                        false => String::from("without it"),
+                    }
+                );
+                samples /= 2;
+                continue;
+            }
+            let error = match error {
+                Some(e) => format!("{e}"),
+                None => String::from("unknown error"),
+            };
+            anyhow::bail!(
+                "Failed to create OpenGL context!\n\
+                 Tried an OpenGL core profile of version {}, {}.\n\
+                 Last error: {}\n\
+                 Naev needs at least OpenGL 3.2, make sure your graphics drivers are up to date.",
+                VERSIONS
+                    .iter()
+                    .map(|(major, minor)| format!("{major}.{minor}"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                match fsaa > 1 {
+                    true => format!("with {fsaa}x MSAA and without multisampling"),
+                    false => String::from("without multisampling"),
+                },
+                error
+            );
         };
         let mut gl = unsafe {
             glow::Context::from_loader_function(|s| sdlvid.gl_get_proc_address(s) as *const _)
         };
 
         // Final touches
-        sdlvid
-            .gl_set_swap_interval(match vsync {
-                true => 1,
-                false => 0,
-            })
-            .unwrap_or_else(|err| {
-                warn_err(anyhow::Error::msg(err).context("unable to set OpenGL swap interval"))
-            });
+        Self::apply_vsync(&sdlvid, vsync);
 
         match gl_attr.framebuffer_srgb_compatible() {
             true => (),
@@ -510,6 +1398,15 @@ impl Context {
             false => log::warn("unable to set OpenGL debug mode!"),
         };
 
+        let limits = GlLimits::new(&gl);
+        debug!("OpenGL limits: {:?}", limits);
+        let info = GlInfo::new(&gl);
+        let feature_level = {
+            let (major, minor) = gl_attr.context_version();
+            FeatureLevel::from_version(major, minor)
+        };
+        debug!("OpenGL feature level: {:?}", feature_level);
+        let debug_groups = has_khr_debug(&gl);
         unsafe {
             naevc::gl_screen.window = window.raw() as *mut naevc::SDL_Window;
             naevc::gl_screen.context = gl_context.raw();
@@ -529,15 +1426,24 @@ impl Context {
             naevc::gl_screen.depth =
                 naevc::gl_screen.r + naevc::gl_screen.g + naevc::gl_screen.b + naevc::gl_screen.a;
             naevc::gl_screen.fsaa = gl_attr.multisample_samples();
-            naevc::gl_screen.tex_max = gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE);
-            naevc::gl_screen.multitex_max = gl.get_parameter_i32(glow::MAX_TEXTURE_IMAGE_UNITS);
+            if naevc::conf.fsaa as u32 > limits.max_samples {
+                warn!(
+                    "Requested {}x MSAA, but the hardware supports at most {}x, clamping",
+                    naevc::conf.fsaa,
+                    limits.max_samples
+                );
+                naevc::conf.fsaa = limits.max_samples.min(u8::MAX as u32) as u8;
+            }
+            naevc::gl_screen.tex_max = limits.max_texture_size as i32;
+            naevc::gl_screen.multitex_max = limits.max_texture_image_units as i32;
         }
 
         // Modern OpenGL requires at least one VAO and the C code uses the same one
         let vao_core = unsafe {
             let vao = gl.create_vertex_array().map_err(|e| anyhow::anyhow!(e))?;
             gl.bind_vertex_array(Some(vao));
-            gl.object_label(
+            set_label(
+                &gl,
                 glow::VERTEX_ARRAY,
                 vao.0.into(),
                 Some("C Core Vertex Array"),
@@ -581,6 +1487,24 @@ impl Context {
             .usage(BufferUsage::Dynamic)
             .data(&SolidUniform::default().buffer()?)
             .build(&gl)?;
+        let buffer_time = BufferBuilder::new(Some("Time Buffer"))
+            .target(BufferTarget::Uniform)
+            .usage(BufferUsage::Dynamic)
+            .data(&TimeUniform::default().buffer()?)
+            .build(&gl)?;
+        // Catch the Rust structures and GLSL blocks drifting apart early
+        program_texture.validate_block("TextureData", TextureUniform::default().buffer()?.len())?;
+        program_texture_scale.validate_block(
+            "TextureData",
+            TextureScaleUniform::default().buffer()?.len(),
+        )?;
+        program_solid.validate_block("SolidData", SolidUniform::default().buffer()?.len())?;
+        buffer_time.bind_base_gl(&gl, render::TIME_BINDING);
+        let batch_solid = UniformBatch::new_gl(
+            &gl,
+            Some("Solid Batch Buffer"),
+            SolidUniform::default().buffer()?.len(),
+        )?;
 
         // Square VBO
         let vbo_square = BufferBuilder::new(Some("Square VBO"))
@@ -594,9 +1518,44 @@ impl Context {
                 stride: 0, // tightly packed
                 offset: 0,
                 divisor: 0,
+                format: None,
             }])
             .build_gl(&gl)?;
 
+        // Instanced rectangles, sharing the square VBO
+        let program_solid_instanced = ShaderBuilder::new(Some("Instanced Solid Shader"))
+            .vert_file("rust_solid_instanced.vert")
+            .frag_file("rust_solid_instanced.frag")
+            .build(&gl)?;
+        let vbo_rect_instances = BufferBuilder::new(Some("Rect Instance VBO"))
+            .usage(BufferUsage::Stream)
+            .data(&[0; RectBatch::STRIDE * 4])
+            .build(&gl)?;
+        let instance = |size, offset| VertexArrayBuffer {
+            buffer: &vbo_rect_instances,
+            size,
+            stride: (RectBatch::STRIDE * 4) as i32,
+            offset: offset * 4,
+            divisor: 1,
+            format: None,
+        };
+        let vao_rect_instanced = VertexArrayBuilder::new(Some("Instanced Rect Vertex Array"))
+            .buffers(&[
+                VertexArrayBuffer {
+                    buffer: &vbo_square,
+                    size: 2,
+                    stride: 0, // tightly packed
+                    offset: 0,
+                    divisor: 0,
+                    format: None,
+                },
+                instance(3, 0),
+                instance(3, 3),
+                instance(3, 6),
+                instance(4, 9),
+            ])
+            .build_gl(&gl)?;
+
         // Center VBO
         let vbo_center = BufferBuilder::new(Some("Center VBO"))
             .usage(BufferUsage::Static)
@@ -609,6 +1568,7 @@ impl Context {
                 stride: 0, // tightly packed
                 offset: 0,
                 divisor: 0,
+                format: None,
             }])
             .build_gl(&gl)?;
 
@@ -624,6 +1584,23 @@ impl Context {
                 stride: 0, // tightly packed
                 offset: 0,
                 divisor: 0,
+                format: None,
+            }])
+            .build_gl(&gl)?;
+
+        // Circle VBO
+        let vbo_circle = BufferBuilder::new(Some("Circle VBO"))
+            .usage(BufferUsage::Static)
+            .data_f32(&Self::circle_data())
+            .build(&gl)?;
+        let vao_circle = VertexArrayBuilder::new(Some("Circle Vertex Array"))
+            .buffers(&[VertexArrayBuffer {
+                buffer: &vbo_circle,
+                size: 2,
+                stride: 0, // tightly packed
+                offset: 0,
+                divisor: 0,
+                format: None,
             }])
             .build_gl(&gl)?;
 
@@ -639,42 +1616,714 @@ impl Context {
             gl_context,
             gl,
             main_thread: std::thread::current().id(),
+            limits,
+            info,
+            feature_level,
+            feature_warned: AtomicU32::new(0),
+            debug_groups,
+            reverse_z: AtomicBool::new(false),
+            check_errors: AtomicBool::new(cfg!(debug_assertions)),
+            depth_prepass: AtomicBool::new(false),
+            aspect_mode: RwLock::new(AspectMode::default()),
+            render_scale: RwLock::new(1.0),
+            scaled: Mutex::new(None),
             dimensions,
+            camera: RwLock::new(Camera2D::default()),
             program_texture,
             buffer_texture,
+            default_sampler: RwLock::new(None),
             program_texture_scale,
             buffer_texture_scale,
             program_solid,
             buffer_solid,
+            batch_solid: Mutex::new(batch_solid),
+            buffer_time,
+            time: Mutex::new(FrameTime::default()),
+            program_solid_instanced,
+            vbo_rect_instances,
+            vao_rect_instanced,
             vbo_square,
             vao_square,
             vbo_center,
             vao_center,
             vbo_triangle,
             vao_triangle,
+            vbo_circle,
+            vao_circle,
             vao_core,
         };
+        let render_scale = unsafe { naevc::conf.render_scale } as f32;
+        if let Err(e) = ctx.set_render_scale(render_scale) {
+            warn_err(e);
+        }
         let _ = CONTEXT.set(ctx);
         Ok(CONTEXT.get().unwrap())
     }
 
-    pub fn resize(&self) -> Result<()> {
-        *self.dimensions.write().unwrap() = Dimensions::new(&self.window);
-        Ok(())
+    /// Changes the vertical synchronization of the main window, returning the mode that could
+    /// actually be set. Adaptive synchronization falls back to normal synchronization when it
+    /// isn't supported.
+    pub fn set_vsync(&self, mode: VsyncMode) -> VsyncMode {
+        debug_assert!(
+            self.is_main_thread(),
+            "Context::set_vsync called outside of the main thread"
+        );
+        Self::apply_vsync(&self.sdlvid, mode)
     }
 
-    fn is_main_thread(&self) -> bool {
-        self.main_thread == std::thread::current().id()
+    fn apply_vsync(sdlvid: &sdl::VideoSubsystem, mode: VsyncMode) -> VsyncMode {
+        let achieved = match sdlvid.gl_set_swap_interval(mode.to_sdl()) {
+            Ok(()) => mode,
+            Err(err) if mode == VsyncMode::Adaptive => {
+                debug!("adaptive vsync not supported: {}", err);
+                match sdlvid.gl_set_swap_interval(VsyncMode::On.to_sdl()) {
+                    Ok(()) => VsyncMode::On,
+                    Err(err) => {
+                        warn_err(
+                            anyhow::Error::msg(err).context("unable to set OpenGL swap interval"),
+                        );
+                        VsyncMode::from_swap_interval(sdlvid.gl_get_swap_interval())
+                    }
+                }
+            }
+            Err(err) => {
+                warn_err(anyhow::Error::msg(err).context("unable to set OpenGL swap interval"));
+                VsyncMode::from_swap_interval(sdlvid.gl_get_swap_interval())
+            }
+        };
+        info!("vsync set to {:?}", achieved);
+        achieved
     }
 
-    pub fn execute_messages(&self) {
-        let mut queue = MESSAGE_QUEUE.lock().unwrap();
+    /// Creates a new window with an OpenGL context sharing objects with the main context. The
+    /// context that was current on this thread before is current again afterwards.
+    pub fn create_shared(&self, title: &str, w: u32, h: u32) -> Result<SecondaryContext> {
+        let previous = PreviousContext::save();
+        let gl_attr = self.sdlvid.gl_attr();
+        self.window
+            .gl_make_current(&self.gl_context)
+            .map_err(anyhow::Error::msg)?;
+        gl_attr.set_share_with_current_context(true);
+        let res = self
+            .sdlvid
+            .window(title, w, h)
+            .opengl()
+            .resizable()
+            .allow_highdpi()
+            .build()
+            .map_err(anyhow::Error::new)
+            .and_then(|window| {
+                let gl_context = window.gl_create_context().map_err(anyhow::Error::msg)?;
+                Ok((window, gl_context))
+            });
+        gl_attr.set_share_with_current_context(false);
+        let (window, gl_context) = res?;
+
+        // The new context is current now, so it needs its own vertex array
+        let vao_core = unsafe {
+            let vao = self
+                .gl
+                .create_vertex_array()
+                .map_err(|e| anyhow::anyhow!(e))?;
+            self.gl.bind_vertex_array(Some(vao));
+            vao
+        };
+        drop(previous);
+        Ok(SecondaryContext {
+            main: self,
+            window,
+            gl_context,
+            vao_core,
+        })
+    }
+
+    /// Updates the dimensions after the window has been resized, given the logical window size
+    /// and the real (drawable) size in pixels. This recomputes the view size and projection,
+    /// mirrors them into `gl_screen` and sets the viewport to the new drawing area, so that
+    /// rendering is correct right away. Called through [`naev_resize_rust`] when the window
+    /// changes size.
+    pub fn resize(
+        &self,
+        window_width: u32,
+        window_height: u32,
+        draw_width: u32,
+        draw_height: u32,
+    ) -> Result<()> {
+        if window_width == 0 || window_height == 0 || draw_width == 0 || draw_height == 0 {
+            anyhow::bail!(
+                "invalid window size {window_width}x{window_height} ({draw_width}x{draw_height} real)"
+            );
+        }
+        debug_assert!(
+            self.is_main_thread(),
+            "Context::resize called outside of the main thread"
+        );
+        let mode = *self.aspect_mode.read().unwrap();
+        let dims =
+            Dimensions::from_sizes(window_width, window_height, draw_width, draw_height, mode);
+        let [x, y, w, h] = dims.viewport;
+        unsafe {
+            self.gl.viewport(x, y, w, h);
+        }
+        debug!(
+            "resized to {}x{} ({}x{} real), view is {}x{} at scale {}",
+            window_width,
+            window_height,
+            draw_width,
+            draw_height,
+            dims.view_width,
+            dims.view_height,
+            dims.view_scale
+        );
+        *self.dimensions.write().unwrap() = dims;
+        Ok(())
+    }
+
+    /// Sets how the game is fitted into the window when the aspect ratios don't match, and
+    /// resizes everything accordingly.
+    pub fn set_aspect_mode(&self, mode: AspectMode) {
+        *self.aspect_mode.write().unwrap() = mode;
+        unsafe {
+            naevc::naev_resize();
+        }
+    }
+
+    pub fn aspect_mode(&self) -> AspectMode {
+        *self.aspect_mode.read().unwrap()
+    }
+
+    /// Sets the colour of the bars around the drawing area with [`AspectMode::Letterbox`], in
+    /// linear space.
+    pub fn set_letterbox_colour(&self, colour: Vector4<f32>) {
+        unsafe {
+            naevc::gl_screen.letterbox = naevc::glColour {
+                r: colour.x,
+                g: colour.y,
+                b: colour.z,
+                a: colour.w,
+            };
+        }
+    }
+
+    /// Advances the time shared with shaders through the "time.glsl" include. Should be called
+    /// once per frame, with `game_dt` already scaled and zero when paused.
+    pub fn update_time(&self, game_dt: f64, real_dt: f64) -> Result<()> {
+        let mut time = self.time.lock().unwrap();
+        time.game += game_dt;
+        time.real += real_dt;
+        let uniform = TimeUniform {
+            time: time.game as f32,
+            time_real: time.real as f32,
+            dt: game_dt as f32,
+            dt_real: real_dt as f32,
+        };
+        self.buffer_time.write(self, &uniform.buffer()?)?;
+        // Make sure nothing else took over the binding
+        self.buffer_time.bind_base(self, render::TIME_BINDING);
+        Ok(())
+    }
+
+    /// Gets the game and real time in seconds as seen by the shaders.
+    pub fn time(&self) -> (f64, f64) {
+        let time = self.time.lock().unwrap();
+        (time.game, time.real)
+    }
+
+    /// Makes all the textures drawn with the default texture shader use a copy of `sampler`
+    /// instead of their own sampling parameters, unless a sampler is given for the draw. This
+    /// allows, e.g., switching everything to nearest filtering for pixel art.
+    pub fn set_default_sampler(&self, sampler: &Sampler) -> Result<()> {
+        let sampler = sampler.try_clone(self)?;
+        *self.default_sampler.write().unwrap() = Some(sampler);
+        Ok(())
+    }
+
+    /// Goes back to using the sampling parameters of each texture.
+    pub fn clear_default_sampler(&self) {
+        *self.default_sampler.write().unwrap() = None;
+    }
+
+    pub fn default_sampler(&self) -> RwLockReadGuard<'_, Option<Sampler>> {
+        self.default_sampler.read().unwrap()
+    }
+
+    /// Sets the resolution the world is rendered at relative to the window, e.g., 0.5 for half
+    /// the resolution, which is then upscaled. The interface is always rendered at full
+    /// resolution. Scales are clamped to [0.25, 1].
+    pub fn set_render_scale(&self, scale: f32) -> Result<()> {
+        if !scale.is_finite() || scale <= 0.0 {
+            anyhow::bail!("invalid render scale '{scale}'");
+        }
+        *self.render_scale.write().unwrap() = scale.clamp(0.25, 1.0);
+        Ok(())
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        *self.render_scale.read().unwrap()
+    }
+
+    /// Redirects rendering to an offscreen framebuffer at the render scale. Returns whether it
+    /// was redirected, in which case [`Context::end_scaled`] has to be called to upscale it.
+    pub fn begin_scaled(&self) -> Result<bool> {
+        let scale = self.render_scale();
+        let mut scaled = self.scaled.lock().unwrap();
+        if scale >= 1.0 {
+            // Free the framebuffer if it is no longer used
+            *scaled = None;
+            return Ok(false);
+        }
+        let [_, _, vw, vh] = self.dimensions.read().unwrap().viewport;
+        let w = ((vw as f32 * scale).round() as usize).max(1);
+        let h = ((vh as f32 * scale).round() as usize).max(1);
+        let fb = FramebufferBuilder::new(Some("Scaled World"))
+            .width(w)
+            .height(h)
+            .depth(true)
+            .filter(FilterMode::Linear)
+            .build_reusing(self, scaled.take().map(|s| s.fb))?;
+
+        let gl = &self.gl;
+        let prev_fbo = unsafe { naevc::gl_screen.current_fbo };
+        fb.bind(self);
+        unsafe {
+            gl.viewport(0, 0, w as i32, h as i32);
+            gl.clear_color(0.0, 0.0, 0.0, 1.0);
+            gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+            gl.clear_color(0.0, 0.0, 0.0, 0.0);
+            // So that C code rebinding the current framebuffer stays in the scaled one
+            naevc::gl_screen.current_fbo = fb.framebuffer.0.get();
+            naevc::gl_screen.scaled_fbo = fb.framebuffer.0.get();
+            naevc::gl_screen.scaled_w = w as i32;
+            naevc::gl_screen.scaled_h = h as i32;
+        }
+        *scaled = Some(ScaledTarget { fb, prev_fbo });
+        Ok(true)
+    }
+
+    /// Upscales what was rendered since [`Context::begin_scaled`] to the previous framebuffer,
+    /// which is bound again.
+    pub fn end_scaled(&self) -> Result<()> {
+        let scaled = self.scaled.lock().unwrap();
+        let s = match &*scaled {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        unsafe {
+            naevc::gl_screen.current_fbo = s.prev_fbo;
+            naevc::gl_screen.scaled_fbo = 0;
+        }
+        Framebuffer::unbind(self);
+        unsafe {
+            naevc::gl_screenViewport();
+        }
+
+        let tex = match &s.fb.texture {
+            Some(tex) => tex,
+            None => anyhow::bail!("scaled framebuffer has no texture"),
+        };
+        let (vw, vh) = {
+            let dims = self.dimensions.read().unwrap();
+            (dims.view_width, dims.view_height)
+        };
+        // The world is opaque, so just replace what is there
+        let gl = &self.gl;
+        unsafe {
+            gl.disable(glow::BLEND);
+        }
+        let res = tex.draw(self, 0.0, 0.0, vw, vh);
+        unsafe {
+            gl.enable(glow::BLEND);
+        }
+        res
+    }
+
+    /// Saves what has been drawn to the screen so far to `path` in the write directory. The image
+    /// format is taken from the extension, defaulting to PNG.
+    ///
+    /// The framebuffer already holds sRGB encoded colours, and reading it back doesn't convert
+    /// them, so the pixels are stored as is.
+    pub fn screenshot(&self, path: &str) -> Result<()> {
+        assert!(
+            self.is_main_thread(),
+            "Context::screenshot called outside of the main thread"
+        );
+        let gl = &self.gl;
+        let [x, y, w, h] = self.dimensions.read().unwrap().viewport;
+        let (w, h) = (w.max(0) as usize, h.max(0) as usize);
+        let stride = 3 * w;
+        let mut data = vec![0u8; stride * h];
+        unsafe {
+            gl.bind_framebuffer(
+                glow::READ_FRAMEBUFFER,
+                std::num::NonZero::new(naevc::gl_screen.current_fbo).map(NativeFramebuffer),
+            );
+            gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+            gl.read_pixels(
+                x,
+                y,
+                w as i32,
+                h as i32,
+                glow::RGB,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(Some(&mut data)),
+            );
+            gl.pixel_store_i32(glow::PACK_ALIGNMENT, 4);
+        }
+        self.check_errors();
+
+        // OpenGL has the origin at the bottom left
+        let flipped: Vec<u8> = data.chunks_exact(stride).rev().flatten().copied().collect();
+        let img = match image::RgbImage::from_raw(w as u32, h as u32, flipped) {
+            Some(img) => img,
+            None => anyhow::bail!("invalid screenshot dimensions {w}x{h}"),
+        };
+        let format = image::ImageFormat::from_path(path).unwrap_or(image::ImageFormat::Png);
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut encoded, format)?;
+
+        let mut file = physfs::File::open(path, physfs::Mode::Write)?;
+        file.write_all(encoded.get_ref())?;
+        Ok(())
+    }
+
+    pub fn is_main_thread(&self) -> bool {
+        self.main_thread == std::thread::current().id()
+    }
+
+    pub fn execute_messages(&self) {
+        debug_assert!(
+            self.is_main_thread(),
+            "Context::execute_messages called outside of the main thread"
+        );
+        let mut queue = MESSAGE_QUEUE.lock().unwrap();
         for msg in queue.drain(..) {
             msg.execute(self);
         }
     }
 
+    /// Sets whether [`Context::check_errors`] queries `glGetError`. Only has an effect in debug
+    /// builds, where it is on by default, as it forces the driver to synchronize.
+    pub fn set_error_checking(&self, enable: bool) {
+        self.check_errors.store(enable, Ordering::Relaxed);
+    }
+
+    pub fn error_checking(&self) -> bool {
+        self.check_errors.load(Ordering::Relaxed)
+    }
+
+    /// Logs the first pending OpenGL error along with the caller location, and clears the rest.
+    /// Complements the debug callback on drivers with poor `KHR_debug` support. Does nothing
+    /// in release builds, or when disabled with [`Context::set_error_checking`].
+    #[track_caller]
+    pub fn check_errors(&self) {
+        #[cfg(debug_assertions)]
+        if self.error_checking() {
+            let gl = &self.gl;
+            let err = unsafe { gl.get_error() };
+            if err == glow::NO_ERROR {
+                return;
+            }
+            let location = std::panic::Location::caller();
+            warn!("OpenGL error {} at {}", gl_error_name(err), location);
+            // Errors are queued per flag, so make sure they don't get attributed to later calls
+            for _ in 0..8 {
+                if unsafe { gl.get_error() } == glow::NO_ERROR {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Opens a named debug group, which tools like RenderDoc use to group the calls until the
+    /// matching [`Context::pop_debug_group`]. Does nothing without `KHR_debug`.
+    pub fn push_debug_group(&self, name: &str) {
+        if self.debug_groups {
+            unsafe {
+                self.gl
+                    .push_debug_group(glow::DEBUG_SOURCE_APPLICATION, 0, name);
+            }
+        }
+    }
+
+    /// Closes the last debug group opened with [`Context::push_debug_group`].
+    pub fn pop_debug_group(&self) {
+        if self.debug_groups {
+            unsafe {
+                self.gl.pop_debug_group();
+            }
+        }
+    }
+
+    /// Opens a debug group that is closed when the returned guard is dropped.
+    pub fn debug_scope(&self, name: &str) -> DebugScope<'_> {
+        self.push_debug_group(name);
+        DebugScope { ctx: self }
+    }
+
+    /// Gets what the OpenGL context is capable of.
+    pub fn feature_level(&self) -> FeatureLevel {
+        self.feature_level
+    }
+
+    /// Anisotropy used by mipmapped textures that don't set their own, i.e., the one from the
+    /// configuration limited to what is supported. 1 means anisotropic filtering is off.
+    pub fn anisotropy(&self) -> f32 {
+        let anisotropy = unsafe { naevc::conf.anisotropy } as f32;
+        anisotropy.clamp(1.0, self.limits.max_anisotropy.max(1.0))
+    }
+
+    /// Whether an optional feature can be used.
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.feature_level >= feature.min_level()
+    }
+
+    /// Checks if a feature can be used, warning the first time it isn't, so that callers can
+    /// fall back to a simpler implementation without spamming the log.
+    pub fn require(&self, feature: Feature) -> bool {
+        if self.supports(feature) {
+            return true;
+        }
+        let bit = 1 << (feature as u32);
+        if self.feature_warned.fetch_or(bit, Ordering::Relaxed) & bit == 0 {
+            warn!(
+                "{} need {:?}, but the OpenGL context is only {:?}, falling back",
+                feature.name(),
+                feature.min_level(),
+                self.feature_level
+            );
+        }
+        false
+    }
+
+    /// Makes sure the writes done by shaders, e.g., compute shaders writing to storage buffers,
+    /// are visible to the operations given by `flags`. Does nothing on contexts that can't do
+    /// such writes in the first place.
+    pub fn memory_barrier(&self, flags: BarrierFlags) {
+        if flags.is_empty() || !self.supports(Feature::StorageBuffers) {
+            return;
+        }
+        unsafe {
+            self.gl.memory_barrier(flags.bits());
+        }
+    }
+
+    /// Waits for all shader writes to be visible to everything, for when correctness matters
+    /// more than performance.
+    pub fn barrier_all(&self) {
+        self.memory_barrier(BarrierFlags::ALL);
+    }
+
+    /// Gets the OpenGL vendor string, e.g. "AMD" or "NVIDIA Corporation".
+    pub fn gl_vendor(&self) -> &str {
+        &self.info.vendor
+    }
+
+    /// Gets the OpenGL renderer string, usually the GPU name.
+    pub fn gl_renderer(&self) -> &str {
+        &self.info.renderer
+    }
+
+    /// Gets the OpenGL version string, including driver specific information.
+    pub fn gl_version(&self) -> &str {
+        &self.info.version
+    }
+
+    /// Gets the GLSL version string.
+    pub fn glsl_version(&self) -> &str {
+        &self.info.glsl_version
+    }
+
+    /// Gets the MSAA sample counts supported by the hardware, for presenting valid choices in
+    /// the options. Always includes 1, meaning no multisampling.
+    pub fn available_sample_counts(&self) -> Vec<u32> {
+        std::iter::successors(Some(1u32), |n| n.checked_mul(2))
+            .take_while(|n| *n <= self.limits.max_samples)
+            .collect()
+    }
+
+    /// Perspective projection for 3D rendering, honouring the reverse-Z setting. See
+    /// [`perspective4`] for the clip space convention.
+    pub fn perspective(&self, fov: f32, aspect: f32, near: f32, far: f32) -> Matrix4<f32> {
+        perspective4(fov, aspect, near, far, self.reverse_z())
+    }
+
+    /// Orthographic projection for 3D rendering, honouring the reverse-Z setting.
+    pub fn ortho3d(
+        &self,
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+    ) -> Matrix4<f32> {
+        ortho3d(left, right, bottom, top, near, far, self.reverse_z())
+    }
+
+    /// Sets whether 3D projections map the far plane to 0 depth instead of 1.
+    pub fn set_reverse_z(&self, enable: bool) {
+        self.reverse_z.store(enable, Ordering::Relaxed);
+    }
+
+    pub fn reverse_z(&self) -> bool {
+        self.reverse_z.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether opaque 3D models are first rendered to the depth buffer only, so that the
+    /// expensive shading is done once per pixel instead of once per overlapping surface. This
+    /// costs an extra geometry pass, so it only pays off when there is a lot of overdraw, e.g.,
+    /// with many overlapping ships, and should be measured on the target hardware. The fragments
+    /// shaded per frame are shown next to the FPS, see [`crate::model::take_shaded_samples`], and
+    /// drop to one per covered pixel with the pre-pass.
+    pub fn set_depth_prepass(&self, enable: bool) {
+        self.depth_prepass.store(enable, Ordering::Relaxed);
+    }
+
+    pub fn depth_prepass(&self) -> bool {
+        self.depth_prepass.load(Ordering::Relaxed)
+    }
+
+    /// Depth function to use with the current depth convention.
+    pub fn depth_func(&self) -> u32 {
+        match self.reverse_z() {
+            true => glow::GREATER,
+            false => glow::LESS,
+        }
+    }
+
+    /// Depth function that also passes equal depths, e.g., to shade after a depth pre-pass.
+    pub fn depth_func_or_equal(&self) -> u32 {
+        match self.reverse_z() {
+            true => glow::GEQUAL,
+            false => glow::LEQUAL,
+        }
+    }
+
+    /// Value to clear the depth buffer to with the current depth convention.
+    pub fn depth_clear(&self) -> f32 {
+        match self.reverse_z() {
+            true => 0.0,
+            false => 1.0,
+        }
+    }
+
+    /// Captures the current blend, depth, scissor, program, vertex array and viewport state.
+    pub fn capture_state(&self) -> RenderState {
+        let gl = &self.gl;
+        unsafe {
+            let get = |param| gl.get_parameter_i32(param) as u32;
+            let mut scissor_box = [0; 4];
+            gl.get_parameter_i32_slice(glow::SCISSOR_BOX, &mut scissor_box);
+            let mut viewport = [0; 4];
+            gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+            RenderState {
+                blend: gl.is_enabled(glow::BLEND),
+                blend_func: [
+                    get(glow::BLEND_SRC_RGB),
+                    get(glow::BLEND_DST_RGB),
+                    get(glow::BLEND_SRC_ALPHA),
+                    get(glow::BLEND_DST_ALPHA),
+                ],
+                blend_equation: [
+                    get(glow::BLEND_EQUATION_RGB),
+                    get(glow::BLEND_EQUATION_ALPHA),
+                ],
+                depth_test: gl.is_enabled(glow::DEPTH_TEST),
+                depth_func: get(glow::DEPTH_FUNC),
+                depth_mask: gl.get_parameter_bool(glow::DEPTH_WRITEMASK),
+                scissor_test: gl.is_enabled(glow::SCISSOR_TEST),
+                scissor_box,
+                program: gl.get_parameter_program(glow::CURRENT_PROGRAM),
+                vertex_array: gl.get_parameter_vertex_array(glow::VERTEX_ARRAY_BINDING),
+                viewport,
+            }
+        }
+    }
+
+    /// Restores state previously captured with [`Context::capture_state`].
+    pub fn restore_state(&self, state: &RenderState) {
+        let gl = &self.gl;
+        let set = |cap, enable| unsafe {
+            match enable {
+                true => gl.enable(cap),
+                false => gl.disable(cap),
+            }
+        };
+        set(glow::BLEND, state.blend);
+        set(glow::DEPTH_TEST, state.depth_test);
+        set(glow::SCISSOR_TEST, state.scissor_test);
+        unsafe {
+            let [src_rgb, dst_rgb, src_alpha, dst_alpha] = state.blend_func;
+            gl.blend_func_separate(src_rgb, dst_rgb, src_alpha, dst_alpha);
+            let [eq_rgb, eq_alpha] = state.blend_equation;
+            gl.blend_equation_separate(eq_rgb, eq_alpha);
+            gl.depth_func(state.depth_func);
+            gl.depth_mask(state.depth_mask);
+            let [x, y, w, h] = state.scissor_box;
+            gl.scissor(x, y, w, h);
+            let [x, y, w, h] = state.viewport;
+            gl.viewport(x, y, w, h);
+            gl.use_program(state.program);
+            gl.bind_vertex_array(state.vertex_array);
+        }
+    }
+
+    /// Gets a copy of the current 2D camera.
+    pub fn camera(&self) -> Camera2D {
+        *self.camera.read().unwrap()
+    }
+
+    /// Gets the 2D camera for modification.
+    pub fn camera_mut(&self) -> RwLockWriteGuard<'_, Camera2D> {
+        self.camera.write().unwrap()
+    }
+
+    /// Gets the combined projection and camera view matrix used for world space drawing.
+    pub fn view_projection(&self) -> Matrix3<f32> {
+        let dims = self.dimensions.read().unwrap();
+        let view = self.camera().view(dims.view_width, dims.view_height);
+        dims.projection * view
+    }
+
+    /// Draws a rectangle in world coordinates, so it follows the camera. The colour is in sRGB,
+    /// like [`Context::draw_rect`].
+    pub fn draw_rect_world(
+        &self,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        colour: Vector4<f32>,
+    ) -> Result<()> {
+        #[rustfmt::skip]
+        let transform: Matrix3<f32> = self.view_projection() * Matrix3::new(
+             w,  0.0,  x,
+            0.0,  h,   y,
+            0.0, 0.0, 1.0,
+        );
+        let uniform = SolidUniform {
+            transform,
+            colour: colour::srgb_to_linear(colour),
+        };
+        self.draw_rect_ex(&uniform)
+    }
+
+    /// Draws a rectangle in screen coordinates. The colour is taken as sRGB, e.g., as written in
+    /// hex, and converted to linear space so that it comes out right with the sRGB framebuffer.
+    /// Use [`Context::draw_rect_linear`] for colours that are already linear, such as `glColour`.
     pub fn draw_rect(&self, x: f32, y: f32, w: f32, h: f32, colour: Vector4<f32>) -> Result<()> {
+        self.draw_rect_linear(x, y, w, h, colour::srgb_to_linear(colour))
+    }
+
+    /// Draws a rectangle in screen coordinates with a colour in linear space.
+    pub fn draw_rect_linear(
+        &self,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        colour: Vector4<f32>,
+    ) -> Result<()> {
         let dims = self.dimensions.read().unwrap();
         #[rustfmt::skip]
         let transform: Matrix3<f32> = dims.projection * Matrix3::new(
@@ -686,18 +2335,252 @@ impl Context {
         self.draw_rect_ex(&uniform)
     }
 
+    /// Draws a texture stretched over a rectangle in screen coordinates.
+    pub fn draw_texture(&self, tex: &Texture, x: f32, y: f32, w: f32, h: f32) -> Result<()> {
+        self.draw_texture_ex(tex, x, y, w, h, &TextureUniform::default(), None)
+    }
+
+    /// Draws a texture over a rectangle in screen coordinates. The transform of `uniform` is
+    /// replaced, while its texture coordinate transform and linear colour are used as is, e.g.,
+    /// to draw part of a texture or tint it. The sampling parameters of the texture can be
+    /// overridden with `sampler`.
+    pub fn draw_texture_ex(
+        &self,
+        tex: &Texture,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        uniform: &TextureUniform,
+        sampler: Option<&Sampler>,
+    ) -> Result<()> {
+        let dims = self.dimensions.read().unwrap();
+        #[rustfmt::skip]
+        let transform: Matrix3<f32> = dims.projection * Matrix3::new(
+             w,  0.0,  x,
+            0.0,  h,   y,
+            0.0, 0.0, 1.0,
+        );
+        drop(dims);
+        let uniform = TextureUniform {
+            transform,
+            ..*uniform
+        };
+        tex.draw_ex_sampler(self, &uniform, sampler)
+    }
+
+    /// Draws a line in screen coordinates with an sRGB colour. The line is drawn as a thin
+    /// rectangle one pixel wide, since wide lines are not supported by core OpenGL.
+    pub fn draw_line(
+        &self,
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        colour: Vector4<f32>,
+    ) -> Result<()> {
+        self.draw_line_linear(x1, y1, x2, y2, 1.0, colour::srgb_to_linear(colour))
+    }
+
+    /// Draws a line of `width` in screen coordinates with a colour in linear space.
+    pub fn draw_line_linear(
+        &self,
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        width: f32,
+        colour: Vector4<f32>,
+    ) -> Result<()> {
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let len = dx.hypot(dy);
+        if len <= 0.0 {
+            return Ok(());
+        }
+        // Normal to the line with the length of the width
+        let (nx, ny) = (-dy / len * width, dx / len * width);
+        let dims = self.dimensions.read().unwrap();
+        #[rustfmt::skip]
+        let transform: Matrix3<f32> = dims.projection * Matrix3::new(
+            dx, nx, x1 - 0.5 * nx,
+            dy, ny, y1 - 0.5 * ny,
+            0.0, 0.0, 1.0,
+        );
+        let uniform = SolidUniform { transform, colour };
+        self.draw_rect_ex(&uniform)
+    }
+
+    /// Draws a circle in screen coordinates with an sRGB colour, either filled or as an outline.
+    /// The circle is a polygon without anti-aliasing, unlike the SDF circle of
+    /// `gl_renderCircle`.
+    pub fn draw_circle(
+        &self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        colour: Vector4<f32>,
+        filled: bool,
+    ) -> Result<()> {
+        self.draw_circle_linear(x, y, radius, colour::srgb_to_linear(colour), filled)
+    }
+
+    /// Draws a circle in screen coordinates with a colour in linear space.
+    pub fn draw_circle_linear(
+        &self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        colour: Vector4<f32>,
+        filled: bool,
+    ) -> Result<()> {
+        let dims = self.dimensions.read().unwrap();
+        #[rustfmt::skip]
+        let transform: Matrix3<f32> = dims.projection * Matrix3::new(
+            radius, 0.0,   x,
+            0.0,   radius, y,
+            0.0,   0.0,   1.0,
+        );
+        drop(dims);
+        let uniform = SolidUniform { transform, colour };
+        let n = Self::CIRCLE_SEGMENTS as i32;
+        let (mode, first, count) = match filled {
+            true => (glow::TRIANGLE_FAN, 0, n + 2),
+            false => (glow::LINE_LOOP, 1, n),
+        };
+        self.draw_solid(&self.vao_circle, mode, first, count, &uniform)
+    }
+
+    /// Draws a horizontal progress bar filled up to `fraction`, which is clamped to [0, 1].
+    pub fn draw_progress(
+        &self,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        fraction: f32,
+        style: &ProgressStyle,
+    ) -> Result<()> {
+        let fraction = match fraction.is_nan() {
+            true => 0.0,
+            false => fraction.clamp(0.0, 1.0),
+        };
+        self.draw_bar(x, y, w, h, style.bg, style.rounded)?;
+        if fraction > 0.0 {
+            // Rounded bars can't be shorter than their caps
+            let fw = match style.rounded {
+                true => (w * fraction).max(h.min(w)),
+                false => w * fraction,
+            };
+            self.draw_bar(x, y, fw, h, style.fg, style.rounded)?;
+        }
+        Ok(())
+    }
+
+    /// Draws a rectangle, optionally with semicircular ends.
+    fn draw_bar(
+        &self,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        colour: Vector4<f32>,
+        rounded: bool,
+    ) -> Result<()> {
+        let r = 0.5 * h;
+        if !rounded || w < h {
+            return self.draw_rect(x, y, w, h, colour);
+        }
+        self.draw_rect(x + r, y, w - h, h, colour)?;
+        // Uses the anti-aliased circle of the C renderer
+        let c = colour::srgb_to_linear(colour);
+        for cx in [x + r, x + w - r] {
+            unsafe {
+                naevc::gl_renderCircle(
+                    cx.into(),
+                    (y + r).into(),
+                    r.into(),
+                    &c as *const Vector4<f32> as *const naevc::glColour,
+                    1,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws a solid shape with the square vertex array. The colour of the uniform is in linear
+    /// space.
     pub fn draw_rect_ex(&self, uniform: &SolidUniform) -> Result<()> {
+        self.draw_solid(&self.vao_square, glow::TRIANGLE_STRIP, 0, 4, uniform)
+    }
+
+    /// Draws `count` vertices of `vao` starting at `first` with the solid shader.
+    fn draw_solid(
+        &self,
+        vao: &VertexArray,
+        mode: u32,
+        first: i32,
+        count: i32,
+        uniform: &SolidUniform,
+    ) -> Result<()> {
+        debug_assert!(
+            self.is_main_thread(),
+            "Context::draw_solid called outside of the main thread"
+        );
+        let _scope = self.debug_scope("Solid");
         let gl = &self.gl;
         self.program_solid.use_program(gl);
-        self.vao_square.bind(self);
+        vao.bind(self);
 
         self.buffer_solid
             .bind_write_base(self, &uniform.buffer()?, 0)?;
         unsafe {
-            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+            gl.draw_arrays(mode, first, count);
         }
         VertexArray::unbind(self);
         self.buffer_solid.unbind(self);
+        self.check_errors();
+
+        Ok(())
+    }
+
+    /// Starts a batch of rectangles to draw with a single instanced draw call, using the current
+    /// projection.
+    pub fn rect_batch(&self) -> RectBatch {
+        RectBatch {
+            projection: self.dimensions.read().unwrap().projection,
+            instances: Vec::new(),
+        }
+    }
+
+    /// Draws many solid rectangles, uploading all their uniforms with a single buffer write
+    /// instead of one per rectangle. Colours are in linear space, like [`Context::draw_rect_ex`].
+    pub fn draw_rects(&self, uniforms: &[SolidUniform]) -> Result<()> {
+        debug_assert!(
+            self.is_main_thread(),
+            "Context::draw_rects called outside of the main thread"
+        );
+        if uniforms.is_empty() {
+            return Ok(());
+        }
+        let mut batch = self.batch_solid.lock().unwrap();
+        batch.clear();
+        for uniform in uniforms {
+            batch.push(&uniform.buffer()?)?;
+        }
+        batch.upload(self);
+
+        let gl = &self.gl;
+        self.program_solid.use_program(gl);
+        self.vao_square.bind(self);
+        for idx in 0..batch.len() {
+            batch.bind_range(self, idx, 0);
+            unsafe {
+                gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+            }
+        }
+        VertexArray::unbind(self);
+        self.buffer_solid.unbind(self);
+        self.check_errors();
 
         Ok(())
     }
@@ -711,16 +2594,171 @@ pub extern "C" fn gl_renderRect(
     h: c_double,
     c: *mut Vector4<f32>,
 ) {
-    let ctx = Context::get().unwrap();
-    let colour = unsafe { *c };
-    let _ = ctx.draw_rect(x as f32, y as f32, w as f32, h as f32, colour);
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let colour = unsafe { *c };
+        // glColour is already in linear space
+        let _ = ctx.draw_rect_linear(x as f32, y as f32, w as f32, h as f32, colour);
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gl_resize() {
-    {
-        let ctx = CONTEXT.get().unwrap();
-        let _ = ctx.resize();
-    }
-    unsafe { naevc::gl_resize_c() };
+    ffi_guard((), || unsafe { naevc::gl_resize_c() })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn gl_setVsync(vsync: c_int) {
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        ctx.set_vsync(VsyncMode::from_conf(vsync));
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn gl_screenshot(filename: *const c_char) {
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let filename = unsafe { CStr::from_ptr(filename) };
+        if let Err(e) = ctx.screenshot(&filename.to_string_lossy()) {
+            warn_err(e.context("unable to take screenshot"));
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn render_updateTime(game_dt: c_double, real_dt: c_double) {
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        if let Err(e) = ctx.update_time(game_dt, real_dt) {
+            warn_err(e.context("unable to update shader time"));
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn render_scaleBegin() -> c_int {
+    ffi_guard(0, || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return 0;
+            }
+        };
+        match ctx.begin_scaled() {
+            Ok(active) => active as c_int,
+            Err(e) => {
+                warn_err(e.context("unable to set up render scale"));
+                0
+            }
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn render_scaleEnd() {
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        if let Err(e) = ctx.end_scaled() {
+            warn_err(e.context("unable to upscale world"));
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn render_setScale(scale: c_double) {
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        if let Err(e) = ctx.set_render_scale(scale as f32) {
+            warn_err(e);
+        }
+    })
+}
+
+/// Whether shader subroutines are available, see [`GlLimits::shader_subroutines`].
+#[unsafe(no_mangle)]
+pub extern "C" fn gl_hasSubroutines() -> c_int {
+    ffi_guard(0, || match Context::get() {
+        Ok(ctx) => ctx.limits.shader_subroutines as c_int,
+        Err(_) => 0,
+    })
+}
+
+/// Gets the maximum anisotropic filtering level supported, 1 if it is not available at all.
+#[unsafe(no_mangle)]
+pub extern "C" fn gl_maxAnisotropy() -> c_double {
+    ffi_guard(1.0, || match Context::get() {
+        Ok(ctx) => ctx.limits.max_anisotropy.into(),
+        Err(_) => 1.0,
+    })
+}
+
+/// Gets the OpenGL feature level as a version number, e.g., 330 or 460, so that options that
+/// need a newer version can be hidden.
+#[unsafe(no_mangle)]
+pub extern "C" fn gl_featureLevel() -> c_int {
+    ffi_guard(330, || match Context::get() {
+        Ok(ctx) => match ctx.feature_level() {
+            FeatureLevel::Gl33 => 330,
+            FeatureLevel::Gl43 => 430,
+            FeatureLevel::Gl44 => 440,
+            FeatureLevel::Gl46 => 460,
+        },
+        Err(_) => 330,
+    })
+}
+
+/// Called from `naev_resize` so the Rust dimensions match the C ones. `w` and `h` are the
+/// logical window size, while `real_w` and `real_h` are the drawable size in pixels.
+#[unsafe(no_mangle)]
+pub extern "C" fn naev_resize_rust(w: c_int, h: c_int, real_w: c_int, real_h: c_int) {
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        if let Err(e) = ctx.resize(w as u32, h as u32, real_w as u32, real_h as u32) {
+            warn_err(e);
+        }
+    })
 }
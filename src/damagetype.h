@@ -3,13 +3,15 @@
  */
 #pragma once
 
+#include "colour.h"
 #include "outfit.h"
 
 /*
  * stack manipulation
  */
-int         dtype_get( const char *name );
-const char *dtype_damageTypeToStr( int type );
+int             dtype_get( const char *name );
+const char     *dtype_damageTypeToStr( int type );
+const glColour *dtype_colour( int type );
 
 /*
  * misc
@@ -1,26 +1,30 @@
 use anyhow::Result;
+use nalgebra::Vector4;
 use rayon::prelude::*;
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_int};
 
+use crate::ffi::ffi_guard;
 use crate::gettext::gettext;
 use crate::log::warn_err;
-use crate::ndata;
 use crate::utils::{binary_search_by_key_ref, sort_by_key_ref};
 use crate::warn;
+use crate::{colour, ndata};
 use crate::{nxml, nxml_err_attr_missing, nxml_warn_node_unknown};
 
 #[unsafe(no_mangle)]
 pub extern "C" fn dtype_get(name: *const c_char) -> c_int {
-    let ptr = unsafe { CStr::from_ptr(name) };
-    let name = ptr.to_str().unwrap();
-    match binary_search_by_key_ref(&DAMAGE_TYPES, name, |dt: &DamageType| &dt.name) {
-        Ok(i) => (i + 1) as c_int,
-        Err(_) => {
-            warn!("damage type '{}' not found", name);
-            0
+    ffi_guard(0, || {
+        let ptr = unsafe { CStr::from_ptr(name) };
+        let name = ptr.to_str().unwrap();
+        match binary_search_by_key_ref(&DAMAGE_TYPES, name, |dt: &DamageType| &dt.name) {
+            Ok(i) => (i + 1) as c_int,
+            Err(_) => {
+                warn!("damage type '{}' not found", name);
+                0
+            }
         }
-    }
+    })
 }
 
 // Assume static here, because it doesn't really change after loading
@@ -31,13 +35,26 @@ pub fn get_c(dt: c_int) -> Option<&'static DamageType> {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn dtype_damageTypeToStr(dtid: c_int) -> *const c_char {
-    match get_c(dtid) {
+    ffi_guard(std::ptr::null(), || match get_c(dtid) {
         Some(dt) => match &dt.display {
             Some(d) => d.as_ptr(),
             None => dt.cname.as_ptr(),
         },
         None => std::ptr::null(),
-    }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn dtype_colour(dtid: c_int) -> *const naevc::glColour {
+    ffi_guard(std::ptr::null(), || {
+        let col: &'static Vector4<f32> = match get_c(dtid) {
+            Some(DamageType {
+                colour: Some(col), ..
+            }) => col,
+            _ => &NEUTRAL_COLOUR,
+        };
+        col as *const Vector4<f32> as *const naevc::glColour
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -47,7 +64,7 @@ pub extern "C" fn dtype_raw(
     armour: *mut f64,
     knockback: *mut f64,
 ) -> c_int {
-    match get_c(dtid) {
+    ffi_guard(0, || match get_c(dtid) {
         Some(dt) => {
             if !shield.is_null() {
                 unsafe {
@@ -67,7 +84,7 @@ pub extern "C" fn dtype_raw(
             0
         }
         None => -1,
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -79,43 +96,45 @@ pub extern "C" fn dtype_calcDamage(
     dmg: *const naevc::Damage,
     _s: *const naevc::ShipStats,
 ) {
-    /*
-    if ( dshield != NULL ) {
-       if ( ( dtype->soffset == 0 ) || ( s == NULL ) )
-          *dshield = dtype->sdam * dmg->damage * absorb;
-       else {
-          ptr = (char *)s;
-          memcpy( &multiplier, &ptr[dtype->soffset], sizeof( double ) );
-          multiplier = MAX( 0., 1. - multiplier );
-          *dshield   = dtype->sdam * dmg->damage * absorb * multiplier;
-       }
-    }
-    if ( darmour != NULL ) {
-       if ( ( dtype->aoffset ) == 0 || ( s == NULL ) )
-          *darmour = dtype->adam * dmg->damage * absorb;
-       else {
-          ptr = (char *)s;
-          memcpy( &multiplier, &ptr[dtype->aoffset], sizeof( double ) );
-          multiplier = MAX( 0., 1. - multiplier );
-          *darmour   = dtype->adam * dmg->damage * absorb * multiplier;
-       }
-    }
-    if ( knockback != NULL )
-       *knockback = dtype->knock;
-     */
-    if let Some(dt) = get_c(unsafe { (*dmg).type_ }) {
-        if !dshield.is_null() {
-            unsafe { *dshield = dt.shield_mod * (*dmg).damage * absorb }
+    ffi_guard((), || {
+        /*
+        if ( dshield != NULL ) {
+           if ( ( dtype->soffset == 0 ) || ( s == NULL ) )
+              *dshield = dtype->sdam * dmg->damage * absorb;
+           else {
+              ptr = (char *)s;
+              memcpy( &multiplier, &ptr[dtype->soffset], sizeof( double ) );
+              multiplier = MAX( 0., 1. - multiplier );
+              *dshield   = dtype->sdam * dmg->damage * absorb * multiplier;
+           }
         }
-        if !darmour.is_null() {
-            unsafe { *darmour = dt.armour_mod * (*dmg).damage * absorb }
+        if ( darmour != NULL ) {
+           if ( ( dtype->aoffset ) == 0 || ( s == NULL ) )
+              *darmour = dtype->adam * dmg->damage * absorb;
+           else {
+              ptr = (char *)s;
+              memcpy( &multiplier, &ptr[dtype->aoffset], sizeof( double ) );
+              multiplier = MAX( 0., 1. - multiplier );
+              *darmour   = dtype->adam * dmg->damage * absorb * multiplier;
+           }
         }
-        if !knockback.is_null() {
-            unsafe {
-                *knockback = dt.knockback;
+        if ( knockback != NULL )
+           *knockback = dtype->knock;
+         */
+        if let Some(dt) = get_c(unsafe { (*dmg).type_ }) {
+            if !dshield.is_null() {
+                unsafe { *dshield = dt.shield_mod * (*dmg).damage * absorb }
+            }
+            if !darmour.is_null() {
+                unsafe { *darmour = dt.armour_mod * (*dmg).damage * absorb }
+            }
+            if !knockback.is_null() {
+                unsafe {
+                    *knockback = dt.knockback;
+                }
             }
         }
-    }
+    })
 }
 
 #[derive(Debug, Clone)]
@@ -126,9 +145,31 @@ pub struct DamageType {
     shield_mod: f64,
     armour_mod: f64,
     knockback: f64,
+    colour: Option<Vector4<f32>>,
+    icon: Option<String>,
     // TODO ship stat modifiers
 }
 
+/// How a damage type should be shown in the UI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayInfo {
+    /// Colour in linear space, laid out like `glColour`.
+    pub colour: Vector4<f32>,
+    /// Path to the icon, if any.
+    pub icon: Option<String>,
+}
+impl Default for DisplayInfo {
+    fn default() -> Self {
+        DisplayInfo {
+            colour: NEUTRAL_COLOUR,
+            icon: None,
+        }
+    }
+}
+
+/// Used for damage types that don't specify a colour, and for unknown damage types.
+const NEUTRAL_COLOUR: Vector4<f32> = Vector4::new(0.5, 0.5, 0.5, 1.0);
+
 impl DamageType {
     fn load(filename: &str) -> Result<Self> {
         let data = ndata::read(filename)?;
@@ -159,11 +200,75 @@ impl DamageType {
                 "shield" => dt.shield_mod = nxml::node_f64(node)?,
                 "armour" => dt.armour_mod = nxml::node_f64(node)?,
                 "knockback" => dt.knockback = nxml::node_f64(node)?,
+                "colour" => match colour::parse(nxml::node_str(node)?) {
+                    Ok(col) => dt.colour = Some(col),
+                    Err(e) => warn_err(
+                        e.context(format!("invalid colour for Damage Type '{}'", &dt.name)),
+                    ),
+                },
+                "icon" => dt.icon = Some(nxml::node_string(node)?),
                 tag => nxml_warn_node_unknown!("Damage Type", &dt.name, tag),
             }
         }
         Ok(dt)
     }
+
+    /// Creates a damage type with the given modifiers, for testing code that uses them.
+    #[cfg(test)]
+    pub fn new(name: &str, shield_mod: f64, armour_mod: f64) -> Self {
+        DamageType {
+            name: String::from(name),
+            cname: CString::new(name).unwrap(),
+            shield_mod,
+            armour_mod,
+            ..DamageType::default()
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Multiplier applied to damage dealt to shields.
+    pub fn shield_mod(&self) -> f64 {
+        self.shield_mod
+    }
+
+    /// Multiplier applied to damage dealt to armour.
+    pub fn armour_mod(&self) -> f64 {
+        self.armour_mod
+    }
+
+    /// Gets the colour and icon used to display the damage type.
+    #[allow(dead_code)]
+    pub fn display(&self) -> DisplayInfo {
+        DisplayInfo {
+            colour: self.colour.unwrap_or(NEUTRAL_COLOUR),
+            icon: self.icon.clone(),
+        }
+    }
+}
+
+/// Gets a damage type by name.
+pub fn get(name: &str) -> Option<&'static DamageType> {
+    binary_search_by_key_ref(&DAMAGE_TYPES, name, |dt: &DamageType| &dt.name)
+        .ok()
+        .map(|i| &DAMAGE_TYPES[i])
+}
+
+/// Gets all the loaded damage types, sorted by name.
+pub fn get_all() -> &'static [DamageType] {
+    &DAMAGE_TYPES
+}
+
+/// Gets the display information of a damage type by name, falling back to a neutral gray for
+/// unknown damage types.
+#[allow(dead_code)]
+pub fn display_info(name: &str) -> DisplayInfo {
+    match get(name) {
+        Some(dt) => dt.display(),
+        None => DisplayInfo::default(),
+    }
 }
 
 impl Default for DamageType {
@@ -175,6 +280,8 @@ impl Default for DamageType {
             shield_mod: 1.0,
             armour_mod: 1.0,
             knockback: 0.0,
+            colour: None,
+            icon: None,
         }
     }
 }
@@ -9,6 +9,7 @@ use std::sync::{Mutex, RwLock};
 
 use crate::array::ArrayCString;
 use crate::context::{Context, ContextWrapper};
+use crate::ffi::ffi_guard;
 use crate::gettext::gettext;
 use crate::log::warn_err;
 use crate::nlua::LuaEnv;
@@ -572,85 +573,99 @@ use std::os::raw::{c_char, c_double, c_int};
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_isFaction(f: c_int) -> c_int {
-    if f < 0 {
-        return 0;
-    }
-    match FACTIONS.read().unwrap().contains_slot(f as u32) {
-        Some(_) => 1,
-        None => 0,
-    }
+    ffi_guard(0, || {
+        if f < 0 {
+            return 0;
+        }
+        match FACTIONS.read().unwrap().contains_slot(f as u32) {
+            Some(_) => 1,
+            None => 0,
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_exists(name: *const c_char) -> c_int {
-    let ptr = unsafe { CStr::from_ptr(name) };
-    let name = ptr.to_str().unwrap();
-    for fct in FACTIONS.read().unwrap().iter() {
-        let (key, val) = fct;
-        if name == val.data.name {
-            return key.slot() as c_int;
+    ffi_guard(0, || {
+        let ptr = unsafe { CStr::from_ptr(name) };
+        let name = ptr.to_str().unwrap();
+        for fct in FACTIONS.read().unwrap().iter() {
+            let (key, val) = fct;
+            if name == val.data.name {
+                return key.slot() as c_int;
+            }
         }
-    }
-    0
+        0
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_get(name: *const c_char) -> c_int {
-    let ptr = unsafe { CStr::from_ptr(name) };
-    let name = ptr.to_str().unwrap();
-    for fct in FACTIONS.read().unwrap().iter() {
-        let (key, val) = fct;
-        if name == val.data.name {
-            return key.slot() as c_int;
+    ffi_guard(0, || {
+        let ptr = unsafe { CStr::from_ptr(name) };
+        let name = ptr.to_str().unwrap();
+        for fct in FACTIONS.read().unwrap().iter() {
+            let (key, val) = fct;
+            if name == val.data.name {
+                return key.slot() as c_int;
+            }
         }
-    }
-    warn!(gettext("Faction '{}' not found in stack."), name);
-    0
+        warn!(gettext("Faction '{}' not found in stack."), name);
+        0
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_getAll() -> *const c_int {
-    let mut fcts: Vec<c_int> = vec![];
-    for fct in FACTIONS.read().unwrap().iter() {
-        let (key, val) = fct;
-        fcts.push(key.slot() as c_int);
-    }
-    let arr = ManuallyDrop::new(array::Array::new(&fcts).unwrap());
-    arr.as_ptr() as *const c_int
+    ffi_guard(std::ptr::null(), || {
+        let mut fcts: Vec<c_int> = vec![];
+        for fct in FACTIONS.read().unwrap().iter() {
+            let (key, val) = fct;
+            fcts.push(key.slot() as c_int);
+        }
+        let arr = ManuallyDrop::new(array::Array::new(&fcts).unwrap());
+        arr.as_ptr() as *const c_int
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_getAllVisible() -> *const c_int {
-    let mut fcts: Vec<c_int> = vec![];
-    for fct in FACTIONS.read().unwrap().iter() {
-        let (key, val) = fct;
-        if !val.data.f_invisible {
-            fcts.push(key.slot() as c_int);
+    ffi_guard(std::ptr::null(), || {
+        let mut fcts: Vec<c_int> = vec![];
+        for fct in FACTIONS.read().unwrap().iter() {
+            let (key, val) = fct;
+            if !val.data.f_invisible {
+                fcts.push(key.slot() as c_int);
+            }
         }
-    }
-    let arr = ManuallyDrop::new(array::Array::new(&fcts).unwrap());
-    arr.as_ptr() as *const c_int
+        let arr = ManuallyDrop::new(array::Array::new(&fcts).unwrap());
+        arr.as_ptr() as *const c_int
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_getKnown() -> *const c_int {
-    let mut fcts: Vec<c_int> = vec![];
-    for fct in FACTIONS.read().unwrap().iter() {
-        let (key, val) = fct;
-        if !val.data.f_invisible && !val.standing.read().unwrap().f_known {
-            fcts.push(key.slot() as c_int);
+    ffi_guard(std::ptr::null(), || {
+        let mut fcts: Vec<c_int> = vec![];
+        for fct in FACTIONS.read().unwrap().iter() {
+            let (key, val) = fct;
+            if !val.data.f_invisible && !val.standing.read().unwrap().f_known {
+                fcts.push(key.slot() as c_int);
+            }
         }
-    }
-    let arr = ManuallyDrop::new(array::Array::new(&fcts).unwrap());
-    arr.as_ptr() as *const c_int
+        let arr = ManuallyDrop::new(array::Array::new(&fcts).unwrap());
+        arr.as_ptr() as *const c_int
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_clearKnown() {
-    for fct in FACTIONS.read().unwrap().iter() {
-        let (key, val) = fct;
-        val.standing.write().unwrap().f_known = val.data.f_known;
-    }
+    ffi_guard((), || {
+        for fct in FACTIONS.read().unwrap().iter() {
+            let (key, val) = fct;
+            val.standing.write().unwrap().f_known = val.data.f_known;
+        }
+    })
 }
 
 /// Helper function for the C-side
@@ -667,184 +682,218 @@ where
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_isStatic(id: c_int) -> c_int {
-    faction_c_call(id, |fct| match fct.fixed() {
-        true => 1,
-        false => 0,
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        0
+    ffi_guard(0, || {
+        faction_c_call(id, |fct| match fct.fixed() {
+            true => 1,
+            false => 0,
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            0
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_isInvisible(id: c_int) -> c_int {
-    faction_c_call(id, |fct| match fct.invisible() {
-        true => 1,
-        false => 0,
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        0
+    ffi_guard(0, || {
+        faction_c_call(id, |fct| match fct.invisible() {
+            true => 1,
+            false => 0,
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            0
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_setInvisible(id: c_int, state: c_int) -> c_int {
-    faction_c_call(id, |fct| {
-        fct.set_invisible(!matches!(state, 0));
-        0
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        -1
+    ffi_guard(-1, || {
+        faction_c_call(id, |fct| {
+            fct.set_invisible(!matches!(state, 0));
+            0
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            -1
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_isKnown(id: c_int) -> c_int {
-    faction_c_call(id, |fct| match fct.known() {
-        true => 1,
-        false => 0,
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        0
+    ffi_guard(0, || {
+        faction_c_call(id, |fct| match fct.known() {
+            true => 1,
+            false => 0,
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            0
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_setKnown(id: c_int, state: c_int) -> c_int {
-    faction_c_call(id, |fct| {
-        fct.set_known(!matches!(state, 0));
-        0
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        -1
+    ffi_guard(-1, || {
+        faction_c_call(id, |fct| {
+            fct.set_known(!matches!(state, 0));
+            0
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            -1
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_name(id: c_int) -> *const c_char {
-    faction_c_call(id, |fct| {
-        // Not translated on purpose
-        fct.data.cname.as_ptr()
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        std::ptr::null()
+    ffi_guard(std::ptr::null(), || {
+        faction_c_call(id, |fct| {
+            // Not translated on purpose
+            fct.data.cname.as_ptr()
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            std::ptr::null()
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_shortname(id: c_int) -> *const c_char {
-    faction_c_call(id, |fct| {
-        let ptr = match &fct.data.cdisplayname {
-            Some(name) => name.as_ptr(),
-            None => fct.data.cname.as_ptr(),
-        };
-        unsafe { naevc::gettext_rust(ptr) }
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        std::ptr::null()
+    ffi_guard(std::ptr::null(), || {
+        faction_c_call(id, |fct| {
+            let ptr = match &fct.data.cdisplayname {
+                Some(name) => name.as_ptr(),
+                None => fct.data.cname.as_ptr(),
+            };
+            unsafe { naevc::gettext_rust(ptr) }
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            std::ptr::null()
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_longname(id: c_int) -> *const c_char {
-    faction_c_call(id, |fct| {
-        let ptr = match &fct.data.clongname {
-            Some(name) => name.as_ptr(),
-            None => fct.data.cname.as_ptr(),
-        };
-        unsafe { naevc::gettext_rust(ptr) }
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        std::ptr::null()
+    ffi_guard(std::ptr::null(), || {
+        faction_c_call(id, |fct| {
+            let ptr = match &fct.data.clongname {
+                Some(name) => name.as_ptr(),
+                None => fct.data.cname.as_ptr(),
+            };
+            unsafe { naevc::gettext_rust(ptr) }
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            std::ptr::null()
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_mapname(id: c_int) -> *const c_char {
-    faction_c_call(id, |fct| {
-        let ptr = match &fct.data.cmapname {
-            Some(name) => name.as_ptr(),
-            None => fct.data.cname.as_ptr(),
-        };
-        unsafe { naevc::gettext_rust(ptr) }
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        std::ptr::null()
+    ffi_guard(std::ptr::null(), || {
+        faction_c_call(id, |fct| {
+            let ptr = match &fct.data.cmapname {
+                Some(name) => name.as_ptr(),
+                None => fct.data.cname.as_ptr(),
+            };
+            unsafe { naevc::gettext_rust(ptr) }
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            std::ptr::null()
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_description(id: c_int) -> *const c_char {
-    faction_c_call(id, |fct| {
-        let ptr = fct.data.cdescription.as_ptr();
-        unsafe { naevc::gettext_rust(ptr) }
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        std::ptr::null()
+    ffi_guard(std::ptr::null(), || {
+        faction_c_call(id, |fct| {
+            let ptr = fct.data.cdescription.as_ptr();
+            unsafe { naevc::gettext_rust(ptr) }
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            std::ptr::null()
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_default_ai(id: c_int) -> *const c_char {
-    faction_c_call(id, |fct| fct.data.cai.as_ptr()).unwrap_or_else(|err| {
-        warn_err(err);
-        std::ptr::null()
+    ffi_guard(std::ptr::null(), || {
+        faction_c_call(id, |fct| fct.data.cai.as_ptr()).unwrap_or_else(|err| {
+            warn_err(err);
+            std::ptr::null()
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_tags(id: c_int) -> *mut *const c_char {
-    faction_c_call(id, |fct| fct.data.ctags.as_ptr()).unwrap_or_else(|err| {
-        warn_err(err);
-        std::ptr::null_mut()
+    ffi_guard(std::ptr::null_mut(), || {
+        faction_c_call(id, |fct| fct.data.ctags.as_ptr()).unwrap_or_else(|err| {
+            warn_err(err);
+            std::ptr::null_mut()
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_lane_length_per_presence(id: c_int) -> c_double {
-    faction_c_call(id, |fct| fct.data.lane_length_per_presence as c_double).unwrap_or_else(|err| {
-        warn_err(err);
-        0.0
+    ffi_guard(0.0, || {
+        faction_c_call(id, |fct| fct.data.lane_length_per_presence as c_double).unwrap_or_else(
+            |err| {
+                warn_err(err);
+                0.0
+            },
+        )
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_lane_base_cost(id: c_int) -> c_double {
-    faction_c_call(id, |fct| fct.data.lane_base_cost as c_double).unwrap_or_else(|err| {
-        warn_err(err);
-        0.0
+    ffi_guard(0.0, || {
+        faction_c_call(id, |fct| fct.data.lane_base_cost as c_double).unwrap_or_else(|err| {
+            warn_err(err);
+            0.0
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_logo(id: c_int) -> *const naevc::glTexture {
-    faction_c_call(id, |fct| match &fct.data.logo {
-        Some(logo) => logo as *const texture::Texture as *const naevc::glTexture,
-        None => std::ptr::null(),
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        std::ptr::null()
+    ffi_guard(std::ptr::null(), || {
+        faction_c_call(id, |fct| match &fct.data.logo {
+            Some(logo) => logo as *const texture::Texture as *const naevc::glTexture,
+            None => std::ptr::null(),
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            std::ptr::null()
+        })
     })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _faction_colour(id: c_int) -> *const naevc::glColour {
-    faction_c_call(id, |fct| {
-        &fct.data.ccolour as *const Vector4<f32> as *const naevc::glColour
-    })
-    .unwrap_or_else(|err| {
-        warn_err(err);
-        std::ptr::null()
+    ffi_guard(std::ptr::null(), || {
+        faction_c_call(id, |fct| {
+            &fct.data.ccolour as *const Vector4<f32> as *const naevc::glColour
+        })
+        .unwrap_or_else(|err| {
+            warn_err(err);
+            std::ptr::null()
+        })
     })
 }
@@ -0,0 +1,28 @@
+//! Helpers for the boundary between Rust and C.
+//!
+//! Unwinding out of an `extern "C"` function is undefined behaviour, so exported functions run
+//! their body through [`ffi_guard`]. Functions that operate on a `lua_State` directly are left
+//! unguarded, since Lua errors are foreign exceptions and `catch_unwind` would abort on them.
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::gettext::gettext;
+use crate::warn;
+
+/// Runs `f`, catching any panic so it does not unwind into C. On panic the panic is logged and
+/// `sentinel` is returned instead.
+pub fn ffi_guard<R, F: FnOnce() -> R>(sentinel: R, f: F) -> R {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(r) => r,
+        Err(e) => {
+            let msg = if let Some(s) = e.downcast_ref::<&str>() {
+                s
+            } else if let Some(s) = e.downcast_ref::<String>() {
+                s.as_str()
+            } else {
+                "unknown panic"
+            };
+            warn!(gettext("panic caught at the C boundary: {}"), msg);
+            sentinel
+        }
+    }
+}
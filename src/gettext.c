@@ -26,6 +26,7 @@ typedef struct translation {
    char     *language;   /**< Language code (allocated string). */
    msgcat_t *chain;      /**< Array of message catalogs to try in order. */
    char    **chain_lang; /**< Array of those catalogs' names. */
+   int       retired;    /**< Replaced by a reload, kept alive until exit. */
    struct translation
       *next; /**< Next entry in the list of loaded translations. */
 } translation_t;
@@ -146,7 +147,7 @@ void gettext_setLanguage( const char *lang )
    /* Search for the selected language in the loaded translations. */
    for ( translation_t *ptrans = gettext_translations; ptrans != NULL;
          ptrans                = ptrans->next )
-      if ( !strcmp( lang, ptrans->language ) ) {
+      if ( !ptrans->retired && !strcmp( lang, ptrans->language ) ) {
          gettext_activeTranslation = ptrans;
          return;
       }
@@ -195,6 +196,28 @@ void gettext_setLanguage( const char *lang )
    gettext_activeTranslation = newtrans;
 }
 
+/**
+ * @brief Reloads the active translation from ndata.
+ *
+ * The previous catalogs are retired instead of freed, so pointers to strings
+ * translated before the reload stay valid until gettext_exit().
+ */
+void gettext_reload( void )
+{
+   translation_t *old = gettext_activeTranslation;
+   char          *lang;
+
+   if ( old == NULL )
+      return;
+
+   lang                      = strdup( old->language );
+   old->retired              = 1;
+   gettext_activeTranslation = NULL;
+   gettext_setLanguage( lang );
+   free( lang );
+   DEBUG( _( "Reloaded translations for '%s'" ), gettext_getLanguage() );
+}
+
 /**
  * @brief Pick the best match from "available" (a physfs listing) for the
  * string-slice with address lang, length lang_len.
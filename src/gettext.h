@@ -20,6 +20,7 @@ void            gettext_exit( void );
 const char     *gettext_getSystemLanguage( void );
 const char     *gettext_getLanguage( void );
 void            gettext_setLanguage( const char *lang );
+void            gettext_reload( void );
 LanguageOption *gettext_languageOptions( void );
 double          gettext_languageCoverage( const char *lang );
 
@@ -48,6 +49,7 @@ FORMAT_ARG( 1 ) static inline const char *_( const char *msgid )
 }
 /* Helper for rust. */
 const char *gettext_rust( const char *msgid );
+void        gettext_reloadCatalog( void );
 
 /** \see gettext_noop */
 #define N_( msgid ) gettext_noop( msgid )
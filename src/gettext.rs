@@ -1,4 +1,14 @@
+use anyhow::Result;
 use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use crate::ffi::ffi_guard;
+
+/// Guards the C catalogs, lookups take it for reading while a reload swaps them.
+static CATALOG: RwLock<()> = RwLock::new(());
+/// Bumped every time the catalogs are reloaded so cached translations can refresh.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
 
 pub fn init() {
     unsafe {
@@ -6,6 +16,60 @@ pub fn init() {
     };
 }
 
+/// Re-reads the catalogs for the active language from ndata and swaps them in. Strings
+/// translated before the reload remain valid, but [`LazyTr`] will pick up the new ones.
+pub fn reload() {
+    let _lock = CATALOG.write().unwrap();
+    unsafe {
+        naevc::gettext_reload();
+    }
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Current catalog generation, changes whenever the catalogs are reloaded.
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+/// A translated string that is looked up on first use and refreshed when the catalogs are
+/// reloaded.
+#[allow(dead_code)]
+pub struct LazyTr {
+    msgid: &'static str,
+    cache: RwLock<Option<(u64, &'static str)>>,
+}
+#[allow(dead_code)]
+impl LazyTr {
+    pub const fn new(msgid: &'static str) -> Self {
+        LazyTr {
+            msgid,
+            cache: RwLock::new(None),
+        }
+    }
+
+    pub fn get(&self) -> &'static str {
+        let generation = generation();
+        if let Some((g, s)) = *self.cache.read().unwrap() {
+            if g == generation {
+                return s;
+            }
+        }
+        let s = gettext(self.msgid);
+        *self.cache.write().unwrap() = Some((generation, s));
+        s
+    }
+}
+impl std::fmt::Display for LazyTr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.get())
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn gettext_reloadCatalog() {
+    ffi_guard((), reload)
+}
+
 #[allow(non_snake_case, dead_code)]
 pub fn N_(s: &str) -> &str {
     s
@@ -14,6 +78,7 @@ pub fn N_(s: &str) -> &str {
 //pub fn gettext<T: Into<String>>(msgid: T) -> String {
 pub fn gettext(msg_id: &str) -> &str {
     let msgid = CString::new(msg_id).expect("`msgid` contains an internal 0 byte");
+    let _lock = CATALOG.read().unwrap();
     unsafe {
         let ptr1 = msgid.as_ptr();
         let ptr2 = naevc::gettext_rust(ptr1);
@@ -36,6 +101,7 @@ pub fn ngettext<'a>(msg_id: &'a str, msg_id_plural: &'a str, n: i32) -> &'a str
     let msgid = CString::new(msg_id).expect("`msgid` contains an internal 0 byte");
     let msgid_plural =
         CString::new(msg_id_plural).expect("`msgid_plural` contains an internal 0 byte");
+    let _lock = CATALOG.read().unwrap();
     unsafe {
         let ptr1 = msgid.as_ptr();
         let ptr2 = msgid_plural.as_ptr();
@@ -58,6 +124,7 @@ pub fn ngettext<'a>(msg_id: &'a str, msg_id_plural: &'a str, n: i32) -> &'a str
 pub fn pgettext<'a>(msgctxt: &'a str, msg_id: &'a str) -> &'a str {
     let msgctxt = CString::new(msgctxt).expect("`msgctxt` contains an internal 0 byte");
     let msgid = CString::new(msg_id).expect("`msgid` contains an internal 0 byte");
+    let _lock = CATALOG.read().unwrap();
     unsafe {
         let ptr1 = msgid.as_ptr();
         let ptr2 = naevc::pgettext_var(msgctxt.as_ptr(), ptr1);
@@ -69,3 +136,79 @@ pub fn pgettext<'a>(msgctxt: &'a str, msg_id: &'a str) -> &'a str {
             .expect("ngettext() returned invalid UTF-8")
     }
 }
+
+/// Checks that a translated format string only uses the arguments that are provided, so a bad
+/// translation gets reported instead of producing garbled output. `msgid` is only used to name
+/// the offending string in the error.
+pub fn check_format(msgid: &str, fmt: &str, positional: usize, named: &[&str]) -> Result<()> {
+    let mut next = 0; // Next implicit positional argument
+    let mut required = 0; // Number of positional arguments needed
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+            }
+            '{' => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => anyhow::bail!("'{msgid}': unterminated placeholder in '{fmt}'"),
+                    }
+                }
+                let arg = inner.split(':').next().unwrap_or("").trim();
+                if arg.is_empty() {
+                    next += 1;
+                    required = required.max(next);
+                } else if let Ok(idx) = arg.parse::<usize>() {
+                    required = required.max(idx + 1);
+                } else if !named.contains(&arg) {
+                    anyhow::bail!("'{msgid}': unknown named placeholder '{{{arg}}}' in '{fmt}'");
+                }
+            }
+            '}' => anyhow::bail!("'{msgid}': unmatched '}}' in '{fmt}'"),
+            _ => (),
+        }
+    }
+    if required != positional {
+        anyhow::bail!(
+            "'{msgid}': '{fmt}' uses {required} positional arguments but {positional} were given"
+        );
+    }
+    Ok(())
+}
+
+/// Translates `msgid` and formats it with `formatx!`, checking the placeholders of the
+/// translation against the arguments first. Returns an `anyhow::Result<String>`.
+///
+/// ```ignore
+/// let s = formatx_checked!("{} credits", amount)?;
+/// let s = formatx_checked!("UST {c}:{p}", c = cycles, p = periods)?;
+/// ```
+#[macro_export]
+macro_rules! formatx_checked {
+    (@count [$n:expr] [$($names:expr),*]) => {
+        ($n, vec![$($names),*])
+    };
+    (@count [$n:expr] [$($names:expr),*] $name:ident = $val:expr $(, $($rest:tt)*)?) => {
+        $crate::formatx_checked!(@count [$n] [$($names,)* stringify!($name)] $($($rest)*)?)
+    };
+    (@count [$n:expr] [$($names:expr),*] $val:expr $(, $($rest:tt)*)?) => {
+        $crate::formatx_checked!(@count [$n + 1] [$($names),*] $($($rest)*)?)
+    };
+    ($msgid:expr $(, $($args:tt)*)?) => {{
+        let msgid: &str = $msgid;
+        let fmt = $crate::gettext::gettext(msgid);
+        let (positional, named): (usize, Vec<&str>) =
+            $crate::formatx_checked!(@count [0usize] [] $($($args)*)?);
+        $crate::gettext::check_format(msgid, fmt, positional, &named).and_then(|_| {
+            formatx::formatx!(fmt.to_string() $(, $($args)*)?)
+                .map_err(|e| anyhow::anyhow!("'{msgid}': {e}"))
+        })
+    }};
+}
@@ -1546,7 +1546,7 @@ void gltf_renderScene( GLuint fb, GltfObject *obj, int scene, const mat4 *H,
    glDisable( GL_DEPTH_TEST );
    glUseProgram( 0 );
 #ifdef HAVE_NAEV
-   glViewport( 0, 0, gl_screen.rw, gl_screen.rh );
+   gl_screenViewport();
 #endif /* HAVE_NAEV */
 }
 
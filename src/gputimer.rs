@@ -0,0 +1,192 @@
+//! GPU timer queries for profiling render passes without external tools. Named scopes are timed
+//! with `GL_TIME_ELAPSED` queries, and the results are picked up a few frames later once they are
+//! available, so the CPU never waits on the GPU.
+//!
+//! ```ignore
+//! gputimer::begin(ctx, "nebula");
+//! neb.render(ctx)?;
+//! gputimer::end(ctx, "nebula");
+//! // ... later ...
+//! let ms = gputimer::latest("nebula");
+//! ```
+use anyhow::Result;
+use glow::HasContext;
+use std::collections::HashMap;
+use std::os::raw::c_double;
+use std::sync::Mutex;
+
+use crate::context;
+use crate::context::Context;
+use crate::ffi::ffi_guard;
+
+/// Number of queries per scope. Results usually take a couple of frames to be available, so a
+/// single query would mean skipping most frames.
+const POOL_SIZE: usize = 4;
+
+/// Wrapper around a single `GL_TIME_ELAPSED` query.
+pub struct TimerQuery {
+    query: glow::Query,
+    /// Waiting for the result after [`TimerQuery::end`].
+    pending: bool,
+}
+impl TimerQuery {
+    pub fn new(ctx: &Context) -> Result<Self> {
+        let query = unsafe { ctx.gl.create_query() }.map_err(|e| anyhow::anyhow!(e))?;
+        Ok(TimerQuery {
+            query,
+            pending: false,
+        })
+    }
+
+    /// Starts timing. Only one timer query can be active at a time, they can't be nested.
+    pub fn begin(&mut self, ctx: &Context) {
+        unsafe {
+            ctx.gl.begin_query(glow::TIME_ELAPSED, self.query);
+        }
+    }
+
+    /// Stops timing, the result can be retrieved with [`TimerQuery::poll_ms`] once the GPU is
+    /// done.
+    pub fn end(&mut self, ctx: &Context) {
+        unsafe {
+            ctx.gl.end_query(glow::TIME_ELAPSED);
+        }
+        self.pending = true;
+    }
+
+    /// Whether the query is waiting for its result.
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Gets the elapsed time in milliseconds if the result is available, without blocking.
+    pub fn poll_ms(&mut self, ctx: &Context) -> Option<f64> {
+        if !self.pending {
+            return None;
+        }
+        let gl = &ctx.gl;
+        unsafe {
+            if gl.get_query_parameter_u32(self.query, glow::QUERY_RESULT_AVAILABLE) == 0 {
+                return None;
+            }
+            self.pending = false;
+            let ns = gl.get_query_parameter_u64_with_offset(self.query, glow::QUERY_RESULT, 0);
+            Some(ns as f64 / 1e6)
+        }
+    }
+}
+impl Drop for TimerQuery {
+    fn drop(&mut self) {
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteQuery(self.query));
+    }
+}
+
+#[derive(Default)]
+struct Scope {
+    pool: Vec<TimerQuery>,
+    /// Query of the pool currently timing.
+    active: Option<usize>,
+    latest: Option<f64>,
+}
+impl Scope {
+    fn poll(&mut self, ctx: &Context) {
+        for query in &mut self.pool {
+            if let Some(ms) = query.poll_ms(ctx) {
+                self.latest = Some(ms);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Timers {
+    scopes: HashMap<String, Scope>,
+    /// Scope with an active query, as they can't be nested.
+    active: Option<String>,
+}
+
+static TIMERS: Mutex<Option<Timers>> = Mutex::new(None);
+
+/// Starts timing the scope `name`. Nothing is timed if another scope is already being timed, or
+/// all the queries of the scope are still waiting for results.
+pub fn begin(ctx: &Context, name: &str) {
+    debug_assert!(
+        ctx.is_main_thread(),
+        "gputimer::begin called outside of the main thread"
+    );
+    let mut timers = TIMERS.lock().unwrap();
+    let timers = timers.get_or_insert_with(Default::default);
+    if timers.active.is_some() {
+        return;
+    }
+    let scope = timers.scopes.entry(String::from(name)).or_default();
+    scope.poll(ctx);
+    let idx = match scope.pool.iter().position(|q| !q.is_pending()) {
+        Some(idx) => idx,
+        None if scope.pool.len() < POOL_SIZE => match TimerQuery::new(ctx) {
+            Ok(query) => {
+                scope.pool.push(query);
+                scope.pool.len() - 1
+            }
+            Err(_) => return,
+        },
+        None => return,
+    };
+    scope.pool[idx].begin(ctx);
+    scope.active = Some(idx);
+    timers.active = Some(String::from(name));
+}
+
+/// Stops timing the scope `name`.
+pub fn end(ctx: &Context, name: &str) {
+    let mut timers = TIMERS.lock().unwrap();
+    let Some(timers) = timers.as_mut() else {
+        return;
+    };
+    if timers.active.as_deref() != Some(name) {
+        return;
+    }
+    timers.active = None;
+    if let Some(scope) = timers.scopes.get_mut(name) {
+        if let Some(idx) = scope.active.take() {
+            scope.pool[idx].end(ctx);
+        }
+    }
+}
+
+/// Latest GPU time of the scope `name` in milliseconds, if any.
+pub fn latest(name: &str) -> Option<f64> {
+    let timers = TIMERS.lock().unwrap();
+    timers.as_ref()?.scopes.get(name)?.latest
+}
+
+/// Latest GPU times of all the scopes in milliseconds, sorted by name.
+pub fn results() -> Vec<(String, f64)> {
+    let timers = TIMERS.lock().unwrap();
+    let Some(timers) = timers.as_ref() else {
+        return Vec::new();
+    };
+    let mut res: Vec<(String, f64)> = timers
+        .scopes
+        .iter()
+        .filter_map(|(name, scope)| scope.latest.map(|ms| (name.clone(), ms)))
+        .collect();
+    res.sort_by(|a, b| a.0.cmp(&b.0));
+    res
+}
+
+/// Sum of the latest GPU times of all the scopes in milliseconds, or a negative value if nothing
+/// has been timed yet.
+#[unsafe(no_mangle)]
+pub extern "C" fn gputimer_totalMs() -> c_double {
+    ffi_guard(-1.0, || {
+        let res = results();
+        match res.is_empty() {
+            true => -1.0,
+            false => res.iter().map(|(_, ms)| ms).sum(),
+        }
+    })
+}
@@ -29,6 +29,7 @@
 #include "map_overlay.h"
 #include "menu.h"
 #include "nstring.h"
+#include "opengl.h"
 #include "pause.h"
 #include "pilot.h"
 #include "player.h"
@@ -213,6 +214,14 @@ static const char *keybind_info[KST_END][3] = {
    [KST_PASTE]      = { N_( "Paste" ),
                         N_( "Paste from the operating system's clipboard." ),
                         "paste" },
+   [KST_RELOAD_TRANSLATIONS] =
+      { N_( "Reload Translations" ),
+        N_( "Reloads the translation catalogs from the data files." ),
+        "reload_translations" },
+   [KST_RELOAD_SHADERS] = { N_( "Reload Shaders" ),
+                            N_( "Reloads the shaders from the data files, "
+                                "only works in debug builds." ),
+                            "reload_shaders" },
 };
 
 static Keybind input_keybinds[KST_END]; /**< contains the players keybindings */
@@ -370,6 +379,13 @@ void input_setDefault( int wasd )
 #else
    input_setKeybind( KST_PASTE, KEYBIND_KEYBOARD, SDLK_v, NMOD_CTRL );
 #endif
+
+#if DEBUGGING
+   input_setKeybind( KST_RELOAD_TRANSLATIONS, KEYBIND_KEYBOARD, SDLK_F5,
+                     NMOD_CTRL );
+   input_setKeybind( KST_RELOAD_SHADERS, KEYBIND_KEYBOARD, SDLK_F6,
+                     NMOD_CTRL );
+#endif /* DEBUGGING */
 }
 
 /**
@@ -1333,6 +1349,22 @@ static void input_key( KeySemanticType keynum, double value, double kabs,
          cli_open();
       break;
 
+   /* Reloads the translations, useful for translators. */
+   case KST_RELOAD_TRANSLATIONS:
+      if ( repeat )
+         break;
+      if ( value == KEY_PRESS )
+         gettext_reloadCatalog();
+      break;
+
+   /* Reloads the shaders, useful when working on them. */
+   case KST_RELOAD_SHADERS:
+      if ( repeat )
+         break;
+      if ( value == KEY_PRESS )
+         gl_reloadShaders();
+      break;
+
    /* Key not used. */
    default:
       return;
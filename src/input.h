@@ -92,6 +92,10 @@ typedef enum KeySemanticType_ {
 
    KST_PASTE,
 
+   KST_RELOAD_TRANSLATIONS,
+
+   KST_RELOAD_SHADERS,
+
    KST_END
 } KeySemanticType;
 
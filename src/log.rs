@@ -1,7 +1,13 @@
 // Logging tools
 #![allow(dead_code)]
 
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::io::Write;
+use std::path::PathBuf;
 use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, Mutex, RwLock};
 pub static WARN_NUM: AtomicU32 = AtomicU32::new(0);
 pub const WARN_MAX: u32 = 1000;
 
@@ -14,6 +20,214 @@ pub fn init() {
     };
 }
 
+/// Severity of a log message, used to filter what reaches each sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+}
+
+/// In-memory log that keeps the last `capacity` lines. Cloning shares the same buffer.
+#[derive(Clone)]
+pub struct MemoryLog {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+impl MemoryLog {
+    pub fn new(capacity: usize) -> Self {
+        MemoryLog {
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    fn push(&self, msg: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() >= self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(String::from(msg));
+    }
+
+    /// Gets a copy of the stored lines, oldest first.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.lines.lock().unwrap().clear();
+    }
+}
+
+enum SinkTarget {
+    Stdout,
+    Stderr,
+    File(Mutex<std::fs::File>),
+    Memory(MemoryLog),
+    #[cfg(unix)]
+    Syslog(std::os::unix::net::UnixDatagram),
+}
+
+struct Sink {
+    target: SinkTarget,
+    level: Level,
+}
+impl Sink {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn write(&self, level: Level, msg: &str) {
+        // Errors writing logs have nowhere to go, so they are ignored.
+        match &self.target {
+            SinkTarget::Stdout => println!("{msg}"),
+            SinkTarget::Stderr => eprintln!("{msg}"),
+            SinkTarget::File(file) => {
+                let mut file = file.lock().unwrap();
+                let _ = writeln!(file, "{msg}");
+                let _ = file.flush();
+            }
+            SinkTarget::Memory(mem) => mem.push(msg),
+            #[cfg(unix)]
+            SinkTarget::Syslog(socket) => {
+                // Facility is LOG_USER (1), severity follows RFC 5424.
+                let severity = match level {
+                    Level::Debug => 7,
+                    Level::Info => 6,
+                    Level::Warn => 4,
+                };
+                let _ = socket.send(format!("<{}>naev: {msg}", 8 + severity).as_bytes());
+            }
+        }
+    }
+}
+
+/// Active sinks, `None` uses the default of stdout for info and stderr for warnings.
+static SINKS: RwLock<Option<Vec<Sink>>> = RwLock::new(None);
+
+/// Writes a message to all the sinks that accept its level. Used by the logging macros.
+pub fn write(level: Level, msg: &str) {
+    let sinks = SINKS.read().unwrap();
+    match &*sinks {
+        None => match level {
+            Level::Warn => eprintln!("{msg}"),
+            _ => println!("{msg}"),
+        },
+        Some(sinks) => {
+            for sink in sinks.iter().filter(|s| level >= s.level) {
+                sink.write(level, msg);
+            }
+        }
+    }
+}
+
+enum SinkConfig {
+    Stdout(Level),
+    Stderr(Level),
+    File(PathBuf, Level),
+    Memory(MemoryLog, Level),
+    #[cfg(unix)]
+    Syslog(Level),
+}
+
+/// Chooses where the Rust side log messages go, each sink with its own level filter.
+///
+/// ```ignore
+/// let mem = log::MemoryLog::new(512);
+/// log::Builder::new()
+///     .stderr(log::Level::Warn)
+///     .memory(&mem, log::Level::Debug)
+///     .init()?;
+/// ```
+#[derive(Default)]
+pub struct Builder {
+    sinks: Vec<SinkConfig>,
+}
+impl Builder {
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    pub fn stdout(mut self, level: Level) -> Self {
+        self.sinks.push(SinkConfig::Stdout(level));
+        self
+    }
+
+    pub fn stderr(mut self, level: Level) -> Self {
+        self.sinks.push(SinkConfig::Stderr(level));
+        self
+    }
+
+    /// Appends to a file. Relative paths are resolved against the write directory, so they
+    /// can only be used once PhysFS is set up.
+    pub fn file<P: Into<PathBuf>>(mut self, path: P, level: Level) -> Self {
+        self.sinks.push(SinkConfig::File(path.into(), level));
+        self
+    }
+
+    pub fn memory(mut self, mem: &MemoryLog, level: Level) -> Self {
+        self.sinks.push(SinkConfig::Memory(mem.clone(), level));
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn syslog(mut self, level: Level) -> Self {
+        self.sinks.push(SinkConfig::Syslog(level));
+        self
+    }
+
+    /// Opens the sinks and replaces the current ones. Nothing changes on failure.
+    pub fn init(self) -> Result<()> {
+        let mut sinks = Vec::with_capacity(self.sinks.len());
+        for cfg in self.sinks {
+            let (target, level) = match cfg {
+                SinkConfig::Stdout(level) => (SinkTarget::Stdout, level),
+                SinkConfig::Stderr(level) => (SinkTarget::Stderr, level),
+                SinkConfig::File(path, level) => {
+                    let path = match path.is_relative() {
+                        true => write_dir()?.join(path),
+                        false => path,
+                    };
+                    let file = std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&path)
+                        .map_err(|e| {
+                            anyhow::anyhow!("unable to open log file '{}': {e}", path.display())
+                        })?;
+                    (SinkTarget::File(Mutex::new(file)), level)
+                }
+                SinkConfig::Memory(mem, level) => (SinkTarget::Memory(mem), level),
+                #[cfg(unix)]
+                SinkConfig::Syslog(level) => {
+                    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                    socket.connect("/dev/log")?;
+                    (SinkTarget::Syslog(socket), level)
+                }
+            };
+            sinks.push(Sink { target, level });
+        }
+        *SINKS.write().unwrap() = Some(sinks);
+        Ok(())
+    }
+}
+
+fn write_dir() -> Result<PathBuf> {
+    let dir = unsafe { naevc::PHYSFS_getWriteDir() };
+    if dir.is_null() {
+        anyhow::bail!("write directory is not set up");
+    }
+    Ok(PathBuf::from(
+        unsafe { CStr::from_ptr(dir) }.to_string_lossy().as_ref(),
+    ))
+}
+
+/// Restores the default sinks.
+pub fn reset() {
+    *SINKS.write().unwrap() = None;
+}
+
 pub fn einfo(msg: &str) {
     einfo!(msg);
 }
@@ -37,14 +251,14 @@ pub fn warn_err(err: anyhow::Error) {
 #[macro_export]
 macro_rules! info {
     ($($arg:tt)*) => {
-        println!("{}",&formatx::formatx!($($arg)*).unwrap_or(String::from("Unknown")))
+        $crate::log::write($crate::log::Level::Info, &formatx::formatx!($($arg)*).unwrap_or(String::from("Unknown")))
     };
 }
 
 #[macro_export]
 macro_rules! einfo {
     ($($arg:tt)*) => {
-        eprintln!("{}",&formatx::formatx!($($arg)*).unwrap_or(String::from("Unknown")))
+        $crate::log::write($crate::log::Level::Warn, &formatx::formatx!($($arg)*).unwrap_or(String::from("Unknown")))
     };
 }
 
@@ -52,7 +266,7 @@ macro_rules! einfo {
 macro_rules! debug {
     ($($arg:tt)*) => {
         if naevc::config::DEBUG {
-            println!("{}",&formatx::formatx!($($arg)*).unwrap_or(String::from("Unknown")));
+            $crate::log::write($crate::log::Level::Debug, &formatx::formatx!($($arg)*).unwrap_or(String::from("Unknown")));
         }
     };
 }
@@ -62,13 +276,13 @@ macro_rules! warn {
     ($($arg:tt)*) => {
         let nw = $crate::log::WARN_NUM.fetch_add( 1, std::sync::atomic::Ordering::SeqCst );
         if nw <= $crate::log::WARN_MAX {
-            eprintln!("{}WARNING {}:{}: {}",
+            $crate::log::write($crate::log::Level::Warn, &format!("{}WARNING {}:{}: {}",
                 std::backtrace::Backtrace::force_capture(),
                 file!(), line!(),
-                &formatx::formatx!($($arg)*).unwrap_or(String::from("Unknown")));
+                &formatx::formatx!($($arg)*).unwrap_or(String::from("Unknown"))));
         }
         if nw==$crate::log::WARN_MAX {
-            eprintln!("{}",gettext("TOO MANY WARNINGS, NO LONGER DISPLAYING TOO WARNINGS"));
+            $crate::log::write($crate::log::Level::Warn, gettext("TOO MANY WARNINGS, NO LONGER DISPLAYING TOO WARNINGS"));
         }
         if naevc::config::DEBUG_PARANOID {
             #[cfg(unix)]
@@ -84,12 +298,12 @@ macro_rules! warn_err {
     ($err:ident) => {
         let nw = $crate::log::WARN_NUM.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         if nw <= $crate::log::WARN_MAX {
-            eprintln!("WARNING: {:?}", $err);
+            $crate::log::write($crate::log::Level::Warn, &format!("WARNING: {:?}", $err));
         }
         if nw == $crate::log::WARN_MAX {
-            eprintln!(
-                "{}",
-                gettext("TOO MANY WARNINGS, NO LONGER DISPLAYING TOO WARNINGS")
+            $crate::log::write(
+                $crate::log::Level::Warn,
+                gettext("TOO MANY WARNINGS, NO LONGER DISPLAYING TOO WARNINGS"),
             );
         }
         if naevc::config::DEBUG_PARANOID {
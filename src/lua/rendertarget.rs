@@ -0,0 +1,185 @@
+use glow::HasContext;
+use mlua::{UserData, UserDataMethods, Value};
+use std::num::NonZero;
+use std::os::raw::{c_int, c_void};
+
+use crate::context::Context;
+use crate::ffi::ffi_guard;
+use crate::nlua::{LuaEnv, NLUA};
+use crate::texture::{Framebuffer, FramebufferBuilder};
+use crate::warn_err;
+
+/// Render target that Lua can draw into once and reuse as a texture. The framebuffer and its
+/// texture are deleted through the message queue when the userdata is collected.
+pub struct LuaRenderTarget {
+    framebuffer: Framebuffer,
+}
+
+/// GL state that gets clobbered while drawing into a render target.
+struct SavedState {
+    fbo: u32,
+    viewport: [i32; 4],
+    scissor: bool,
+}
+impl SavedState {
+    fn capture(gl: &glow::Context) -> Self {
+        let mut viewport = [0; 4];
+        unsafe {
+            gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+            SavedState {
+                fbo: naevc::gl_screen.current_fbo,
+                viewport,
+                scissor: gl.is_enabled(glow::SCISSOR_TEST),
+            }
+        }
+    }
+
+    fn restore(self, gl: &glow::Context) {
+        unsafe {
+            naevc::gl_screen.current_fbo = self.fbo;
+            gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                NonZero::new(self.fbo).map(glow::NativeFramebuffer),
+            );
+            let [x, y, w, h] = self.viewport;
+            gl.viewport(x, y, w, h);
+            if self.scissor {
+                gl.enable(glow::SCISSOR_TEST);
+            }
+            naevc::render_needsReset();
+        }
+    }
+}
+
+/// Pushes a C texture userdata from a light userdata holding a `*mut Texture`.
+#[allow(non_snake_case)]
+unsafe extern "C-unwind" fn push_tex(L: *mut mlua::lua_State) -> c_int {
+    unsafe {
+        let tex = mlua::ffi::lua_touserdata(L, 1) as *mut naevc::glTexture;
+        naevc::lua_pushtex(L as *mut naevc::lua_State, tex);
+    }
+    1
+}
+
+/// @brief Lua bindings to render into textures that can be reused.
+///
+/// @code
+/// overlay = rt.new( 256, 256 )
+/// overlay:draw( function ()
+///    gfx.renderRect( 0, 0, 128, 128, colour.new("Red") )
+/// end )
+/// t = overlay:texture()
+/// @endcode
+///
+/// @luamod rt
+#[allow(unused_doc_comments)]
+impl UserData for LuaRenderTarget {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        /// @brief Creates a new render target cleared to transparent.
+        ///
+        ///    @luatparam integer w Width of the render target.
+        ///    @luatparam integer h Height of the render target.
+        ///    @luatreturn RenderTarget The new render target.
+        /// @luafunc new
+        methods.add_function("new", |_, (w, h): (i64, i64)| -> mlua::Result<Self> {
+            if w <= 0 || h <= 0 {
+                return Err(mlua::Error::RuntimeError(format!(
+                    "rt.new: invalid dimensions {w}x{h}"
+                )));
+            }
+            let ctx = Context::get().map_err(mlua::Error::external)?;
+            let framebuffer = FramebufferBuilder::new(Some("Lua Render Target"))
+                .width(w as usize)
+                .height(h as usize)
+                .build(ctx)
+                .map_err(mlua::Error::external)?;
+            let gl = &ctx.gl;
+            let saved = SavedState::capture(gl);
+            unsafe {
+                framebuffer.bind_gl(gl);
+                gl.clear_color(0.0, 0.0, 0.0, 0.0);
+                gl.clear(glow::COLOR_BUFFER_BIT);
+            }
+            saved.restore(gl);
+            Ok(LuaRenderTarget { framebuffer })
+        });
+        /// @brief Gets the dimensions of the render target.
+        ///
+        ///    @luatparam RenderTarget rt Render target to get dimensions of.
+        ///    @luatreturn integer Width of the render target.
+        ///    @luatreturn integer Height of the render target.
+        /// @luafunc dims
+        methods.add_method("dims", |_, this, ()| {
+            Ok((this.framebuffer.w, this.framebuffer.h))
+        });
+        /// @brief Draws into the render target by running a function.
+        ///
+        /// The framebuffer, viewport and scissor state are restored afterwards, even if the
+        /// function raises an error.
+        ///
+        ///    @luatparam RenderTarget rt Render target to draw into.
+        ///    @luatparam function func Function that does the rendering.
+        /// @luafunc draw
+        methods.add_method("draw", |_, this, func: mlua::Function| {
+            let ctx = Context::get().map_err(mlua::Error::external)?;
+            let fb = &this.framebuffer;
+            let saved = SavedState::capture(&ctx.gl);
+            unsafe {
+                naevc::gl_screen.current_fbo = fb.framebuffer.0.get();
+                ctx.gl.disable(glow::SCISSOR_TEST);
+                ctx.gl.viewport(0, 0, fb.w as i32, fb.h as i32);
+                fb.bind(ctx);
+                naevc::render_needsReset();
+            }
+            let ret: mlua::Result<()> = func.call(());
+            saved.restore(&ctx.gl);
+            ret
+        });
+        /// @brief Gets a drawable texture with the contents of the render target.
+        ///
+        ///    @luatparam RenderTarget rt Render target to get texture of.
+        ///    @luatreturn Tex Texture of the render target.
+        /// @luafunc texture
+        methods.add_method("texture", |lua, this, ()| -> mlua::Result<Value> {
+            let tex = match &this.framebuffer.texture {
+                Some(tex) => tex.try_clone().map_err(mlua::Error::external)?,
+                None => {
+                    return Err(mlua::Error::RuntimeError(String::from(
+                        "rt:texture: render target has no texture",
+                    )));
+                }
+            };
+            let push: mlua::Function = lua.named_registry_value("push_tex")?;
+            push.call(mlua::LightUserData(tex.into_ptr() as *mut c_void))
+        });
+    }
+}
+
+pub fn open_rendertarget(lua: &mlua::Lua, env: &LuaEnv) -> anyhow::Result<()> {
+    let proxy = lua.create_proxy::<LuaRenderTarget>()?;
+    env.set("rt", &proxy)?;
+    let naev: mlua::Table = env.get("naev")?;
+    naev.set("rt", proxy)?;
+
+    if let Value::Nil = lua.named_registry_value("push_tex")? {
+        let push = unsafe { lua.create_c_function(push_tex)? };
+        lua.set_named_registry_value("push_tex", push)?;
+    }
+
+    Ok(())
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nlua_loadRenderTarget(env: *mut LuaEnv) -> c_int {
+    ffi_guard(-1, || {
+        let lua = NLUA.lock().unwrap();
+        let env = unsafe { &*env };
+        match open_rendertarget(&lua.lua, env) {
+            Err(e) => {
+                warn_err!(e);
+                -1
+            }
+            _ => 0,
+        }
+    })
+}
@@ -0,0 +1,391 @@
+use glow::HasContext;
+use mlua::{UserData, UserDataMethods, Value};
+use std::num::NonZero;
+use std::os::raw::c_int;
+use std::sync::Arc;
+
+use crate::buffer::{
+    Buffer, BufferBuilder, BufferTarget, BufferUsage, VertexArray, VertexArrayBuffer,
+    VertexArrayBuilder,
+};
+use crate::context::Context;
+use crate::ffi::ffi_guard;
+use crate::nlua::{LuaEnv, NLUA};
+use crate::warn_err;
+
+/// Metatable name used by the C shader bindings.
+const SHADER_METATABLE: &str = "shader";
+
+fn lua_error<T>(msg: String) -> mlua::Result<T> {
+    Err(mlua::Error::RuntimeError(msg))
+}
+
+/// Vertex buffer created from Lua. Deletion goes through the message queue when the userdata is
+/// collected.
+#[derive(Clone)]
+pub struct LuaVertexBuffer {
+    buffer: Arc<Buffer>,
+    len: usize, // in floats
+}
+
+/// @brief Lua bindings to create vertex buffers for custom rendering.
+///
+/// @code
+/// vbo = vertexbuffer.new( { 0,0, 1,0, 0,1, 1,1 } )
+/// @endcode
+///
+/// @luamod vertexbuffer
+#[allow(unused_doc_comments)]
+impl UserData for LuaVertexBuffer {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        /// @brief Creates a new static vertex buffer from a table of floats.
+        ///
+        ///    @luatparam table data Sequence of numbers to upload.
+        ///    @luatreturn VertexBuffer The new vertex buffer.
+        /// @luafunc new
+        methods.add_function("new", |_, data: mlua::Table| -> mlua::Result<Self> {
+            let mut floats: Vec<f32> = Vec::with_capacity(data.raw_len());
+            for (i, v) in data.sequence_values::<Value>().enumerate() {
+                match v? {
+                    Value::Number(n) => floats.push(n as f32),
+                    Value::Integer(n) => floats.push(n as f32),
+                    v => {
+                        return lua_error(format!(
+                            "vertexbuffer.new: element {} is a '{}', expected a number",
+                            i + 1,
+                            v.type_name()
+                        ));
+                    }
+                }
+            }
+            if floats.is_empty() {
+                return lua_error(String::from("vertexbuffer.new: data table is empty"));
+            }
+            let ctx = Context::get().map_err(mlua::Error::external)?;
+            let buffer = BufferBuilder::new(Some("Lua Vertex Buffer"))
+                .target(BufferTarget::Array)
+                .usage(BufferUsage::Static)
+                .data_f32(&floats)
+                .build(&ctx.gl)
+                .map_err(mlua::Error::external)?;
+            Ok(LuaVertexBuffer {
+                buffer: Arc::new(buffer),
+                len: floats.len(),
+            })
+        });
+        /// @brief Gets the number of floats stored in the vertex buffer.
+        ///
+        ///    @luatparam VertexBuffer vbo Vertex buffer to get length of.
+        ///    @luatreturn integer Number of floats in the buffer.
+        /// @luafunc __len
+        methods.add_meta_method(mlua::MetaMethod::Len, |_, this, ()| Ok(this.len));
+    }
+}
+
+/// Vertex array created from Lua. Keeps the buffers it references alive.
+pub struct LuaVertexArray {
+    vertex_array: VertexArray,
+    _buffers: Vec<Arc<Buffer>>,
+    /// Number of vertices the per-vertex attributes can provide.
+    max_vertices: usize,
+    /// Number of instances the per-instance attributes can provide.
+    max_instances: usize,
+}
+
+/// Gets the number of elements an attribute can read from a buffer of `len` floats.
+/// [`parse_attribute`] already checked that there is room for at least one.
+fn attribute_capacity(len: usize, size: i32, stride: i32, offset: i32) -> usize {
+    let elem = size as usize * std::mem::size_of::<f32>();
+    let stride = match stride {
+        0 => elem,
+        s => s as usize,
+    };
+    (len * std::mem::size_of::<f32>() - offset as usize - elem) / stride + 1
+}
+
+fn parse_attribute(idx: usize, attr: Value) -> mlua::Result<(LuaVertexBuffer, i32, i32, i32, u32)> {
+    let t = match attr {
+        Value::Table(t) => t,
+        v => {
+            return lua_error(format!(
+                "vertexarray.new: attribute {idx} is a '{}', expected a table",
+                v.type_name()
+            ));
+        }
+    };
+    let buffer: Value = t.get("buffer")?;
+    let buffer = match buffer {
+        Value::UserData(ud) => match ud.borrow::<LuaVertexBuffer>() {
+            Ok(b) => b.clone(),
+            Err(_) => {
+                return lua_error(format!(
+                    "vertexarray.new: attribute {idx} 'buffer' is not a vertexbuffer"
+                ));
+            }
+        },
+        _ => {
+            return lua_error(format!(
+                "vertexarray.new: attribute {idx} is missing a 'buffer'"
+            ));
+        }
+    };
+    let get_int = |field: &str, default: Option<i64>| -> mlua::Result<i64> {
+        let v: Value = t.get(field)?;
+        match v {
+            Value::Integer(n) => Ok(n),
+            Value::Number(n) if n.fract() == 0.0 => Ok(n as i64),
+            Value::Nil => match default {
+                Some(d) => Ok(d),
+                None => lua_error(format!(
+                    "vertexarray.new: attribute {idx} is missing '{field}'"
+                )),
+            },
+            v => lua_error(format!(
+                "vertexarray.new: attribute {idx} '{field}' is a '{}', expected an integer",
+                v.type_name()
+            )),
+        }
+    };
+    let size = get_int("size", None)?;
+    if !(1..=4).contains(&size) {
+        return lua_error(format!(
+            "vertexarray.new: attribute {idx} 'size' must be between 1 and 4, got {size}"
+        ));
+    }
+    let stride = get_int("stride", Some(0))?;
+    let offset = get_int("offset", Some(0))?;
+    let divisor = get_int("divisor", Some(0))?;
+    if stride < 0 || offset < 0 || divisor < 0 {
+        return lua_error(format!(
+            "vertexarray.new: attribute {idx} has negative 'stride', 'offset' or 'divisor'"
+        ));
+    }
+    let bytes = (buffer.len * std::mem::size_of::<f32>()) as i64;
+    if offset + size * std::mem::size_of::<f32>() as i64 > bytes {
+        return lua_error(format!(
+            "vertexarray.new: attribute {idx} 'offset' {offset} is out of the buffer range ({bytes} bytes)"
+        ));
+    }
+    Ok((
+        buffer,
+        size as i32,
+        stride as i32,
+        offset as i32,
+        divisor as u32,
+    ))
+}
+
+fn parse_mode(mode: Option<String>) -> mlua::Result<u32> {
+    Ok(match mode.as_deref() {
+        None | Some("triangles") => glow::TRIANGLES,
+        Some("triangle_strip") => glow::TRIANGLE_STRIP,
+        Some("triangle_fan") => glow::TRIANGLE_FAN,
+        Some("lines") => glow::LINES,
+        Some("line_strip") => glow::LINE_STRIP,
+        Some("line_loop") => glow::LINE_LOOP,
+        Some("points") => glow::POINTS,
+        Some(m) => {
+            return lua_error(format!("vertexarray.draw: unknown primitive mode '{m}'"));
+        }
+    })
+}
+
+/// Gets the program of a shader created by the C shader bindings, checking the metatable.
+fn shader_program(lua: &mlua::Lua, shader: &Value) -> mlua::Result<glow::NativeProgram> {
+    let getmetatable: mlua::Function = lua.globals().get("getmetatable")?;
+    let mt: Value = getmetatable.call(shader.clone())?;
+    let shader_mt: Value = lua.named_registry_value(SHADER_METATABLE)?;
+    if !matches!(shader, Value::UserData(_))
+        || matches!(mt, Value::Nil)
+        || mt.to_pointer() != shader_mt.to_pointer()
+    {
+        return lua_error(format!(
+            "vertexarray.draw: expected a shader, got a '{}'",
+            shader.type_name()
+        ));
+    }
+    let ls = shader.to_pointer() as *const naevc::LuaShader_t;
+    match NonZero::new(unsafe { (*ls).program }) {
+        Some(program) => Ok(glow::NativeProgram(program)),
+        None => lua_error(String::from("vertexarray.draw: shader has no program")),
+    }
+}
+
+/// @brief Lua bindings to describe vertex attributes and draw them with custom shaders.
+///
+/// Attribute n of the description is bound to shader location n-1, so shaders should use
+/// explicit `layout(location=...)` qualifiers.
+///
+/// @code
+/// vbo = vertexbuffer.new( { 0,0, 1,0, 0,1, 1,1 } )
+/// vao = vertexarray.new( { { buffer=vbo, size=2 } } )
+/// vao:draw( myshader, "triangle_strip", 0, 4 )
+/// @endcode
+///
+/// @luamod vertexarray
+#[allow(unused_doc_comments)]
+impl UserData for LuaVertexArray {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        /// @brief Creates a new vertex array from a list of attribute descriptions.
+        ///
+        /// Each attribute is a table with the fields `buffer` (VertexBuffer), `size`
+        /// (number of floats, 1 to 4), and the optional `stride`, `offset` (both in bytes)
+        /// and `divisor` (per-instance advance rate).
+        ///
+        ///    @luatparam table attributes List of attribute descriptions.
+        ///    @luatreturn VertexArray The new vertex array.
+        /// @luafunc new
+        methods.add_function("new", |_, attrs: mlua::Table| -> mlua::Result<Self> {
+            let mut parsed = Vec::new();
+            for (i, attr) in attrs.sequence_values::<Value>().enumerate() {
+                parsed.push(parse_attribute(i + 1, attr?)?);
+            }
+            if parsed.is_empty() {
+                return lua_error(String::from(
+                    "vertexarray.new: no attributes were described",
+                ));
+            }
+            let buffers: Vec<VertexArrayBuffer> = parsed
+                .iter()
+                .map(|(b, size, stride, offset, divisor)| VertexArrayBuffer {
+                    buffer: &b.buffer,
+                    size: *size,
+                    stride: *stride,
+                    offset: *offset,
+                    divisor: *divisor,
+                    format: None,
+                })
+                .collect();
+            let ctx = Context::get().map_err(mlua::Error::external)?;
+            let vertex_array = VertexArrayBuilder::new(Some("Lua Vertex Array"))
+                .buffers(&buffers)
+                .build(ctx)
+                .map_err(mlua::Error::external)?;
+            let mut max_vertices = usize::MAX;
+            let mut max_instances = usize::MAX;
+            for (b, size, stride, offset, divisor) in &parsed {
+                let n = attribute_capacity(b.len, *size, *stride, *offset);
+                match *divisor {
+                    0 => max_vertices = max_vertices.min(n),
+                    d => max_instances = max_instances.min(n.saturating_mul(d as usize)),
+                }
+            }
+            let buffers = parsed.iter().map(|(b, ..)| b.buffer.clone()).collect();
+            Ok(LuaVertexArray {
+                vertex_array,
+                _buffers: buffers,
+                max_vertices,
+                max_instances,
+            })
+        });
+        /// @brief Draws the vertex array with a shader.
+        ///
+        /// The shader is used as is, so any uniforms have to be sent beforehand.
+        ///
+        ///    @luatparam VertexArray vao Vertex array to draw.
+        ///    @luatparam Shader shader Shader to draw with.
+        ///    @luatparam[opt="triangles"] string mode Primitive mode, one of "triangles",
+        /// "triangle_strip", "triangle_fan", "lines", "line_strip", "line_loop" or "points".
+        ///    @luatparam[opt=0] integer first First vertex to draw.
+        ///    @luatparam integer count Number of vertices to draw.
+        ///    @luatparam[opt=0] integer instances Number of instances to draw, 0 disables
+        /// instancing.
+        /// @luafunc draw
+        methods.add_method(
+            "draw",
+            |lua,
+             this,
+             (shader, mode, first, count, instances): (
+                Value,
+                Option<String>,
+                Option<i32>,
+                i32,
+                Option<i32>,
+            )| {
+                let program = shader_program(lua, &shader)?;
+                let mode = parse_mode(mode)?;
+                let first = first.unwrap_or(0);
+                let instances = instances.unwrap_or(0);
+                if first < 0 || count < 0 || instances < 0 {
+                    return lua_error(String::from(
+                        "vertexarray.draw: 'first', 'count' and 'instances' must not be negative",
+                    ));
+                }
+                let last = first as usize + count as usize;
+                if last > this.max_vertices {
+                    return lua_error(format!(
+                        "vertexarray.draw: vertices {first} to {last} are out of the buffer range ({} vertices)",
+                        this.max_vertices
+                    ));
+                }
+                if instances as usize > this.max_instances {
+                    return lua_error(format!(
+                        "vertexarray.draw: {instances} instances are out of the buffer range ({} instances)",
+                        this.max_instances
+                    ));
+                }
+                let ctx = Context::get().map_err(mlua::Error::external)?;
+                let gl = &ctx.gl;
+                unsafe {
+                    gl.use_program(Some(program));
+                    this.vertex_array.bind(ctx);
+                    if instances > 0 {
+                        gl.draw_arrays_instanced(mode, first, count, instances);
+                    } else {
+                        gl.draw_arrays(mode, first, count);
+                    }
+                    VertexArray::unbind(ctx);
+                    gl.use_program(None);
+                }
+                Ok(())
+            },
+        );
+    }
+}
+
+pub fn open_vertexbuffer(lua: &mlua::Lua, env: &LuaEnv) -> anyhow::Result<()> {
+    let naev: mlua::Table = env.get("naev")?;
+
+    let proxy = lua.create_proxy::<LuaVertexBuffer>()?;
+    env.set("vertexbuffer", &proxy)?;
+    naev.set("vertexbuffer", proxy)?;
+
+    let proxy = lua.create_proxy::<LuaVertexArray>()?;
+    env.set("vertexarray", &proxy)?;
+    naev.set("vertexarray", proxy)?;
+
+    Ok(())
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nlua_loadVertexBuffer(env: *mut LuaEnv) -> c_int {
+    ffi_guard(-1, || {
+        let lua = NLUA.lock().unwrap();
+        let env = unsafe { &*env };
+        match open_vertexbuffer(&lua.lua, env) {
+            Err(e) => {
+                warn_err!(e);
+                -1
+            }
+            _ => 0,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity() {
+        // 8 floats of vec2
+        assert_eq!(attribute_capacity(8, 2, 0, 0), 4);
+        assert_eq!(attribute_capacity(8, 2, 0, 8), 3);
+        // Interleaved vec2 position and vec2 texture coordinates
+        assert_eq!(attribute_capacity(8, 2, 16, 0), 2);
+        assert_eq!(attribute_capacity(8, 2, 16, 8), 2);
+        // Last element doesn't need the full stride
+        assert_eq!(attribute_capacity(6, 2, 16, 0), 2);
+        assert_eq!(attribute_capacity(4, 4, 0, 0), 1);
+    }
+}
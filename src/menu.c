@@ -193,6 +193,7 @@ void menu_main( void )
    player_resetSpeed();
    render_postprocessCleanup();
    space_gfxUnload( cur_system ); /* Should stop wormhole sound and such. */
+   tex_preloadSystemClear();
 
    /* Play load music. */
    music_choose( "load" );
@@ -5,14 +5,18 @@ use glow::HasContext;
 use gltf::Gltf;
 use nalgebra::{Matrix3, Matrix4, Point3, Vector3, Vector4};
 use std::ffi::{CStr, CString};
-use std::os::raw::{c_char, c_double, c_int, c_uint};
+use std::os::raw::{c_char, c_double, c_int, c_uint, c_ulong};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::buffer::{
     Buffer, BufferBuilder, BufferTarget, BufferUsage, VertexArray, VertexArrayBuffer,
     VertexArrayBuilder,
 };
+use crate::context;
 use crate::context::{look_at4, ortho4, Context, ContextWrapper};
+use crate::ffi::ffi_guard;
+use crate::log::warn_err;
 use crate::ndata;
 use crate::render::Uniform;
 use crate::shader::{Shader, ShaderBuilder};
@@ -202,8 +206,60 @@ impl Default for LightingUniform {
     }
 }
 
+/// Fragments shaded by the mesh pass since the last [`take_shaded_samples`], to measure how much
+/// overdraw the depth pre-pass saves.
+static SHADED_SAMPLES: AtomicU64 = AtomicU64::new(0);
+
+/// Gets and resets the number of fragments shaded by the mesh pass. Results come in a few frames
+/// late, as they are read without waiting on the GPU.
+pub fn take_shaded_samples() -> u64 {
+    SHADED_SAMPLES.swap(0, Ordering::Relaxed)
+}
+
+/// `GL_SAMPLES_PASSED` query around the mesh pass of a scene.
+struct SamplesQuery {
+    query: glow::Query,
+    pending: bool,
+}
+impl SamplesQuery {
+    fn new(ctx: &Context) -> Result<Self> {
+        let query = unsafe { ctx.gl.create_query() }.map_err(|e| anyhow::anyhow!(e))?;
+        Ok(SamplesQuery {
+            query,
+            pending: false,
+        })
+    }
+
+    /// Adds the result to [`SHADED_SAMPLES`] if it is available. Returns whether the query can
+    /// be used again.
+    fn poll(&mut self, ctx: &Context) -> bool {
+        let gl = &ctx.gl;
+        if self.pending {
+            unsafe {
+                if gl.get_query_parameter_u32(self.query, glow::QUERY_RESULT_AVAILABLE) == 0 {
+                    return false;
+                }
+                let n = gl.get_query_parameter_u32(self.query, glow::QUERY_RESULT);
+                SHADED_SAMPLES.fetch_add(n as u64, Ordering::Relaxed);
+            }
+            self.pending = false;
+        }
+        true
+    }
+}
+impl Drop for SamplesQuery {
+    fn drop(&mut self) {
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteQuery(self.query));
+    }
+}
+
 pub struct ModelShader {
     shader: Shader,
+    /// Same vertex shader without shading, for the depth pre-pass.
+    depth: Shader,
     lighting_buffer: Buffer,
     use_ao: bool,
 }
@@ -236,6 +292,13 @@ impl ModelShader {
             shaderbuilder = shaderbuilder.sampler("occlusion_tex", 4)
         }
         let shader = shaderbuilder.build(gl)?;
+        // The vertex shader has an invariant position, so that the depth matches the shaded pass
+        let depth = ShaderBuilder::new(Some("PBR Depth Shader"))
+            .vert_file("material_pbr.vert")
+            .frag_file("shadow.frag")
+            .uniform_buffer("Lighting", Self::U_LIGHTING)
+            .uniform_buffer("Primitive", Self::U_PRIMITIVE)
+            .build(gl)?;
 
         let lighting_buffer = BufferBuilder::new(Some("PBR Lighting Buffer"))
             .target(BufferTarget::Uniform)
@@ -245,6 +308,7 @@ impl ModelShader {
 
         Ok(ModelShader {
             shader,
+            depth,
             lighting_buffer,
             use_ao: !low_memory,
         })
@@ -284,6 +348,83 @@ impl ShadowShader {
     }
 }
 
+/// Sampling tweaks for a single map of a material.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MapSampling {
+    /// Mipmap LOD bias, negative sharpens and positive softens.
+    pub lod_bias: f32,
+    /// Anisotropy overriding the configuration one.
+    pub anisotropy: Option<f32>,
+}
+impl MapSampling {
+    fn from_json(value: &gltf::json::Value) -> Self {
+        MapSampling {
+            lod_bias: value
+                .get("lod_bias")
+                .and_then(|v| v.as_f64())
+                .unwrap_or(0.0) as f32,
+            anisotropy: value
+                .get("anisotropy")
+                .and_then(|v| v.as_f64())
+                .map(|v| v as f32),
+        }
+    }
+
+    /// Gets the texture to use for the map, cloning it with its own sampler if the sampling
+    /// differs from the default, as textures are shared between materials.
+    fn apply(&self, ctx: &ContextWrapper, tex: Rc<Texture>) -> Result<Rc<Texture>> {
+        if *self == MapSampling::default() {
+            return Ok(tex);
+        }
+        let lctx = ctx.lock();
+        let gl = &lctx.gl;
+        let tex = tex.try_clone_gl(gl)?;
+        if self.lod_bias != 0.0 {
+            tex.set_lod_bias(gl, self.lod_bias);
+        }
+        if let Some(anisotropy) = self.anisotropy {
+            tex.set_anisotropy(gl, anisotropy);
+        }
+        Ok(Rc::new(tex))
+    }
+}
+
+/// Per-map sampling of a material. Set from the glTF material extras, for example
+/// `"NAEV_sampling": { "normal": { "lod_bias": -0.5 }, "diffuse": { "anisotropy": 16 } }`.
+/// Maps not specified keep the texture's sampling.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaterialSampling {
+    pub diffuse: MapSampling,
+    pub metallic: MapSampling,
+    pub emissive: MapSampling,
+    pub normal: MapSampling,
+    pub occlusion: MapSampling,
+}
+impl MaterialSampling {
+    fn from_gltf(mat: &gltf::Material) -> Result<Self> {
+        let mut sampling = MaterialSampling::default();
+        let Some(extras) = mat.extras() else {
+            return Ok(sampling);
+        };
+        let json: gltf::json::Value = gltf::json::deserialize::from_str(extras.get())?;
+        let Some(value) = json.get("NAEV_sampling") else {
+            return Ok(sampling);
+        };
+        for (name, map) in [
+            ("diffuse", &mut sampling.diffuse),
+            ("metallic", &mut sampling.metallic),
+            ("emissive", &mut sampling.emissive),
+            ("normal", &mut sampling.normal),
+            ("occlusion", &mut sampling.occlusion),
+        ] {
+            if let Some(v) = value.get(name) {
+                *map = MapSampling::from_json(v);
+            }
+        }
+        Ok(sampling)
+    }
+}
+
 pub struct Material {
     uniform_buffer: Buffer,
     diffuse: Rc<Texture>,
@@ -365,6 +506,20 @@ impl Material {
             }
         };
 
+        let sampling = MaterialSampling::from_gltf(mat).unwrap_or_else(|e| {
+            warn!(
+                "invalid sampling for material '{}': {}",
+                mat.name().unwrap_or("?"),
+                e
+            );
+            MaterialSampling::default()
+        });
+        let diffuse = sampling.diffuse.apply(ctx, diffuse)?;
+        let metallic = sampling.metallic.apply(ctx, metallic)?;
+        let emissive = sampling.emissive.apply(ctx, emissive)?;
+        let normalmap = sampling.normal.apply(ctx, normalmap)?;
+        let ambientocclusion = sampling.occlusion.apply(ctx, ambientocclusion)?;
+
         let uniform_buffer = {
             let lctx = ctx.lock();
             let gl = &lctx.gl;
@@ -405,6 +560,19 @@ pub struct Primitive {
 }
 
 impl Primitive {
+    /// Updates and binds the primitive uniform for the model transform.
+    fn write_uniform(&self, ctx: &Context, transform: &Matrix4<f32>) -> Result<()> {
+        let mut data = self.uniform_data;
+        data.view = *transform;
+        data.normal = transform
+            .fixed_resize::<3, 3>(0.0)
+            .try_inverse()
+            .unwrap()
+            .transpose();
+        self.uniform_buffer
+            .bind_write_base(ctx, &data.buffer()?, ModelShader::U_PRIMITIVE)
+    }
+
     pub fn from_gltf(
         ctx: &ContextWrapper,
         prim: &gltf::Primitive,
@@ -478,6 +646,7 @@ impl Primitive {
                     stride: vertex_size,
                     offset: std::mem::offset_of!(Vertex, pos) as i32,
                     divisor: 0,
+                    format: None,
                 },
                 VertexArrayBuffer {
                     buffer: &vertices,
@@ -485,6 +654,7 @@ impl Primitive {
                     stride: vertex_size,
                     offset: std::mem::offset_of!(Vertex, nor) as i32,
                     divisor: 0,
+                    format: None,
                 },
                 VertexArrayBuffer {
                     buffer: &vertices,
@@ -492,6 +662,7 @@ impl Primitive {
                     stride: vertex_size,
                     offset: std::mem::offset_of!(Vertex, tex0) as i32,
                     divisor: 0,
+                    format: None,
                 },
                 VertexArrayBuffer {
                     buffer: &vertices,
@@ -499,6 +670,7 @@ impl Primitive {
                     stride: vertex_size,
                     offset: std::mem::offset_of!(Vertex, tex1) as i32,
                     divisor: 0,
+                    format: None,
                 },
             ])
             .indices(Some(&indices))
@@ -552,17 +724,7 @@ impl Mesh {
     ) -> Result<()> {
         let gl = &ctx.gl;
         for p in &self.primitives {
-            // Update the primitive uniform
-            let new_transform = *transform;
-            let mut data = p.uniform_data;
-            data.view = new_transform;
-            data.normal = new_transform
-                .fixed_resize::<3, 3>(0.0)
-                .try_inverse()
-                .unwrap()
-                .transpose();
-            p.uniform_buffer
-                .bind_write_base(ctx, &data.buffer()?, ModelShader::U_PRIMITIVE)?;
+            p.write_uniform(ctx, transform)?;
 
             let m = &p.material;
 
@@ -600,6 +762,31 @@ impl Mesh {
                 }
             }
         }
+        ctx.check_errors();
+        Ok(())
+    }
+
+    /// Renders the depth of the opaque primitives with the depth shader of [`ModelShader`].
+    fn render_depth(&self, ctx: &Context, transform: &Matrix4<f32>) -> Result<()> {
+        let gl = &ctx.gl;
+        for p in &self.primitives {
+            // Transparent primitives don't write depth in the shaded pass either
+            if p.material.blend {
+                continue;
+            }
+            p.write_uniform(ctx, transform)?;
+            p.vao.bind(ctx);
+            unsafe {
+                if p.material.double_sided {
+                    gl.disable(glow::CULL_FACE);
+                }
+                gl.draw_elements(p.topology, p.num_indices, p.element_type, 0);
+                if p.material.double_sided {
+                    gl.enable(glow::CULL_FACE);
+                }
+            }
+        }
+        ctx.check_errors();
         Ok(())
     }
 
@@ -641,6 +828,7 @@ impl Mesh {
                 }
             }
         }
+        ctx.check_errors();
         Ok(())
     }
 }
@@ -807,6 +995,23 @@ impl Node {
         Ok(())
     }
 
+    /// Renders the depth of the opaque meshes only, for the depth pre-pass.
+    fn render_depth(&mut self, ctx: &Context, transform: &Matrix4<f32>) -> Result<()> {
+        let new_transform = transform * self.transform;
+        let det = new_transform.fixed_resize::<3, 3>(0.0).determinant();
+        unsafe {
+            ctx.gl
+                .front_face(if det < 0.0 { glow::CW } else { glow::CCW });
+        }
+        if let Some(mesh) = &self.mesh {
+            mesh.render_depth(ctx, &new_transform)?;
+        }
+        for child in &mut self.children {
+            child.render_depth(ctx, &new_transform)?;
+        }
+        Ok(())
+    }
+
     fn render_shadow(
         &mut self,
         ctx: &Context,
@@ -829,6 +1034,7 @@ pub struct Scene {
     name: Option<String>,
     nodes: Vec<Node>,
     radius: f32,
+    samples: Option<SamplesQuery>,
 }
 
 impl Scene {
@@ -852,6 +1058,7 @@ impl Scene {
             nodes,
             name,
             radius,
+            samples: None,
         })
     }
 
@@ -876,7 +1083,7 @@ impl Scene {
                 glow::ONE_MINUS_SRC_ALPHA,
             );
             gl.enable(glow::DEPTH_TEST);
-            gl.depth_func(glow::LESS);
+            gl.depth_func(ctx.depth_func());
         }
 
         // Shadow pass
@@ -961,8 +1168,51 @@ impl Scene {
             gl.enable(glow::CULL_FACE);
         }
         target.bind(ctx);
-        for node in &mut self.nodes {
-            node.render(ctx, shader, transform)?;
+        let prepass = ctx.depth_prepass();
+        if prepass {
+            shader.depth.use_program(gl);
+            unsafe {
+                gl.color_mask(false, false, false, false);
+            }
+            for node in &mut self.nodes {
+                node.render_depth(ctx, transform)?;
+            }
+            unsafe {
+                gl.color_mask(true, true, true, true);
+                // Only the surfaces that are visible match the depth of the pre-pass
+                gl.depth_func(ctx.depth_func_or_equal());
+            }
+            shader.shader.use_program(gl);
+        }
+
+        // Count the shaded fragments, skipping frames where the last result isn't back yet
+        if self.samples.is_none() {
+            self.samples = SamplesQuery::new(ctx).ok();
+        }
+        let measure = match &mut self.samples {
+            Some(samples) if samples.poll(ctx) => {
+                unsafe {
+                    gl.begin_query(glow::SAMPLES_PASSED, samples.query);
+                }
+                samples.pending = true;
+                true
+            }
+            _ => false,
+        };
+        let res = self
+            .nodes
+            .iter_mut()
+            .try_for_each(|node| node.render(ctx, shader, transform));
+        if measure {
+            unsafe {
+                gl.end_query(glow::SAMPLES_PASSED);
+            }
+        }
+        res?;
+        if prepass {
+            unsafe {
+                gl.depth_func(ctx.depth_func());
+            }
         }
 
         // Clean up
@@ -979,8 +1229,8 @@ impl Scene {
             gl.use_program(None);
             gl.disable(glow::DEPTH_TEST);
             gl.disable(glow::CULL_FACE);
-            gl.viewport(0, 0, naevc::gl_screen.rw, naevc::gl_screen.rh);
             target.unbind(ctx);
+            naevc::gl_screenViewport();
         }
 
         Ok(())
@@ -1108,7 +1358,7 @@ impl Common {
                 if let Some(depth) = &fb.depth {
                     // Can't currently use FramebufferBuilder to set the following, so we do it
                     // as post-processing
-                    let sampler = depth.sampler;
+                    let sampler = depth.sampler.sampler;
                     gl.sampler_parameter_i32(
                         sampler,
                         glow::TEXTURE_WRAP_S,
@@ -1180,6 +1430,13 @@ impl Common {
 }
 static COMMON: OnceLock<Common> = OnceLock::new();
 
+/// Gets the data shared by all the models, which is set up by `gltf_init`.
+fn common() -> Result<&'static Common> {
+    COMMON
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("glTF used before being initialized"))
+}
+
 struct TextureWrap<'a> {
     texture: gltf::texture::Texture<'a>,
     srgb: bool,
@@ -1348,13 +1605,36 @@ impl Model {
     }
 }
 
+/// Gets and resets the number of fragments shaded by the 3D mesh pass, see
+/// [`take_shaded_samples`].
+#[unsafe(no_mangle)]
+pub extern "C" fn gltf_takeShadedSamples() -> c_ulong {
+    ffi_guard(0, || take_shaded_samples() as c_ulong)
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_init() -> c_int {
-    let _ = COMMON.get_or_init(|| {
-        let ctx = Context::get().unwrap().as_wrap();
-        Common::new(&ctx).unwrap()
-    });
-    0
+    ffi_guard(-1, || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx.as_wrap(),
+            Err(e) => {
+                warn_err(e);
+                return -1;
+            }
+        };
+        if COMMON.get().is_none() {
+            match Common::new(&ctx) {
+                Ok(common) => {
+                    let _ = COMMON.set(common);
+                }
+                Err(e) => {
+                    warn_err(e.context("unable to initialize glTF"));
+                    return -1;
+                }
+            }
+        }
+        0
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -1362,54 +1642,111 @@ pub extern "C" fn gltf_exit() {}
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_lightReset() {
-    let mut data = COMMON.get().unwrap().data.write().unwrap();
-    data.light_uniform = Default::default();
+    ffi_guard((), || {
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let mut data = common.data.write().unwrap();
+        data.light_uniform = Default::default();
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_lightSet(idx: c_int, light: *const naevc::Light) -> c_int {
-    let n: usize = (2 + idx).try_into().unwrap();
-    if n >= MAX_LIGHTS {
-        warn!("Trying to set more lights than MAX_LIGHTS allows!");
-        return -1;
-    }
-    let mut data = COMMON.get().unwrap().data.write().unwrap();
-    let light = unsafe { &*light };
-    data.light_uniform.nlights = data.light_uniform.nlights.max((n + 1) as i32);
-    data.light_uniform.lights[n] = light.into();
-    0
+    ffi_guard(-1, || {
+        let Ok(n) = usize::try_from(2 + idx) else {
+            warn!("Trying to set a light with invalid index '{}'!", idx);
+            return -1;
+        };
+        if n >= MAX_LIGHTS {
+            warn!("Trying to set more lights than MAX_LIGHTS allows!");
+            return -1;
+        }
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return -1;
+            }
+        };
+        let mut data = common.data.write().unwrap();
+        let light = unsafe { &*light };
+        data.light_uniform.nlights = data.light_uniform.nlights.max((n + 1) as i32);
+        data.light_uniform.lights[n] = light.into();
+        0
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_lightAmbient(r: c_double, g: c_double, b: c_double) {
-    const FACTOR: f32 = 1.0 / std::f32::consts::PI;
-    let mut data = COMMON.get().unwrap().data.write().unwrap();
-    data.light_uniform.ambient =
-        Vector3::new(r as f32 * FACTOR, g as f32 * FACTOR, b as f32 * FACTOR);
+    ffi_guard((), || {
+        const FACTOR: f32 = 1.0 / std::f32::consts::PI;
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let mut data = common.data.write().unwrap();
+        data.light_uniform.ambient =
+            Vector3::new(r as f32 * FACTOR, g as f32 * FACTOR, b as f32 * FACTOR);
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_lightAmbientGet(r: *mut c_double, g: *mut c_double, b: *mut c_double) {
-    let data = COMMON.get().unwrap().data.read().unwrap();
-    let amb = data.light_uniform.ambient;
-    const FACTOR: f64 = std::f64::consts::PI;
-    unsafe {
-        *r = amb.x as f64 * FACTOR;
-        *g = amb.y as f64 * FACTOR;
-        *b = amb.z as f64 * FACTOR;
-    }
+    ffi_guard((), || {
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let data = common.data.read().unwrap();
+        let amb = data.light_uniform.ambient;
+        const FACTOR: f64 = std::f64::consts::PI;
+        unsafe {
+            *r = amb.x as f64 * FACTOR;
+            *g = amb.y as f64 * FACTOR;
+            *b = amb.z as f64 * FACTOR;
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_lightIntensity(strength: c_double) {
-    let mut data = COMMON.get().unwrap().data.write().unwrap();
-    data.light_uniform.intensity *= strength as f32;
+    ffi_guard((), || {
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let mut data = common.data.write().unwrap();
+        data.light_uniform.intensity *= strength as f32;
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_lightIntensityGet() -> c_double {
-    let data = COMMON.get().unwrap().data.read().unwrap();
-    data.light_uniform.intensity as f64
+    ffi_guard(0.0, || {
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return 0.0;
+            }
+        };
+        let data = common.data.read().unwrap();
+        data.light_uniform.intensity as f64
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -1417,53 +1754,83 @@ pub extern "C" fn gltf_lightTransform(
     _lighting: *mut naevc::Lighting,
     transform: *const Matrix4<f32>,
 ) {
-    let transform = unsafe { &*transform };
-    let mut data = COMMON.get().unwrap().data.write().unwrap();
-    let lighting = &mut data.light_uniform;
-    for i in 0..lighting.nlights as usize {
-        let l = &mut lighting.lights[i];
-        if l.sun != 0 {
-            l.position = transform.transform_vector(&l.position);
-        } else {
-            l.position = Vector3::from_homogeneous(
-                transform.transform_point(&Point3::from(l.position)).into(),
-            )
-            .unwrap();
+    ffi_guard((), || {
+        let transform = unsafe { &*transform };
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let mut data = common.data.write().unwrap();
+        let lighting = &mut data.light_uniform;
+        for i in 0..lighting.nlights as usize {
+            let l = &mut lighting.lights[i];
+            if l.sun != 0 {
+                l.position = transform.transform_vector(&l.position);
+            } else {
+                l.position = Vector3::from_homogeneous(
+                    transform.transform_point(&Point3::from(l.position)).into(),
+                )
+                .unwrap();
+            }
         }
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_sceneBody(obj: *mut Model) -> c_int {
-    let model = unsafe { &*obj };
-    model.body as c_int
+    ffi_guard(0, || {
+        let model = unsafe { &*obj };
+        model.body as c_int
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_sceneEngine(obj: *mut Model) -> c_int {
-    let model = unsafe { &*obj };
-    model.engine as c_int
+    ffi_guard(0, || {
+        let model = unsafe { &*obj };
+        model.engine as c_int
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_numAnimations(_obj: *mut Model) -> c_uint {
-    // TODO
-    0
+    ffi_guard(0, || {
+        // TODO
+        0
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_loadFromFile(cpath: *const c_char) -> *const Model {
-    let path = unsafe { CStr::from_ptr(cpath) };
-    let ctx = Context::get().unwrap().as_wrap();
-    let model = Model::from_path(&ctx, path.to_str().unwrap()).unwrap();
-    model.into_ptr()
+    ffi_guard(std::ptr::null(), || {
+        let path = unsafe { CStr::from_ptr(cpath) };
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx.as_wrap(),
+            Err(e) => {
+                warn_err(e);
+                return std::ptr::null();
+            }
+        };
+        match Model::from_path(&ctx, &path.to_string_lossy()) {
+            Ok(model) => model.into_ptr(),
+            Err(e) => {
+                warn_err(e.context(format!("unable to load glTF '{}'", path.to_string_lossy())));
+                std::ptr::null()
+            }
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_free(model: *mut Model) {
-    if !model.is_null() {
-        let _ = unsafe { Box::from_raw(model) }; // should drop
-    }
+    ffi_guard((), || {
+        if !model.is_null() {
+            let _ = unsafe { Box::from_raw(model) }; // should drop
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -1474,21 +1841,36 @@ pub extern "C" fn gltf_render(
     _time: f32,
     size: f64,
 ) {
-    let model = unsafe { &mut *model };
-    let transform = match ctransform.is_null() {
-        true => &Matrix4::identity(),
-        false => unsafe { &*ctransform },
-    };
-    let ctx = Context::get().unwrap(); /* Lock early. */
-    let data = COMMON.get().unwrap().data.read().unwrap();
-    let lighting = &data.light_uniform;
-    let _ = model.render_scene(
-        ctx,
-        &FramebufferTarget::from_gl(fb, size as usize, size as usize),
-        0,
-        lighting,
-        transform,
-    );
+    ffi_guard((), || {
+        let model = unsafe { &mut *model };
+        let transform = match ctransform.is_null() {
+            true => &Matrix4::identity(),
+            false => unsafe { &*ctransform },
+        };
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        }; /* Lock early. */
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let data = common.data.read().unwrap();
+        let lighting = &data.light_uniform;
+        let _ = model.render_scene(
+            ctx,
+            &FramebufferTarget::from_gl(fb, size as usize, size as usize),
+            0,
+            lighting,
+            transform,
+        );
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -1501,66 +1883,102 @@ pub extern "C" fn gltf_renderScene(
     size: f64,
     clighting: *const naevc::Lighting,
 ) {
-    // TODO animations
-    let model = unsafe { &mut *model };
-    let transform = match ctransform.is_null() {
-        true => &Matrix4::identity(),
-        false => unsafe { &*ctransform },
-    };
-    let ctx = Context::get().unwrap(); /* Lock early. */
-    let data = COMMON.get().unwrap().data.read().unwrap();
-    let lighting = match clighting.is_null() {
-        true => &data.light_uniform,
-        false => &(unsafe { &*clighting }).into(),
-    };
-    let _ = model.render_scene(
-        ctx,
-        &FramebufferTarget::from_gl(fb, size as usize, size as usize),
-        scene as usize,
-        lighting,
-        transform,
-    );
+    ffi_guard((), || {
+        // TODO animations
+        let model = unsafe { &mut *model };
+        let transform = match ctransform.is_null() {
+            true => &Matrix4::identity(),
+            false => unsafe { &*ctransform },
+        };
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        }; /* Lock early. */
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let data = common.data.read().unwrap();
+        let lighting = match clighting.is_null() {
+            true => &data.light_uniform,
+            false => &(unsafe { &*clighting }).into(),
+        };
+        let _ = model.render_scene(
+            ctx,
+            &FramebufferTarget::from_gl(fb, size as usize, size as usize),
+            scene as usize,
+            lighting,
+            transform,
+        );
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_numLights() -> c_int {
-    let data = COMMON.get().unwrap().data.read().unwrap();
-    data.light_uniform.nlights as c_int
+    ffi_guard(0, || {
+        let common = match common() {
+            Ok(common) => common,
+            Err(e) => {
+                warn_err(e);
+                return 0;
+            }
+        };
+        let data = common.data.read().unwrap();
+        data.light_uniform.nlights as c_int
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_numTrails(obj: *const Model) -> c_uint {
-    let model = unsafe { &*obj };
-    model.trails.len() as c_uint
+    ffi_guard(0, || {
+        let model = unsafe { &*obj };
+        model.trails.len() as c_uint
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_trailName(obj: *const Model, id: c_int) -> *const c_char {
-    let model = unsafe { &*obj };
-    let g = &model.trails[id as usize].cstr;
-    g.as_ptr()
+    ffi_guard(std::ptr::null(), || {
+        let model = unsafe { &*obj };
+        let g = &model.trails[id as usize].cstr;
+        g.as_ptr()
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_trailPosition(obj: *const Model, id: c_int) -> Vector3<f64> {
-    let model = unsafe { &*obj };
-    model.trails[id as usize].position.cast::<f64>()
+    ffi_guard(Vector3::zeros(), || {
+        let model = unsafe { &*obj };
+        model.trails[id as usize].position.cast::<f64>()
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_numMounts(obj: *const Model) -> c_uint {
-    let model = unsafe { &*obj };
-    model.mounts.len() as c_uint
+    ffi_guard(0, || {
+        let model = unsafe { &*obj };
+        model.mounts.len() as c_uint
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_mountIndex(obj: *const Model, id: c_int) -> c_uint {
-    let model = unsafe { &*obj };
-    model.mounts[id as usize].id as c_uint
+    ffi_guard(0, || {
+        let model = unsafe { &*obj };
+        model.mounts[id as usize].id as c_uint
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gltf_mountPosition(obj: *const Model, id: c_int) -> Vector3<f64> {
-    let model = unsafe { &*obj };
-    model.mounts[id as usize].position.cast::<f64>()
+    ffi_guard(Vector3::zeros(), || {
+        let model = unsafe { &*obj };
+        model.mounts[id as usize].position.cast::<f64>()
+    })
 }
@@ -99,6 +99,8 @@ static double game_dt = 0.;       /**< Current game deltatick (uses dt_mod). */
 static double real_dt = 0.;       /**< Real deltatick. */
 static double fps     = 0.;       /**< FPS to finally display. */
 static double fps_cur = 0.;       /**< FPS accumulator to trigger change. */
+static double fps_samples     = 0.; /**< Shaded 3D fragments per frame. */
+static double fps_samples_cur = 0.; /**< Shaded 3D fragments accumulator. */
 static double fps_x   = 15.;      /**< FPS X position. */
 static double fps_y   = -15.;     /**< FPS Y position. */
 const double  fps_min = 1. / 10.; /**< New collisions allow larger fps_min. */
@@ -215,12 +217,21 @@ int naev_main_events( void )
             break;
          }
       } else if ( event.type == SDL_WINDOWEVENT &&
-                  event.window.event == SDL_WINDOWEVENT_RESIZED ) {
+                  ( event.window.event == SDL_WINDOWEVENT_SIZE_CHANGED ||
+                    event.window.event == SDL_WINDOWEVENT_DISPLAY_CHANGED ) ) {
+         /* SIZE_CHANGED also covers changes not done by the user, and moving
+          * to another display may change the drawable size with HiDPI. */
          naev_resize();
          continue;
       }
+      /* Live input is ignored when playing back a replay. */
+      if ( replay_filterEvent( &event ) )
+         continue;
       input_handle( &event ); /* handles all the events and player keybinds */
    }
+   /* Feed the recorded input of the upcoming frame. */
+   while ( !quit && replay_poll( &event ) )
+      input_handle( &event );
    return 0;
 }
 
@@ -230,6 +241,10 @@ int naev_main_cleanup( void )
    /* Save configuration. */
    conf_saveConfig( conf_file_path );
 
+   /* Write out any replay being recorded. */
+   if ( replay_isActive() )
+      replay_stop();
+
    /* data unloading */
    unload_all();
 
@@ -362,6 +377,9 @@ void naev_doRenderLoadscreen( void )
       lua_pop( naevL, 1 );
    }
 
+   /* Show the progress of asynchronous asset loading. */
+   loadscreen_render_assets();
+
    /* Flip buffers. HACK: Also try to catch a late-breaking resize from the WM
     * (...or a crazy user?). */
    SDL_GL_SwapWindow( gl_screen.window );
@@ -456,8 +474,9 @@ void main_loop( int nested )
       double dt =
          (double)( t - last_t ) / (double)SDL_GetPerformanceFrequency();
       last_t  = t;
-      real_dt = dt;
-      game_dt = real_dt * dt_mod; /* Apply the modifier. */
+      real_dt = capture_dt( dt ); /* Fixed when capturing frames. */
+      real_dt = replay_dt( real_dt ); /* Recorded when playing back. */
+      game_dt = ntime_scaleDt( real_dt * dt_mod ); /* Apply the modifiers. */
    }
 
    /*
@@ -490,6 +509,8 @@ void main_loop( int nested )
                      game. Avoid rendering when quitting just in case. */
       /* Clear buffer. */
       render_all( game_dt, real_dt );
+      /* Save the frame if capturing. */
+      capture_frame();
       /* Draw buffer. */
       SDL_GL_SwapWindow( gl_screen.window );
 
@@ -539,6 +560,12 @@ void naev_resize( void )
    if ( ( w == gl_screen.rw ) && ( h == gl_screen.rh ) )
       return;
 
+   /* Let the Rust side update its dimensions, with both the logical and real
+    * sizes so that both agree on the scaling. */
+   int ww, wh;
+   SDL_GetWindowSize( gl_screen.window, &ww, &wh );
+   naev_resize_rust( ww, wh, w, h );
+
    /* Resize the GL context, etc. */
    gl_resize();
 
@@ -613,25 +640,39 @@ void fps_display( double dt )
 
    fps_dt += dt;
    fps_cur += 1.;
+   fps_samples_cur += (double)gltf_takeShadedSamples();
    if ( fps_dt > 1. ) { /* recalculate every second */
-      fps    = fps_cur / fps_dt;
-      fps_dt = fps_cur = 0.;
+      fps         = fps_cur / fps_dt;
+      fps_samples = fps_samples_cur / fps_cur;
+      fps_dt = fps_cur = fps_samples_cur = 0.;
    }
 
    x = fps_x;
    y = fps_y;
    if ( conf.fps_show ) {
+      double gpu_ms = gputimer_totalMs();
       gl_print( &gl_defFontMono, x, y, &cFontWhite, "%3.2f", fps );
       y -= gl_defFontMono.h + 5.;
+      if ( gpu_ms >= 0. ) {
+         gl_print( &gl_defFontMono, x, y, &cFontWhite, _( "GPU %.2f ms" ),
+                   gpu_ms );
+         y -= gl_defFontMono.h + 5.;
+      }
+      /* Shaded 3D fragments per frame, to compare with the depth pre-pass. */
+      if ( fps_samples > 0. ) {
+         gl_print( &gl_defFontMono, x, y, &cFontWhite, _( "3D %.0f frags" ),
+                   fps_samples );
+         y -= gl_defFontMono.h + 5.;
+      }
    }
 
    if ( ( player.p != NULL ) && !player_isFlag( PLAYER_DESTROYED ) &&
         !player_isFlag( PLAYER_CREATING ) ) {
       dt_mod_base = player_dt_default();
    }
-   if ( dt_mod != dt_mod_base )
+   if ( dt_mod * ntime_getScale() != dt_mod_base )
       gl_print( &gl_defFontMono, x, y, &cFontWhite, "%3.1fx",
-                dt_mod / dt_mod_base );
+                dt_mod * ntime_getScale() / dt_mod_base );
 
    if ( !paused || !player_paused || !conf.pause_show )
       return;
@@ -687,7 +728,7 @@ static void update_all( int dohooks )
           * mauled by an enemy ship.
           */
          accumdt += microdt;
-         if ( accumdt > dt_mod * real_dt )
+         if ( accumdt > dt_mod * ntime_getScale() * real_dt )
             break;
       }
 
@@ -711,7 +752,10 @@ void update_routine( double dt, int dohooks )
 {
    NTracingZone( _ctx, 1 );
 
-   double real_update = dt / dt_mod;
+   /* Real time elements opt out of the time scale. When it pauses the game
+    * time at 0, they keep running with the real frame time. */
+   double scale       = ntime_getScale();
+   double real_update = ( scale > 0. ) ? dt / ( dt_mod * scale ) : real_dt;
 
    if ( dohooks ) {
       hook_exclusionStart();
@@ -24,6 +24,7 @@
 /** @cond */
 #include <math.h> // IWYU pragma: export
 
+#include "SDL_events.h"
 #include "SDL_stdinc.h"
 /** @endcond */
 
@@ -75,6 +76,21 @@ void                fps_setPos( double x, double y );
 void                fps_display( double dt );
 double              fps_current( void );
 void                naev_resize( void );
+void                naev_resize_rust( int w, int h, int real_w, int real_h );
+int                 capture_start( double fps, int w, int h );
+int                 capture_stop( void );
+double              capture_dt( double real_dt );
+void                capture_frame( void );
+int                 replay_record( const char *path );
+int                 replay_play( const char *path );
+int                 replay_stop( void );
+int                 replay_isActive( void );
+double              replay_dt( double real_dt );
+int                 replay_filterEvent( const SDL_Event *event );
+int                 replay_poll( SDL_Event *event );
+double              gputimer_totalMs( void );
+unsigned long       gltf_takeShadedSamples( void );
+void                loadscreen_render_assets( void );
 void                naev_toggleFullscreen( void );
 void                update_routine( double dt, int dohooks );
 const char         *naev_version( int long_version );
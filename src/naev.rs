@@ -12,36 +12,51 @@ unsafe extern "C" {
 }
 
 mod array;
+mod assets;
+mod bloom;
 mod buffer;
 mod camera;
+mod capture;
+mod colour;
 mod context;
 mod damagetype;
 mod env;
 mod faction;
+mod ffi;
 mod gettext;
+mod gputimer;
 mod linebreak;
 mod log;
 mod model;
 mod ndata;
 mod nebula;
 mod nlua;
+mod noise;
 mod ntime;
 mod nxml;
 mod outfit;
+mod painter;
 mod physfs;
 mod physics;
 mod render;
+mod replay;
 mod rng;
 mod shader;
 mod ship;
 mod slots;
+mod sprite;
 mod start;
+#[cfg(test)]
+mod testing;
 mod texture;
+mod ui;
 mod utils;
 mod vec2;
 mod version;
 mod lua {
+    pub mod rendertarget;
     pub mod ryaml;
+    pub mod vertexbuffer;
 }
 
 use crate::gettext::gettext;
@@ -85,14 +100,8 @@ pub fn naev() -> Result<()> {
         let argv0 = CString::new(env::ENV.argv0.clone()).unwrap();
         if naevc::PHYSFS_init(argv0.as_ptr() as *const c_char) == 0 {
             let err = physfs::error_as_io_error();
-            println!("{err}");
+            ui::fatal_error(gettext("Naev Critical Error"), &err.to_string());
             return Err(Error::new(err));
-            /* TODO probably move the error handling to the "real" main, when shit hits the
-                * fan. Below depends on sdl3
-            SDL_ShowSimpleMessageBox( SDL_MESSAGEBOX_ERROR,
-                _( "Naev Critical Error" ), buf,
-                gl_screen.window );
-            */
         }
         naevc::PHYSFS_permitSymbolicLinks(1);
     }
@@ -225,7 +234,7 @@ pub fn naev() -> Result<()> {
         if naevc::start_load() != 0 {
             let err = gettext("Failed to load start data.");
             warn!(err);
-            // TODO show some simple error message
+            ui::fatal_error(gettext("Naev Critical Error"), err);
             anyhow::bail!(err);
         }
         info!(
@@ -239,13 +248,19 @@ pub fn naev() -> Result<()> {
     }
 
     /* Set up OpenGL. */
-    let context = context::Context::new(sdlvid)?;
+    let context = match context::Context::new(sdlvid) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            ui::fatal_error(gettext("Naev Critical Error"), &format!("{e:?}"));
+            return Err(e);
+        }
+    };
 
     unsafe {
         if naevc::gl_init() != 0 {
             let err = gettext("Initializing video output failed, exiting…");
             warn!(err);
-            // TODO show some simple error message
+            ui::fatal_error(gettext("Naev Critical Error"), err);
             anyhow::bail!(err);
         }
 
@@ -392,6 +407,9 @@ pub fn naev() -> Result<()> {
 
         // Process clean up messages
         context.execute_messages();
+
+        // Process some pending asset uploads without blowing the frame
+        assets::drain(context, std::time::Duration::from_millis(4));
     }
 
     unsafe {
@@ -399,13 +417,6 @@ pub fn naev() -> Result<()> {
     }
 
     Ok(())
-    /*
-    #if SDL_VERSION_ATLEAST( 3, 0, 0 )
-          SDL_ShowSimpleMessageBox( SDL_MESSAGEBOX_ERROR,
-                                    _( "Naev Critical Error" ), buf,
-                                    gl_screen.window );
-    #endif
-            */
 }
 
 /// Small wrapper to handle loading
@@ -518,6 +529,35 @@ fn load_all(env: &nlua::LuaEnv) -> Result<()> {
         });
     }
 
+    // Textures the data wants ready before gameplay starts
+    let preload = match ndata::exists(texture::PRELOAD_MANIFEST) {
+        true => texture::preload_manifest(texture::PRELOAD_MANIFEST)
+            .map_err(|err| log::warn_err(err.context("unable to preload textures")))
+            .ok(),
+        false => None,
+    };
+
+    // Finish any asynchronous asset loading the stages started
+    if !assets::is_idle() {
+        let ctx = context::Context::get()?;
+        while !assets::is_idle() {
+            assets::loader().wait(std::time::Duration::from_millis(100));
+            assets::drain(ctx, std::time::Duration::from_millis(16));
+            let done = (nstages + 1.0 + assets::progress()) / (nstages + 2.0);
+            loadscreen_update(env, done, gettext("Loading Assets…")).unwrap_or_else(|err| {
+                log::warn_err(err.context("loadscreen failed to update!"));
+            });
+        }
+    }
+
+    if let Some(preload) = preload {
+        info!(
+            gettext("Preloaded {} textures, {} failed"),
+            preload.succeeded(),
+            preload.failed()
+        );
+    }
+
     loadscreen_update(env, 1.0, gettext("Loading Completed!")).unwrap_or_else(|err| {
         log::warn_err(err.context("loadscreen failed to update!"));
     });
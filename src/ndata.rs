@@ -35,6 +35,13 @@ pub fn rwops(path: &str) -> Result<sdl::rwops::RWops> {
     physfs::rwops(path, physfs::Mode::Read)
 }
 
+pub fn exists(path: &str) -> bool {
+    match CString::new(path) {
+        Ok(c_path) => unsafe { naevc::PHYSFS_exists(c_path.as_ptr()) != 0 },
+        Err(_) => false,
+    }
+}
+
 pub fn open(path: &str) -> Result<physfs::File> {
     physfs::File::open(path, physfs::Mode::Read)
 }
@@ -1,4 +1,5 @@
 #![allow(dead_code)]
+use crate::ffi::ffi_guard;
 use crate::render::Uniform;
 use anyhow::Result;
 use encase::ShaderType;
@@ -13,9 +14,10 @@ use crate::buffer::{
     Buffer, BufferBuilder, BufferTarget, BufferUsage, VertexArray, VertexArrayBuffer,
     VertexArrayBuilder,
 };
+use crate::log::warn_err;
 use crate::shader::{Shader, ShaderBuilder};
 use crate::texture::{Framebuffer, FramebufferBuilder};
-use crate::{context, rng};
+use crate::{context, gputimer, rng};
 
 pub const DEFAULT_HUE: f64 = 260.0;
 pub const PUFF_BUFFER: f32 = 300.;
@@ -108,6 +110,7 @@ impl PuffLayer {
                     stride: 0, // tightly packed
                     offset: 0,
                     divisor: 0,
+                    format: None,
                 },
                 VertexArrayBuffer {
                     buffer: &buffer,
@@ -115,6 +118,7 @@ impl PuffLayer {
                     stride: puff_size,
                     offset: 0,
                     divisor: 1, // Advances once per instance
+                    format: None,
                 },
                 VertexArrayBuffer {
                     buffer: &buffer,
@@ -122,6 +126,7 @@ impl PuffLayer {
                     stride: puff_size,
                     offset: std::mem::offset_of!(Puff, rand) as i32,
                     divisor: 1,
+                    format: None,
                 },
             ])
             .build(ctx)?;
@@ -146,6 +151,7 @@ impl PuffLayer {
         }
         VertexArray::unbind(ctx);
         data.puff_buffer.unbind(ctx);
+        ctx.check_errors();
 
         Ok(())
     }
@@ -440,7 +446,7 @@ impl NebulaData {
 
 use std::sync::{LazyLock, Mutex};
 static NEBULA: LazyLock<Mutex<NebulaData>> = LazyLock::new(|| {
-    let ctx = context::Context::get().unwrap();
+    let ctx = context::Context::get().expect("the nebula is used before the OpenGL context exists");
     Mutex::new(NebulaData::new(ctx).unwrap())
 });
 
@@ -449,9 +455,17 @@ pub extern "C" fn nebu_init() {}
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nebu_resize() {
-    let ctx = context::Context::get().unwrap();
-    let mut neb = NEBULA.lock().unwrap();
-    neb.resize(ctx);
+    ffi_guard((), || {
+        let ctx = match context::Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let mut neb = NEBULA.lock().unwrap();
+        neb.resize(ctx);
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -459,36 +473,74 @@ pub extern "C" fn nebu_exit() {}
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nebu_render(_dt: f64) {
-    let neb = NEBULA.lock().unwrap();
-    let ctx = context::Context::get().unwrap();
-    let _ = neb.render(ctx);
+    ffi_guard((), || {
+        let ctx = match context::Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let neb = NEBULA.lock().unwrap();
+        gputimer::begin(ctx, "nebula");
+        let _ = neb.render(ctx);
+        gputimer::end(ctx, "nebula");
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nebu_renderOverlay(_dt: f64) {
-    let neb = NEBULA.lock().unwrap();
-    let ctx = context::Context::get().unwrap();
-    let _ = neb.render_overlay(ctx);
+    ffi_guard((), || {
+        let ctx = match context::Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let neb = NEBULA.lock().unwrap();
+        gputimer::begin(ctx, "nebula overlay");
+        let _ = neb.render_overlay(ctx);
+        gputimer::end(ctx, "nebula overlay");
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nebu_update(dt: f64) {
-    let mut neb = NEBULA.lock().unwrap();
-    let ctx = context::Context::get().unwrap();
-    let _ = neb.update(ctx, dt);
+    ffi_guard((), || {
+        let ctx = match context::Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let mut neb = NEBULA.lock().unwrap();
+        let _ = neb.update(ctx, dt);
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nebu_getSightRadius() -> c_double {
-    let neb = NEBULA.lock().unwrap();
-    neb.view as c_double
+    ffi_guard(0.0, || {
+        let neb = NEBULA.lock().unwrap();
+        neb.view as c_double
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nebu_prep(density: c_double, volatility: c_double, hue: c_double) {
-    let mut neb = NEBULA.lock().unwrap();
-    let ctx = context::Context::get().unwrap();
-    let _ = neb.setup(ctx, density as f32, volatility as f32, hue as f32);
+    ffi_guard((), || {
+        let ctx = match context::Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let mut neb = NEBULA.lock().unwrap();
+        let _ = neb.setup(ctx, density as f32, volatility as f32, hue as f32);
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -3,6 +3,7 @@ use anyhow::Result;
 use constcat::concat;
 use mlua::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti};
 
+use crate::ffi::ffi_guard;
 use crate::gettext::{gettext, ngettext, pgettext};
 use crate::lua::ryaml;
 use crate::{ndata, vec2};
@@ -520,75 +521,89 @@ use std::ffi::CStr;
 // C API
 #[unsafe(no_mangle)]
 pub extern "C" fn nlua_newEnv(name: *const c_char) -> *mut LuaEnv {
-    let ptr = unsafe { CStr::from_ptr(name) };
-    let name = ptr.to_str().unwrap();
-    let mut lua = NLUA.lock().unwrap();
-    match lua.environment_new(name) {
-        Ok(env) => Box::into_raw(Box::new(env)),
-        Err(e) => {
-            warn!("unable to create Lua environment: {}", e);
-            std::ptr::null_mut()
+    ffi_guard(std::ptr::null_mut(), || {
+        let ptr = unsafe { CStr::from_ptr(name) };
+        let name = ptr.to_str().unwrap();
+        let mut lua = NLUA.lock().unwrap();
+        match lua.environment_new(name) {
+            Ok(env) => Box::into_raw(Box::new(env)),
+            Err(e) => {
+                warn!("unable to create Lua environment: {}", e);
+                std::ptr::null_mut()
+            }
         }
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nlua_dupEnv(env: *mut LuaEnv) -> *mut LuaEnv {
-    if env.is_null() {
-        return env;
-    }
-    let env = unsafe { &*env };
-    let t = &env.table;
-    let lua = &NLUA.lock().unwrap();
-    let newenv = LuaEnv {
-        table: t.clone(),
-        rk: lua.lua.create_registry_value(t).unwrap(),
-    };
-    Box::into_raw(Box::new(newenv))
+    ffi_guard(std::ptr::null_mut(), || {
+        if env.is_null() {
+            return env;
+        }
+        let env = unsafe { &*env };
+        let t = &env.table;
+        let lua = &NLUA.lock().unwrap();
+        let newenv = LuaEnv {
+            table: t.clone(),
+            rk: lua.lua.create_registry_value(t).unwrap(),
+        };
+        Box::into_raw(Box::new(newenv))
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nlua_freeEnv(env: *mut LuaEnv) {
-    if !env.is_null() {
-        let _ = unsafe { Box::from_raw(env) };
-    }
+    ffi_guard((), || {
+        if !env.is_null() {
+            let _ = unsafe { Box::from_raw(env) };
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nlua_pushenv(lua: *mut mlua::lua_State, env: *mut LuaEnv) {
-    let env = unsafe { &*env };
-    unsafe {
-        mlua::ffi::lua_rawgeti(lua, mlua::ffi::LUA_REGISTRYINDEX, env.rk.id().into());
-    }
+    ffi_guard((), || {
+        let env = unsafe { &*env };
+        unsafe {
+            mlua::ffi::lua_rawgeti(lua, mlua::ffi::LUA_REGISTRYINDEX, env.rk.id().into());
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nlua_pushEnvTable(lua: *mut mlua::lua_State) {
-    let nlua = NLUA.lock().unwrap();
-    unsafe {
-        mlua::ffi::lua_rawgeti(lua, mlua::ffi::LUA_REGISTRYINDEX, nlua.envs_rk.id().into());
-    }
+    ffi_guard((), || {
+        let nlua = NLUA.lock().unwrap();
+        unsafe {
+            mlua::ffi::lua_rawgeti(lua, mlua::ffi::LUA_REGISTRYINDEX, nlua.envs_rk.id().into());
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nlua_resize() {
-    let lua = NLUA.lock().unwrap();
-    let (screen_w, screen_h) = unsafe { (naevc::gl_screen.w, naevc::gl_screen.h) };
-    lua.resize(screen_w, screen_h).unwrap();
+    ffi_guard((), || {
+        let lua = NLUA.lock().unwrap();
+        let (screen_w, screen_h) = unsafe { (naevc::gl_screen.w, naevc::gl_screen.h) };
+        lua.resize(screen_w, screen_h).unwrap();
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn nlua_loadStandard(env: *mut LuaEnv) -> c_int {
-    if env.is_null() {
-        return -1;
-    }
-    let nlua = NLUA.lock().unwrap();
-    let env = unsafe { &mut *env };
-    match env.load_standard(&nlua) {
-        Ok(()) => 0,
-        Err(e) => {
-            warn_err!(e);
-            -1
+    ffi_guard(-1, || {
+        if env.is_null() {
+            return -1;
         }
-    }
+        let nlua = NLUA.lock().unwrap();
+        let env = unsafe { &mut *env };
+        match env.load_standard(&nlua) {
+            Ok(()) => 0,
+            Err(e) => {
+                warn_err!(e);
+                -1
+            }
+        }
+    })
 }
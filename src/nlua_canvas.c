@@ -291,6 +291,6 @@ void canvas_reset( void )
    previous_fbo_set      = 0;
    if ( was_scissored )
       glEnable( GL_SCISSOR_TEST );
-   glViewport( 0, 0, gl_screen.rw, gl_screen.rh );
+   gl_screenViewport();
    glBindFramebuffer( GL_FRAMEBUFFER, gl_screen.current_fbo );
 }
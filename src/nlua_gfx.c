@@ -20,10 +20,12 @@
 #include "nlua_canvas.h"
 #include "nlua_colour.h"
 #include "nlua_font.h"
+#include "nlua_rendertarget.h"
 #include "nlua_shader.h"
 #include "nlua_tex.h"
 #include "nlua_transform.h"
 #include "nlua_vec2.h"
+#include "nlua_vertexbuffer.h"
 #include "nluadef.h"
 #include "opengl.h"
 #include "render.h"
@@ -115,13 +117,16 @@ int nlua_loadGFX( nlua_env *env )
    nlua_register( env, "gfx", gfxL_methods, 0 );
 
    /* We also load the texture, colour, font, and transform modules as
-    * dependencies. */
+    * dependencies, along with the vertex buffer and render target bindings
+    * for custom rendering. */
    nlua_loadCol( env );
    nlua_loadTex( env );
    nlua_loadFont( env );
    nlua_loadTransform( env );
    nlua_loadShader( env );
    nlua_loadCanvas( env );
+   nlua_loadVertexBuffer( env );
+   nlua_loadRenderTarget( env );
 
    return 0;
 }
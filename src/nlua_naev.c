@@ -46,6 +46,11 @@ static int naevL_ticks( lua_State *L );
 static int naevL_ticksGame( lua_State *L );
 static int naevL_clock( lua_State *L );
 static int naevL_fps( lua_State *L );
+static int naevL_captureStart( lua_State *L );
+static int naevL_captureStop( lua_State *L );
+static int naevL_replayRecord( lua_State *L );
+static int naevL_replayPlay( lua_State *L );
+static int naevL_replayStop( lua_State *L );
 static int naevL_keyGet( lua_State *L );
 static int naevL_keyEnable( lua_State *L );
 static int naevL_keyEnableAll( lua_State *L );
@@ -91,6 +96,11 @@ static const luaL_Reg naev_methods[] = {
    { "ticksGame", naevL_ticksGame },
    { "clock", naevL_clock },
    { "fps", naevL_fps },
+   { "captureStart", naevL_captureStart },
+   { "captureStop", naevL_captureStop },
+   { "replayRecord", naevL_replayRecord },
+   { "replayPlay", naevL_replayPlay },
+   { "replayStop", naevL_replayStop },
    { "keyGet", naevL_keyGet },
    { "keyEnable", naevL_keyEnable },
    { "keyEnableAll", naevL_keyEnableAll },
@@ -335,6 +345,96 @@ static int naevL_fps( lua_State *L )
    return 1;
 }
 
+/**
+ * @brief Starts capturing frames to a numbered sequence of images.
+ *
+ * While capturing, the game advances by a fixed time step every frame,
+ * regardless of how long the frame takes to render, so the resulting images
+ * can be assembled into a smooth video. Frames are stored in
+ * "captures/captureNNN/" in the user data directory.
+ *
+ * @usage naev.captureStart( 60, 1920, 1080 ) -- 60 fps at 1080p
+ *
+ *    @luatparam[opt=60] number fps Framerate to capture at.
+ *    @luatparam[opt] integer w Width to resize the window to while capturing.
+ *    @luatparam[opt] integer h Height to resize the window to while capturing.
+ *    @luatreturn boolean true if the capture was started.
+ * @luafunc captureStart
+ */
+static int naevL_captureStart( lua_State *L )
+{
+   double fps = luaL_optnumber( L, 1, 60. );
+   int    w   = luaL_optinteger( L, 2, 0 );
+   int    h   = luaL_optinteger( L, 3, 0 );
+   lua_pushboolean( L, capture_start( fps, w, h ) == 0 );
+   return 1;
+}
+
+/**
+ * @brief Stops capturing frames.
+ *
+ *    @luatreturn integer|nil Number of frames captured, or nil if there was
+ * no capture in progress.
+ * @luafunc captureStop
+ */
+static int naevL_captureStop( lua_State *L )
+{
+   int n = capture_stop();
+   if ( n < 0 )
+      return 0;
+   lua_pushinteger( L, n );
+   return 1;
+}
+
+/**
+ * @brief Starts recording a replay of the random seed, time steps and input.
+ *
+ * The replay has to be played back from the same state it was recorded in,
+ * e.g., right after loading the same save. It is written when stopped.
+ *
+ * @usage naev.replayRecord( "test.replay" )
+ *
+ *    @luatparam string path Path in the user data directory to write to.
+ *    @luatreturn boolean true if the recording was started.
+ * @luafunc replayRecord
+ */
+static int naevL_replayRecord( lua_State *L )
+{
+   const char *path = luaL_checkstring( L, 1 );
+   lua_pushboolean( L, replay_record( path ) == 0 );
+   return 1;
+}
+
+/**
+ * @brief Plays back a recorded replay, ignoring live input until it finishes.
+ *
+ *    @luatparam string path Path of the replay to play.
+ *    @luatreturn boolean true if the playback was started.
+ * @luafunc replayPlay
+ */
+static int naevL_replayPlay( lua_State *L )
+{
+   const char *path = luaL_checkstring( L, 1 );
+   lua_pushboolean( L, replay_play( path ) == 0 );
+   return 1;
+}
+
+/**
+ * @brief Stops recording or playing back a replay.
+ *
+ *    @luatreturn integer|nil Number of frames recorded or left to play, or nil
+ * if there was no replay in progress.
+ * @luafunc replayStop
+ */
+static int naevL_replayStop( lua_State *L )
+{
+   int n = replay_stop();
+   if ( n < 0 )
+      return 0;
+   lua_pushinteger( L, n );
+   return 1;
+}
+
 /**
  * @brief Gets a human-readable name for the key bound to a function.
  *
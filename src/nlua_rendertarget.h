@@ -0,0 +1,11 @@
+/*
+ * See Licensing and Copyright notice in naev.h
+ */
+#pragma once
+
+#include "nlua.h"
+
+/*
+ * Render target library.
+ */
+int nlua_loadRenderTarget( nlua_env *env );
@@ -9,6 +9,8 @@
 /** @cond */
 #include <lauxlib.h>
 #include <stdlib.h>
+
+#include "naev.h"
 /** @endcond */
 
 #include "nlua_time.h"
@@ -30,6 +32,8 @@ static int timeL_str( lua_State *L );
 static int timeL_inc( lua_State *L );
 static int timeL_tonumber( lua_State *L );
 static int timeL_fromnumber( lua_State *L );
+static int timeL_parse( lua_State *L );
+static int timeL_human( lua_State *L );
 
 static const luaL_Reg time_methods[] = { { "new", timeL_new },
                                          { "add", timeL_add__ },
@@ -45,6 +49,8 @@ static const luaL_Reg time_methods[] = { { "new", timeL_new },
                                          { "inc", timeL_inc },
                                          { "tonumber", timeL_tonumber },
                                          { "fromnumber", timeL_fromnumber },
+                                         { "parse", timeL_parse },
+                                         { "human", timeL_human },
                                          { 0, 0 } }; /**< Time Lua methods. */
 
 /**
@@ -75,6 +81,12 @@ int nlua_loadTime( nlua_env *env )
  * end
  * @endcode
  *
+ * Durations can also be given as strings such as "3 periods" or
+ * "1 cycle, 50 STU" wherever a time is added or subtracted:
+ * @code
+ * deadline = time.get() + "3 periods"
+ * @endcode
+ *
  * @luamod time
  */
 /**
@@ -128,6 +140,27 @@ ntime_t *lua_pushtime( lua_State *L, ntime_t time )
    lua_setmetatable( L, -2 );
    return p;
 }
+/**
+ * @brief Gets a duration, which can be either a time or a string such as
+ * "3 periods", raising an error if it is neither.
+ *
+ *    @param L Lua state to get duration from.
+ *    @param ind Index position to find the duration.
+ *    @return Duration found at the index in the state.
+ */
+static ntime_t luaL_validduration( lua_State *L, int ind )
+{
+   ntime_t     t;
+   const char *str;
+
+   if ( lua_type( L, ind ) != LUA_TSTRING )
+      return luaL_validtime( L, ind );
+
+   str = lua_tostring( L, ind );
+   if ( ntime_parseDuration( str, &t ) )
+      return NLUA_ERROR( L, _( "Invalid duration '%s'!" ), str );
+   return t;
+}
 /**
  * @brief Checks to see if ind is a time.
  *
@@ -184,9 +217,10 @@ static int timeL_new( lua_State *L )
  * @usage new_time = time.get() + time.new( 0, 5, 0 ) -- Adds 5 periods to the
  * current date
  * @usage t:add( time.new( 0, 3, 0 ) ) -- Directly modifies t
+ * @usage deadline = time.get() + "2 periods, 500 STU"
  *
  *    @luatparam Time t1 Time metatable to add to.
- *    @luatparam Time t2 Time metatable added.
+ *    @luatparam Time|string t2 Time metatable or duration string added.
  * @luafunc add
  */
 static int timeL_add( lua_State *L )
@@ -195,7 +229,7 @@ static int timeL_add( lua_State *L )
 
    /* Parameters. */
    t1 = luaL_validtime( L, 1 );
-   t2 = luaL_validtime( L, 2 );
+   t2 = luaL_validduration( L, 2 );
 
    /* Add them. */
    lua_pushtime( L, t1 + t2 );
@@ -211,7 +245,7 @@ static int timeL_add__( lua_State *L )
 
    /* Parameters. */
    t1 = luaL_checktime( L, 1 );
-   t2 = luaL_validtime( L, 2 );
+   t2 = luaL_validduration( L, 2 );
 
    /* Add them. */
    *t1 += t2;
@@ -229,7 +263,7 @@ static int timeL_add__( lua_State *L )
  * @usage t:sub( time.new( 0, 3, 0 ) ) -- Directly modifies t
  *
  *    @luatparam Time t1 Time metatable to subtract from.
- *    @luatparam Time t2 Time metatable subtracted.
+ *    @luatparam Time|string t2 Time metatable or duration string subtracted.
  * @luafunc sub
  */
 static int timeL_sub( lua_State *L )
@@ -238,7 +272,7 @@ static int timeL_sub( lua_State *L )
 
    /* Parameters. */
    t1 = luaL_validtime( L, 1 );
-   t2 = luaL_validtime( L, 2 );
+   t2 = luaL_validduration( L, 2 );
 
    /* Sub them. */
    lua_pushtime( L, t1 - t2 );
@@ -254,7 +288,7 @@ static int timeL_sub__( lua_State *L )
 
    /* Parameters. */
    t1 = luaL_checktime( L, 1 );
-   t2 = luaL_validtime( L, 2 );
+   t2 = luaL_validduration( L, 2 );
 
    /* Sub them. */
    *t1 -= t2;
@@ -420,3 +454,40 @@ static int timeL_fromnumber( lua_State *L )
    lua_pushtime( L, t );
    return 1;
 }
+
+/**
+ * @brief Creates a time from a duration string.
+ *
+ * Units are case-insensitive and can be cycles (SCU), periods (STP) or seconds
+ * (STU).
+ *
+ * @usage t = time.parse( "1 cycle, 2.5 periods" )
+ *
+ *    @luatparam string str Duration to parse.
+ *    @luatreturn Time Time representing the duration.
+ * @luafunc parse
+ */
+static int timeL_parse( lua_State *L )
+{
+   lua_pushtime( L, luaL_validduration( L, 1 ) );
+   return 1;
+}
+
+/**
+ * @brief Converts a time to a translated duration string for displaying to
+ * the player.
+ *
+ * @usage left = time.human( deadline - time.get() ) -- e.g., "3 periods, 50
+ * STU"
+ *
+ *    @luatparam Time t Time to convert.
+ *    @luatreturn string The time as a translated duration string.
+ * @luafunc human
+ */
+static int timeL_human( lua_State *L )
+{
+   char buf[STRMAX_SHORT];
+   ntime_durationBuf( buf, sizeof( buf ), luaL_validtime( L, 1 ) );
+   lua_pushstring( L, buf );
+   return 1;
+}
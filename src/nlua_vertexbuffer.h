@@ -0,0 +1,11 @@
+/*
+ * See Licensing and Copyright notice in naev.h
+ */
+#pragma once
+
+#include "nlua.h"
+
+/*
+ * Vertex buffer and vertex array library.
+ */
+int nlua_loadVertexBuffer( nlua_env *env );
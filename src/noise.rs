@@ -0,0 +1,469 @@
+#![allow(dead_code)]
+//! Deterministic procedural noise. These are ports of the GLSL implementations in
+//! `dat/glsl/lib/` (Stefan Gustavson's webgl-noise), so that the CPU and GPU give the same
+//! results up to floating point differences. Shaders can use the same functions and seeding by
+//! including the virtual "noise.glsl" file.
+use nalgebra::{Vector2, Vector3, Vector4};
+use std::ops::Mul;
+
+/// GLSL counterpart, provided to shaders as the virtual include "noise.glsl".
+pub const GLSL: &str = r#"
+#ifndef _NOISE_GLSL
+#define _NOISE_GLSL
+
+#include "lib/perlin.glsl"
+#include "lib/simplex.glsl"
+#include "lib/cellular.glsl"
+
+vec3 noise_seed( uint seed )
+{
+   return vec3( seed % 289u, ( seed / 289u ) % 289u, ( seed / 83521u ) % 289u );
+}
+
+float perlin2( vec2 p, uint seed ) { return cnoise( p + noise_seed( seed ).xy ); }
+float perlin3( vec3 p, uint seed ) { return cnoise( p + noise_seed( seed ) ); }
+float simplex2( vec2 p, uint seed ) { return snoise( p + noise_seed( seed ).xy ); }
+float simplex3( vec3 p, uint seed ) { return snoise( p + noise_seed( seed ) ); }
+float worley( vec2 p, uint seed ) { return cellular2x2( p + noise_seed( seed ).xy ).x; }
+
+float fbm_perlin2( vec2 p, uint seed, int octaves, float lacunarity, float gain )
+{
+   float sum  = 0.0;
+   float amp  = 1.0;
+   float norm = 0.0;
+   for (int i = 0; i < octaves; i++) {
+      sum  += amp * perlin2( p, seed );
+      norm += amp;
+      amp  *= gain;
+      p    *= lacunarity;
+   }
+   return (norm > 0.0) ? sum / norm : 0.0;
+}
+
+float fbm_perlin3( vec3 p, uint seed, int octaves, float lacunarity, float gain )
+{
+   float sum  = 0.0;
+   float amp  = 1.0;
+   float norm = 0.0;
+   for (int i = 0; i < octaves; i++) {
+      sum  += amp * perlin3( p, seed );
+      norm += amp;
+      amp  *= gain;
+      p    *= lacunarity;
+   }
+   return (norm > 0.0) ? sum / norm : 0.0;
+}
+
+float fbm_simplex2( vec2 p, uint seed, int octaves, float lacunarity, float gain )
+{
+   float sum  = 0.0;
+   float amp  = 1.0;
+   float norm = 0.0;
+   for (int i = 0; i < octaves; i++) {
+      sum  += amp * simplex2( p, seed );
+      norm += amp;
+      amp  *= gain;
+      p    *= lacunarity;
+   }
+   return (norm > 0.0) ? sum / norm : 0.0;
+}
+
+float fbm_simplex3( vec3 p, uint seed, int octaves, float lacunarity, float gain )
+{
+   float sum  = 0.0;
+   float amp  = 1.0;
+   float norm = 0.0;
+   for (int i = 0; i < octaves; i++) {
+      sum  += amp * simplex3( p, seed );
+      norm += amp;
+      amp  *= gain;
+      p    *= lacunarity;
+   }
+   return (norm > 0.0) ? sum / norm : 0.0;
+}
+
+#endif /* _NOISE_GLSL */
+"#;
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+fn mod289(x: f32) -> f32 {
+    x - (x * (1.0 / 289.0)).floor() * 289.0
+}
+fn mod7(x: f32) -> f32 {
+    x - (x * (1.0 / 7.0)).floor() * 7.0
+}
+fn permute(x: f32) -> f32 {
+    mod289((34.0 * x + 10.0) * x)
+}
+fn taylor_inv_sqrt(r: f32) -> f32 {
+    1.79284291400159 - 0.85373472095314 * r
+}
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+/// GLSL `step(edge, x)`.
+fn step(edge: f32, x: f32) -> f32 {
+    if x < edge {
+        0.0
+    } else {
+        1.0
+    }
+}
+
+/// Offset applied to the coordinates for a given seed. Integer offsets keep the results exact
+/// on both the CPU and GPU.
+fn seed_offset(seed: u32) -> Vector3<f32> {
+    Vector3::new(
+        (seed % 289) as f32,
+        ((seed / 289) % 289) as f32,
+        ((seed / 83521) % 289) as f32,
+    )
+}
+
+/// Classic Perlin noise in 2D, roughly in [-1, 1].
+pub fn perlin2(p: Vector2<f32>, seed: u32) -> f32 {
+    let p = p + seed_offset(seed).xy();
+    let pi = Vector4::new(
+        p.x.floor(),
+        p.y.floor(),
+        p.x.floor() + 1.0,
+        p.y.floor() + 1.0,
+    )
+    .map(mod289);
+    let pf = Vector4::new(fract(p.x), fract(p.y), fract(p.x) - 1.0, fract(p.y) - 1.0);
+    let ix = Vector4::new(pi.x, pi.z, pi.x, pi.z);
+    let iy = Vector4::new(pi.y, pi.y, pi.w, pi.w);
+    let fx = Vector4::new(pf.x, pf.z, pf.x, pf.z);
+    let fy = Vector4::new(pf.y, pf.y, pf.w, pf.w);
+
+    let i = (ix.map(permute) + iy).map(permute);
+
+    let gx = i.map(|i| fract(i * (1.0 / 41.0)) * 2.0 - 1.0);
+    let gy = gx.map(|g| g.abs() - 0.5);
+    let gx = gx.map(|g| g - (g + 0.5).floor());
+
+    let g00 = Vector2::new(gx.x, gy.x);
+    let g10 = Vector2::new(gx.y, gy.y);
+    let g01 = Vector2::new(gx.z, gy.z);
+    let g11 = Vector2::new(gx.w, gy.w);
+    let g00 = g00 * taylor_inv_sqrt(g00.dot(&g00));
+    let g01 = g01 * taylor_inv_sqrt(g01.dot(&g01));
+    let g10 = g10 * taylor_inv_sqrt(g10.dot(&g10));
+    let g11 = g11 * taylor_inv_sqrt(g11.dot(&g11));
+
+    let n00 = g00.dot(&Vector2::new(fx.x, fy.x));
+    let n10 = g10.dot(&Vector2::new(fx.y, fy.y));
+    let n01 = g01.dot(&Vector2::new(fx.z, fy.z));
+    let n11 = g11.dot(&Vector2::new(fx.w, fy.w));
+
+    let (fade_x, fade_y) = (fade(pf.x), fade(pf.y));
+    let n_x = (mix(n00, n10, fade_x), mix(n01, n11, fade_x));
+    2.3 * mix(n_x.0, n_x.1, fade_y)
+}
+
+/// Classic Perlin noise in 3D, roughly in [-1, 1].
+pub fn perlin3(p: Vector3<f32>, seed: u32) -> f32 {
+    let p = p + seed_offset(seed);
+    let pi0 = p.map(f32::floor);
+    let pi1 = pi0.add_scalar(1.0).map(mod289);
+    let pi0 = pi0.map(mod289);
+    let pf0 = p.map(fract);
+    let pf1 = pf0.add_scalar(-1.0);
+    let ix = Vector4::new(pi0.x, pi1.x, pi0.x, pi1.x);
+    let iy = Vector4::new(pi0.y, pi0.y, pi1.y, pi1.y);
+
+    let ixy = (ix.map(permute) + iy).map(permute);
+    let ixy0 = ixy.add_scalar(pi0.z).map(permute);
+    let ixy1 = ixy.add_scalar(pi1.z).map(permute);
+
+    let gradients = |ixy: Vector4<f32>| -> [Vector3<f32>; 4] {
+        let gx = ixy * (1.0 / 7.0);
+        let gy = gx.map(|g| fract(g.floor() * (1.0 / 7.0)) - 0.5);
+        let gx = gx.map(fract);
+        let gz = Vector4::from_fn(|k, _| 0.5 - gx[k].abs() - gy[k].abs());
+        let sz = gz.map(|g| step(g, 0.0));
+        let gx = Vector4::from_fn(|k, _| gx[k] - sz[k] * (step(0.0, gx[k]) - 0.5));
+        let gy = Vector4::from_fn(|k, _| gy[k] - sz[k] * (step(0.0, gy[k]) - 0.5));
+        std::array::from_fn(|k| {
+            let g = Vector3::new(gx[k], gy[k], gz[k]);
+            g * taylor_inv_sqrt(g.dot(&g))
+        })
+    };
+    let [g000, g100, g010, g110] = gradients(ixy0);
+    let [g001, g101, g011, g111] = gradients(ixy1);
+
+    let n000 = g000.dot(&pf0);
+    let n100 = g100.dot(&Vector3::new(pf1.x, pf0.y, pf0.z));
+    let n010 = g010.dot(&Vector3::new(pf0.x, pf1.y, pf0.z));
+    let n110 = g110.dot(&Vector3::new(pf1.x, pf1.y, pf0.z));
+    let n001 = g001.dot(&Vector3::new(pf0.x, pf0.y, pf1.z));
+    let n101 = g101.dot(&Vector3::new(pf1.x, pf0.y, pf1.z));
+    let n011 = g011.dot(&Vector3::new(pf0.x, pf1.y, pf1.z));
+    let n111 = g111.dot(&pf1);
+
+    let f = pf0.map(fade);
+    let n_z = [
+        mix(n000, n001, f.z),
+        mix(n100, n101, f.z),
+        mix(n010, n011, f.z),
+        mix(n110, n111, f.z),
+    ];
+    let n_yz = (mix(n_z[0], n_z[2], f.y), mix(n_z[1], n_z[3], f.y));
+    2.2 * mix(n_yz.0, n_yz.1, f.x)
+}
+
+/// Simplex noise in 2D, roughly in [-1, 1].
+pub fn simplex2(v: Vector2<f32>, seed: u32) -> f32 {
+    const C: [f32; 4] = [
+        0.211324865405187,  // (3.0-sqrt(3.0))/6.0
+        0.366025403784439,  // 0.5*(sqrt(3.0)-1.0)
+        -0.577350269189626, // -1.0 + 2.0 * C.x
+        0.024390243902439,  // 1.0 / 41.0
+    ];
+    let v = v + seed_offset(seed).xy();
+    // First corner
+    let i = v.add_scalar(v.x * C[1] + v.y * C[1]).map(f32::floor);
+    let x0 = (v - i).add_scalar(i.x * C[0] + i.y * C[0]);
+
+    // Other corners
+    let i1 = match x0.x > x0.y {
+        true => Vector2::new(1.0, 0.0),
+        false => Vector2::new(0.0, 1.0),
+    };
+    let x12 = Vector4::new(
+        x0.x + C[0] - i1.x,
+        x0.y + C[0] - i1.y,
+        x0.x + C[2],
+        x0.y + C[2],
+    );
+
+    // Permutations
+    let i = i.map(mod289);
+    let p = (Vector3::new(0.0, i1.y, 1.0).add_scalar(i.y).map(permute)
+        + Vector3::new(0.0, i1.x, 1.0).add_scalar(i.x))
+    .map(permute);
+
+    let m = Vector3::new(
+        x0.dot(&x0),
+        x12.x * x12.x + x12.y * x12.y,
+        x12.z * x12.z + x12.w * x12.w,
+    )
+    .map(|d| (0.5 - d).max(0.0).powi(4));
+
+    // Gradients: 41 points uniformly over a line, mapped onto a diamond
+    let x = p.map(|p| 2.0 * fract(p * C[3]) - 1.0);
+    let h = x.map(|x| x.abs() - 0.5);
+    let a0 = x.map(|x| x - (x + 0.5).floor());
+
+    // Normalise gradients implicitly by scaling m
+    let m = Vector3::from_fn(|k, _| m[k] * taylor_inv_sqrt(a0[k] * a0[k] + h[k] * h[k]));
+
+    let g = Vector3::new(
+        a0.x * x0.x + h.x * x0.y,
+        a0.y * x12.x + h.y * x12.y,
+        a0.z * x12.z + h.z * x12.w,
+    );
+    130.0 * m.dot(&g)
+}
+
+/// Simplex noise in 3D, roughly in [-1, 1].
+pub fn simplex3(v: Vector3<f32>, seed: u32) -> f32 {
+    const C: [f32; 2] = [1.0 / 6.0, 1.0 / 3.0];
+    let v = v + seed_offset(seed);
+
+    // First corner
+    let i = v.add_scalar(v.sum() * C[1]).map(f32::floor);
+    let x0 = (v - i).add_scalar(i.sum() * C[0]);
+
+    // Other corners
+    let g = Vector3::new(step(x0.y, x0.x), step(x0.z, x0.y), step(x0.x, x0.z));
+    let l = g.map(|g| 1.0 - g);
+    let lzxy = Vector3::new(l.z, l.x, l.y);
+    let i1 = g.zip_map(&lzxy, f32::min);
+    let i2 = g.zip_map(&lzxy, f32::max);
+    let x1 = (x0 - i1).add_scalar(C[0]);
+    let x2 = (x0 - i2).add_scalar(C[1]);
+    let x3 = x0.add_scalar(-0.5);
+
+    // Permutations
+    let i = i.map(mod289);
+    let corners = |k: usize| Vector4::new(0.0, i1[k], i2[k], 1.0);
+    let p = corners(2).add_scalar(i.z).map(permute);
+    let p = (p + corners(1).add_scalar(i.y)).map(permute);
+    let p = (p + corners(0).add_scalar(i.x)).map(permute);
+
+    // Gradients: 7x7 points over a square, mapped onto an octahedron
+    let n_ = 0.142857142857; // 1.0/7.0
+    let ns = Vector3::new(2.0 * n_, 0.5 * n_ - 1.0, n_);
+
+    let j = p.map(|p| p - 49.0 * (p * ns.z * ns.z).floor());
+    let x_ = j.map(|j| (j * ns.z).floor());
+    let y_ = j.zip_map(&x_, |j, x_| (j - 7.0 * x_).floor());
+
+    let x = x_.map(|x| x * ns.x + ns.y);
+    let y = y_.map(|y| y * ns.x + ns.y);
+    let h = x.zip_map(&y, |x, y| 1.0 - x.abs() - y.abs());
+
+    let b0 = Vector4::new(x.x, x.y, y.x, y.y);
+    let b1 = Vector4::new(x.z, x.w, y.z, y.w);
+    let s0 = b0.map(|b| b.floor() * 2.0 + 1.0);
+    let s1 = b1.map(|b| b.floor() * 2.0 + 1.0);
+    let sh = h.map(|h| -step(h, 0.0));
+
+    let a0 = Vector4::new(
+        b0.x + s0.x * sh.x,
+        b0.z + s0.z * sh.x,
+        b0.y + s0.y * sh.y,
+        b0.w + s0.w * sh.y,
+    );
+    let a1 = Vector4::new(
+        b1.x + s1.x * sh.z,
+        b1.z + s1.z * sh.z,
+        b1.y + s1.y * sh.w,
+        b1.w + s1.w * sh.w,
+    );
+
+    let normalise = |p: Vector3<f32>| p * taylor_inv_sqrt(p.dot(&p));
+    let p0 = normalise(Vector3::new(a0.x, a0.y, h.x));
+    let p1 = normalise(Vector3::new(a0.z, a0.w, h.y));
+    let p2 = normalise(Vector3::new(a1.x, a1.y, h.z));
+    let p3 = normalise(Vector3::new(a1.z, a1.w, h.w));
+
+    // Mix final noise value
+    let m = Vector4::new(x0.dot(&x0), x1.dot(&x1), x2.dot(&x2), x3.dot(&x3))
+        .map(|d| (0.5 - d).max(0.0).powi(4));
+    105.0
+        * m.dot(&Vector4::new(
+            p0.dot(&x0),
+            p1.dot(&x1),
+            p2.dot(&x2),
+            p3.dot(&x3),
+        ))
+}
+
+/// Cellular (Worley) noise in 2D, giving the distance to the closest feature point (F1). Uses
+/// the same fast 2x2 search as the GLSL `cellular2x2`.
+pub fn worley(p: Vector2<f32>, seed: u32) -> f32 {
+    const K: f32 = 0.142857142857; // 1/7
+    const K2: f32 = 0.0714285714285; // K/2
+    const JITTER: f32 = 0.8;
+    let p = p + seed_offset(seed).xy();
+    let pi = p.map(|x| mod289(x.floor()));
+    let pf = p.map(fract);
+    let pfx = Vector4::new(-0.5, -1.5, -0.5, -1.5).add_scalar(pf.x);
+    let pfy = Vector4::new(-0.5, -0.5, -1.5, -1.5).add_scalar(pf.y);
+    let p = Vector4::new(0.0, 1.0, 0.0, 1.0)
+        .add_scalar(pi.x)
+        .map(permute);
+    let p = (p + Vector4::new(0.0, 0.0, 1.0, 1.0).add_scalar(pi.y)).map(permute);
+    let ox = p.map(|p| mod7(p) * K + K2);
+    let oy = p.map(|p| mod7((p * K).floor()) * K + K2);
+    let d = Vector4::from_fn(|k, _| {
+        let dx = pfx[k] + JITTER * ox[k];
+        let dy = pfy[k] + JITTER * oy[k];
+        dx * dx + dy * dy
+    });
+    d.min().sqrt()
+}
+
+/// Fractal Brownian motion, summing `octaves` layers of `noise` with the frequency multiplied
+/// by `lacunarity` and the amplitude by `gain` at each layer. The result is normalized by the
+/// total amplitude, so it stays in the range of the noise function.
+///
+/// ```ignore
+/// let v = fbm(|p| noise::simplex2(p, seed), p, 5, 2.0, 0.5);
+/// ```
+pub fn fbm<P, F>(noise: F, p: P, octaves: u32, lacunarity: f32, gain: f32) -> f32
+where
+    P: Copy + Mul<f32, Output = P>,
+    F: Fn(P) -> f32,
+{
+    let mut p = p;
+    let mut sum = 0.0;
+    let mut amp = 1.0;
+    let mut norm = 0.0;
+    for _ in 0..octaves {
+        sum += amp * noise(p);
+        norm += amp;
+        amp *= gain;
+        p = p * lacunarity;
+    }
+    match norm > 0.0 {
+        true => sum / norm,
+        false => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks against values computed with a plain single precision port of the same code.
+    fn assert_pinned(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() < 1e-5,
+            "got {value}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn perlin2_pinned() {
+        assert_pinned(perlin2(Vector2::new(0.3, 0.7), 0), 0.023_384_517);
+        assert_pinned(perlin2(Vector2::new(12.5, -3.25), 42), 0.007_816_807);
+        assert_pinned(perlin2(Vector2::new(-7.8, 4.1), 123_456), -0.309_767_48);
+        // Gradients are zero at the lattice points
+        assert_eq!(perlin2(Vector2::new(3.0, 5.0), 0), 0.0);
+        assert_eq!(perlin2(Vector2::new(-2.0, 7.0), 99), 0.0);
+    }
+
+    #[test]
+    fn simplex2_pinned() {
+        assert_pinned(simplex2(Vector2::new(0.3, 0.7), 0), -0.538_777_65);
+        assert_pinned(simplex2(Vector2::new(12.5, -3.25), 42), -0.408_603_9);
+        assert_pinned(simplex2(Vector2::new(-7.8, 4.1), 123_456), 0.033_985_37);
+    }
+
+    #[test]
+    fn perlin3_pinned() {
+        assert_pinned(perlin3(Vector3::new(0.3, 0.7, 0.2), 0), 0.280_155_96);
+        assert_pinned(perlin3(Vector3::new(12.5, -3.25, 7.75), 42), -0.232_823_07);
+        assert_pinned(
+            perlin3(Vector3::new(-7.8, 4.1, -2.6), 123_456),
+            0.394_824_53,
+        );
+        assert_eq!(perlin3(Vector3::new(3.0, 5.0, -2.0), 0), 0.0);
+        assert_eq!(perlin3(Vector3::new(-2.0, 7.0, 1.0), 99), 0.0);
+    }
+
+    #[test]
+    fn simplex3_pinned() {
+        assert_pinned(simplex3(Vector3::new(0.3, 0.7, 0.2), 0), 0.438_102_78);
+        assert_pinned(simplex3(Vector3::new(12.5, -3.25, 7.75), 42), 0.258_134_48);
+        assert_pinned(
+            simplex3(Vector3::new(-7.8, 4.1, -2.6), 123_456),
+            0.088_165_28,
+        );
+    }
+
+    #[test]
+    fn worley_pinned() {
+        assert_pinned(worley(Vector2::new(0.3, 0.7), 0), 0.103_015_736);
+        assert_pinned(worley(Vector2::new(12.5, -3.25), 42), 0.481_970_85);
+        assert_pinned(worley(Vector2::new(-7.8, 4.1), 123_456), 0.268_400_9);
+    }
+
+    #[test]
+    fn fbm_pinned() {
+        let v = fbm(|p| simplex2(p, 7), Vector2::new(0.3, 0.7), 4, 2.0, 0.5);
+        assert_pinned(v, -0.399_235_04);
+        assert_eq!(
+            fbm(|p| simplex2(p, 7), Vector2::new(0.3, 0.7), 0, 2.0, 0.5),
+            0.0
+        );
+    }
+}
@@ -30,6 +30,8 @@ double  ntime_convertSeconds( ntime_t t );
 double  ntime_getRemainder( ntime_t t );
 char   *ntime_pretty( ntime_t t, int d );
 void    ntime_prettyBuf( char *str, int max, ntime_t t, int d );
+void    ntime_durationBuf( char *str, int max, ntime_t t );
+int     ntime_parseDuration( const char *str, ntime_t *t );
 
 /* set */
 void ntime_set( ntime_t t );
@@ -40,3 +42,8 @@ void ntime_incLagged( ntime_t t );
 /* misc */
 void ntime_refresh( void );
 void ntime_allowUpdate( int enable );
+
+/* scale */
+void   ntime_setScale( double scale );
+double ntime_getScale( void );
+double ntime_scaleDt( double dt );
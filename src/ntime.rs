@@ -1,7 +1,11 @@
-use crate::gettext::gettext;
-use formatx::formatx;
+use crate::ffi::ffi_guard;
+use crate::formatx_checked;
+use crate::gettext::{gettext, ngettext};
+use crate::log::warn_err;
+use crate::warn;
+use anyhow::Result;
 use std::collections::VecDeque;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_double, c_int, c_ulong};
 use std::sync::Mutex;
 
@@ -38,6 +42,11 @@ impl From<NTime> for u32 {
         t.0.try_into().unwrap()
     }
 }
+impl From<i64> for NTime {
+    fn from(t: i64) -> NTime {
+        NTime(t)
+    }
+}
 impl From<NTime> for i64 {
     fn from(t: NTime) -> i64 {
         t.0
@@ -80,43 +89,184 @@ impl NTime {
         let periods = self.periods();
         let seconds = self.seconds();
         // TODO try to move 2 to variable decimal length, but not that important
-        if cycles == 0 && periods == 0 {
-            formatx!(gettext("{:04d} s").to_string(), seconds).unwrap()
+        let p = periods as f64 + 0.0001 * seconds as f64;
+        let res = if cycles == 0 && periods == 0 {
+            formatx_checked!("{:04d} s", seconds)
         } else if cycles == 0 {
-            formatx!(
-                gettext("{p:.2f} s").to_string(),
-                p = periods as f64 + 0.0001 * seconds as f64
-            )
-            .unwrap()
+            formatx_checked!("{p:.2f} s", p = p)
         } else {
-            formatx!(
-                gettext("UST {c}:{p:.2f}").to_string(),
-                c = cycles,
-                p = periods as f64 + 0.0001 * seconds as f64
-            )
-            .unwrap()
+            formatx_checked!("UST {c}:{p:.2f}", c = cycles, p = p)
+        };
+        res.unwrap_or_else(|err| {
+            warn_err(err);
+            format!("UST {cycles}:{p:.2}")
+        })
+    }
+}
+
+/// A span of game time, as opposed to [`NTime`] which is a date.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+pub struct Duration(i64);
+impl From<Duration> for NTime {
+    fn from(d: Duration) -> NTime {
+        NTime(d.0)
+    }
+}
+impl From<NTime> for Duration {
+    fn from(t: NTime) -> Duration {
+        Duration(t.0)
+    }
+}
+impl From<Duration> for i64 {
+    fn from(d: Duration) -> i64 {
+        d.0
+    }
+}
+impl std::ops::Sub for NTime {
+    type Output = Duration;
+    fn sub(self, other: NTime) -> Duration {
+        Duration(self.0 - other.0)
+    }
+}
+#[allow(dead_code)]
+impl Duration {
+    const STU: i64 = 1_000;
+    const STP: i64 = 10_000 * Self::STU;
+    const SCU: i64 = 5_000 * Self::STP;
+
+    pub fn new(scu: i32, stp: i32, stu: i32) -> Duration {
+        NTime::new(scu, stp, stu).into()
+    }
+
+    /// Creates a duration from a number of seconds (STU), rounded to the internal resolution.
+    pub fn from_seconds(stu: f64) -> Duration {
+        Duration((stu * Self::STU as f64).round() as i64)
+    }
+
+    pub fn to_seconds(self) -> f64 {
+        self.0 as f64 / Self::STU as f64
+    }
+
+    /// Formats the duration as for example "1 cycle, 3 periods, 50 STU", which
+    /// [`parse_duration`] reads back to the same duration. Not translated, see
+    /// [`Duration::to_human`] for showing durations to the player.
+    pub fn to_canonical(&self) -> String {
+        self.format(
+            |n| match n {
+                1 => String::from("1 cycle"),
+                n => format!("{n} cycles"),
+            },
+            |n| match n {
+                1 => String::from("1 period"),
+                n => format!("{n} periods"),
+            },
+            |stu| format!("{stu} STU"),
+        )
+    }
+
+    /// Formats the duration for displaying to the player, like [`Duration::to_canonical`] but
+    /// with the units translated.
+    pub fn to_human(&self) -> String {
+        // Cycles fit in an i32 even for the longest durations
+        self.format(
+            |n| ngettext("{} cycle", "{} cycles", n as i32).replace("{}", &n.to_string()),
+            |n| ngettext("{} period", "{} periods", n as i32).replace("{}", &n.to_string()),
+            |stu| gettext("{} STU").replace("{}", stu),
+        )
+    }
+
+    fn format(
+        &self,
+        cycles: impl Fn(u64) -> String,
+        periods: impl Fn(u64) -> String,
+        stu: impl Fn(&str) -> String,
+    ) -> String {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let t = self.0.unsigned_abs();
+        let scu = t / Self::SCU as u64;
+        let stp = t % Self::SCU as u64 / Self::STP as u64;
+        let rem = t % Self::STP as u64;
+        let mut parts = Vec::new();
+        if scu > 0 {
+            parts.push(cycles(scu));
+        }
+        if stp > 0 {
+            parts.push(periods(stp));
+        }
+        if rem > 0 || parts.is_empty() {
+            let whole = rem / Self::STU as u64;
+            parts.push(match rem % Self::STU as u64 {
+                0 => stu(&whole.to_string()),
+                frac => stu(format!("{whole}.{frac:03}").trim_end_matches('0')),
+            });
         }
+        format!("{sign}{}", parts.join(", "))
+    }
+}
+impl std::fmt::Display for Duration {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_canonical())
     }
 }
 
+/// Parses durations such as "3 periods", "50 STU" or "1 cycle, 2.5 periods". Units are
+/// case-insensitive and can be cycles (SCU), periods (STP) or seconds (STU).
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let (neg, body) = match s.trim().strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.trim()),
+    };
+    let tokens: Vec<&str> = body
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .collect();
+    if tokens.is_empty() {
+        anyhow::bail!("empty duration");
+    }
+    let mut total: f64 = 0.0;
+    for pair in tokens.chunks(2) {
+        let [value, unit] = pair else {
+            anyhow::bail!("duration '{s}' has a value without unit");
+        };
+        let value: f64 = value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid number '{value}' in duration '{s}'"))?;
+        if !value.is_finite() || value < 0.0 {
+            anyhow::bail!("invalid number '{value}' in duration '{s}'");
+        }
+        let mult = match unit.to_lowercase().as_str() {
+            "cycle" | "cycles" | "scu" => Duration::SCU,
+            "period" | "periods" | "stp" => Duration::STP,
+            "second" | "seconds" | "stu" => Duration::STU,
+            _ => anyhow::bail!("unknown unit '{unit}' in duration '{s}'"),
+        };
+        total += value * mult as f64;
+    }
+    let total = total.round() as i64;
+    Ok(Duration(if neg { -total } else { total }))
+}
+
 static DEFERLIST: Mutex<VecDeque<NTime>> = Mutex::new(VecDeque::new());
 static TIME: Mutex<NTimeInternal> = Mutex::new(NTimeInternal {
     time: NTime(0),
     remainder: 0.,
 });
 static ENABLED: Mutex<bool> = Mutex::new(true);
+static SCALE: Mutex<f32> = Mutex::new(1.0);
 
 #[unsafe(no_mangle)]
 pub extern "C" fn ntime_update(dt: c_double) {
-    update(dt);
+    ffi_guard((), || {
+        update(dt);
+    })
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn ntime_create(scu: c_int, stp: c_int, stu: c_int) -> NTimeC {
-    NTime::new(scu, stp, stu).0
+    ffi_guard(0, || NTime::new(scu, stp, stu).0)
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn ntime_get() -> NTimeC {
-    get().0
+    ffi_guard(0, || get().0)
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn ntime_getR(
@@ -125,112 +275,171 @@ pub unsafe extern "C" fn ntime_getR(
     seconds: *mut c_int,
     rem: *mut c_double,
 ) {
-    let nt = TIME.lock().unwrap();
-    let t = nt.time;
-    unsafe {
-        *cycles = t.cycles();
-        *periods = t.periods();
-        *seconds = t.seconds();
-        *rem = nt.time.remainder() + nt.remainder;
-    }
+    ffi_guard((), || {
+        let nt = TIME.lock().unwrap();
+        let t = nt.time;
+        unsafe {
+            *cycles = t.cycles();
+            *periods = t.periods();
+            *seconds = t.seconds();
+            *rem = nt.time.remainder() + nt.remainder;
+        }
+    })
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn ntime_getCycles(t: NTimeC) -> c_int {
-    NTime(t).cycles()
+    ffi_guard(0, || NTime(t).cycles())
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn ntime_getPeriods(t: NTimeC) -> c_int {
-    NTime(t).periods()
+    ffi_guard(0, || NTime(t).periods())
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn ntime_getSeconds(t: NTimeC) -> c_int {
-    NTime(t).seconds()
+    ffi_guard(0, || NTime(t).seconds())
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn ntime_convertSeconds(t: NTimeC) -> c_double {
-    NTime(t).to_seconds()
+    ffi_guard(0.0, || NTime(t).to_seconds())
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn ntime_getRemainder(t: NTimeC) -> c_double {
-    NTime(t).remainder()
+    ffi_guard(0.0, || NTime(t).remainder())
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn ntime_pretty(t: NTimeC, d: c_int) -> *mut c_char {
-    let mut str: [c_char; 64] = [0; 64];
-    unsafe {
-        ntime_prettyBuf(
-            str.as_mut_ptr(),
-            ::core::mem::size_of::<[c_char; 64]>() as c_ulong as c_int,
-            t,
-            d,
-        );
-        naevc::strdup(str.as_mut_ptr())
-    }
-}
-#[unsafe(no_mangle)]
-pub unsafe extern "C" fn ntime_prettyBuf(cstr: *mut c_char, max: c_int, t: NTimeC, d: c_int) {
-    let nt = if t == 0 {
-        TIME.lock().unwrap().time
-    } else {
-        NTime(t)
-    };
-    let cycles = nt.cycles();
-    let periods = nt.periods();
-    let seconds = nt.seconds();
-    let max = max as usize;
-    if cycles == 0 && periods == 0 {
-        let cmsg = CString::new(gettext("%04d s")).unwrap();
-        unsafe {
-            naevc::scnprintf(cstr, max, cmsg.as_ptr().cast(), seconds);
-        }
-    } else if cycles == 0 || d == 0 {
-        let cmsg = CString::new(gettext("%.*f p")).unwrap();
+    ffi_guard(std::ptr::null_mut(), || {
+        let mut str: [c_char; 64] = [0; 64];
         unsafe {
-            naevc::scnprintf(
-                cstr,
-                max,
-                cmsg.as_ptr().cast(),
+            ntime_prettyBuf(
+                str.as_mut_ptr(),
+                ::core::mem::size_of::<[c_char; 64]>() as c_ulong as c_int,
+                t,
                 d,
-                periods as c_double + 0.0001 * seconds as c_double,
             );
+            naevc::strdup(str.as_mut_ptr())
         }
-    } else {
-        let cmsg = CString::new(gettext("UST %d:%.*f")).unwrap();
+    })
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ntime_prettyBuf(cstr: *mut c_char, max: c_int, t: NTimeC, d: c_int) {
+    ffi_guard((), || {
+        let nt = if t == 0 {
+            TIME.lock().unwrap().time
+        } else {
+            NTime(t)
+        };
+        let cycles = nt.cycles();
+        let periods = nt.periods();
+        let seconds = nt.seconds();
+        let max = max as usize;
+        if cycles == 0 && periods == 0 {
+            let cmsg = CString::new(gettext("%04d s")).unwrap();
+            unsafe {
+                naevc::scnprintf(cstr, max, cmsg.as_ptr().cast(), seconds);
+            }
+        } else if cycles == 0 || d == 0 {
+            let cmsg = CString::new(gettext("%.*f p")).unwrap();
+            unsafe {
+                naevc::scnprintf(
+                    cstr,
+                    max,
+                    cmsg.as_ptr().cast(),
+                    d,
+                    periods as c_double + 0.0001 * seconds as c_double,
+                );
+            }
+        } else {
+            let cmsg = CString::new(gettext("UST %d:%.*f")).unwrap();
+            unsafe {
+                naevc::scnprintf(
+                    cstr,
+                    max,
+                    cmsg.as_ptr().cast(),
+                    cycles,
+                    d,
+                    periods as c_double + 0.0001 * seconds as c_double,
+                );
+            }
+        };
+    })
+}
+/// Parses a duration such as "3 periods" or "1 cycle, 50 STU" into `t`. Returns 0 on success.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ntime_parseDuration(cstr: *const c_char, t: *mut NTimeC) -> c_int {
+    ffi_guard(-1, || {
+        let s = unsafe { CStr::from_ptr(cstr) }.to_string_lossy();
+        match parse_duration(&s) {
+            Ok(d) => {
+                unsafe {
+                    *t = d.into();
+                }
+                0
+            }
+            Err(_) => -1,
+        }
+    })
+}
+/// Writes a time as a translated duration such as "3 periods, 50 STU" for displaying to the
+/// player.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ntime_durationBuf(cstr: *mut c_char, max: c_int, t: NTimeC) {
+    ffi_guard((), || {
+        let s = CString::new(Duration::from(NTime(t)).to_human()).unwrap_or_default();
         unsafe {
-            naevc::scnprintf(
-                cstr,
-                max,
-                cmsg.as_ptr().cast(),
-                cycles,
-                d,
-                periods as c_double + 0.0001 * seconds as c_double,
-            );
+            naevc::scnprintf(cstr, max as usize, c"%s".as_ptr(), s.as_ptr());
         }
-    };
+    })
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn ntime_set(t: NTimeC) {
-    set(NTime(t));
+    ffi_guard((), || {
+        set(NTime(t));
+    })
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn ntime_setR(cycles: c_int, periods: c_int, seconds: c_int, rem: c_double) {
-    set_remainder(NTime::new(cycles, periods, seconds), rem);
+    ffi_guard((), || {
+        set_remainder(NTime::new(cycles, periods, seconds), rem);
+    })
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn ntime_inc(tc: NTimeC) {
-    inc(NTime(tc));
+    ffi_guard((), || {
+        inc(NTime(tc));
+    })
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn ntime_allowUpdate(enable: c_int) {
-    allow_update(enable != 0);
+    ffi_guard((), || {
+        allow_update(enable != 0);
+    })
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn ntime_incLagged(t: NTimeC) {
-    inc_queue(NTime(t));
+    ffi_guard((), || {
+        inc_queue(NTime(t));
+    })
+}
+#[unsafe(no_mangle)]
+pub extern "C" fn ntime_setScale(scale: c_double) {
+    ffi_guard((), || {
+        set_scale(scale as f32);
+    })
+}
+#[unsafe(no_mangle)]
+pub extern "C" fn ntime_getScale() -> c_double {
+    ffi_guard(1.0, || scale() as c_double)
+}
+#[unsafe(no_mangle)]
+pub extern "C" fn ntime_scaleDt(dt: c_double) -> c_double {
+    ffi_guard(dt, || scaled_dt(dt))
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn ntime_refresh() {
-    refresh();
+    ffi_guard((), || {
+        refresh();
+    })
 }
 
 pub fn get() -> NTime {
@@ -263,6 +472,27 @@ pub fn update(dt: f64) {
     unsafe { naevc::hooks_updateDate(inc) };
 }
 
+/// Sets the game time scale, 1 is normal speed and 0 pauses the game time. Negative or
+/// non-finite scales are rejected.
+pub fn set_scale(scale: f32) {
+    if !scale.is_finite() || scale < 0.0 {
+        warn!(gettext("invalid time scale '{}', ignoring"), scale);
+        return;
+    }
+    *SCALE.lock().unwrap() = scale;
+}
+
+pub fn scale() -> f32 {
+    *SCALE.lock().unwrap()
+}
+
+/// Scales a real time delta by the time scale. The main loop uses this to compute the game dt
+/// that time, physics and the camera advance with, while real-time elements such as the UI
+/// keep using the unscaled dt.
+pub fn scaled_dt(dt: f64) -> f64 {
+    dt * scale() as f64
+}
+
 pub fn allow_update(enable: bool) {
     *ENABLED.lock().unwrap() = enable;
 }
@@ -291,3 +521,59 @@ pub fn refresh() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_round_trip() {
+        for d in [
+            Duration(0),
+            Duration::new(0, 0, 50),
+            Duration::new(1, 3, 50),
+            Duration::new(12, 0, 0),
+            Duration::new(0, 4999, 9999),
+            Duration(2_500),
+            Duration(1),
+            Duration(Duration::STP + 250),
+            Duration(-Duration::STU),
+            Duration(-(Duration::SCU + 2 * Duration::STP + 1_500)),
+        ] {
+            let s = d.to_canonical();
+            assert_eq!(parse_duration(&s).unwrap(), d, "'{s}'");
+        }
+    }
+
+    #[test]
+    fn duration_canonical() {
+        assert_eq!(Duration(0).to_canonical(), "0 STU");
+        assert_eq!(
+            Duration::new(1, 1, 1).to_canonical(),
+            "1 cycle, 1 period, 1 STU"
+        );
+        assert_eq!(Duration::new(2, 3, 0).to_canonical(), "2 cycles, 3 periods");
+        assert_eq!(Duration(2_500).to_canonical(), "2.5 STU");
+        assert_eq!(Duration(10).to_canonical(), "0.01 STU");
+        assert_eq!(
+            Duration(-(Duration::STP + 250)).to_canonical(),
+            "-1 period, 0.25 STU"
+        );
+        for s in [
+            "3 periods",
+            "50 STU",
+            "1 cycle, 2 periods, 0.5 STU",
+            "-7.125 STU",
+        ] {
+            assert_eq!(parse_duration(s).unwrap().to_canonical(), s);
+        }
+    }
+
+    #[test]
+    fn duration_extremes() {
+        // The magnitude of i64::MIN doesn't fit in an i64
+        let s = Duration(i64::MIN).to_canonical();
+        assert!(s.starts_with("-184467440 cycles"), "'{s}'");
+        assert!(!Duration(i64::MAX).to_canonical().starts_with('-'));
+    }
+}
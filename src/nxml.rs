@@ -1,8 +1,12 @@
 use anyhow::Result;
+use nalgebra::Vector2;
 use roxmltree::Node;
 use std::ffi::CString;
 use std::io::Error;
 
+use crate::ntime::NTime;
+use crate::vec2::Vec2;
+
 #[macro_export]
 macro_rules! nxml_err_attr_missing {
     ($nodetype: expr, $name: expr) => {
@@ -87,3 +91,353 @@ pub fn node_texturepath(node: Node, default: &str) -> Result<String> {
         false => format!("{default}{path}"),
     })
 }
+
+/// Types that can be stored as fields of the XML save files, using the same representation as
+/// the C side so that saves round-trip between both.
+#[allow(dead_code)]
+pub trait SaveField: Sized {
+    /// Writes the value as an element called `name`.
+    fn write(&self, writer: &mut SaveWriter, name: &str) -> Result<()>;
+    /// Reads the value from its element.
+    fn read(node: Node) -> Result<Self>;
+}
+
+/// Implements SaveField for types stored as element text with a given formatting.
+macro_rules! save_field_text {
+    ($ty: ty, $v: ident => $fmt: expr, $s: ident => $parse: expr) => {
+        impl SaveField for $ty {
+            fn write(&self, writer: &mut SaveWriter, name: &str) -> Result<()> {
+                let $v = self;
+                writer.elem(name, &$fmt)
+            }
+            fn read(node: Node) -> Result<Self> {
+                let $s = node_str(node)?.trim();
+                Ok($parse)
+            }
+        }
+    };
+}
+
+// Matches the "%d", "%f", PRIu64, etc. formats used by the C save code
+save_field_text!(i32, v => v.to_string(), s => s.parse()?);
+save_field_text!(u32, v => v.to_string(), s => s.parse()?);
+save_field_text!(i64, v => v.to_string(), s => s.parse()?);
+save_field_text!(u64, v => v.to_string(), s => s.parse()?);
+save_field_text!(f32, v => format!("{v:.6}"), s => s.parse()?);
+save_field_text!(f64, v => format!("{v:.6}"), s => s.parse()?);
+save_field_text!(bool, v => (*v as i32).to_string(), s => s.parse::<i64>()? != 0);
+save_field_text!(NTime, v => i64::from(*v).to_string(), s => NTime::from(s.parse::<i64>()?));
+
+impl SaveField for String {
+    fn write(&self, writer: &mut SaveWriter, name: &str) -> Result<()> {
+        writer.elem(name, self)
+    }
+    fn read(node: Node) -> Result<Self> {
+        // Empty elements are empty strings, not errors
+        Ok(String::from(node.text().unwrap_or_default()))
+    }
+}
+
+/// Vectors are stored as the "x" and "y" attributes of an empty element.
+impl SaveField for Vector2<f64> {
+    fn write(&self, writer: &mut SaveWriter, name: &str) -> Result<()> {
+        writer.start_elem(name)?;
+        writer.attr("x", &format!("{:.6}", self.x))?;
+        writer.attr("y", &format!("{:.6}", self.y))?;
+        writer.end_elem()
+    }
+    fn read(node: Node) -> Result<Self> {
+        let coord = |attr: &str| -> Result<f64> {
+            match node.attribute(attr) {
+                Some(v) => Ok(v.trim().parse()?),
+                None => nxml_err_attr_missing!(node.tag_name().name(), attr),
+            }
+        };
+        Ok(Vector2::new(coord("x")?, coord("y")?))
+    }
+}
+
+impl SaveField for Vec2 {
+    fn write(&self, writer: &mut SaveWriter, name: &str) -> Result<()> {
+        Vector2::<f64>::from(*self).write(writer, name)
+    }
+    fn read(node: Node) -> Result<Self> {
+        Ok(Vector2::<f64>::read(node)?.into())
+    }
+}
+
+#[allow(dead_code)]
+fn xml_cstring(s: &str) -> Result<CString> {
+    Ok(CString::new(s)?)
+}
+
+/// Writes fields into a save file being written by the C side with libxml2.
+#[allow(dead_code)]
+pub struct SaveWriter {
+    writer: naevc::xmlTextWriterPtr,
+}
+
+#[allow(dead_code)]
+impl SaveWriter {
+    /// Wraps the writer passed in from C.
+    ///
+    /// # Safety
+    ///
+    /// The writer must be valid and not be used elsewhere while the `SaveWriter` is alive.
+    pub unsafe fn from_raw(writer: naevc::xmlTextWriterPtr) -> Self {
+        SaveWriter { writer }
+    }
+
+    /// Opens a new element, which has to be closed with [`SaveWriter::end_elem`].
+    pub fn start_elem(&mut self, name: &str) -> Result<()> {
+        let cname = xml_cstring(name)?;
+        match unsafe { naevc::xmlTextWriterStartElement(self.writer, cname.as_ptr() as _) } {
+            ..=-1 => anyhow::bail!("xmlw: unable to create start element '{name}'"),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn end_elem(&mut self) -> Result<()> {
+        match unsafe { naevc::xmlTextWriterEndElement(self.writer) } {
+            ..=-1 => anyhow::bail!("xmlw: unable to create end element"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes an element containing only text.
+    pub fn elem(&mut self, name: &str, text: &str) -> Result<()> {
+        let cname = xml_cstring(name)?;
+        let ctext = xml_cstring(text)?;
+        match unsafe {
+            naevc::xmlTextWriterWriteElement(self.writer, cname.as_ptr() as _, ctext.as_ptr() as _)
+        } {
+            ..=-1 => anyhow::bail!("xmlw: unable to write element '{name}'"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes an attribute of the currently open element.
+    pub fn attr(&mut self, name: &str, value: &str) -> Result<()> {
+        let cname = xml_cstring(name)?;
+        let cvalue = xml_cstring(value)?;
+        match unsafe {
+            naevc::xmlTextWriterWriteAttribute(
+                self.writer,
+                cname.as_ptr() as _,
+                cvalue.as_ptr() as _,
+            )
+        } {
+            ..=-1 => anyhow::bail!("xmlw: unable to write element attribute '{name}'"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes a typed field.
+    pub fn write_field<T: SaveField>(&mut self, name: &str, value: &T) -> Result<()> {
+        value.write(self, name)
+    }
+
+    /// Writes a nested element, with `f` writing its contents.
+    pub fn write_elem(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        self.start_elem(name)?;
+        f(self)?;
+        self.end_elem()
+    }
+}
+
+/// Reads fields from an element of a save file.
+///
+/// The Naev version the save was written with can be set, so that fields added in later versions
+/// can fall back to defaults when loading older saves with [`SaveReader::read_field_since`].
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct SaveReader<'a> {
+    node: Node<'a, 'a>,
+    version: Option<semver::Version>,
+}
+
+#[allow(dead_code)]
+impl<'a> SaveReader<'a> {
+    pub fn new(node: Node<'a, 'a>) -> Self {
+        SaveReader {
+            node,
+            version: None,
+        }
+    }
+
+    /// Sets the version the save was written with, as found in its "version/naev" element.
+    pub fn with_version(mut self, version: &str) -> Result<Self> {
+        self.version = Some(semver::Version::parse(version)?);
+        Ok(self)
+    }
+
+    /// Version the save was written with, if known.
+    pub fn version(&self) -> Option<&semver::Version> {
+        self.version.as_ref()
+    }
+
+    pub fn node(&self) -> Node<'a, 'a> {
+        self.node
+    }
+
+    fn find(&self, name: &str) -> Option<Node<'a, 'a>> {
+        self.node
+            .children()
+            .find(|n| n.is_element() && n.has_tag_name(name))
+    }
+
+    /// Gets a child element to read nested fields from, keeping the version.
+    pub fn child(&self, name: &str) -> Option<Self> {
+        self.find(name).map(|node| SaveReader {
+            node,
+            version: self.version.clone(),
+        })
+    }
+
+    /// Iterates over all the child elements with a given name.
+    pub fn children<'s>(&'s self, name: &'s str) -> impl Iterator<Item = Self> + 's {
+        self.node
+            .children()
+            .filter(move |n| n.is_element() && n.has_tag_name(name))
+            .map(|node| SaveReader {
+                node,
+                version: self.version.clone(),
+            })
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&'a str> {
+        self.node.attribute(name)
+    }
+
+    /// Reads a typed field, which must be present.
+    pub fn read_field<T: SaveField>(&self, name: &str) -> Result<T> {
+        match self.find(name) {
+            Some(node) => {
+                T::read(node).map_err(|e| e.context(format!("unable to read save field '{name}'")))
+            }
+            None => anyhow::bail!(
+                "{nodetype} missing save field '{name}'",
+                nodetype = self.node.tag_name().name()
+            ),
+        }
+    }
+
+    /// Reads a typed field, using `default` if it is not present.
+    pub fn read_field_or<T: SaveField>(&self, name: &str, default: T) -> Result<T> {
+        match self.find(name) {
+            Some(_) => self.read_field(name),
+            None => Ok(default),
+        }
+    }
+
+    /// Reads a field that was added in version `since`. Saves from older versions, or of an
+    /// unknown version, use `default` when it is missing, while it is an error for newer ones.
+    pub fn read_field_since<T: SaveField>(
+        &self,
+        name: &str,
+        since: &semver::Version,
+        default: T,
+    ) -> Result<T> {
+        let required = match &self.version {
+            Some(version) => version >= since,
+            None => false,
+        };
+        match required {
+            true => self.read_field(name),
+            false => self.read_field_or(name, default),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    /// Writes a "save" element with `f` and returns the resulting document.
+    fn write_xml(f: impl FnOnce(&mut SaveWriter) -> Result<()>) -> String {
+        unsafe {
+            let buf = naevc::xmlBufferCreate();
+            let writer = naevc::xmlNewTextWriterMemory(buf, 0);
+            assert!(!writer.is_null());
+            naevc::xmlTextWriterStartDocument(
+                writer,
+                std::ptr::null(),
+                std::ptr::null(),
+                std::ptr::null(),
+            );
+            SaveWriter::from_raw(writer).write_elem("save", f).unwrap();
+            naevc::xmlTextWriterEndDocument(writer);
+            // Freeing the writer flushes it into the buffer
+            naevc::xmlFreeTextWriter(writer);
+            let xml = CStr::from_ptr(naevc::xmlBufferContent(buf) as *const _)
+                .to_string_lossy()
+                .into_owned();
+            naevc::xmlBufferFree(buf);
+            xml
+        }
+    }
+
+    #[test]
+    fn save_field_round_trip() {
+        let xml = write_xml(|w| {
+            w.write_field("i32", &-5i32)?;
+            w.write_field("u32", &7u32)?;
+            w.write_field("i64", &-(1i64 << 40))?;
+            w.write_field("u64", &u64::MAX)?;
+            w.write_field("f32", &1.5f32)?;
+            w.write_field("f64", &-1234.125f64)?;
+            w.write_field("true", &true)?;
+            w.write_field("false", &false)?;
+            w.write_field("ntime", &NTime::new(1, 2, 3))?;
+            w.write_field("string", &String::from("a <b> & \"c\""))?;
+            w.write_field("empty", &String::new())?;
+            w.write_field("vector2", &Vector2::new(1.25, -3.5))?;
+            w.write_field("vec2", &Vec2::new(-0.5, 100.0))?;
+            w.write_elem("nested", |w| w.write_field("inner", &3i32))
+        });
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let r = SaveReader::new(doc.root_element());
+        assert_eq!(r.read_field::<i32>("i32").unwrap(), -5);
+        assert_eq!(r.read_field::<u32>("u32").unwrap(), 7);
+        assert_eq!(r.read_field::<i64>("i64").unwrap(), -(1 << 40));
+        assert_eq!(r.read_field::<u64>("u64").unwrap(), u64::MAX);
+        assert_eq!(r.read_field::<f32>("f32").unwrap(), 1.5);
+        assert_eq!(r.read_field::<f64>("f64").unwrap(), -1234.125);
+        assert!(r.read_field::<bool>("true").unwrap());
+        assert!(!r.read_field::<bool>("false").unwrap());
+        assert_eq!(r.read_field::<NTime>("ntime").unwrap(), NTime::new(1, 2, 3));
+        assert_eq!(r.read_field::<String>("string").unwrap(), "a <b> & \"c\"");
+        assert_eq!(r.read_field::<String>("empty").unwrap(), "");
+        assert_eq!(
+            r.read_field::<Vector2<f64>>("vector2").unwrap(),
+            Vector2::new(1.25, -3.5)
+        );
+        assert_eq!(
+            Vector2::from(r.read_field::<Vec2>("vec2").unwrap()),
+            Vector2::new(-0.5, 100.0)
+        );
+        let nested = r.child("nested").unwrap();
+        assert_eq!(nested.read_field::<i32>("inner").unwrap(), 3);
+    }
+
+    #[test]
+    fn save_field_missing() {
+        let xml = write_xml(|w| w.write_field("present", &1i32));
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let r = SaveReader::new(doc.root_element());
+        assert!(r.read_field::<i32>("missing").is_err());
+        assert_eq!(r.read_field_or("missing", 2i32).unwrap(), 2);
+        assert_eq!(r.read_field_or("present", 2i32).unwrap(), 1);
+
+        let since = semver::Version::new(0, 13, 0);
+        let old = r.clone().with_version("0.12.0").unwrap();
+        assert_eq!(old.read_field_since("missing", &since, 3i32).unwrap(), 3);
+        let new = r.with_version("0.13.0").unwrap();
+        assert!(new.read_field_since::<i32>("missing", &since, 3).is_err());
+    }
+}
@@ -32,6 +32,7 @@
 
 #include "opengl.h"
 
+#include "colour.h"
 #include "conf.h"
 #include "debug.h" // IWYU pragma: keep
 #include "gltf.h"
@@ -71,48 +72,6 @@ static int gl_defState( void );
  * M I S C
  *
  */
-/**
- * @brief Takes a screenshot.
- *
- *    @param filename PhysicsFS path (e.g., "screenshots/screenshot042.png") of
- * the file to save screenshot as.
- */
-void gl_screenshot( const char *filename )
-{
-   GLubyte     *screenbuf;
-   SDL_RWops   *rw;
-   SDL_Surface *surface;
-   int          w, h;
-
-   /* Allocate data. */
-   w         = gl_screen.rw;
-   h         = gl_screen.rh;
-   screenbuf = malloc( sizeof( GLubyte ) * 3 * w * h );
-   surface   = SDL_CreateRGBSurface( 0, w, h, 24, RGBAMASK );
-
-   /* Read pixels from buffer -- SLOW. */
-   glPixelStorei( GL_PACK_ALIGNMENT, 1 ); /* Force them to pack the bytes. */
-   glReadPixels( 0, 0, w, h, GL_RGB, GL_UNSIGNED_BYTE, screenbuf );
-
-   /* Convert data. */
-   for ( int i = 0; i < h; i++ )
-      memcpy( (GLubyte *)surface->pixels + i * surface->pitch,
-              &screenbuf[( h - i - 1 ) * ( 3 * w )], 3 * w );
-   free( screenbuf );
-
-   /* Save PNG. */
-   if ( !( rw = PHYSFSRWOPS_openWrite( filename ) ) )
-      WARN( _( "Aborting screenshot" ) );
-   else
-      IMG_SavePNG_RW( surface, rw, 1 );
-
-   /* Check to see if an error occurred. */
-   gl_checkErr();
-
-   /* Free memory. */
-   SDL_FreeSurface( surface );
-}
-
 void gl_saveFboDepth( GLuint fbo, const char *filename )
 {
    GLfloat     *screenbuf;
@@ -271,7 +230,7 @@ static int gl_getGLInfo( void )
    SDL_GL_GetAttribute( SDL_GL_DOUBLEBUFFER, &doublebuf );
    if ( doublebuf )
       gl_screen.flags |= OPENGL_DOUBLEBUF;
-   if ( GLAD_GL_ARB_shader_subroutine && glGetSubroutineIndex &&
+   if ( gl_hasSubroutines() && glGetSubroutineIndex &&
         glGetSubroutineUniformLocation && glUniformSubroutinesuiv )
       gl_screen.flags |= OPENGL_SUBROUTINES;
 
@@ -405,7 +364,7 @@ int gl_init( void )
  */
 void gl_resize_c( void )
 {
-   glViewport( 0, 0, gl_screen.rw, gl_screen.rh );
+   gl_screenViewport();
    gl_setDefViewport( 0, 0, gl_screen.nw, gl_screen.nh );
    gl_defViewport();
 
@@ -483,13 +442,54 @@ void gl_defViewport( void )
    gl_viewport( gl_view_x, gl_view_y, gl_view_w, gl_view_h );
 }
 
+/**
+ * @brief Sets the OpenGL viewport to the drawing area of the current
+ * framebuffer.
+ *
+ * The drawing area of the window can be offset when letterboxing, while the
+ * screen framebuffers always start at the origin.
+ */
+void gl_screenViewport( void )
+{
+   if ( gl_screen.current_fbo == 0 )
+      glViewport( gl_screen.vx, gl_screen.vy, gl_screen.rw, gl_screen.rh );
+   else if ( ( gl_screen.scaled_fbo != 0 ) &&
+             ( gl_screen.current_fbo == gl_screen.scaled_fbo ) )
+      glViewport( 0, 0, gl_screen.scaled_w, gl_screen.scaled_h );
+   else
+      glViewport( 0, 0, gl_screen.rw, gl_screen.rh );
+}
+
+/**
+ * @brief Clears the window framebuffer, filling the area outside of the
+ * drawing area with the letterbox colour.
+ *
+ * Leaves the window framebuffer bound.
+ */
+void gl_clearWindow( void )
+{
+   const glColour *c = &gl_screen.letterbox;
+   glBindFramebuffer( GL_FRAMEBUFFER, 0 );
+   glClearColor( c->r, c->g, c->b, 1. );
+   glClear( GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT );
+   glClearColor( 0., 0., 0., 1. );
+   if ( ( gl_screen.vx != 0 ) || ( gl_screen.vy != 0 ) ) {
+      GLboolean scissor = glIsEnabled( GL_SCISSOR_TEST );
+      glEnable( GL_SCISSOR_TEST );
+      glScissor( gl_screen.vx, gl_screen.vy, gl_screen.rw, gl_screen.rh );
+      glClear( GL_COLOR_BUFFER_BIT );
+      if ( !scissor )
+         glDisable( GL_SCISSOR_TEST );
+   }
+}
+
 /**
  * @brief Translates the window position to screen position.
  */
 void gl_windowToScreenPos( int *sx, int *sy, int wx, int wy )
 {
-   wx /= gl_screen.dwscale;
-   wy /= gl_screen.dhscale;
+   wx = wx / gl_screen.dwscale - gl_screen.vx;
+   wy = wy / gl_screen.dhscale - gl_screen.vy;
 
    *sx = gl_screen.mxscale * (double)wx - (double)gl_screen.x;
    *sy =
@@ -501,9 +501,9 @@ void gl_windowToScreenPos( int *sx, int *sy, int wx, int wy )
  */
 void gl_screenToWindowPos( int *wx, int *wy, int sx, int sy )
 {
-   *wx = ( sx + (double)gl_screen.x ) / gl_screen.mxscale;
-   *wy =
-      (double)gl_screen.rh - ( sy + (double)gl_screen.y ) / gl_screen.myscale;
+   *wx = ( sx + (double)gl_screen.x ) / gl_screen.mxscale + gl_screen.vx;
+   *wy = (double)gl_screen.rh -
+         ( sy + (double)gl_screen.y ) / gl_screen.myscale + gl_screen.vy;
 
    *wx *= gl_screen.dwscale;
    *wy *= gl_screen.dhscale;
@@ -604,14 +604,17 @@ GLenum gl_stringToBlendFactor( const char *s )
  */
 void gl_colourblind( void )
 {
+   GLfloat daltonize[9];
+
    /* Load up shader uniforms. */
    glUseProgram( shaders.colourblind_sim.program );
    glUniform1i( shaders.colourblind_sim.type, conf.colourblind_type );
    glUniform1f( shaders.colourblind_sim.intensity, conf.colourblind_sim );
    glUseProgram( shaders.colourblind_correct.program );
-   glUniform1i( shaders.colourblind_correct.type, conf.colourblind_type );
-   glUniform1f( shaders.colourblind_correct.intensity,
-                conf.colourblind_correct );
+   col_daltonizeMatrix( conf.colourblind_type, conf.colourblind_correct,
+                        daltonize );
+   glUniformMatrix3fv( shaders.colourblind_correct.daltonize, 1, GL_FALSE,
+                       daltonize );
    glUseProgram( 0 );
 
    /* See if we have to correct. */
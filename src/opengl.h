@@ -51,6 +51,9 @@ typedef struct glInfo_ {
     * offsets. */
    int           rw;                   /**< Real window width. */
    int           rh;                   /**< Real window height. */
+   int           vx;                   /**< Real X offset of drawing area. */
+   int           vy;                   /**< Real Y offset of drawing area. */
+   glColour      letterbox;            /**< Colour of the letterbox bars. */
    double        scale;                /**< Scale factor. */
    double        wscale;               /**< Width scale factor. */
    double        hscale;               /**< Height scale factor. */
@@ -70,6 +73,10 @@ typedef struct glInfo_ {
    SDL_Window   *window;               /**< Window for SDL2. */
    SDL_GLContext context;              /**< Context for OpenGL. */
    GLuint        current_fbo;          /**< Current framebuffer. */
+   GLuint        scaled_fbo;   /**< Framebuffer the world is rendered to when
+                                    using a render scale, or 0. */
+   int           scaled_w;     /**< Width of the scaled framebuffer. */
+   int           scaled_h;     /**< Height of the scaled framebuffer. */
    GLuint        fbo[OPENGL_NUM_FBOS]; /**< Framebuffers. */
    GLuint        fbo_tex[OPENGL_NUM_FBOS]; /**< Texture for framebuffers. */
    GLuint
@@ -100,17 +107,24 @@ void gl_screenToWindowPos( int *wx, int *wy, int sx, int sy );
 void gl_viewport( int x, int y, int w, int h );
 void gl_defViewport( void );
 void gl_setDefViewport( int x, int y, int w, int h );
+void gl_screenViewport( void );
+int  gl_featureLevel( void ); /* Implemented in Rust. */
+int  gl_hasSubroutines( void ); /* Implemented in Rust. */
+int  gl_reloadShaders( void ); /* Implemented in Rust. */
+void gl_setVsync( int vsync ); /* Implemented in Rust. */
+void gl_clearWindow( void );
 int  gl_setupFullscreen( void );
 
 /*
  * misc
  */
 void   gl_colourblind( void );
+double gl_maxAnisotropy( void ); /* Implemented in Rust. */
 GLenum gl_stringToFilter( const char *s );
 GLenum gl_stringToClamp( const char *s );
 GLenum gl_stringToBlendFunc( const char *s );
 GLenum gl_stringToBlendFactor( const char *s );
-void   gl_screenshot( const char *filename );
+void   gl_screenshot( const char *filename ); /* Implemented in Rust. */
 void   gl_saveFboDepth( GLuint fbo, const char *filename );
 #ifdef DEBUGGING
 #define gl_debugGroupStart()                                                   \
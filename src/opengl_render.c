@@ -1317,6 +1317,10 @@ void gl_clipRect( int x, int y, int w, int h )
    ry = ( y + gl_screen.y ) / gl_screen.myscale;
    rw = w / gl_screen.mxscale;
    rh = h / gl_screen.myscale;
+   if ( gl_screen.current_fbo == 0 ) {
+      rx += gl_screen.vx;
+      ry += gl_screen.vy;
+   }
    glScissor( rx, ry, rw, rh );
    glEnable( GL_SCISSOR_TEST );
 }
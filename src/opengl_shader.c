@@ -252,9 +252,11 @@ GLuint gl_program_backend( const char *vertfile, const char *fragfile,
    snprintf( prepend, sizeof( prepend ) - 1,
              "#version %d\n\n#define GLSL_VERSION %d\n", gl_screen.glsl,
              gl_screen.glsl );
-   if ( gl_has( OPENGL_SUBROUTINES ) )
-      strncat( prepend, "#define HAS_GL_ARB_shader_subroutine 1\n",
-               sizeof( prepend ) - strlen( prepend ) - 1 );
+   strncat( prepend,
+            gl_has( OPENGL_SUBROUTINES )
+               ? "#define HAS_GL_ARB_shader_subroutine 1\n"
+               : "#define HAS_GL_ARB_shader_subroutine 0\n",
+            sizeof( prepend ) - strlen( prepend ) - 1 );
    if ( prependtext != NULL )
       strncat( prepend, prependtext,
                sizeof( prepend ) - strlen( prepend ) - 1 );
@@ -1035,7 +1035,7 @@ glTexture *gl_resizeTexture( const glTexture *texture, double scale )
 
    glViewport( 0, 0, nw, nh );
    glDrawArrays( GL_TRIANGLE_STRIP, 0, 4 );
-   glViewport( 0, 0, gl_screen.rw, gl_screen.rh );
+   gl_screenViewport();
 
    glDisableVertexAttribArray( shaders.resize.vertex );
    glUseProgram( 0 );
@@ -65,6 +65,13 @@ USE_RESULT glTexture *gl_rawTexture( const char *name, GLuint tex, double w,
  * Clean up.
  */
 void gl_freeTexture( glTexture *texture );
+void gl_reapplySamplerDefaults( void );
+
+/*
+ * Preloading.
+ */
+void tex_preloadSystem( const char *name, int nebula );
+void tex_preloadSystemClear( void );
 
 /*
  * FBO stuff.
@@ -29,6 +29,7 @@
 #include "nebula.h"
 #include "nfile.h"
 #include "nstring.h"
+#include "opengl.h"
 #include "pause.h"
 #include "player.h"
 #include "plugin.h"
@@ -104,9 +105,12 @@ static int  opt_videoSave( unsigned int wid, const char *str );
 static void opt_videoDefaults( unsigned int wid, const char *str );
 static void opt_getVideoMode( int *w, int *h, int *fullscreen );
 static void opt_setGammaCorrection( unsigned int wid, const char *str );
+static void opt_setRenderScale( unsigned int wid, const char *str );
 static void opt_setScalefactor( unsigned int wid, const char *str );
 static void opt_setZoomFar( unsigned int wid, const char *str );
 static void opt_setZoomNear( unsigned int wid, const char *str );
+static void opt_setAnisotropy( unsigned int wid, const char *str );
+static int  opt_hasAnisotropy( void );
 static void opt_checkHealth( unsigned int wid, const char *str );
 static void opt_checkViewport( unsigned int wid, const char *str );
 static void opt_checkRestart( unsigned int wid, const char *str );
@@ -228,6 +232,7 @@ static void opt_close( unsigned int wid, const char *name )
    sound_volume( conf.sound );
    music_volume( conf.music );
    render_setGamma( conf.gamma_correction );
+   render_setScale( conf.render_scale );
 
    window_destroy( opt_wid );
    opt_wid = 0;
@@ -1426,6 +1431,12 @@ static void opt_video( unsigned int wid )
                     -log( 3. ), log( 3. ), log( conf.gamma_correction ),
                     opt_setGammaCorrection );
    opt_setGammaCorrection( wid, "fadGammaCorrection" );
+   y -= 30;
+   window_addText( wid, x, y - 3, 130, 20, 0, "txtRenderScale", NULL, NULL,
+                   NULL );
+   window_addFader( wid, x + 140, y, cw - 160, 20, "fadRenderScale", 0.25, 1.,
+                    conf.render_scale, opt_setRenderScale );
+   opt_setRenderScale( wid, "fadRenderScale" );
    y -= 40;
 
    /* FPS stuff. */
@@ -1457,8 +1468,21 @@ static void opt_video( unsigned int wid )
                        conf.low_memory );
    y -= 25;
    window_addCheckbox( wid, x, y, cw, 20, "chkVSync", _( "Vertical Sync" ),
-                       opt_checkRestart, conf.vsync );
-   y -= 40;
+                       NULL, conf.vsync );
+   y -= 25;
+   window_addCheckbox( wid, x, y, cw, 20, "chkTrilinear",
+                       _( "Trilinear filtering" ), NULL, conf.trilinear );
+   y -= 30;
+   if ( opt_hasAnisotropy() ) {
+      window_addText( wid, x, y - 3, cw - 20, 20, 0, "txtAnisotropy", NULL,
+                      NULL, NULL );
+      y -= 20;
+      window_addFader( wid, x + 20, y, cw - 60, 20, "fadAnisotropy", 1., 16.,
+                       conf.anisotropy, opt_setAnisotropy );
+      opt_setAnisotropy( wid, "fadAnisotropy" );
+      y -= 40;
+   } else
+      y -= 10;
 
    /* Features. */
    window_addText( wid, x, y, 100, 20, 0, "txtSFeatures", NULL, cHeader,
@@ -1521,6 +1545,7 @@ static int opt_videoSave( unsigned int wid, const char *str )
    (void)str;
    const char  *inp;
    int          ret, f, fullscreen;
+   double       d;
    unsigned int w, h;
 
    /* Handle resolution. */
@@ -1558,7 +1583,16 @@ static int opt_videoSave( unsigned int wid, const char *str )
    f = window_checkboxState( wid, "chkVSync" );
    if ( conf.vsync != f ) {
       conf.vsync = f;
-      opt_needRestart();
+      gl_setVsync( conf.vsync );
+   }
+   f = window_checkboxState( wid, "chkTrilinear" );
+   d = opt_hasAnisotropy()
+          ? round( window_getFaderValue( wid, "fadAnisotropy" ) )
+          : conf.anisotropy;
+   if ( ( conf.trilinear != f ) || ( conf.anisotropy != d ) ) {
+      conf.trilinear  = f;
+      conf.anisotropy = d;
+      gl_reapplySamplerDefaults();
    }
 
    /* Features. */
@@ -1760,6 +1794,7 @@ static void opt_videoDefaults( unsigned int wid, const char *str )
    /* Checkboxes. */
    window_checkboxSet( wid, "chkFullscreen", FULLSCREEN_DEFAULT );
    window_checkboxSet( wid, "chkVSync", VSYNC_DEFAULT );
+   window_checkboxSet( wid, "chkTrilinear", TRILINEAR_DEFAULT );
    window_checkboxSet( wid, "chkFPS", SHOW_FPS_DEFAULT );
    window_checkboxSet( wid, "chkMinimize", MINIMIZE_DEFAULT );
    window_checkboxSet( wid, "chkBigIcons", BIG_ICONS_DEFAULT );
@@ -1772,8 +1807,11 @@ static void opt_videoDefaults( unsigned int wid, const char *str )
    window_faderSetBoundedValue(
       wid, "fadGammaCorrection",
       log( GAMMA_CORRECTION_DEFAULT ) /* a.k.a. 0. */ );
+   window_faderSetBoundedValue( wid, "fadRenderScale", RENDER_SCALE_DEFAULT );
    window_faderSetBoundedValue( wid, "fadMapOverlayOpacity",
                                 MAP_OVERLAY_OPACITY_DEFAULT );
+   if ( opt_hasAnisotropy() )
+      window_faderSetBoundedValue( wid, "fadAnisotropy", ANISOTROPY_DEFAULT );
 }
 
 /**
@@ -1858,6 +1896,22 @@ static void opt_setGammaCorrection( unsigned int wid, const char *str )
    render_setGamma( conf.gamma_correction );
 }
 
+/**
+ * @brief Callback to set the render scale.
+ *
+ *    @param wid Window calling the callback.
+ *    @param str Name of the widget calling the callback.
+ */
+static void opt_setRenderScale( unsigned int wid, const char *str )
+{
+   char buf[STRMAX_SHORT];
+   conf.render_scale = window_getFaderValue( wid, str );
+   snprintf( buf, sizeof( buf ), _( "Render Scale: %.0f%%" ),
+             round( 100. * conf.render_scale ) );
+   window_modifyText( wid, "txtRenderScale", buf );
+   render_setScale( conf.render_scale );
+}
+
 /**
  * @brief Callback to set the background brightness.
  *
@@ -1948,6 +2002,34 @@ static void opt_setMapOverlayOpacity( unsigned int wid, const char *str )
    window_modifyText( wid, "txtMOpacity", buf );
 }
 
+/**
+ * @brief Callback to set the anisotropic filtering level, applied when saving.
+ *
+ *    @param wid Window calling the callback.
+ *    @param str Name of the widget calling the callback.
+ */
+static void opt_setAnisotropy( unsigned int wid, const char *str )
+{
+   char   buf[STRMAX_SHORT];
+   double fad = round( window_getFaderValue( wid, str ) );
+   if ( fad > 1. )
+      snprintf( buf, sizeof( buf ), _( "Anisotropic filtering: %.0fx" ), fad );
+   else
+      snprintf( buf, sizeof( buf ), _( "Anisotropic filtering: off" ) );
+   window_modifyText( wid, "txtAnisotropy", buf );
+}
+
+/**
+ * @brief Checks whether the anisotropic filtering option should be shown, as
+ * not all drivers support it.
+ *
+ *    @return Non-zero if anisotropic filtering is supported.
+ */
+static int opt_hasAnisotropy( void )
+{
+   return gl_maxAnisotropy() > 1.;
+}
+
 /**
  * @brief Opens the keybindings menu.
  */
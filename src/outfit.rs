@@ -1,4 +1,6 @@
 use crate::context::Context;
+use crate::ffi::ffi_guard;
+use crate::log::warn_err;
 use crate::texture::TextureBuilder;
 use rayon::prelude::*;
 use std::ffi::{c_void, CStr};
@@ -26,28 +28,38 @@ fn get_mut() -> &'static mut [OutfitWrapper] {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn outfit_gfxStoreLoadNeeded() {
-    let ctx = Context::get().unwrap().as_safe_wrap();
-    get_mut().par_iter_mut().for_each(|ptr| {
-        let o = &mut ptr.0;
-        if o.properties & naevc::OUTFIT_PROP_NEEDSGFX == 0 {
-            return;
-        }
-        o.properties &= !naevc::OUTFIT_PROP_NEEDSGFX;
-        if !o.gfx_store.is_null() || o.gfx_store_path.is_null() {
-            return;
-        }
-
-        let gfx_path = unsafe { CStr::from_ptr(o.gfx_store_path).to_str().unwrap() };
-        let path = {
-            match gfx_path.chars().next() {
-                Some('/') => String::from(gfx_path),
-                _ => format!("gfx/outfit/store/{gfx_path}"),
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx.as_safe_wrap(),
+            Err(e) => {
+                warn_err(e);
+                return;
             }
         };
+        get_mut().par_iter_mut().for_each(|ptr| {
+            let o = &mut ptr.0;
+            if o.properties & naevc::OUTFIT_PROP_NEEDSGFX == 0 {
+                return;
+            }
+            o.properties &= !naevc::OUTFIT_PROP_NEEDSGFX;
+            if !o.gfx_store.is_null() || o.gfx_store_path.is_null() {
+                return;
+            }
 
-        let tex = TextureBuilder::new().path(&path).build_wrap(&ctx).unwrap();
-        o.gfx_store = tex.into_ptr() as *mut naevc::glTexture;
-    });
+            let gfx_path = unsafe { CStr::from_ptr(o.gfx_store_path).to_string_lossy() };
+            let path = {
+                match gfx_path.chars().next() {
+                    Some('/') => gfx_path.into_owned(),
+                    _ => format!("gfx/outfit/store/{gfx_path}"),
+                }
+            };
+
+            match TextureBuilder::new().path(&path).build_wrap(&ctx) {
+                Ok(tex) => o.gfx_store = tex.into_ptr() as *mut naevc::glTexture,
+                Err(e) => warn_err(e.context(format!("unable to load store graphic '{path}'"))),
+            }
+        });
+    })
 }
 
 /*
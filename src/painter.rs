@@ -0,0 +1,209 @@
+#![allow(dead_code)]
+use anyhow::Result;
+use glow::HasContext;
+use nalgebra::{Matrix3, Vector4};
+use std::ffi::CString;
+
+use crate::colour;
+use crate::context::Context;
+use crate::log::warn_err;
+use crate::render::SolidUniform;
+use crate::texture::Texture;
+
+/// Blending modes available to [`Painter::blended`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlendMode {
+    /// Standard alpha blending, the default.
+    Alpha,
+    /// Adds the colour on top, for glows and lights.
+    Additive,
+    /// Multiplies with what is already drawn.
+    Multiply,
+}
+impl BlendMode {
+    /// Source RGB, destination RGB, source alpha and destination alpha factors.
+    fn to_gl(self) -> [u32; 4] {
+        match self {
+            Self::Alpha => [
+                glow::SRC_ALPHA,
+                glow::ONE_MINUS_SRC_ALPHA,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA,
+            ],
+            Self::Additive => [glow::SRC_ALPHA, glow::ONE, glow::ONE, glow::ONE],
+            Self::Multiply => [
+                glow::DST_COLOR,
+                glow::ZERO,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA,
+            ],
+        }
+    }
+}
+
+/// Immediate mode 2D drawing in screen coordinates. Rectangles are accumulated and drawn in a
+/// single batch, which is flushed whenever something else is drawn, the blend or scissor state
+/// changes, or the painter is dropped, so the drawing order is always preserved.
+///
+/// Colours are in sRGB like [`Context::draw_rect`].
+///
+/// ```ignore
+/// let mut painter = Painter::new(ctx);
+/// painter.rect(10.0, 10.0, 100.0, 20.0, bg);
+/// painter.clipped(10.0, 10.0, 100.0, 20.0, |p| {
+///     p.text(12.0, 14.0, fg, "Hello")
+/// })?;
+/// ```
+pub struct Painter<'ctx> {
+    ctx: &'ctx Context,
+    rects: Vec<SolidUniform>,
+}
+
+impl<'ctx> Painter<'ctx> {
+    pub fn new(ctx: &'ctx Context) -> Self {
+        Painter {
+            ctx,
+            rects: Vec::new(),
+        }
+    }
+
+    pub fn context(&self) -> &'ctx Context {
+        self.ctx
+    }
+
+    /// Draws all the pending rectangles.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.rects.is_empty() {
+            return Ok(());
+        }
+        let res = self.ctx.draw_rects(&self.rects);
+        self.rects.clear();
+        res
+    }
+
+    /// Queues a filled rectangle.
+    pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, colour: Vector4<f32>) {
+        let dims = self.ctx.dimensions.read().unwrap();
+        #[rustfmt::skip]
+        let transform: Matrix3<f32> = dims.projection * Matrix3::new(
+             w,  0.0,  x,
+            0.0,  h,   y,
+            0.0, 0.0, 1.0,
+        );
+        self.rects.push(SolidUniform {
+            transform,
+            colour: colour::srgb_to_linear(colour),
+        });
+    }
+
+    /// Draws a line between two points.
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, colour: Vector4<f32>) -> Result<()> {
+        self.flush()?;
+        let c = colour::srgb_to_linear(colour);
+        unsafe {
+            naevc::gl_renderLine(
+                x1.into(),
+                y1.into(),
+                x2.into(),
+                y2.into(),
+                &c as *const Vector4<f32> as *const naevc::glColour,
+            );
+        }
+        Ok(())
+    }
+
+    /// Draws a circle centered at `(x, y)`, either filled or as an outline.
+    pub fn circle(
+        &mut self,
+        x: f32,
+        y: f32,
+        r: f32,
+        colour: Vector4<f32>,
+        filled: bool,
+    ) -> Result<()> {
+        self.flush()?;
+        let c = colour::srgb_to_linear(colour);
+        unsafe {
+            naevc::gl_renderCircle(
+                x.into(),
+                y.into(),
+                r.into(),
+                &c as *const Vector4<f32> as *const naevc::glColour,
+                filled as i32,
+            );
+        }
+        Ok(())
+    }
+
+    /// Draws a texture stretched over a rectangle.
+    pub fn texture(&mut self, tex: &Texture, x: f32, y: f32, w: f32, h: f32) -> Result<()> {
+        self.flush()?;
+        tex.draw(self.ctx, x, y, w, h)
+    }
+
+    /// Draws a single line of text with the default font, with `(x, y)` being the bottom left.
+    pub fn text(&mut self, x: f32, y: f32, colour: Vector4<f32>, text: &str) -> Result<()> {
+        self.flush()?;
+        let c = colour::srgb_to_linear(colour);
+        let ctext = CString::new(text)?;
+        unsafe {
+            naevc::gl_printRaw(
+                &raw const naevc::gl_defFont,
+                x.into(),
+                y.into(),
+                &c as *const Vector4<f32> as *const naevc::glColour,
+                -1.0,
+                ctext.as_ptr(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs `f` with drawing restricted to the given rectangle. The previous scissor state is
+    /// restored afterwards.
+    pub fn clipped<R>(
+        &mut self,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        f: impl FnOnce(&mut Self) -> Result<R>,
+    ) -> Result<R> {
+        self.flush()?;
+        let state = self.ctx.capture_state();
+        unsafe {
+            naevc::gl_clipRect(x as i32, y as i32, w as i32, h as i32);
+        }
+        let res = f(self).and_then(|r| self.flush().map(|_| r));
+        self.ctx.restore_state(&state);
+        res
+    }
+
+    /// Runs `f` with the given blend mode. The previous blend state is restored afterwards.
+    pub fn blended<R>(
+        &mut self,
+        mode: BlendMode,
+        f: impl FnOnce(&mut Self) -> Result<R>,
+    ) -> Result<R> {
+        self.flush()?;
+        let state = self.ctx.capture_state();
+        let [src_rgb, dst_rgb, src_alpha, dst_alpha] = mode.to_gl();
+        unsafe {
+            let gl = &self.ctx.gl;
+            gl.enable(glow::BLEND);
+            gl.blend_equation(glow::FUNC_ADD);
+            gl.blend_func_separate(src_rgb, dst_rgb, src_alpha, dst_alpha);
+        }
+        let res = f(self).and_then(|r| self.flush().map(|_| r));
+        self.ctx.restore_state(&state);
+        res
+    }
+}
+
+impl Drop for Painter<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn_err(e.context("unable to flush painter"));
+        }
+    }
+}
@@ -67,6 +67,23 @@ double angle_diff( double ref, double a )
    return d;
 }
 
+#if HAVE_FIXED_POINT
+/**
+ * @brief Updates the solid's position using an Euler integration in
+ * fixed-point, so that the results are the same on every machine.
+ */
+static void solid_update_euler( Solid *obj, double dt )
+{
+   /* Save previous position. */
+   obj->pre = obj->pos;
+
+   /* Make sure angle doesn't flip */
+   obj->dir += obj->dir_vel * dt;
+   obj->dir = angle_clean( obj->dir );
+
+   solid_integrateFixed( obj, dt );
+}
+#else  /* HAVE_FIXED_POINT */
 /**
  * @brief Updates the solid's position using an Euler integration.
  *
@@ -118,6 +135,7 @@ static void solid_update_euler( Solid *obj, double dt )
    vec2_cset( &obj->vel, vx, vy );
    vec2_cset( &obj->pos, px, py );
 }
+#endif /* HAVE_FIXED_POINT */
 
 /**
  * @brief Runge-Kutta method of updating a solid based on its acceleration.
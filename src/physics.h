@@ -3,6 +3,10 @@
  */
 #pragma once
 
+/** @cond */
+#include <stdint.h>
+/** @endcond */
+
 #include "vec2.h"
 
 /*
@@ -54,6 +58,8 @@ typedef struct Solid_ {
    double aerodynamics; /**< A factor that *divides* the over-the-max opposing
                            force */
    void ( *update )( struct Solid_ *, double ); /**< Update method. */
+   int64_t fixed[4]; /**< Position and velocity in Q32.32 fixed-point, only
+                        used when built with fixed_point. */
 } Solid;
 
 int physics_init( void ); /* Initializes some constants. */
@@ -64,6 +70,9 @@ int physics_init( void ); /* Initializes some constants. */
 double solid_maxspeed( const Solid *s, double speed, double accel );
 void   solid_init( Solid *dest, double mass, double dir, const vec2 *pos,
                    const vec2 *vel, int update );
+#if HAVE_FIXED_POINT
+void solid_integrateFixed( Solid *obj, double dt ); /* Implemented in Rust. */
+#endif /* HAVE_FIXED_POINT */
 
 /*
  * misc
@@ -1,3 +1,6 @@
+#![allow(dead_code)]
+use nalgebra::{Point2, Vector2};
+use std::collections::HashMap;
 use std::f64::consts::PI;
 
 /// Converts an angle to the [0, 2*PI] range.
@@ -24,3 +27,563 @@ pub fn angle_diff(a: f64, b: f64) -> f64 {
         d
     }
 }
+
+/// Entity stored in the spatial hash, treated as a circle.
+#[derive(Clone, Copy, Debug)]
+struct SpatialEntry {
+    id: usize,
+    pos: Point2<f64>,
+    radius: f64,
+}
+
+/// Uniform grid to speed up spatial queries on circular entities.
+pub struct SpatialHash {
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    entries: Vec<SpatialEntry>,
+}
+impl SpatialHash {
+    pub fn new(cell_size: f64) -> Self {
+        SpatialHash {
+            cell_size: cell_size.max(f64::EPSILON),
+            cells: HashMap::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    fn cell(&self, x: f64, y: f64) -> (i64, i64) {
+        (
+            (x / self.cell_size).floor() as i64,
+            (y / self.cell_size).floor() as i64,
+        )
+    }
+
+    /// Inserts an entity into all the cells it overlaps.
+    pub fn insert(&mut self, id: usize, pos: Point2<f64>, radius: f64) {
+        let idx = self.entries.len();
+        self.entries.push(SpatialEntry { id, pos, radius });
+        let (x0, y0) = self.cell(pos.x - radius, pos.y - radius);
+        let (x1, y1) = self.cell(pos.x + radius, pos.y + radius);
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                self.cells.entry((x, y)).or_default().push(idx);
+            }
+        }
+    }
+
+    /// Removes all the entities.
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.entries.clear();
+    }
+}
+
+/// Result of a successful raycast.
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub id: usize,
+    pub distance: f64,
+    pub point: Point2<f64>,
+    pub normal: Vector2<f64>,
+}
+
+/// Intersects a ray with a circle, returning the distance along the ray if hit.
+fn ray_circle(origin: &Point2<f64>, dir: &Vector2<f64>, entry: &SpatialEntry) -> Option<f64> {
+    let oc = origin - entry.pos;
+    let c = oc.norm_squared() - entry.radius * entry.radius;
+    if c <= 0.0 {
+        // Starting inside
+        return Some(0.0);
+    }
+    let b = oc.dot(dir);
+    let disc = b * b - c;
+    if disc < 0.0 {
+        return None;
+    }
+    let t = -b - disc.sqrt();
+    (t >= 0.0).then_some(t)
+}
+
+/// Casts a ray through the spatial hash, returning the nearest entity hit within max_dist.
+/// Only the cells along the ray are visited, so max_dist has to be finite.
+pub fn raycast(
+    origin: Point2<f64>,
+    dir: Vector2<f64>,
+    max_dist: f64,
+    hash: &SpatialHash,
+) -> Option<RayHit> {
+    if !max_dist.is_finite() {
+        return None;
+    }
+    let dir = dir.try_normalize(f64::EPSILON)?;
+    let cs = hash.cell_size;
+    let (mut cx, mut cy) = hash.cell(origin.x, origin.y);
+
+    // Set up the DDA traversal
+    let axis = |o: f64, d: f64, c: i64| -> (i64, f64, f64) {
+        if d > 0.0 {
+            (1, (((c + 1) as f64) * cs - o) / d, cs / d)
+        } else if d < 0.0 {
+            (-1, ((c as f64) * cs - o) / d, -cs / d)
+        } else {
+            (0, f64::INFINITY, f64::INFINITY)
+        }
+    };
+    let (step_x, mut tmax_x, tdelta_x) = axis(origin.x, dir.x, cx);
+    let (step_y, mut tmax_y, tdelta_y) = axis(origin.y, dir.y, cy);
+
+    let mut best: Option<(f64, &SpatialEntry)> = None;
+    let mut tenter = 0.0;
+    while tenter <= max_dist {
+        if let Some(cell) = hash.cells.get(&(cx, cy)) {
+            for e in cell.iter().map(|idx| &hash.entries[*idx]) {
+                if let Some(t) = ray_circle(&origin, &dir, e) {
+                    if t <= max_dist && best.is_none_or(|(bt, _)| t < bt) {
+                        best = Some((t, e));
+                    }
+                }
+            }
+        }
+
+        // Nothing further can be closer than a hit before leaving the cell
+        let texit = tmax_x.min(tmax_y);
+        if best.is_some_and(|(bt, _)| bt <= texit) {
+            break;
+        }
+
+        if tmax_x < tmax_y {
+            cx += step_x;
+            tenter = tmax_x;
+            tmax_x += tdelta_x;
+        } else {
+            cy += step_y;
+            tenter = tmax_y;
+            tmax_y += tdelta_y;
+        }
+        if !tenter.is_finite() {
+            break;
+        }
+    }
+
+    best.map(|(t, e)| {
+        let point = origin + dir * t;
+        let normal = (point - e.pos).try_normalize(f64::EPSILON).unwrap_or(-dir);
+        RayHit {
+            id: e.id,
+            distance: t,
+            point,
+            normal,
+        }
+    })
+}
+
+/// Physical state of a body needed for collision response. Static bodies have infinite (or
+/// non-positive) mass.
+#[derive(Clone, Copy, Debug)]
+pub struct Body<S: Scalar = f64> {
+    pub vel: Vector2<S>,
+    pub mass: S,
+}
+impl<S: Scalar> Body<S> {
+    fn inv_mass(&self) -> S {
+        let zero = S::from_f64(0.0);
+        match self.mass.to_f64().is_finite() && self.mass > zero {
+            true => S::from_f64(1.0) / self.mass,
+            false => zero,
+        }
+    }
+}
+
+/// Contact between two bodies.
+#[derive(Clone, Copy, Debug)]
+pub struct Contact<S: Scalar = f64> {
+    /// Unit normal pointing from the first body to the second.
+    pub normal: Vector2<S>,
+    /// Coefficient of restitution, 0 is fully inelastic and 1 is fully elastic.
+    pub restitution: S,
+}
+
+/// Computes the impulses to apply to two colliding bodies. Dividing an impulse by the mass
+/// of the body gives the change in velocity. Bodies that are already separating get no impulse.
+pub fn resolve<S: Scalar>(
+    a: &Body<S>,
+    b: &Body<S>,
+    contact: &Contact<S>,
+) -> (Vector2<S>, Vector2<S>) {
+    let zero = S::from_f64(0.0);
+    let one = S::from_f64(1.0);
+    let none = Vector2::new(zero, zero);
+    let inv_sum = a.inv_mass() + b.inv_mass();
+    if inv_sum <= zero {
+        // Both static
+        return (none, none);
+    }
+    // Written per component, since fixed-point values don't implement nalgebra's numeric traits
+    let n = contact.normal;
+    let vrel = (b.vel.x - a.vel.x) * n.x + (b.vel.y - a.vel.y) * n.y;
+    if vrel >= zero {
+        return (none, none);
+    }
+    let e = match contact.restitution {
+        e if e < zero => zero,
+        e if e > one => one,
+        e => e,
+    };
+    let j = -(one + e) * vrel / inv_sum;
+    (
+        Vector2::new(-(n.x * j), -(n.y * j)),
+        Vector2::new(n.x * j, n.y * j),
+    )
+}
+
+/// Scalar arithmetic needed by the integrator, so it can run on floats or fixed-point values.
+pub trait Scalar:
+    Copy
+    + PartialOrd
+    + std::fmt::Debug
+    + 'static
+    + std::ops::Add<Output = Self>
+    + std::ops::Sub<Output = Self>
+    + std::ops::Mul<Output = Self>
+    + std::ops::Div<Output = Self>
+    + std::ops::Neg<Output = Self>
+{
+    fn from_f64(v: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+impl Scalar for f64 {
+    fn from_f64(v: f64) -> Self {
+        v
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+/// Semi-implicit Euler integration step of a position and velocity under a given force.
+pub fn integrate<S: Scalar>(pos: &mut [S; 2], vel: &mut [S; 2], force: [S; 2], mass: S, dt: S) {
+    for ((p, v), f) in pos.iter_mut().zip(vel.iter_mut()).zip(force) {
+        *v = *v + f / mass * dt;
+        *p = *p + *v * dt;
+    }
+}
+
+/// Advances the position and velocity of a solid kept in fixed-point between steps. `state` is
+/// the raw position and velocity, and `doubles` what the C side sees of them. Values that the C
+/// side changed since the last step, e.g., when a pilot is teleported, are taken from `doubles`.
+/// Returns the new values for the C side.
+#[cfg(feature = "fixed_point")]
+fn step_fixed(state: &mut [i64; 4], doubles: [f64; 4], dir: f64, accel: f64, dt: f64) -> [f64; 4] {
+    let s: [Fixed; 4] = std::array::from_fn(|i| match Fixed(state[i]).to_f64() == doubles[i] {
+        true => Fixed(state[i]),
+        false => Fixed::from_f64(doubles[i]),
+    });
+    let (mut pos, mut vel) = ([s[0], s[1]], [s[2], s[3]]);
+    let dir = Fixed::from_f64(dir);
+    let accel = Fixed::from_f64(accel);
+    integrate(
+        &mut pos,
+        &mut vel,
+        [accel * dir.cos(), accel * dir.sin()],
+        Fixed::ONE,
+        Fixed::from_f64(dt),
+    );
+    *state = [pos[0].0, pos[1].0, vel[0].0, vel[1].0];
+    state.map(|v| Fixed(v).to_f64())
+}
+
+/// Euler update of a solid for the C side when built with the `fixed_point` feature.
+#[cfg(feature = "fixed_point")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn solid_integrateFixed(obj: *mut naevc::Solid, dt: std::os::raw::c_double) {
+    crate::ffi::ffi_guard((), || {
+        let obj = unsafe { &mut *obj };
+        let doubles = [obj.pos.x, obj.pos.y, obj.vel.x, obj.vel.y];
+        let [px, py, vx, vy] = step_fixed(&mut obj.fixed, doubles, obj.dir, obj.accel, dt);
+        unsafe {
+            naevc::vec2_cset(&mut obj.vel, vx, vy);
+            naevc::vec2_cset(&mut obj.pos, px, py);
+        }
+    })
+}
+
+/// Q32.32 fixed-point number, giving bit-identical results across machines unlike floats.
+#[cfg(feature = "fixed_point")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(pub i64);
+#[cfg(feature = "fixed_point")]
+impl Fixed {
+    const FRAC_BITS: u32 = 32;
+    pub const ONE: Fixed = Fixed(1 << Self::FRAC_BITS);
+    pub const ZERO: Fixed = Fixed(0);
+    pub const PI: Fixed = Fixed(13493037705);
+    const HALF_PI: Fixed = Fixed(6746518852);
+    const TWO_PI: Fixed = Fixed(26986075409);
+
+    /// Sine computed with fixed-point arithmetic only, unlike the platform dependent `f64::sin`.
+    pub fn sin(self) -> Fixed {
+        // Reduce to [-pi, pi] and then to [-pi/2, pi/2] using the symmetry around pi/2
+        let mut x = Fixed(self.0.rem_euclid(Self::TWO_PI.0));
+        if x > Self::PI {
+            x = x - Self::TWO_PI;
+        }
+        if x > Self::HALF_PI {
+            x = Self::PI - x;
+        } else if x < -Self::HALF_PI {
+            x = -Self::PI - x;
+        }
+        // Taylor series up to x^15 in Horner form, the error is below the resolution there
+        let x2 = x * x;
+        let mut term = Fixed::ONE;
+        for k in (1..=7).rev() {
+            term = Fixed::ONE - Fixed((x2 * term).0 / (2 * k * (2 * k + 1)));
+        }
+        x * term
+    }
+
+    pub fn cos(self) -> Fixed {
+        (self + Self::HALF_PI).sin()
+    }
+}
+#[cfg(feature = "fixed_point")]
+impl std::ops::Add for Fixed {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Fixed(self.0.wrapping_add(rhs.0))
+    }
+}
+#[cfg(feature = "fixed_point")]
+impl std::ops::Sub for Fixed {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Fixed(self.0.wrapping_sub(rhs.0))
+    }
+}
+#[cfg(feature = "fixed_point")]
+impl std::ops::Mul for Fixed {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Fixed(((self.0 as i128 * rhs.0 as i128) >> Self::FRAC_BITS) as i64)
+    }
+}
+#[cfg(feature = "fixed_point")]
+impl std::ops::Div for Fixed {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        if rhs.0 == 0 {
+            // Saturate instead of panicking
+            return Fixed(match self.0 >= 0 {
+                true => i64::MAX,
+                false => i64::MIN,
+            });
+        }
+        Fixed((((self.0 as i128) << Self::FRAC_BITS) / rhs.0 as i128) as i64)
+    }
+}
+#[cfg(feature = "fixed_point")]
+impl std::ops::Neg for Fixed {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Fixed(self.0.wrapping_neg())
+    }
+}
+#[cfg(feature = "fixed_point")]
+impl Scalar for Fixed {
+    fn from_f64(v: f64) -> Self {
+        Fixed((v * (1u64 << Self::FRAC_BITS) as f64).round() as i64)
+    }
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / (1u64 << Self::FRAC_BITS) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(circles: &[(usize, f64, f64, f64)]) -> SpatialHash {
+        let mut hash = SpatialHash::new(10.0);
+        for &(id, x, y, r) in circles {
+            hash.insert(id, Point2::new(x, y), r);
+        }
+        hash
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "got {a}, expected {b}");
+    }
+
+    #[test]
+    fn raycast_axis_aligned() {
+        let hash = hash(&[
+            (1, 50.0, 0.0, 5.0),
+            (2, 80.0, 0.0, 5.0),
+            (3, -25.0, 0.0, 5.0),
+        ]);
+        let origin = Point2::new(0.0, 0.0);
+
+        let hit = raycast(origin, Vector2::new(1.0, 0.0), 100.0, &hash).unwrap();
+        assert_eq!(hit.id, 1);
+        assert_close(hit.distance, 45.0);
+        assert_close(hit.point.x, 45.0);
+        assert_close(hit.normal.x, -1.0);
+        assert_close(hit.normal.y, 0.0);
+
+        let hit = raycast(origin, Vector2::new(-3.0, 0.0), 100.0, &hash).unwrap();
+        assert_eq!(hit.id, 3);
+        assert_close(hit.distance, 20.0);
+        assert_close(hit.normal.x, 1.0);
+
+        // Out of range, or nothing in the way
+        assert!(raycast(origin, Vector2::new(1.0, 0.0), 40.0, &hash).is_none());
+        assert!(raycast(origin, Vector2::new(0.0, 1.0), 100.0, &hash).is_none());
+        assert!(raycast(origin, Vector2::new(0.0, -1.0), 100.0, &hash).is_none());
+    }
+
+    #[test]
+    fn raycast_diagonal() {
+        let hash = hash(&[(1, 30.0, 30.0, 2.0), (2, 15.0, -15.0, 2.0)]);
+        let origin = Point2::new(0.0, 0.0);
+
+        let hit = raycast(origin, Vector2::new(1.0, 1.0), 100.0, &hash).unwrap();
+        assert_eq!(hit.id, 1);
+        assert_close(hit.distance, 30.0 * 2f64.sqrt() - 2.0);
+        assert_close(hit.normal.x, -std::f64::consts::FRAC_1_SQRT_2);
+        assert_close(hit.normal.y, -std::f64::consts::FRAC_1_SQRT_2);
+
+        let hit = raycast(origin, Vector2::new(1.0, -1.0), 100.0, &hash).unwrap();
+        assert_eq!(hit.id, 2);
+        assert_close(hit.distance, 15.0 * 2f64.sqrt() - 2.0);
+
+        // Passes between the two
+        assert!(raycast(origin, Vector2::new(1.0, 0.0), 100.0, &hash).is_none());
+    }
+
+    #[test]
+    fn raycast_edge_cases() {
+        let hash = hash(&[(1, 0.0, 0.0, 5.0)]);
+        let hit = raycast(Point2::new(1.0, 1.0), Vector2::new(1.0, 0.0), 10.0, &hash).unwrap();
+        assert_eq!(hit.id, 1);
+        assert_close(hit.distance, 0.0);
+        assert!(raycast(Point2::new(20.0, 0.0), Vector2::zeros(), 10.0, &hash).is_none());
+        assert!(raycast(
+            Point2::new(20.0, 0.0),
+            Vector2::new(-1.0, 0.0),
+            f64::INFINITY,
+            &hash
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn resolve_impulses() {
+        let a = Body {
+            vel: Vector2::new(1.0, 0.0),
+            mass: 1.0,
+        };
+        let b = Body {
+            vel: Vector2::new(-1.0, 0.0),
+            mass: 1.0,
+        };
+        let contact = Contact {
+            normal: Vector2::new(1.0, 0.0),
+            restitution: 1.0,
+        };
+        // Elastic collision of equal masses swaps the velocities
+        let (ia, ib) = resolve(&a, &b, &contact);
+        assert_eq!(ia, Vector2::new(-2.0, 0.0));
+        assert_eq!(ib, Vector2::new(2.0, 0.0));
+
+        // Separating bodies and static pairs get nothing
+        let (ia, ib) = resolve(&b, &a, &contact);
+        assert_eq!((ia, ib), (Vector2::zeros(), Vector2::zeros()));
+        let wall = Body {
+            vel: Vector2::zeros(),
+            mass: f64::INFINITY,
+        };
+        let (ia, ib) = resolve(&wall, &wall, &contact);
+        assert_eq!((ia, ib), (Vector2::zeros(), Vector2::zeros()));
+    }
+
+    /// Simulates a body thrown around in a box with a bouncy floor.
+    #[cfg(feature = "fixed_point")]
+    fn simulate() -> ([Fixed; 2], [Fixed; 2]) {
+        let dt = Fixed::from_f64(1.0 / 60.0);
+        let mass = Fixed::from_f64(2.5);
+        let mut pos = [Fixed::ZERO; 2];
+        let mut vel = [Fixed::from_f64(3.0), Fixed::from_f64(-1.5)];
+        let floor = Body {
+            vel: Vector2::new(Fixed::ZERO, Fixed::ZERO),
+            mass: Fixed::ZERO,
+        };
+        let contact = Contact {
+            normal: Vector2::new(Fixed::ZERO, Fixed::ONE),
+            restitution: Fixed::from_f64(0.8),
+        };
+        for i in 0..600 {
+            let force = [Fixed::from_f64((i % 7) as f64 * 0.1), Fixed::from_f64(-9.8)];
+            integrate(&mut pos, &mut vel, force, mass, dt);
+            if pos[1] < Fixed::from_f64(-5.0) {
+                let body = Body {
+                    vel: Vector2::new(vel[0], vel[1]),
+                    mass,
+                };
+                let (_, impulse) = resolve(&floor, &body, &contact);
+                vel[0] = vel[0] + impulse.x / mass;
+                vel[1] = vel[1] + impulse.y / mass;
+            }
+        }
+        (pos, vel)
+    }
+
+    #[cfg(feature = "fixed_point")]
+    #[test]
+    fn fixed_integration_bit_identical() {
+        let (pos, vel) = simulate();
+        assert_eq!((pos, vel), simulate());
+        // Pinned so a change in the arithmetic, or a platform computing it differently, shows up
+        assert_eq!(pos.map(|v| v.0), [154546999385, -20482220210]);
+        assert_eq!(vel.map(|v| v.0), [18024545902, 2153753049]);
+    }
+
+    #[cfg(feature = "fixed_point")]
+    #[test]
+    fn fixed_sin_cos() {
+        for i in -100..=100 {
+            let a = i as f64 * 0.1;
+            let x = Fixed::from_f64(a);
+            assert!((x.sin().to_f64() - a.sin()).abs() < 1e-8, "sin({a})");
+            assert!((x.cos().to_f64() - a.cos()).abs() < 1e-8, "cos({a})");
+        }
+    }
+
+    #[cfg(feature = "fixed_point")]
+    #[test]
+    fn fixed_step() {
+        let run = || {
+            let mut state = [0; 4];
+            let mut doubles = [10.0, -5.0, 0.0, 0.0];
+            for i in 0..100 {
+                doubles = step_fixed(&mut state, doubles, i as f64 * 0.05, 50.0, 1.0 / 60.0);
+            }
+            (state, doubles)
+        };
+        let (mut state, doubles) = run();
+        assert_eq!(state, run().0);
+        // The C side sees the fixed-point state
+        assert_eq!(
+            doubles.map(f64::to_bits),
+            state.map(|v| Fixed(v).to_f64().to_bits())
+        );
+
+        // Teleporting on the C side is picked up, while the velocity carries on
+        let moved = step_fixed(
+            &mut state,
+            [1.0, 2.0, doubles[2], doubles[3]],
+            0.0,
+            0.0,
+            0.0,
+        );
+        assert_eq!(moved, [1.0, 2.0, doubles[2], doubles[3]]);
+    }
+}
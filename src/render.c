@@ -130,6 +130,7 @@ static void render_fbo_list( double dt, PPShader *list, int *current, int done )
    pplast = &list[i];
    if ( done ) {
       gl_screen.current_fbo = 0;
+      gl_screenViewport();
       /* Do the render. */
       render_fbo( dt, gl_screen.current_fbo, gl_screen.fbo_tex[cur], pplast );
       glBindFramebuffer( GL_FRAMEBUFFER, gl_screen.current_fbo );
@@ -187,8 +188,7 @@ void render_all( double game_dt, double real_dt )
    /* Case we have a post-processing shader we use the framebuffers. */
    if ( pp_game || pp_gui || pp_final || pp_core ) {
       /* Clear main screen. */
-      glBindFramebuffer( GL_FRAMEBUFFER, 0 );
-      glClear( GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT );
+      gl_clearWindow();
 
       /* Clear back buffer. */
       glBindFramebuffer( GL_FRAMEBUFFER, gl_screen.fbo[1] );
@@ -200,15 +200,26 @@ void render_all( double game_dt, double real_dt )
       gl_screen.current_fbo = 0;
 
    /* Bind and clear new drawing area. */
-   glBindFramebuffer( GL_FRAMEBUFFER, gl_screen.current_fbo );
-   glClear( GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT );
+   if ( gl_screen.current_fbo == 0 )
+      gl_clearWindow();
+   else {
+      glBindFramebuffer( GL_FRAMEBUFFER, gl_screen.current_fbo );
+      glClear( GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT );
+   }
    glClearColor( 0., 0., 0., 0. );
+   gl_screenViewport();
 
    dt = ( paused ) ? 0. : game_dt;
 
+   /* Time for the shaders. */
+   render_updateTime( dt, real_dt );
+
    /* Set up the default viewport. */
    gl_defViewport();
 
+   /* Render the world at a lower resolution if wanted. */
+   render_scaleBegin();
+
    /* Background stuff */
    space_render( real_dt ); /* Nebula looks really weird otherwise. This also
                                sets up the lighting from the background. */
@@ -237,6 +248,9 @@ void render_all( double game_dt, double real_dt )
    NTracingZoneEnd( _ctx_renderfg );
    render_reset();
 
+   /* Upscale the world, the rest is rendered at full resolution. */
+   render_scaleEnd();
+
    /* Process game stuff only. */
    if ( pp_game ) {
       NTracingZoneName( _ctx_pp_game, "postprocess_shader[game]", 1 );
@@ -31,6 +31,13 @@ void         render_postprocessCleanup( void );
 /* Special post-processing shaders. */
 void render_setGamma( double gamma );
 
+/* Shader time and rendering the world at a different resolution (implemented
+ * in Rust). */
+void render_updateTime( double game_dt, double real_dt );
+int  render_scaleBegin( void );
+void render_scaleEnd( void );
+void render_setScale( double scale );
+
 /* For dealing with Lua messing things up. */
 void render_reset( void );
 void render_needsReset( void );
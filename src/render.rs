@@ -1,6 +1,14 @@
 use anyhow::Result;
 use encase::{ShaderSize, ShaderType};
-use nalgebra::{Matrix3, Vector4};
+use glow::*;
+use nalgebra::{Matrix3, Vector2, Vector4};
+
+use crate::buffer::{
+    Buffer, BufferBuilder, BufferTarget, BufferUsage, VertexArray, VertexArrayBuffer,
+    VertexArrayBuilder,
+};
+use crate::shader::{Shader, ShaderBuilder};
+use crate::{colour, context, rng};
 
 // Use trait extension to give buffer support
 pub trait Uniform {
@@ -67,3 +75,383 @@ impl Default for SolidUniform {
         }
     }
 }
+
+/// Uniform block binding reserved for [`TimeUniform`]. Shaders built with
+/// [`ShaderBuilder`] that declare the block get it bound automatically.
+pub const TIME_BINDING: u32 = 15;
+/// Name of the uniform block of [`TimeUniform`].
+pub const TIME_BLOCK: &str = "TimeData";
+/// Virtual "time.glsl" include declaring the [`TimeUniform`] block.
+pub const TIME_GLSL: &str = r#"
+#ifndef _TIME_GLSL
+#define _TIME_GLSL
+
+layout(std140) uniform TimeData {
+   float u_time;      /* Game time in seconds, honours the time scale and stops when paused. */
+   float u_time_real; /* Real time in seconds since the start. */
+   float u_dt;        /* Game time elapsed since the last frame. */
+   float u_dt_real;   /* Real time elapsed since the last frame. */
+};
+
+#endif /* _TIME_GLSL */
+"#;
+
+/// Frame timing shared by all shaders, updated once per frame by [`context::Context::update_time`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, ShaderType)]
+pub struct TimeUniform {
+    pub time: f32,
+    pub time_real: f32,
+    pub dt: f32,
+    pub dt_real: f32,
+}
+
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, ShaderType)]
+pub struct ParticleUniform {
+    pub transform: Matrix3<f32>,
+}
+
+/// Per-instance data uploaded to the GPU.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParticleInstance {
+    data: [f32; 4], // x, y, size, life fraction
+    colour: [f32; 4],
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+struct Particle {
+    pos: Vector2<f32>,
+    vel: Vector2<f32>,
+    life: f32,
+    life_max: f32,
+    size: f32,
+    colour: Vector4<f32>,
+}
+
+/// Parameters used when emitting new particles.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct ParticleParams {
+    pub pos: Vector2<f32>,
+    pub vel: Vector2<f32>,
+    /// Maximum random velocity added in each direction.
+    pub spread: f32,
+    /// Life time in seconds.
+    pub life: f32,
+    pub size: f32,
+    pub colour: Vector4<f32>,
+}
+impl Default for ParticleParams {
+    fn default() -> Self {
+        Self {
+            pos: Vector2::zeros(),
+            vel: Vector2::zeros(),
+            spread: 0.0,
+            life: 1.0,
+            size: 1.0,
+            colour: Vector4::<f32>::from([1.0, 1.0, 1.0, 1.0]),
+        }
+    }
+}
+
+/// CPU simulated particles that are drawn in a single instanced call.
+#[allow(dead_code)]
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    instances: Vec<ParticleInstance>,
+    max: usize,
+    shader: Shader,
+    buffer: Buffer,
+    buffer_uniform: Buffer,
+    vertex_array: VertexArray,
+}
+#[allow(dead_code)]
+impl ParticleSystem {
+    pub fn new(ctx: &context::Context, max: usize) -> Result<Self> {
+        let gl = &ctx.gl;
+        let max = max.max(1);
+        let instances = vec![ParticleInstance::default(); max];
+
+        let shader = ShaderBuilder::new(Some("Particle Shader"))
+            .uniform_buffer("ParticleData", 0)
+            .vert_file("rust_particle.vert")
+            .frag_file("rust_particle.frag")
+            .build(gl)?;
+
+        let buffer = BufferBuilder::new(Some("Particle Instance Buffer"))
+            .usage(BufferUsage::Stream)
+            .data(bytemuck::cast_slice(&instances))
+            .build(gl)?;
+
+        let buffer_uniform = BufferBuilder::new(Some("Particle Uniform Buffer"))
+            .target(BufferTarget::Uniform)
+            .usage(BufferUsage::Dynamic)
+            .data(
+                &ParticleUniform {
+                    transform: Matrix3::identity(),
+                }
+                .buffer()?,
+            )
+            .build(gl)?;
+
+        let instance_size = std::mem::size_of::<ParticleInstance>() as i32;
+        let vertex_array = VertexArrayBuilder::new(Some("Particle Vertex Array"))
+            .buffers(&[
+                VertexArrayBuffer {
+                    buffer: &ctx.vbo_center,
+                    size: 2,
+                    stride: 0, // tightly packed
+                    offset: 0,
+                    divisor: 0,
+                    format: None,
+                },
+                VertexArrayBuffer {
+                    buffer: &buffer,
+                    size: 4,
+                    stride: instance_size,
+                    offset: 0,
+                    divisor: 1, // Advances once per instance
+                    format: None,
+                },
+                VertexArrayBuffer {
+                    buffer: &buffer,
+                    size: 4,
+                    stride: instance_size,
+                    offset: std::mem::offset_of!(ParticleInstance, colour) as i32,
+                    divisor: 1,
+                    format: None,
+                },
+            ])
+            .build(ctx)?;
+
+        Ok(ParticleSystem {
+            particles: Vec::with_capacity(max),
+            instances,
+            max,
+            shader,
+            buffer,
+            buffer_uniform,
+            vertex_array,
+        })
+    }
+
+    /// Number of particles currently alive.
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Emits new particles. When the pool is full, the particles closest to dying get recycled.
+    pub fn emit(&mut self, count: usize, params: &ParticleParams) {
+        for _ in 0..count {
+            let spread = Vector2::new(
+                params.spread * (rng::rngf32() * 2.0 - 1.0),
+                params.spread * (rng::rngf32() * 2.0 - 1.0),
+            );
+            let particle = Particle {
+                pos: params.pos,
+                vel: params.vel + spread,
+                life: params.life,
+                life_max: params.life,
+                size: params.size,
+                colour: params.colour,
+            };
+            if self.particles.len() < self.max {
+                self.particles.push(particle);
+            } else if let Some(oldest) = self
+                .particles
+                .iter_mut()
+                .min_by(|a, b| a.life.total_cmp(&b.life))
+            {
+                *oldest = particle;
+            }
+        }
+    }
+
+    /// Simulates the particles, removing the dead ones.
+    pub fn update(&mut self, dt: f32) {
+        for p in self.particles.iter_mut() {
+            p.pos += p.vel * dt;
+            p.life -= dt;
+        }
+        self.particles.retain(|p| p.life > 0.0);
+    }
+
+    /// Renders all the particles with a single instanced draw call.
+    pub fn render(&mut self, ctx: &context::Context, transform: &Matrix3<f32>) -> Result<()> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "ParticleSystem::render called outside of the main thread"
+        );
+        let count = self.particles.len();
+        if count == 0 {
+            return Ok(());
+        }
+        for (inst, p) in self.instances.iter_mut().zip(self.particles.iter()) {
+            *inst = ParticleInstance {
+                data: [p.pos.x, p.pos.y, p.size, p.life / p.life_max],
+                colour: p.colour.into(),
+            };
+        }
+        // Only upload the live particles, the rest of the buffer is never drawn
+        self.buffer
+            .write_range(ctx, 0, bytemuck::cast_slice(&self.instances[..count]))?;
+
+        let gl = &ctx.gl;
+        let uniform = ParticleUniform {
+            transform: *transform,
+        };
+        self.shader.use_program(gl);
+        self.vertex_array.bind(ctx);
+        self.buffer_uniform
+            .bind_write_base(ctx, &uniform.buffer()?, 0)?;
+        unsafe {
+            gl.draw_arrays_instanced(glow::TRIANGLE_STRIP, 0, 4, count as i32);
+        }
+        VertexArray::unbind(ctx);
+        self.buffer_uniform.unbind(ctx);
+        ctx.check_errors();
+
+        Ok(())
+    }
+}
+
+/// How the points of a [`Mesh2D`] are assembled into primitives.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Primitive {
+    Points,
+    Lines,
+    LineStrip,
+    LineLoop,
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+}
+#[allow(dead_code)]
+impl Primitive {
+    pub fn to_gl(self) -> u32 {
+        match self {
+            Self::Points => glow::POINTS,
+            Self::Lines => glow::LINES,
+            Self::LineStrip => glow::LINE_STRIP,
+            Self::LineLoop => glow::LINE_LOOP,
+            Self::Triangles => glow::TRIANGLES,
+            Self::TriangleStrip => glow::TRIANGLE_STRIP,
+            Self::TriangleFan => glow::TRIANGLE_FAN,
+        }
+    }
+}
+
+/// A 2D shape whose vertices are uploaded once and drawn with a solid colour. Meant for shapes
+/// that don't change every frame, such as sensor arcs, so that they don't have to be uploaded
+/// again every time they are drawn.
+#[allow(dead_code)]
+pub struct Mesh2D {
+    mode: Primitive,
+    count: usize,
+    buffer: Buffer,
+    vertex_array: VertexArray,
+}
+#[allow(dead_code)]
+impl Mesh2D {
+    pub fn new(ctx: &context::Context, points: &[Vector2<f32>], mode: Primitive) -> Result<Self> {
+        let (buffer, vertex_array) = Self::upload(ctx, points)?;
+        Ok(Mesh2D {
+            mode,
+            count: points.len(),
+            buffer,
+            vertex_array,
+        })
+    }
+
+    fn upload(ctx: &context::Context, points: &[Vector2<f32>]) -> Result<(Buffer, VertexArray)> {
+        let data: Vec<f32> = points.iter().flat_map(|p| [p.x, p.y]).collect();
+        let buffer = BufferBuilder::new(Some("Mesh2D Buffer"))
+            .usage(BufferUsage::Static)
+            .data_f32(match data.is_empty() {
+                true => &[0.0, 0.0][..], // Dummy data
+                false => &data[..],
+            })
+            .build(&ctx.gl)?;
+        let vertex_array = VertexArrayBuilder::new(Some("Mesh2D Vertex Array"))
+            .buffers(&[VertexArrayBuffer {
+                buffer: &buffer,
+                size: 2,
+                stride: 0, // tightly packed
+                offset: 0,
+                divisor: 0,
+                format: None,
+            }])
+            .build(ctx)?;
+        Ok((buffer, vertex_array))
+    }
+
+    /// Replaces the vertices, reusing the buffer when the number of points doesn't change.
+    pub fn set_points(&mut self, ctx: &context::Context, points: &[Vector2<f32>]) -> Result<()> {
+        if points.len() == self.count && !points.is_empty() {
+            let data: Vec<f32> = points.iter().flat_map(|p| [p.x, p.y]).collect();
+            return self.buffer.write(ctx, bytemuck::cast_slice(&data));
+        }
+        let (buffer, vertex_array) = Self::upload(ctx, points)?;
+        self.buffer = buffer;
+        self.vertex_array = vertex_array;
+        self.count = points.len();
+        Ok(())
+    }
+
+    pub fn set_mode(&mut self, mode: Primitive) {
+        self.mode = mode;
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Draws the mesh with `program_solid`. The transform takes the points to normalized device
+    /// coordinates, e.g., `dims.projection * model`, and the colour is in sRGB like
+    /// [`context::Context::draw_rect`].
+    pub fn draw(
+        &self,
+        ctx: &context::Context,
+        transform: &Matrix3<f32>,
+        colour: Vector4<f32>,
+    ) -> Result<()> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "Mesh2D::draw called outside of the main thread"
+        );
+        if self.count == 0 {
+            return Ok(());
+        }
+        let gl = &ctx.gl;
+        let uniform = SolidUniform {
+            transform: *transform,
+            colour: colour::srgb_to_linear(colour),
+        };
+        ctx.program_solid.use_program(gl);
+        self.vertex_array.bind(ctx);
+        ctx.buffer_solid
+            .bind_write_base(ctx, &uniform.buffer()?, 0)?;
+        unsafe {
+            gl.draw_arrays(self.mode.to_gl(), 0, self.count as i32);
+        }
+        VertexArray::unbind(ctx);
+        ctx.buffer_solid.unbind(ctx);
+        ctx.check_errors();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,571 @@
+//! Deterministic replays. A recording stores the random number generator seed, and for every
+//! frame the time step and the input events that were handled. Playing it back reseeds the random
+//! number generator and feeds the same time steps and input to the game instead of the real ones.
+//!
+//! The Lua `math.random` is reseeded from the same seed, since data scripts use it alongside the
+//! game's own random number generator.
+//!
+//! The settings that change how the game plays out, like the game speed and the time scale, are
+//! stored too. They are used while playing back, and the player's own are put back afterwards.
+//!
+//! Only time, random numbers, settings and input are covered, so a replay has to be started from
+//! the same state it was recorded in, e.g., right after loading the same save.
+use anyhow::Result;
+use sdl2::sys::{SDL_Event, SDL_EventType};
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::io::Write;
+use std::os::raw::{c_char, c_double, c_int};
+use std::sync::Mutex;
+
+use crate::ffi::ffi_guard;
+use crate::gettext::gettext;
+use crate::log::warn_err;
+use crate::{info, warn};
+use crate::{ndata, ntime, physfs, rng, version};
+
+const MAGIC: &[u8; 8] = b"NREPLAY\0";
+const FORMAT_VERSION: u32 = 2;
+const EVENT_SIZE: usize = std::mem::size_of::<SDL_Event>();
+
+/// Events that are recorded. They don't hold any pointers, so they can be stored as plain bytes.
+const RECORDED: [SDL_EventType; 15] = [
+    SDL_EventType::SDL_KEYDOWN,
+    SDL_EventType::SDL_KEYUP,
+    SDL_EventType::SDL_TEXTEDITING,
+    SDL_EventType::SDL_TEXTINPUT,
+    SDL_EventType::SDL_MOUSEMOTION,
+    SDL_EventType::SDL_MOUSEBUTTONDOWN,
+    SDL_EventType::SDL_MOUSEBUTTONUP,
+    SDL_EventType::SDL_MOUSEWHEEL,
+    SDL_EventType::SDL_JOYAXISMOTION,
+    SDL_EventType::SDL_JOYHATMOTION,
+    SDL_EventType::SDL_JOYBUTTONDOWN,
+    SDL_EventType::SDL_JOYBUTTONUP,
+    SDL_EventType::SDL_CONTROLLERAXISMOTION,
+    SDL_EventType::SDL_CONTROLLERBUTTONDOWN,
+    SDL_EventType::SDL_CONTROLLERBUTTONUP,
+];
+
+type RawEvent = [u8; EVENT_SIZE];
+
+struct Frame {
+    dt: f64,
+    events: VecDeque<RawEvent>,
+}
+
+/// Settings that change how the game plays out, so they have to match when playing back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Settings {
+    time_scale: f32,
+    game_speed: f64,
+    compression_velocity: f64,
+    compression_mult: f64,
+    mouse_doubleclick: f64,
+    autonav_reset_dist: f64,
+    autonav_reset_shield: f64,
+    doubletap_sens: u32,
+    repeat_delay: u32,
+    repeat_freq: u32,
+    mouse_fly: i32,
+    mouse_accel: i32,
+}
+impl Settings {
+    fn current() -> Self {
+        let conf = unsafe { &*std::ptr::addr_of!(naevc::conf) };
+        Settings {
+            time_scale: ntime::scale(),
+            game_speed: conf.game_speed,
+            compression_velocity: conf.compression_velocity,
+            compression_mult: conf.compression_mult,
+            mouse_doubleclick: conf.mouse_doubleclick,
+            autonav_reset_dist: conf.autonav_reset_dist,
+            autonav_reset_shield: conf.autonav_reset_shield,
+            doubletap_sens: conf.doubletap_sens,
+            repeat_delay: conf.repeat_delay,
+            repeat_freq: conf.repeat_freq,
+            mouse_fly: conf.mouse_fly,
+            mouse_accel: conf.mouse_accel,
+        }
+    }
+
+    fn apply(&self) {
+        ntime::set_scale(self.time_scale);
+        let conf = unsafe { &mut *std::ptr::addr_of_mut!(naevc::conf) };
+        conf.game_speed = self.game_speed;
+        conf.compression_velocity = self.compression_velocity;
+        conf.compression_mult = self.compression_mult;
+        conf.mouse_doubleclick = self.mouse_doubleclick;
+        conf.autonav_reset_dist = self.autonav_reset_dist;
+        conf.autonav_reset_shield = self.autonav_reset_shield;
+        conf.doubletap_sens = self.doubletap_sens;
+        conf.repeat_delay = self.repeat_delay;
+        conf.repeat_freq = self.repeat_freq;
+        conf.mouse_fly = self.mouse_fly;
+        conf.mouse_accel = self.mouse_accel;
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&(self.time_scale as f64).to_le_bytes());
+        for v in [
+            self.game_speed,
+            self.compression_velocity,
+            self.compression_mult,
+            self.mouse_doubleclick,
+            self.autonav_reset_dist,
+            self.autonav_reset_shield,
+        ] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [self.doubletap_sens, self.repeat_delay, self.repeat_freq] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [self.mouse_fly, self.mouse_accel] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self> {
+        Ok(Settings {
+            time_scale: r.f64()? as f32,
+            game_speed: r.f64()?,
+            compression_velocity: r.f64()?,
+            compression_mult: r.f64()?,
+            mouse_doubleclick: r.f64()?,
+            autonav_reset_dist: r.f64()?,
+            autonav_reset_shield: r.f64()?,
+            doubletap_sens: r.u32()?,
+            repeat_delay: r.u32()?,
+            repeat_freq: r.u32()?,
+            mouse_fly: r.u32()? as i32,
+            mouse_accel: r.u32()? as i32,
+        })
+    }
+}
+
+enum Replay {
+    Recording {
+        path: String,
+        seed: u64,
+        settings: Settings,
+        frames: Vec<Frame>,
+        /// Events handled since the last frame started.
+        pending: VecDeque<RawEvent>,
+    },
+    Playing {
+        frames: VecDeque<Frame>,
+        /// The player's settings, put back when the replay ends.
+        previous: Settings,
+    },
+}
+
+static REPLAY: Mutex<Option<Replay>> = Mutex::new(None);
+
+/// Seeds `math.random` of the Lua state. The environments all share the state's `math` table, so
+/// this covers every script.
+fn seed_lua(seed: u64) {
+    unsafe {
+        let lua = naevc::naevL as *mut mlua::lua_State;
+        if lua.is_null() {
+            return;
+        }
+        mlua::ffi::lua_getglobal(lua, c"math".as_ptr());
+        mlua::ffi::lua_getfield(lua, -1, c"randomseed".as_ptr());
+        // Keep the seed within the integers a double represents exactly
+        mlua::ffi::lua_pushnumber(lua, (seed >> 11) as f64);
+        if mlua::ffi::lua_pcall(lua, 1, 0, 0) != 0 {
+            let err = CStr::from_ptr(mlua::ffi::lua_tostring(lua, -1));
+            warn!(
+                "unable to seed Lua random numbers: {}",
+                err.to_string_lossy()
+            );
+            mlua::ffi::lua_pop(lua, 1);
+        }
+        mlua::ffi::lua_pop(lua, 1);
+    }
+}
+
+/// Starts `replay` after seeding all the random number generators with `seed`.
+fn start(replay: Replay, seed: u64) -> Result<()> {
+    let mut current = REPLAY.lock().unwrap();
+    if current.is_some() {
+        anyhow::bail!("replay already in progress");
+    }
+    rng::seed(seed);
+    seed_lua(seed);
+    *current = Some(replay);
+    Ok(())
+}
+
+fn recordable(event: &SDL_Event) -> bool {
+    let ty = unsafe { event.type_ };
+    RECORDED.iter().any(|t| *t as u32 == ty)
+}
+
+/// Starts recording a replay to `path` in the write dir. The file is written when the recording
+/// is stopped.
+pub fn record(path: &str) -> Result<()> {
+    if is_active() {
+        anyhow::bail!("replay already in progress");
+    }
+    // Fail early instead of losing the whole recording when stopping
+    physfs::File::open(path, physfs::Mode::Write)?;
+
+    let seed = rand::random::<u64>();
+    start(
+        Replay::Recording {
+            path: String::from(path),
+            seed,
+            settings: Settings::current(),
+            frames: Vec::new(),
+            pending: VecDeque::new(),
+        },
+        seed,
+    )
+}
+
+/// Starts playing back the replay at `path`. Real input is ignored until it finishes or is
+/// stopped.
+pub fn play(path: &str) -> Result<()> {
+    if is_active() {
+        anyhow::bail!("replay already in progress");
+    }
+    let (seed, settings, frames) = decode(&ndata::read(path)?)?;
+    start(
+        Replay::Playing {
+            frames,
+            previous: Settings::current(),
+        },
+        seed,
+    )?;
+    settings.apply();
+    Ok(())
+}
+
+/// Stops the current replay, writing it out if recording. Returns the number of frames recorded,
+/// or left to play.
+pub fn stop() -> Result<usize> {
+    match REPLAY.lock().unwrap().take() {
+        Some(Replay::Recording {
+            path,
+            seed,
+            settings,
+            frames,
+            ..
+        }) => {
+            let mut file = physfs::File::open(&path, physfs::Mode::Write)?;
+            file.write_all(&encode(seed, &settings, &frames))?;
+            Ok(frames.len())
+        }
+        Some(Replay::Playing { frames, previous }) => {
+            previous.apply();
+            Ok(frames.len())
+        }
+        None => anyhow::bail!("no replay in progress"),
+    }
+}
+
+/// Whether a replay is being recorded or played.
+pub fn is_active() -> bool {
+    REPLAY.lock().unwrap().is_some()
+}
+
+/// Time step to use for the current frame. Starts a new frame when recording, and uses the
+/// recorded time step when playing.
+pub fn dt(real_dt: f64) -> f64 {
+    let mut replay = REPLAY.lock().unwrap();
+    match &mut *replay {
+        Some(Replay::Recording {
+            frames, pending, ..
+        }) => {
+            frames.push(Frame {
+                dt: real_dt,
+                events: std::mem::take(pending),
+            });
+            real_dt
+        }
+        Some(Replay::Playing { frames, previous }) => match frames.pop_front() {
+            Some(frame) => frame.dt,
+            None => {
+                previous.apply();
+                *replay = None;
+                info!(gettext("Replay finished"));
+                real_dt
+            }
+        },
+        None => real_dt,
+    }
+}
+
+/// Handles a real input event. Returns true if it should be ignored, which is the case for input
+/// while playing back.
+pub fn filter_event(event: &SDL_Event) -> bool {
+    if !recordable(event) {
+        return false;
+    }
+    match &mut *REPLAY.lock().unwrap() {
+        Some(Replay::Recording { pending, .. }) => {
+            pending.push_back(unsafe { std::mem::transmute::<SDL_Event, RawEvent>(*event) });
+            false
+        }
+        Some(Replay::Playing { .. }) => true,
+        None => false,
+    }
+}
+
+/// Gets the next recorded event of the upcoming frame when playing back.
+pub fn poll() -> Option<SDL_Event> {
+    match &mut *REPLAY.lock().unwrap() {
+        Some(Replay::Playing { frames }) => {
+            let raw = frames.front_mut()?.events.pop_front()?;
+            Some(unsafe { std::mem::transmute::<RawEvent, SDL_Event>(raw) })
+        }
+        _ => None,
+    }
+}
+
+fn encode(seed: u64, settings: &Settings, frames: &[Frame]) -> Vec<u8> {
+    let ver = version::VERSION.to_string();
+    let mut data = Vec::new();
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    data.extend_from_slice(&(ver.len() as u32).to_le_bytes());
+    data.extend_from_slice(ver.as_bytes());
+    data.extend_from_slice(&seed.to_le_bytes());
+    settings.encode(&mut data);
+    data.extend_from_slice(&(EVENT_SIZE as u32).to_le_bytes());
+    for frame in frames {
+        data.extend_from_slice(&frame.dt.to_le_bytes());
+        data.extend_from_slice(&(frame.events.len() as u32).to_le_bytes());
+        for event in &frame.events {
+            data.extend_from_slice(event);
+        }
+    }
+    data
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| anyhow::anyhow!("replay is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+/// Reads the seed, settings and frames of an encoded replay.
+fn decode(data: &[u8]) -> Result<(u64, Settings, VecDeque<Frame>)> {
+    let mut r = Reader { data, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC {
+        anyhow::bail!("not a replay file");
+    }
+    let format = r.u32()?;
+    if format != FORMAT_VERSION {
+        anyhow::bail!("unsupported replay format version '{format}'");
+    }
+    let len = r.u32()? as usize;
+    let ver = String::from_utf8_lossy(r.take(len)?).into_owned();
+    if ver != version::VERSION.to_string() {
+        warn!(
+            gettext("Replay was recorded with Naev {}, but this is Naev {}, it may not play back the same."),
+            ver,
+            version::VERSION.to_string()
+        );
+    }
+    let seed = r.u64()?;
+    let settings = Settings::decode(&mut r)?;
+    if r.u32()? as usize != EVENT_SIZE {
+        anyhow::bail!("replay was recorded on an incompatible platform");
+    }
+
+    let mut frames = VecDeque::new();
+    while !r.is_empty() {
+        let dt = r.f64()?;
+        let n = r.u32()? as usize;
+        let mut events = VecDeque::with_capacity(n);
+        for _ in 0..n {
+            events.push_back(r.take(EVENT_SIZE)?.try_into()?);
+        }
+        frames.push_back(Frame { dt, events });
+    }
+    Ok((seed, settings, frames))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn replay_record(path: *const c_char) -> c_int {
+    ffi_guard(-1, || {
+        let path = unsafe { CStr::from_ptr(path) };
+        match record(&path.to_string_lossy()) {
+            Ok(()) => 0,
+            Err(e) => {
+                warn_err(e.context(gettext("unable to start recording replay")));
+                -1
+            }
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn replay_play(path: *const c_char) -> c_int {
+    ffi_guard(-1, || {
+        let path = unsafe { CStr::from_ptr(path) };
+        match play(&path.to_string_lossy()) {
+            Ok(()) => 0,
+            Err(e) => {
+                warn_err(e.context(gettext("unable to play replay")));
+                -1
+            }
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn replay_stop() -> c_int {
+    ffi_guard(-1, || match stop() {
+        Ok(n) => n as c_int,
+        Err(e) => {
+            warn_err(e);
+            -1
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn replay_isActive() -> c_int {
+    ffi_guard(0, || is_active() as c_int)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn replay_dt(real_dt: c_double) -> c_double {
+    ffi_guard(real_dt, || dt(real_dt))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn replay_filterEvent(event: *const SDL_Event) -> c_int {
+    ffi_guard(0, || filter_event(unsafe { &*event }) as c_int)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn replay_poll(event: *mut SDL_Event) -> c_int {
+    ffi_guard(0, || match poll() {
+        Some(ev) => {
+            unsafe {
+                *event = ev;
+            }
+            1
+        }
+        None => 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small stand-in for the game loop, driven by time, random numbers and input.
+    fn simulate(real_dts: &[f64], input: bool) -> (f64, Vec<i32>) {
+        let mut pos = 0.0;
+        let mut keys = Vec::new();
+        for (i, real_dt) in real_dts.iter().enumerate() {
+            // Input is handled before updating, like in the main loop
+            if input {
+                let mut event: SDL_Event = unsafe { std::mem::zeroed() };
+                event.type_ = SDL_EventType::SDL_KEYDOWN as u32;
+                event.key.keysym.sym = i as i32;
+                assert!(!filter_event(&event));
+            }
+            while let Some(event) = poll() {
+                keys.push(unsafe { event.key.keysym.sym });
+            }
+            pos += dt(*real_dt) * rng::rngf32() as f64;
+        }
+        (pos, keys)
+    }
+
+    #[test]
+    fn replay_end_state() {
+        let real_dts = [0.016, 0.017, 0.033, 0.008, 0.016, 0.05];
+        start(
+            Replay::Recording {
+                path: String::new(),
+                seed: 1234,
+                settings: Settings::current(),
+                frames: Vec::new(),
+                pending: VecDeque::new(),
+            },
+            1234,
+        )
+        .unwrap();
+        let (recorded, _) = simulate(&real_dts, true);
+        let data = match REPLAY.lock().unwrap().take() {
+            Some(Replay::Recording {
+                seed,
+                settings,
+                frames,
+                ..
+            }) => encode(seed, &settings, &frames),
+            _ => unreachable!(),
+        };
+
+        let (seed, _, frames) = decode(&data).unwrap();
+        assert_eq!(frames.len(), real_dts.len());
+        start(
+            Replay::Playing {
+                frames,
+                previous: Settings::current(),
+            },
+            seed,
+        )
+        .unwrap();
+        // Different timings and no real input, the replay has to make up for both
+        let (played, keys) = simulate(&[0.1; 6], false);
+        assert_eq!(recorded, played);
+        assert_eq!(keys, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(stop().unwrap(), 0);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = Settings {
+            time_scale: 0.5,
+            game_speed: 1.5,
+            compression_velocity: 5000.0,
+            compression_mult: 200.0,
+            mouse_doubleclick: 0.5,
+            autonav_reset_dist: 5000.0,
+            autonav_reset_shield: 1.0,
+            doubletap_sens: 250,
+            repeat_delay: 500,
+            repeat_freq: 30,
+            mouse_fly: 1,
+            mouse_accel: -1,
+        };
+        let data = encode(42, &settings, &[]);
+        let (seed, decoded, frames) = decode(&data).unwrap();
+        assert_eq!(seed, 42);
+        assert_eq!(decoded, settings);
+        assert!(frames.is_empty());
+    }
+}
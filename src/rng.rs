@@ -1,25 +1,34 @@
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 use std::os::raw::{c_double, c_uint};
 
+use crate::ffi::ffi_guard;
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn randint() -> c_uint {
-    RNG.with_borrow_mut(|x| x.random::<u32>())
+    ffi_guard(0, || RNG.with_borrow_mut(|x| x.random::<u32>()))
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn randfp() -> c_double {
-    RNG.with_borrow_mut(|x| x.random::<f64>())
+    ffi_guard(0.0, || RNG.with_borrow_mut(|x| x.random::<f64>()))
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn Normal(x: c_double) -> c_double {
-    normal(x)
+    ffi_guard(0.0, || normal(x))
 }
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn NormalInverse(p: c_double) -> c_double {
-    normal_inverse(p) as c_double
+    ffi_guard(0.0, || normal_inverse(p) as c_double)
 }
 
 thread_local! {
-    static RNG: std::cell::RefCell<rand::rngs::ThreadRng> = std::cell::RefCell::new(rand::rng());
+    static RNG: std::cell::RefCell<rand::rngs::StdRng> =
+        std::cell::RefCell::new(rand::rngs::StdRng::from_os_rng());
+}
+
+/// Reseeds the random number generator of the current thread, so that the numbers that follow
+/// are reproducible. Used by replays.
+pub fn seed(seed: u64) {
+    RNG.with_borrow_mut(|x| *x = rand::rngs::StdRng::seed_from_u64(seed));
 }
 
 pub fn rngf32() -> f32 {
@@ -1,39 +1,133 @@
 #![allow(dead_code)]
 use anyhow::Result;
 use glow::*;
-use std::ffi::CStr;
-use std::os::raw::c_char;
+use std::ffi::{CStr, CString};
+use std::io::Write;
+use std::num::NonZero;
+use std::os::raw::{c_char, c_int};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
 
-use crate::context::Context;
+use crate::context::{BarrierFlags, Context, Feature};
+use crate::ffi::ffi_guard;
 use crate::gettext::gettext;
-use crate::{context, ndata};
-use crate::{einfo, warn};
+use crate::log::warn_err;
+use crate::{context, ndata, physfs, render};
+use crate::{debug, einfo, warn};
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ShaderType {
     Fragment,
     Vertex,
+    Geometry,
+    Compute,
 }
 impl ShaderType {
     pub fn to_gl(&self) -> u32 {
         match self {
             ShaderType::Fragment => glow::FRAGMENT_SHADER,
             ShaderType::Vertex => glow::VERTEX_SHADER,
+            ShaderType::Geometry => glow::GEOMETRY_SHADER,
+            ShaderType::Compute => glow::COMPUTE_SHADER,
         }
     }
+
+    /// Name of the stage for messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShaderType::Fragment => "fragment",
+            ShaderType::Vertex => "vertex",
+            ShaderType::Geometry => "geometry",
+            ShaderType::Compute => "compute",
+        }
+    }
+
+    /// Extension used when dumping sources of the stage.
+    fn extension(&self) -> &'static str {
+        match self {
+            ShaderType::Fragment => "frag",
+            ShaderType::Vertex => "vert",
+            ShaderType::Geometry => "geom",
+            ShaderType::Compute => "comp",
+        }
+    }
+}
+
+/// The program of a [`Shader`], kept separately so that it can be swapped from the registry used
+/// by [`reload_all`].
+struct ProgramSlot {
+    name: String,
+    program: AtomicU32,
+    /// Files the program was built from, including all the resolved includes.
+    files: Mutex<Vec<String>>,
+    /// Active uniform blocks of the current program.
+    blocks: Mutex<Vec<UniformBlock>>,
+    /// Configuration the shader was built with, used when reloading.
+    builder: ShaderBuilder,
+}
+impl ProgramSlot {
+    fn program(&self) -> glow::Program {
+        glow::NativeProgram(NonZero::new(self.program.load(Ordering::Acquire)).unwrap())
+    }
+
+    fn reload(&self, gl: &glow::Context) -> Result<()> {
+        let built = self.builder.build_program(gl)?;
+        let old = self.program.swap(built.program.0.get(), Ordering::AcqRel);
+        *self.files.lock().unwrap() = built.files;
+        *self.blocks.lock().unwrap() = built.blocks;
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteProgram(glow::NativeProgram(
+                NonZero::new(old).unwrap(),
+            )));
+        Ok(())
+    }
+}
+
+/// Shaders that can be reloaded with [`reload_all`]. Only kept in debug builds.
+static REGISTRY: Mutex<Vec<Weak<ProgramSlot>>> = Mutex::new(Vec::new());
+
+/// Rebuilds all the live shaders from their sources, so that changes to the files can be seen
+/// without restarting. Shaders that fail to build keep their old program. Returns the number of
+/// shaders reloaded. Does nothing in release builds.
+pub fn reload_all(ctx: &Context) -> usize {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|slot| slot.strong_count() > 0);
+    let mut n = 0;
+    for slot in registry.iter().filter_map(Weak::upgrade) {
+        match slot.reload(&ctx.gl) {
+            Ok(()) => n += 1,
+            Err(e) => {
+                warn!("unable to reload shader '{}': {}", slot.name, e);
+            }
+        }
+    }
+    n
+}
+
+/// Uniform block of a linked program, as reported by the driver.
+#[derive(Clone, Debug)]
+pub struct UniformBlock {
+    pub name: String,
+    pub index: u32,
+    /// Size of the block in bytes, following the std140 layout.
+    pub size: usize,
 }
 
 pub struct Shader {
     pub name: String,
     pub vertname: String,
     pub fragname: String,
-    pub program: glow::Program,
+    pub geomname: Option<String>,
+    slot: Arc<ProgramSlot>,
 }
 impl Drop for Shader {
     fn drop(&mut self) {
         context::MESSAGE_QUEUE
             .lock()
             .unwrap()
-            .push(context::Message::DeleteProgram(self.program));
+            .push(context::Message::DeleteProgram(self.program()));
     }
 }
 impl Shader {
@@ -42,6 +136,7 @@ impl Shader {
         shadertype: ShaderType,
         name: &str,
         source: &str,
+        map: &SourceMap,
     ) -> Result<glow::Shader> {
         let shader = unsafe {
             gl.create_shader(shadertype.to_gl())
@@ -50,14 +145,27 @@ impl Shader {
         unsafe {
             gl.shader_source(shader, source);
             gl.compile_shader(shader);
-            gl.object_label(glow::SHADER, shader.0.into(), Some(name));
+            context::set_label(gl, glow::SHADER, shader.0.into(), Some(name));
         }
         if unsafe { !gl.get_shader_compile_status(shader) } {
             for (i, line) in source.lines().enumerate() {
-                einfo!("{:04}: {}", i, line);
+                match map.locate(i + 1) {
+                    Some((file, fline)) => {
+                        einfo!("{:04} [{}:{}]: {}", i + 1, file, fline, line);
+                    }
+                    None => {
+                        einfo!("{:04}: {}", i + 1, line);
+                    }
+                }
             }
             let slog = unsafe { gl.get_shader_info_log(shader) };
-            warn!("Failed to compile shader '{}': [[\n{}\n]]", name, slog);
+            let slog = map.translate_log(&slog);
+            warn!(
+                "Failed to compile {} shader '{}': [[\n{}\n]]",
+                shadertype.name(),
+                name,
+                slog
+            );
             return Err(anyhow::anyhow!("failed to compile shader program"));
         }
         Ok(shader)
@@ -66,17 +174,32 @@ impl Shader {
     fn link(
         gl: &glow::Context,
         name: &str,
-        vertshader: glow::Shader,
-        fragshader: glow::Shader,
+        shaders: &[glow::Shader],
+        retrievable: bool,
+        feedback: Option<&(Vec<String>, bool)>,
     ) -> Result<glow::Program> {
         let program = unsafe { gl.create_program().map_err(|e| anyhow::anyhow!(e))? };
         unsafe {
-            gl.attach_shader(program, vertshader);
-            gl.attach_shader(program, fragshader);
+            if retrievable {
+                gl.program_binary_retrievable_hint(program, true);
+            }
+            for shader in shaders {
+                gl.attach_shader(program, *shader);
+            }
+            // Has to be set before linking
+            if let Some((varyings, interleaved)) = feedback {
+                let varyings: Vec<&str> = varyings.iter().map(|v| &v[..]).collect();
+                let mode = match interleaved {
+                    true => glow::INTERLEAVED_ATTRIBS,
+                    false => glow::SEPARATE_ATTRIBS,
+                };
+                gl.transform_feedback_varyings(program, &varyings, mode);
+            }
             gl.link_program(program);
-            gl.delete_shader(vertshader);
-            gl.delete_shader(fragshader);
-            gl.object_label(glow::PROGRAM, program.0.into(), Some(name));
+            for shader in shaders {
+                gl.delete_shader(*shader);
+            }
+            context::set_label(gl, glow::PROGRAM, program.0.into(), Some(name));
         }
         if unsafe { !gl.get_program_link_status(program) } {
             let slog = unsafe { gl.get_program_info_log(program) };
@@ -86,14 +209,32 @@ impl Shader {
         Ok(program)
     }
 
+    /// Rebuilds the program from its sources, e.g., after they were modified on disk. The sampler
+    /// units and uniform block bindings given to the builder are applied again, so the shader can
+    /// keep being used as before. The old program is kept if the new one fails to build.
+    pub fn reload(&self, gl: &glow::Context) -> Result<()> {
+        self.slot.reload(gl)
+    }
+
+    /// Current program, which changes when the shader is reloaded.
+    pub fn program(&self) -> glow::Program {
+        self.slot.program()
+    }
+
+    /// Files the shader was built from, including the resolved `#include`s. Virtual includes are
+    /// not listed.
+    pub fn files(&self) -> Vec<String> {
+        self.slot.files.lock().unwrap().clone()
+    }
+
     pub fn use_program(&self, gl: &glow::Context) {
         unsafe {
-            gl.use_program(Some(self.program));
+            gl.use_program(Some(self.program()));
         }
     }
 
     pub fn get_attrib(&self, gl: &glow::Context, name: &str) -> Result<u32> {
-        match unsafe { gl.get_attrib_location(self.program, name) } {
+        match unsafe { gl.get_attrib_location(self.program(), name) } {
             Some(idx) => Ok(idx),
             None => {
                 anyhow::bail!("Shader '{}' does not have '{}' attrib!", self.name, name);
@@ -101,9 +242,9 @@ impl Shader {
         }
     }
 
-    pub fn get_uniform_block(&self, gl: &glow::Context, name: &str) -> Result<u32> {
-        match unsafe { gl.get_uniform_block_index(self.program, name) } {
-            Some(idx) => Ok(idx),
+    pub fn get_uniform_block(&self, _gl: &glow::Context, name: &str) -> Result<u32> {
+        match self.uniform_block(name) {
+            Some(block) => Ok(block.index),
             None => {
                 anyhow::bail!(
                     "Shader '{}' does not have '{}' uniform block!",
@@ -113,8 +254,131 @@ impl Shader {
             }
         }
     }
+
+    /// Gets an active uniform block of the program. Blocks that are declared but not used by the
+    /// shader may be optimized out by the driver.
+    pub fn uniform_block(&self, name: &str) -> Option<UniformBlock> {
+        let blocks = self.slot.blocks.lock().unwrap();
+        blocks.iter().find(|b| b.name == name).cloned()
+    }
+
+    /// Gets the size in bytes of an active uniform block.
+    pub fn uniform_block_size(&self, name: &str) -> Option<usize> {
+        self.uniform_block(name).map(|b| b.size)
+    }
+
+    /// Checks that the size of a uniform block matches the size of the Rust structure filling it,
+    /// e.g., `SolidUniform::default().buffer()?.len()`, to catch layouts that drifted apart. Both
+    /// are std140 sizes, which include the padding at the end of the block, so they have to match
+    /// exactly.
+    pub fn validate_block(&self, name: &str, expected_size: usize) -> Result<()> {
+        match self.uniform_block_size(name) {
+            Some(size) if size == expected_size => Ok(()),
+            Some(size) => anyhow::bail!(
+                "Shader '{}' uniform block '{}' is {} bytes, but {} bytes were expected!",
+                self.name,
+                name,
+                size,
+                expected_size
+            ),
+            None => anyhow::bail!(
+                "Shader '{}' does not have '{}' uniform block!",
+                self.name,
+                name
+            ),
+        }
+    }
+}
+
+/// Maps the lines of a preprocessed source back to the file and line they came from, so that
+/// errors reported by the driver can be located after includes are expanded.
+#[derive(Default)]
+struct SourceMap {
+    names: Vec<String>,
+    /// File index and 1-based line of every line of the source.
+    lines: Vec<(usize, usize)>,
+}
+impl SourceMap {
+    fn name_index(&mut self, name: &str) -> usize {
+        match self.names.iter().position(|n| n == name) {
+            Some(idx) => idx,
+            None => {
+                self.names.push(String::from(name));
+                self.names.len() - 1
+            }
+        }
+    }
+
+    /// Accounts for `text` being inserted at the start of the source.
+    fn prepend(&mut self, name: &str, text: &str) {
+        let idx = self.name_index(name);
+        let n = text.matches('\n').count();
+        self.lines.splice(0..0, (1..=n).map(|l| (idx, l)));
+    }
+
+    /// Gets the file and line a 1-based line of the source came from.
+    fn locate(&self, line: usize) -> Option<(&str, usize)> {
+        let (idx, fline) = self.lines.get(line.checked_sub(1)?)?;
+        Some((&self.names[*idx], *fline))
+    }
+
+    /// Finds the line number in a line of a driver info log. Drivers don't agree on the format,
+    /// but they all put the source string number followed by the line first, e.g., "0:12(3):"
+    /// (Mesa), "0(12) :" (NVIDIA) or "ERROR: 0:12:" (AMD).
+    fn parse_log_line(line: &str) -> Option<usize> {
+        let bytes = line.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if !bytes[i].is_ascii_digit() {
+                i += 1;
+                continue;
+            }
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            if i < bytes.len() && (bytes[i] == b':' || bytes[i] == b'(') {
+                let start = i + 1;
+                let end = start
+                    + bytes[start..]
+                        .iter()
+                        .take_while(|b| b.is_ascii_digit())
+                        .count();
+                if end > start {
+                    return line[start..end].parse().ok();
+                }
+            }
+        }
+        None
+    }
+
+    /// Prefixes every line of a driver info log with the file and line it refers to.
+    fn translate_log(&self, log: &str) -> String {
+        log.lines()
+            .map(
+                |line| match Self::parse_log_line(line).and_then(|l| self.locate(l)) {
+                    Some((file, fline)) => format!("{file}:{fline}: {line}"),
+                    None => String::from(line),
+                },
+            )
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
+/// State of the preprocessor while expanding a shader source.
+#[derive(Default)]
+struct Preprocessor {
+    /// Files read so far.
+    files: Vec<String>,
+    /// Includes currently being expanded, to detect cycles.
+    stack: Vec<String>,
+    /// Includes marked with `#pragma once` in the current stage, which are not expanded again.
+    once: Vec<String>,
+    /// Origin of the lines of the source being expanded.
+    map: SourceMap,
+}
+
+#[derive(Clone)]
 enum ShaderSource {
     Path(String),
     Data(String),
@@ -122,17 +386,27 @@ enum ShaderSource {
 }
 impl ShaderSource {
     const INCLUDE_INSTRUCTION: &str = "#include";
+    const PRAGMA_ONCE: &str = "#pragma once";
     const GLSL_PATH: &str = "glsl/";
+    /// Includes generated from Rust instead of being read from ndata.
+    const VIRTUAL_INCLUDES: &[(&str, &str)] = &[
+        ("noise.glsl", crate::noise::GLSL),
+        ("time.glsl", crate::render::TIME_GLSL),
+    ];
+
+    /// Maximum nesting of includes, in case a cycle somehow isn't caught.
+    const MAX_INCLUDE_DEPTH: usize = 32;
 
     /// Really simple preprocessor
-    fn preprocess(data: &str) -> Result<String> {
+    fn preprocess(data: &str, name: &str, state: &mut Preprocessor) -> Result<String> {
+        let idx = state.map.name_index(name);
         let mut module_string = String::new();
-        for line in data.lines() {
+        for (i, line) in data.lines().enumerate() {
             let line = line.trim();
             if line.starts_with(Self::INCLUDE_INSTRUCTION) {
                 match line.split("\"").nth(1) {
                     Some(include) => {
-                        let include_string = Self::load_file(include)?;
+                        let include_string = Self::load_file(include, state)?;
                         module_string.push_str(&include_string);
                         module_string.push('\n');
                     }
@@ -140,25 +414,96 @@ impl ShaderSource {
                         return Err(anyhow::anyhow!("#include syntax error"));
                     }
                 }
+            } else if line == Self::PRAGMA_ONCE {
+                // Not valid GLSL, so leave an empty line to keep the line numbers
+                if let Some(current) = state.stack.last() {
+                    if !state.once.contains(current) {
+                        state.once.push(current.clone());
+                    }
+                }
+                module_string.push('\n');
             } else {
                 module_string.push_str(line);
                 module_string.push('\n');
             }
+            state.map.lines.push((idx, i + 1));
         }
         Ok(module_string)
     }
 
-    fn load_file(path: &str) -> Result<String> {
-        let fullpath = format!("{}{}", Self::GLSL_PATH, path);
-        let rawdata = ndata::read(&fullpath)?;
-        let data = std::str::from_utf8(&rawdata)?;
-        Self::preprocess(data)
+    /// Resolves an include to a path in ndata. Paths starting with `/` are taken from the root of
+    /// ndata. Other paths are relative to the including file's directory like in C, falling back
+    /// to the shader directory, which is where most includes like "lib/sdf.glsl" are.
+    fn resolve(path: &str, state: &Preprocessor) -> Result<String> {
+        if let Some(path) = path.strip_prefix('/') {
+            return Ok(ndata::simplify_path(path)?);
+        }
+        let dir = state
+            .stack
+            .last()
+            .and_then(|parent| parent.rsplit_once('/'))
+            .map(|(dir, _)| dir);
+        if let Some(dir) = dir {
+            let relative = ndata::simplify_path(&format!("{dir}/{path}"))?;
+            if ndata::exists(&relative) {
+                return Ok(relative);
+            }
+        }
+        Ok(ndata::simplify_path(&format!(
+            "{}{}",
+            Self::GLSL_PATH,
+            path
+        ))?)
+    }
+
+    fn load_file(path: &str, state: &mut Preprocessor) -> Result<String> {
+        let (fullpath, data) = match Self::VIRTUAL_INCLUDES
+            .iter()
+            .find(|(name, _)| *name == path)
+        {
+            Some((name, data)) => (String::from(*name), Some(*data)),
+            None => (Self::resolve(path, state)?, None),
+        };
+
+        if state.once.iter().any(|p| *p == fullpath) {
+            return Ok(String::new());
+        }
+        if state.stack.iter().any(|p| *p == fullpath) {
+            let chain = state.stack.join(" -> ");
+            anyhow::bail!("include cycle detected: {chain} -> {fullpath}");
+        }
+        if state.stack.len() >= Self::MAX_INCLUDE_DEPTH {
+            let chain = state.stack.join(" -> ");
+            anyhow::bail!(
+                "maximum include depth of {} exceeded: {chain} -> {fullpath}",
+                Self::MAX_INCLUDE_DEPTH
+            );
+        }
+
+        let data = match data {
+            Some(data) => String::from(data),
+            None => {
+                let rawdata = ndata::read(&fullpath)?;
+                state.files.push(fullpath.clone());
+                String::from_utf8(rawdata)?
+            }
+        };
+        // Name files in the shader directory like before for the error messages
+        let name = fullpath
+            .strip_prefix(Self::GLSL_PATH)
+            .unwrap_or(&fullpath)
+            .to_string();
+        state.stack.push(fullpath);
+        let res = Self::preprocess(&data, &name, state);
+        state.stack.pop();
+        res
     }
 
-    pub fn to_string(&self) -> Result<String> {
+    /// Gets the preprocessed source, adding the files it was read from to `state`.
+    fn to_string(&self, state: &mut Preprocessor) -> Result<String> {
         match self {
-            Self::Path(path) => Self::load_file(path),
-            Self::Data(data) => Self::preprocess(data),
+            Self::Path(path) => Self::load_file(path, state),
+            Self::Data(data) => Self::preprocess(data, &self.name(), state),
             Self::None => Err(anyhow::anyhow!("no shader source defined!")),
         }
     }
@@ -172,13 +517,56 @@ impl ShaderSource {
     }
 }
 
+struct BuiltProgram {
+    name: String,
+    vertname: String,
+    fragname: String,
+    geomname: Option<String>,
+    program: glow::Program,
+    files: Vec<String>,
+    blocks: Vec<UniformBlock>,
+}
+
+/// Directory in the write dir where linked program binaries are cached.
+const CACHE_PATH: &str = "shader_cache";
+
+/// 64-bit FNV-1a hash for naming cached binaries. Unlike [`std::hash::DefaultHasher`], the
+/// result doesn't change between builds, which would leave the old binaries behind.
+struct CacheHasher(u64);
+impl CacheHasher {
+    fn new() -> Self {
+        CacheHasher(0xcbf29ce484222325)
+    }
+
+    /// Adds a string, followed by a separator so that consecutive strings can't run together.
+    fn write_str(&mut self, s: &str) {
+        for b in s.bytes().chain(std::iter::once(0xff)) {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Clone)]
 pub struct ShaderBuilder {
     name: Option<String>,
     vert: ShaderSource,
     frag: ShaderSource,
+    geom: ShaderSource,
     prepend: String,
+    defines: Vec<(String, Option<String>)>,
+    /// Captured varyings and whether they are interleaved.
+    feedback: Option<(Vec<String>, bool)>,
     samplers: Vec<(String, i32)>,
     uniform_buffers: Vec<(String, u32)>,
+    dump: Option<String>,
+    debug: bool,
+    cache: bool,
+    reloadable: bool,
 }
 impl ShaderBuilder {
     pub fn new(name: Option<&str>) -> Self {
@@ -186,9 +574,16 @@ impl ShaderBuilder {
             name: name.map(String::from),
             vert: ShaderSource::None,
             frag: ShaderSource::None,
+            geom: ShaderSource::None,
             prepend: Default::default(),
+            defines: Vec::new(),
+            feedback: None,
             samplers: Vec::new(),
             uniform_buffers: Vec::new(),
+            dump: None,
+            debug: false,
+            cache: true,
+            reloadable: true,
         }
     }
 
@@ -212,11 +607,66 @@ impl ShaderBuilder {
         self
     }
 
+    /// Adds an optional geometry stage read from a file.
+    pub fn geom_file(mut self, path: &str) -> Self {
+        self.geom = ShaderSource::Path(String::from(path));
+        self
+    }
+
+    /// Adds an optional geometry stage from source.
+    pub fn geom_data(mut self, data: &str) -> Self {
+        self.geom = ShaderSource::Data(String::from(data));
+        self
+    }
+
     pub fn prepend(mut self, data: &str) -> Self {
         self.prepend = String::from(data);
         self
     }
 
+    /// Adds `#define key value` after the `#version` line, e.g., to compile a variant of a
+    /// shader. Defining the same key again replaces the value, keeping the original order.
+    pub fn define(mut self, key: &str, value: &str) -> Self {
+        self.set_define(key, Some(String::from(value)));
+        self
+    }
+
+    /// Adds `#define key` without a value, to be checked with `#ifdef`.
+    pub fn define_flag(mut self, key: &str) -> Self {
+        self.set_define(key, None);
+        self
+    }
+
+    fn set_define(&mut self, key: &str, value: Option<String>) {
+        match self.defines.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.defines.push((String::from(key), value)),
+        }
+    }
+
+    /// Captures the outputs `varyings` of the last vertex processing stage with transform
+    /// feedback, e.g., to simulate particles on the GPU. With `interleaved`, they are all written
+    /// to the buffer bound at index 0, otherwise each one goes to the buffer at its own index. See
+    /// [`TransformFeedback`](crate::buffer::TransformFeedback).
+    pub fn feedback_varyings(mut self, varyings: &[&str], interleaved: bool) -> Self {
+        self.feedback = Some((
+            varyings.iter().map(|v| String::from(*v)).collect(),
+            interleaved,
+        ));
+        self
+    }
+
+    /// Gets the `#define` lines of [`ShaderBuilder::define`] and [`ShaderBuilder::define_flag`].
+    fn defines_string(&self) -> String {
+        self.defines
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => format!("#define {key} {value}\n"),
+                None => format!("#define {key}\n"),
+            })
+            .collect()
+    }
+
     pub fn sampler(mut self, name: &str, idx: i32) -> Self {
         self.samplers.push((name.to_string(), idx));
         self
@@ -227,48 +677,307 @@ impl ShaderBuilder {
         self
     }
 
+    /// Writes the final sources, after preprocessing, to `path.vert` and `path.frag` in the
+    /// write directory. If debug mode is also enabled, the program binary is written to
+    /// `path.bin`.
+    pub fn dump(mut self, path: &str) -> Self {
+        self.dump = Some(String::from(path));
+        self
+    }
+
+    /// Whether the shader is rebuilt by [`reload_all`]. Reloading replaces the program, so it has
+    /// to be disabled for programs whose id is handed out, e.g., to C or Lua.
+    pub fn reloadable(mut self, enable: bool) -> Self {
+        self.reloadable = enable;
+        self
+    }
+
+    /// Requests the program binary from the driver after linking, and logs its format and size.
+    pub fn debug(mut self, enable: bool) -> Self {
+        self.debug = enable;
+        self
+    }
+
+    /// Always compiles the shader from source instead of using the program binary cache, e.g.,
+    /// when working on the shader.
+    pub fn no_cache(mut self) -> Self {
+        self.cache = false;
+        self
+    }
+
+    /// Program binaries need OpenGL 4.1 or GL_ARB_get_program_binary.
+    fn binary_supported(gl: &glow::Context) -> bool {
+        let version = gl.version();
+        (version.major, version.minor) >= (4, 1)
+            || gl
+                .supported_extensions()
+                .contains("GL_ARB_get_program_binary")
+    }
+
+    fn cache_supported(gl: &glow::Context) -> bool {
+        Self::binary_supported(gl)
+            && unsafe { gl.get_parameter_i32(glow::NUM_PROGRAM_BINARY_FORMATS) } > 0
+    }
+
+    /// Path of the cached binary of a program. Besides the final sources, the driver strings are
+    /// part of the key, so that updating the driver doesn't use stale binaries.
+    fn cache_path(gl: &glow::Context, sources: &[&str]) -> String {
+        let mut hasher = CacheHasher::new();
+        unsafe {
+            hasher.write_str(&gl.get_parameter_string(glow::VENDOR));
+            hasher.write_str(&gl.get_parameter_string(glow::RENDERER));
+            hasher.write_str(&gl.get_parameter_string(glow::VERSION));
+        }
+        for source in sources {
+            hasher.write_str(source);
+        }
+        format!("{}/{:016x}.bin", CACHE_PATH, hasher.finish())
+    }
+
+    /// Tries to create the program from a cached binary. Drivers are free to reject binaries, in
+    /// which case the binary is deleted, `None` is returned and the program should be compiled
+    /// from source.
+    fn cache_load(gl: &glow::Context, path: &str, name: &str) -> Option<glow::Program> {
+        if !ndata::exists(path) {
+            return None;
+        }
+        let data = match ndata::read(path) {
+            Ok(data) if data.len() > 4 => data,
+            _ => {
+                Self::cache_delete(path);
+                return None;
+            }
+        };
+        let binary = glow::ProgramBinary {
+            format: u32::from_le_bytes(data[..4].try_into().unwrap()),
+            buffer: data[4..].to_vec(),
+        };
+        unsafe {
+            let program = gl.create_program().ok()?;
+            gl.program_binary(program, &binary);
+            if gl.get_program_link_status(program) {
+                context::set_label(gl, glow::PROGRAM, program.0.into(), Some(name));
+                Some(program)
+            } else {
+                gl.delete_program(program);
+                debug!("shader '{}' cached binary rejected, recompiling", name);
+                Self::cache_delete(path);
+                None
+            }
+        }
+    }
+
+    /// Removes a cached binary that can't be used, so it doesn't get loaded again.
+    fn cache_delete(path: &str) {
+        let ok = match CString::new(path) {
+            Ok(cpath) => unsafe { naevc::PHYSFS_delete(cpath.as_ptr()) != 0 },
+            Err(_) => false,
+        };
+        if !ok {
+            debug!("unable to delete cached shader binary '{}'", path);
+        }
+    }
+
+    fn cache_store(gl: &glow::Context, path: &str, program: glow::Program) {
+        let binary = match unsafe { gl.get_program_binary(program) } {
+            Some(binary) => binary,
+            None => return,
+        };
+        if let Ok(cpath) = CString::new(CACHE_PATH) {
+            unsafe {
+                naevc::PHYSFS_mkdir(cpath.as_ptr());
+            }
+        }
+        let write = || -> Result<()> {
+            let mut file = physfs::File::open(path, physfs::Mode::Write)?;
+            file.write_all(&binary.format.to_le_bytes())?;
+            file.write_all(&binary.buffer)?;
+            Ok(())
+        };
+        if let Err(e) = write() {
+            // Not being able to cache only makes the next launch slower
+            debug!("unable to cache shader binary to '{}': {}", path, e);
+        }
+    }
+
+    fn dump_file(path: &str, data: &[u8]) {
+        let write = || -> Result<()> {
+            let mut file = physfs::File::open(path, physfs::Mode::Write)?;
+            file.write_all(data)?;
+            Ok(())
+        };
+        match write() {
+            Ok(()) => {
+                debug!("dumped shader to '{}'", path);
+            }
+            Err(e) => {
+                warn!("unable to dump shader to '{}': {}", path, e);
+            }
+        }
+    }
+
     pub fn build(self, gl: &glow::Context) -> Result<Shader> {
-        let mut vertdata = ShaderSource::to_string(&self.vert)?;
-        let mut fragdata = ShaderSource::to_string(&self.frag)?;
+        let built = self.build_program(gl)?;
+        let slot = Arc::new(ProgramSlot {
+            name: built.name.clone(),
+            program: AtomicU32::new(built.program.0.get()),
+            files: Mutex::new(built.files),
+            blocks: Mutex::new(built.blocks),
+            builder: self,
+        });
+        #[cfg(debug_assertions)]
+        if slot.builder.reloadable {
+            REGISTRY.lock().unwrap().push(Arc::downgrade(&slot));
+        }
+        Ok(Shader {
+            name: built.name,
+            vertname: built.vertname,
+            fragname: built.fragname,
+            geomname: built.geomname,
+            slot,
+        })
+    }
 
+    /// Compiles and links the program, and sets up its bindings.
+    fn build_program(&self, gl: &glow::Context) -> Result<BuiltProgram> {
         let glsl = unsafe { naevc::gl_screen.glsl };
         let mut prepend = format!("#version {glsl}\n\n#define GLSL_VERSION {glsl}\n");
-        prepend.push_str("#define HAS_GL_ARB_shader_subroutine 1\n");
+        prepend.push_str(&format!(
+            "#define HAS_GL_ARB_shader_subroutine {}\n",
+            // Builtin shaders are built before the context is available
+            match Context::get() {
+                Ok(ctx) => ctx.limits.shader_subroutines,
+                Err(_) => context::has_shader_subroutines(gl),
+            } as i32
+        ));
+        let defines = self.defines_string();
 
-        if !self.prepend.is_empty() {
-            vertdata.insert_str(0, &self.prepend);
-            fragdata.insert_str(0, &self.prepend);
+        // The geometry stage is optional
+        let mut state = Preprocessor::default();
+        let mut stages = Vec::new();
+        for (ty, source) in [
+            (ShaderType::Vertex, &self.vert),
+            (ShaderType::Geometry, &self.geom),
+            (ShaderType::Fragment, &self.frag),
+        ] {
+            if ty == ShaderType::Geometry && matches!(source, ShaderSource::None) {
+                continue;
+            }
+            let mut data = source.to_string(&mut state)?;
+            let mut map = std::mem::take(&mut state.map);
+            // Each stage is compiled on its own, so it needs its own copy of the includes
+            state.once.clear();
+            if !self.prepend.is_empty() {
+                data.insert_str(0, &self.prepend);
+                map.prepend("PREPEND", &self.prepend);
+            }
+            if !defines.is_empty() {
+                data.insert_str(0, &defines);
+                map.prepend("DEFINES", &defines);
+            }
+            data.insert_str(0, &prepend);
+            map.prepend("HEADER", &prepend);
+            stages.push((ty, source.name(), data, map));
+        }
+        let mut files = state.files;
+        files.sort();
+        files.dedup();
+
+        // Dump before compiling so that sources that fail to compile can be inspected
+        if let Some(path) = &self.dump {
+            for (ty, _, data, _) in &stages {
+                Self::dump_file(&format!("{path}.{}", ty.extension()), data.as_bytes());
+            }
         }
-        vertdata.insert_str(0, &prepend);
-        fragdata.insert_str(0, &prepend);
 
         let vertname = self.vert.name();
         let fragname = self.frag.name();
-
-        let vertshader = Shader::compile(gl, ShaderType::Vertex, &vertname, &vertdata)?;
-        let fragshader = Shader::compile(gl, ShaderType::Fragment, &fragname, &fragdata)?;
-        let name = match self.name {
-            Some(name) => name,
+        let geomname = match self.geom {
+            ShaderSource::None => None,
+            _ => Some(self.geom.name()),
+        };
+        let name = match &self.name {
+            Some(name) => name.clone(),
             None => format!("{}-{}", &vertname, &fragname),
         };
-        let program = Shader::link(gl, &name, vertshader, fragshader)?;
+        let cache = match self.cache && Self::cache_supported(gl) {
+            true => {
+                // The captured varyings are part of the binary, but not of the sources
+                let feedback = format!("{:?}", self.feedback);
+                let mut sources: Vec<&str> =
+                    stages.iter().map(|(_, _, data, _)| &data[..]).collect();
+                sources.push(&feedback);
+                Some(Self::cache_path(gl, &sources))
+            }
+            false => None,
+        };
+        let cached = cache
+            .as_ref()
+            .and_then(|path| Self::cache_load(gl, path, &name));
+        let program = match cached {
+            Some(program) => program,
+            None => {
+                let mut shaders = Vec::with_capacity(stages.len());
+                for (ty, sname, data, map) in &stages {
+                    match Shader::compile(gl, *ty, sname, data, map) {
+                        Ok(shader) => shaders.push(shader),
+                        Err(e) => {
+                            for shader in shaders {
+                                unsafe {
+                                    gl.delete_shader(shader);
+                                }
+                            }
+                            return Err(e);
+                        }
+                    }
+                }
+                let retrievable = cache.is_some() || (self.debug && Self::binary_supported(gl));
+                let program =
+                    Shader::link(gl, &name, &shaders, retrievable, self.feedback.as_ref())?;
+                if let Some(path) = &cache {
+                    Self::cache_store(gl, path, program);
+                }
+                program
+            }
+        };
+
+        if self.debug && !Self::binary_supported(gl) {
+            debug!("shader '{}' program binaries are not supported", &name);
+        } else if self.debug {
+            match unsafe { gl.get_program_binary(program) } {
+                Some(binary) => {
+                    debug!(
+                        "shader '{}' program binary: format {}, {} bytes",
+                        &name,
+                        format!("0x{:x}", binary.format),
+                        binary.buffer.len()
+                    );
+                    if let Some(path) = &self.dump {
+                        Self::dump_file(&format!("{path}.bin"), &binary.buffer);
+                    }
+                }
+                None => {
+                    debug!("shader '{}' program binary not available", &name);
+                }
+            }
+        }
 
         unsafe {
             gl.use_program(Some(program));
-            for (samplername, idx) in self.samplers {
-                match gl.get_uniform_location(program, &samplername) {
+            for (samplername, idx) in &self.samplers {
+                match gl.get_uniform_location(program, samplername) {
                     Some(uniformid) => {
-                        gl.uniform_1_i32(Some(&uniformid), idx);
+                        gl.uniform_1_i32(Some(&uniformid), *idx);
                     }
                     None => {
                         warn!("shader '{}' does not have sampler '{}'", &name, samplername);
                     }
                 }
             }
-            for (uniformname, idx) in self.uniform_buffers {
-                match gl.get_uniform_block_index(program, &uniformname) {
+            for (uniformname, idx) in &self.uniform_buffers {
+                match gl.get_uniform_block_index(program, uniformname) {
                     Some(uniformid) => {
-                        gl.uniform_block_binding(program, uniformid, idx);
+                        gl.uniform_block_binding(program, uniformid, *idx);
                     }
                     None => {
                         warn!(
@@ -278,13 +987,188 @@ impl ShaderBuilder {
                     }
                 }
             }
+            // Shaders including "time.glsl" don't have to do anything else
+            if let Some(uniformid) = gl.get_uniform_block_index(program, render::TIME_BLOCK) {
+                gl.uniform_block_binding(program, uniformid, render::TIME_BINDING);
+            }
             gl.use_program(None);
         }
+        let blocks = Self::query_blocks(gl, program);
 
-        Ok(Shader {
+        Ok(BuiltProgram {
             name,
             vertname,
             fragname,
+            geomname,
+            program,
+            files,
+            blocks,
+        })
+    }
+
+    fn query_blocks(gl: &glow::Context, program: glow::Program) -> Vec<UniformBlock> {
+        let n = unsafe { gl.get_program_parameter_i32(program, glow::ACTIVE_UNIFORM_BLOCKS) };
+        (0..n.max(0) as u32)
+            .map(|index| unsafe {
+                UniformBlock {
+                    name: gl.get_active_uniform_block_name(program, index),
+                    index,
+                    size: gl.get_active_uniform_block_parameter_i32(
+                        program,
+                        index,
+                        glow::UNIFORM_BLOCK_DATA_SIZE,
+                    ) as usize,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A standalone compute program, e.g., for simulating particles on the GPU. Needs OpenGL 4.3, so
+/// callers have to check [`Context::supports`] with [`Feature::Compute`] and have a fallback.
+///
+/// ```ignore
+/// let sim = ComputeBuilder::new(Some("Particle Simulation"))
+///     .comp_file("particles.comp")
+///     .uniform_buffer("ParticleData", 0)
+///     .build(ctx)?;
+/// sim.dispatch(ctx, n.div_ceil(64), 1, 1);
+/// ComputeShader::barrier(ctx, BarrierFlags::VERTEX_ATTRIB_ARRAY);
+/// ```
+pub struct ComputeShader {
+    pub name: String,
+    pub compname: String,
+    program: glow::Program,
+}
+impl Drop for ComputeShader {
+    fn drop(&mut self) {
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteProgram(self.program));
+    }
+}
+impl ComputeShader {
+    pub fn program(&self) -> glow::Program {
+        self.program
+    }
+
+    pub fn use_program(&self, gl: &glow::Context) {
+        unsafe {
+            gl.use_program(Some(self.program));
+        }
+    }
+
+    /// Runs the shader over `groups_x`×`groups_y`×`groups_z` work groups. The results are only
+    /// visible to other operations after a [`ComputeShader::barrier`].
+    pub fn dispatch(&self, ctx: &Context, groups_x: u32, groups_y: u32, groups_z: u32) {
+        let gl = &ctx.gl;
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.dispatch_compute(groups_x, groups_y, groups_z);
+            gl.use_program(None);
+        }
+    }
+
+    /// Makes the writes of previous dispatches visible to the operations in `flags`.
+    pub fn barrier(ctx: &Context, flags: BarrierFlags) {
+        ctx.memory_barrier(flags);
+    }
+}
+
+#[derive(Clone)]
+pub struct ComputeBuilder {
+    name: Option<String>,
+    comp: ShaderSource,
+    prepend: String,
+    uniform_buffers: Vec<(String, u32)>,
+}
+impl ComputeBuilder {
+    /// Compute shaders need at least GLSL 4.30, regardless of what the rest of the game uses.
+    const MIN_GLSL: i32 = 430;
+
+    pub fn new(name: Option<&str>) -> Self {
+        ComputeBuilder {
+            name: name.map(String::from),
+            comp: ShaderSource::None,
+            prepend: Default::default(),
+            uniform_buffers: Vec::new(),
+        }
+    }
+
+    pub fn comp_file(mut self, path: &str) -> Self {
+        self.comp = ShaderSource::Path(String::from(path));
+        self
+    }
+
+    pub fn comp_data(mut self, data: &str) -> Self {
+        self.comp = ShaderSource::Data(String::from(data));
+        self
+    }
+
+    pub fn prepend(mut self, data: &str) -> Self {
+        self.prepend = String::from(data);
+        self
+    }
+
+    pub fn uniform_buffer(mut self, name: &str, idx: u32) -> Self {
+        self.uniform_buffers.push((name.to_string(), idx));
+        self
+    }
+
+    pub fn build(self, ctx: &Context) -> Result<ComputeShader> {
+        if !ctx.supports(Feature::Compute) {
+            anyhow::bail!(
+                "{} need {:?}, but the OpenGL context is only {:?}",
+                Feature::Compute.name(),
+                Feature::Compute.min_level(),
+                ctx.feature_level()
+            );
+        }
+        let gl = &ctx.gl;
+        let glsl = unsafe { naevc::gl_screen.glsl }.max(Self::MIN_GLSL);
+        let prepend = format!("#version {glsl}\n\n#define GLSL_VERSION {glsl}\n");
+
+        let mut state = Preprocessor::default();
+        let mut data = self.comp.to_string(&mut state)?;
+        let mut map = std::mem::take(&mut state.map);
+        if !self.prepend.is_empty() {
+            data.insert_str(0, &self.prepend);
+            map.prepend("PREPEND", &self.prepend);
+        }
+        data.insert_str(0, &prepend);
+        map.prepend("HEADER", &prepend);
+
+        let compname = self.comp.name();
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => compname.clone(),
+        };
+        let shader = Shader::compile(gl, ShaderType::Compute, &compname, &data, &map)?;
+        let program = Shader::link(gl, &name, &[shader], false, None)?;
+
+        unsafe {
+            for (uniformname, idx) in &self.uniform_buffers {
+                match gl.get_uniform_block_index(program, uniformname) {
+                    Some(uniformid) => {
+                        gl.uniform_block_binding(program, uniformid, *idx);
+                    }
+                    None => {
+                        warn!(
+                            "shader '{}' does not have uniform block '{}'",
+                            &name, uniformname
+                        );
+                    }
+                }
+            }
+            if let Some(uniformid) = gl.get_uniform_block_index(program, render::TIME_BLOCK) {
+                gl.uniform_block_binding(program, uniformid, render::TIME_BINDING);
+            }
+        }
+
+        Ok(ComputeShader {
+            name,
+            compname,
             program,
         })
     }
@@ -298,21 +1182,33 @@ pub extern "C" fn gl_program_backend(
     cfrag: *const c_char,
     cprepend: *const c_char,
 ) -> u32 {
-    let ctx = Context::get().unwrap(); /* Lock early. */
-    let vert = unsafe { CStr::from_ptr(cvert) };
-    let frag = unsafe { CStr::from_ptr(cfrag) };
-    let mut sb = ShaderBuilder::new(None)
-        .vert_file(vert.to_str().unwrap())
-        .frag_file(frag.to_str().unwrap());
-
-    if !cprepend.is_null() {
-        let prepend = unsafe { CStr::from_ptr(cprepend) };
-        sb = sb.prepend(prepend.to_str().unwrap());
-    }
+    ffi_guard(0, || {
+        let build = || -> Result<u32> {
+            let ctx = Context::get()?; /* Lock early. */
+            let vert = unsafe { CStr::from_ptr(cvert) };
+            let frag = unsafe { CStr::from_ptr(cfrag) };
+            // The program id is kept by C, so it can't be swapped out by reloading
+            let mut sb = ShaderBuilder::new(None)
+                .vert_file(vert.to_str()?)
+                .frag_file(frag.to_str()?)
+                .reloadable(false);
 
-    let shader = ManuallyDrop::new(sb.build(&ctx.gl).unwrap());
+            if !cprepend.is_null() {
+                let prepend = unsafe { CStr::from_ptr(cprepend) };
+                sb = sb.prepend(prepend.to_str()?);
+            }
 
-    shader.program.0.into()
+            let shader = ManuallyDrop::new(sb.build(&ctx.gl)?);
+            Ok(shader.program().0.into())
+        };
+        match build() {
+            Ok(program) => program,
+            Err(e) => {
+                warn_err(e.context("unable to build shader program"));
+                0
+            }
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -322,20 +1218,84 @@ pub extern "C" fn gl_program_vert_frag_string(
     cfrag: *const c_char,
     frag_size: usize,
 ) -> u32 {
-    let ctx = Context::get().unwrap(); /* Lock early. */
-    let vertdata =
-        std::str::from_utf8(unsafe { std::slice::from_raw_parts(cvert as *const u8, vert_size) })
-            .unwrap();
-    let fragdata =
-        std::str::from_utf8(unsafe { std::slice::from_raw_parts(cfrag as *const u8, frag_size) })
-            .unwrap();
-    let shader = ManuallyDrop::new(
-        ShaderBuilder::new(None)
-            .vert_data(vertdata)
-            .frag_data(fragdata)
-            .build(&ctx.gl)
-            .unwrap(),
-    );
-
-    shader.program.0.into()
+    ffi_guard(0, || {
+        let build = || -> Result<u32> {
+            let ctx = Context::get()?; /* Lock early. */
+            let vertdata = std::str::from_utf8(unsafe {
+                std::slice::from_raw_parts(cvert as *const u8, vert_size)
+            })?;
+            let fragdata = std::str::from_utf8(unsafe {
+                std::slice::from_raw_parts(cfrag as *const u8, frag_size)
+            })?;
+            let shader = ManuallyDrop::new(
+                ShaderBuilder::new(None)
+                    .vert_data(vertdata)
+                    .frag_data(fragdata)
+                    .reloadable(false)
+                    .build(&ctx.gl)?,
+            );
+            Ok(shader.program().0.into())
+        };
+        match build() {
+            Ok(program) => program,
+            Err(e) => {
+                warn_err(e.context("unable to build shader program"));
+                0
+            }
+        }
+    })
+}
+
+/// Reloads all the Rust shaders from disk, except for the ones whose program was handed out to C
+/// or Lua. Returns the number of shaders reloaded.
+#[unsafe(no_mangle)]
+pub extern "C" fn gl_reloadShaders() -> c_int {
+    ffi_guard(0, || match Context::get() {
+        Ok(ctx) => reload_all(ctx) as c_int,
+        Err(_) => 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(data: &str) -> Result<String> {
+        crate::testing::init_data();
+        ShaderSource::Data(String::from(data)).to_string(&mut Preprocessor::default())
+    }
+
+    #[test]
+    fn include_cycle() {
+        let err = preprocess("#include \"test/cycle_a.glsl\"\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "include cycle detected: glsl/test/cycle_a.glsl -> glsl/test/cycle_b.glsl -> glsl/test/cycle_a.glsl"
+        );
+    }
+
+    #[test]
+    fn pragma_once() {
+        // a.glsl includes common.glsl relative to itself, while b.glsl uses the absolute path
+        let src = preprocess("#include \"test/a.glsl\"\n#include \"test/b.glsl\"\n").unwrap();
+        assert_eq!(src.matches("const float COMMON").count(), 1);
+        assert!(src.contains("const float A"));
+        assert!(src.contains("const float B"));
+        assert!(!src.contains(ShaderSource::PRAGMA_ONCE));
+    }
+
+    #[test]
+    fn cache_hash_stable() {
+        // Reference FNV-1a values, with the separator byte hashed after each string
+        let hash = |strs: &[&str]| {
+            let mut hasher = CacheHasher::new();
+            for s in strs {
+                hasher.write_str(s);
+            }
+            hasher.finish()
+        };
+        assert_eq!(hash(&[]), 0xcbf29ce484222325);
+        assert_eq!(hash(&["a"]), 0x089bc907b544c769);
+        assert_ne!(hash(&["ab", "c"]), hash(&["a", "bc"]));
+    }
 }
@@ -236,7 +236,7 @@ SHADERS = [
       vs_path = "postprocess.vert",
       fs_path = "colourblind.frag",
       attributes = ["VertexPosition"],
-      uniforms = ["ClipSpaceFromLocal", "MainTex", "type", "intensity"],
+      uniforms = ["ClipSpaceFromLocal", "MainTex", "daltonize"],
    ),
    Shader(
       name = "shake",
@@ -1,10 +1,13 @@
 use anyhow::Result;
 use rayon::prelude::*;
+use std::collections::BTreeMap;
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_int};
 
 use crate::array::ArrayCString;
 use crate::context::{Context, ContextWrapper};
+use crate::damagetype::{self, DamageType};
+use crate::ffi::ffi_guard;
 use crate::gettext::gettext;
 use crate::log::warn_err;
 use crate::utils::{binary_search_by_key_ref, sort_by_key_ref};
@@ -138,7 +141,7 @@ pub fn load() -> Result<Vec<SlotProperty>> {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn sp_get(name: *const c_char) -> c_int {
-    unsafe {
+    ffi_guard(0, || unsafe {
         let ptr = CStr::from_ptr(name);
         let name = ptr.to_str().unwrap();
         match binary_search_by_key_ref(&SLOT_PROPERTIES, name, |sp: &SlotProperty| &sp.name) {
@@ -148,77 +151,239 @@ pub extern "C" fn sp_get(name: *const c_char) -> c_int {
                 0
             }
         }
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn sp_display(sp: c_int) -> *const c_char {
-    match get_c(sp) {
+    ffi_guard(std::ptr::null(), || match get_c(sp) {
         Some(prop) => prop.cdisplay.as_ptr(),
         None => std::ptr::null(),
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn sp_description(sp: c_int) -> *const c_char {
-    match get_c(sp) {
+    ffi_guard(std::ptr::null(), || match get_c(sp) {
         Some(prop) => prop.cdescription.as_ptr(),
         None => std::ptr::null(),
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn sp_visible(sp: c_int) -> c_int {
-    match get_c(sp) {
+    ffi_guard(0, || match get_c(sp) {
         Some(prop) => prop.visible as c_int,
         None => 0,
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn sp_required(sp: c_int) -> c_int {
-    match get_c(sp) {
+    ffi_guard(0, || match get_c(sp) {
         Some(prop) => prop.required as c_int,
         None => 0,
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn sp_exclusive(sp: c_int) -> c_int {
-    match get_c(sp) {
+    ffi_guard(0, || match get_c(sp) {
         Some(prop) => prop.exclusive as c_int,
         None => 0,
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn sp_icon(sp: c_int) -> *const naevc::glTexture {
-    match get_c(sp) {
+    ffi_guard(std::ptr::null(), || match get_c(sp) {
         Some(prop) => match &prop.icon {
             Some(icon) => icon as *const texture::Texture as *const naevc::glTexture,
             None => std::ptr::null(),
         },
         None => std::ptr::null(),
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn sp_locked(sp: c_int) -> c_int {
-    match get_c(sp) {
+    ffi_guard(0, || match get_c(sp) {
         Some(prop) => prop.locked as c_int,
         None => 0,
-    }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn sp_tags(sp: c_int) -> *mut *const c_char {
-    match get_c(sp) {
+    ffi_guard(std::ptr::null_mut(), || match get_c(sp) {
         Some(prop) => prop.ctags.as_ptr(),
         None => std::ptr::null_mut(),
-    }
+    })
 }
 
 // Assume static here, because it doesn't really change after loading
 fn get_c(sp: c_int) -> Option<&'static SlotProperty> {
     SLOT_PROPERTIES.get((sp - 1) as usize)
 }
+
+/// Defensive and energy properties of a ship, as used by [`combat_summary`].
+#[derive(Debug, Clone, Default)]
+pub struct CombatShip {
+    pub shield: f64,
+    pub armour: f64,
+    /// Fraction of incoming damage absorbed, in [0, 1].
+    pub absorb: f64,
+    pub energy: f64,
+    /// Energy regenerated per second.
+    pub energy_regen: f64,
+}
+
+/// A weapon outfit equipped in a slot.
+#[derive(Debug, Clone)]
+pub struct EquippedWeapon {
+    pub damage_type: String,
+    /// Damage per shot.
+    pub damage: f64,
+    /// Seconds between shots.
+    pub delay: f64,
+    /// Energy used per shot.
+    pub energy: f64,
+}
+
+/// Summary of the combat capabilities of a fitting.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CombatSummary {
+    /// Damage per second grouped by damage type.
+    pub dps: BTreeMap<String, f64>,
+    /// Effective hit points against each damage type.
+    pub ehp: BTreeMap<String, f64>,
+    /// Energy used per second when firing all weapons.
+    pub energy_use: f64,
+    /// Seconds of continuous fire until the capacitor is empty, or `None` if the energy
+    /// regeneration can sustain it indefinitely.
+    pub sustain: Option<f64>,
+}
+
+/// Computes DPS, EHP and capacitor sustainability of a ship with the given weapons equipped,
+/// using the loaded damage types.
+#[allow(dead_code)]
+pub fn combat_summary(ship: &CombatShip, equipped: &[EquippedWeapon]) -> CombatSummary {
+    combat_summary_with(ship, equipped, damagetype::get_all())
+}
+
+/// Same as [`combat_summary`], but with an explicit set of damage types. Does not touch any
+/// global state.
+pub fn combat_summary_with(
+    ship: &CombatShip,
+    equipped: &[EquippedWeapon],
+    damage_types: &[DamageType],
+) -> CombatSummary {
+    let mut summary = CombatSummary::default();
+    for w in equipped {
+        if w.delay <= 0.0 {
+            continue;
+        }
+        *summary.dps.entry(w.damage_type.clone()).or_insert(0.0) += w.damage / w.delay;
+        summary.energy_use += w.energy / w.delay;
+    }
+
+    let absorb = 1.0 - ship.absorb.clamp(0.0, 1.0);
+    for dt in damage_types {
+        let part = |hp: f64, modifier: f64| match modifier * absorb {
+            _ if hp <= 0.0 => 0.0,
+            m if m > 0.0 => hp / m,
+            _ => f64::INFINITY,
+        };
+        let ehp = part(ship.shield, dt.shield_mod()) + part(ship.armour, dt.armour_mod());
+        summary.ehp.insert(String::from(dt.name()), ehp);
+    }
+
+    let drain = summary.energy_use - ship.energy_regen;
+    summary.sustain = match drain > 0.0 {
+        true => Some(ship.energy / drain),
+        false => None,
+    };
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weapon(damage_type: &str, damage: f64, delay: f64, energy: f64) -> EquippedWeapon {
+        EquippedWeapon {
+            damage_type: String::from(damage_type),
+            damage,
+            delay,
+            energy,
+        }
+    }
+
+    fn ship(energy_regen: f64) -> CombatShip {
+        CombatShip {
+            shield: 100.0,
+            armour: 200.0,
+            absorb: 0.5,
+            energy: 50.0,
+            energy_regen,
+        }
+    }
+
+    fn equipped() -> Vec<EquippedWeapon> {
+        vec![
+            weapon("kinetic", 10.0, 0.5, 2.0),
+            weapon("kinetic", 5.0, 1.0, 1.0),
+            weapon("energy", 30.0, 2.0, 10.0),
+            // Never fires, so it's ignored
+            weapon("ion", 100.0, 0.0, 100.0),
+        ]
+    }
+
+    #[test]
+    fn dps_per_type() {
+        let summary = combat_summary_with(&ship(0.0), &equipped(), &[]);
+        assert_eq!(summary.dps.len(), 2);
+        assert_eq!(summary.dps["kinetic"], 25.0);
+        assert_eq!(summary.dps["energy"], 15.0);
+        assert_eq!(summary.energy_use, 10.0);
+        assert!(summary.ehp.is_empty());
+    }
+
+    #[test]
+    fn ehp_absorb_and_modifiers() {
+        let types = [
+            DamageType::new("kinetic", 1.0, 0.5),
+            DamageType::new("energy", 2.0, 1.0),
+        ];
+        let summary = combat_summary_with(&ship(0.0), &[], &types);
+        // Half the damage is absorbed, then the modifiers of the type apply
+        assert_eq!(summary.ehp["kinetic"], 100.0 / 0.5 + 200.0 / 0.25);
+        assert_eq!(summary.ehp["energy"], 100.0 / 1.0 + 200.0 / 0.5);
+    }
+
+    #[test]
+    fn ehp_zero_modifier() {
+        let types = [DamageType::new("ion", 0.0, 2.0)];
+        let summary = combat_summary_with(&ship(0.0), &[], &types);
+        assert_eq!(summary.ehp["ion"], f64::INFINITY);
+
+        // Absorbing all the damage makes every type harmless
+        let full_absorb = CombatShip {
+            absorb: 1.0,
+            ..ship(0.0)
+        };
+        let types = [DamageType::new("kinetic", 1.0, 1.0)];
+        let summary = combat_summary_with(&full_absorb, &[], &types);
+        assert_eq!(summary.ehp["kinetic"], f64::INFINITY);
+    }
+
+    #[test]
+    fn sustain() {
+        // Draining 10 - 4 energy per second from a capacitor of 50
+        let summary = combat_summary_with(&ship(4.0), &equipped(), &[]);
+        assert_eq!(summary.sustain, Some(50.0 / 6.0));
+
+        let summary = combat_summary_with(&ship(10.0), &equipped(), &[]);
+        assert_eq!(summary.sustain, None);
+    }
+}
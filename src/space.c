@@ -1714,6 +1714,7 @@ void space_init( const char *sysname, int do_simulate )
 
    /* Load graphics. */
    space_gfxLoad( cur_system );
+   tex_preloadSystem( cur_system->name, cur_system->nebu_density > 0. );
 
    /* Call the scheduler. */
    system_scheduler( 0., 1 );
@@ -3864,6 +3865,9 @@ static void space_updateSpob( const Spob *p, double dt, double real_dt )
  */
 void space_exit( void )
 {
+   /* Release the textures preloaded for the system. */
+   tex_preloadSystemClear();
+
    /* Free standalone graphic textures */
    gl_freeTexture( jumppoint_gfx );
    jumppoint_gfx = NULL;
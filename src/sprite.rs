@@ -0,0 +1,177 @@
+#![allow(dead_code)]
+use anyhow::Result;
+use nalgebra::Matrix3;
+
+use crate::context::Context;
+use crate::render;
+use crate::texture::Texture;
+
+/// How an animation behaves when it reaches the last frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AnimationMode {
+    /// Starts over from the first frame.
+    #[default]
+    Loop,
+    /// Plays backwards to the first frame, then forwards again.
+    PingPong,
+    /// Stops at the last frame.
+    Once,
+}
+
+/// Frame timing for sprite sheet animations. Frames are laid out left to right, top to bottom
+/// on a grid of `columns` × `rows` frames, like the sprite sheets used for ships.
+#[derive(Clone, Debug)]
+pub struct SpriteAnimator {
+    columns: usize,
+    rows: usize,
+    frames: usize,
+    fps: f64,
+    mode: AnimationMode,
+    elapsed: f64,
+}
+
+impl SpriteAnimator {
+    /// Creates an animator for a sheet of `sheet_w` × `sheet_h` pixels, with frames of
+    /// `frame_w` × `frame_h` pixels. Only the first `frames` frames are played.
+    pub fn new(
+        sheet_w: usize,
+        sheet_h: usize,
+        frame_w: usize,
+        frame_h: usize,
+        frames: usize,
+        fps: f64,
+    ) -> Result<Self> {
+        if frame_w == 0 || frame_h == 0 || frame_w > sheet_w || frame_h > sheet_h {
+            anyhow::bail!(
+                "invalid frame size {frame_w}x{frame_h} for sprite sheet of {sheet_w}x{sheet_h}"
+            );
+        }
+        Self::from_grid(sheet_w / frame_w, sheet_h / frame_h, frames, fps)
+    }
+
+    /// Creates an animator using the sprite grid of a texture.
+    pub fn from_texture(tex: &Texture, frames: usize, fps: f64) -> Result<Self> {
+        Self::from_grid(tex.sx, tex.sy, frames, fps)
+    }
+
+    fn from_grid(columns: usize, rows: usize, frames: usize, fps: f64) -> Result<Self> {
+        if frames == 0 || frames > columns * rows {
+            anyhow::bail!(
+                "invalid frame count {frames} for sprite sheet of {columns}x{rows} frames"
+            );
+        }
+        if !(fps > 0.0 && fps.is_finite()) {
+            anyhow::bail!("invalid animation framerate '{fps}'");
+        }
+        Ok(SpriteAnimator {
+            columns,
+            rows,
+            frames,
+            fps,
+            mode: AnimationMode::default(),
+            elapsed: 0.0,
+        })
+    }
+
+    /// Sets how the animation behaves when reaching the last frame.
+    pub fn mode(mut self, mode: AnimationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Advances the animation by `dt` seconds.
+    pub fn update(&mut self, dt: f64) {
+        self.elapsed += dt;
+    }
+
+    /// Restarts the animation from the first frame.
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+
+    /// Whether a [`AnimationMode::Once`] animation has reached the last frame. Other modes never
+    /// finish.
+    pub fn finished(&self) -> bool {
+        self.mode == AnimationMode::Once && self.step() >= self.frames - 1
+    }
+
+    /// Number of frames shown since the start, ignoring the mode.
+    fn step(&self) -> usize {
+        (self.elapsed.max(0.0) * self.fps).floor() as usize
+    }
+
+    /// Index of the frame to show.
+    pub fn current_frame(&self) -> usize {
+        let step = self.step();
+        match self.mode {
+            AnimationMode::Loop => step % self.frames,
+            AnimationMode::Once => step.min(self.frames - 1),
+            AnimationMode::PingPong => {
+                // The end frames are only shown once per cycle
+                let period = 2 * (self.frames - 1);
+                match period {
+                    0 => 0,
+                    _ => {
+                        let i = step % period;
+                        match i < self.frames {
+                            true => i,
+                            false => period - i,
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Source rectangle of the current frame as `(x, y, w, h)` in normalized texture
+    /// coordinates, following the same convention as `gl_renderSprite`.
+    pub fn source_rect(&self) -> (f32, f32, f32, f32) {
+        let frame = self.current_frame();
+        let (col, row) = (frame % self.columns, frame / self.columns);
+        let w = 1.0 / self.columns as f32;
+        let h = 1.0 / self.rows as f32;
+        (col as f32 * w, (self.rows - row - 1) as f32 * h, w, h)
+    }
+
+    /// Texture matrix selecting the current frame, for [`render::TextureUniform`].
+    #[rustfmt::skip]
+    pub fn texture_transform(&self) -> Matrix3<f32> {
+        let (x, y, w, h) = self.source_rect();
+        Matrix3::new(
+             w,  0.0,  x,
+            0.0,  h,   y,
+            0.0, 0.0, 1.0,
+        )
+    }
+
+    /// Draws the current frame of `tex` at the given screen position and size.
+    pub fn draw(&self, ctx: &Context, tex: &Texture, x: f32, y: f32, w: f32, h: f32) -> Result<()> {
+        let dims = ctx.dimensions.read().unwrap();
+        #[rustfmt::skip]
+        let transform: Matrix3<f32> = dims.projection * Matrix3::new(
+             w,  0.0,  x,
+            0.0,  h,   y,
+            0.0, 0.0, 1.0,
+        );
+        drop(dims);
+        let uniform = render::TextureUniform {
+            transform,
+            ..Default::default()
+        };
+        self.draw_ex(ctx, tex, &uniform)
+    }
+
+    /// Draws the current frame of `tex` with a custom uniform, replacing its texture matrix.
+    pub fn draw_ex(
+        &self,
+        ctx: &Context,
+        tex: &Texture,
+        uniform: &render::TextureUniform,
+    ) -> Result<()> {
+        let uniform = render::TextureUniform {
+            texture: self.texture_transform(),
+            ..*uniform
+        };
+        tex.draw_ex(ctx, &uniform)
+    }
+}
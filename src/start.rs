@@ -1,3 +1,4 @@
+use crate::ffi::ffi_guard;
 use crate::ndata;
 use crate::ntime::{NTime, NTimeC};
 use crate::nxml_warn_node_unknown;
@@ -140,10 +141,10 @@ macro_rules! start_c_func_str {
     ($funcname: ident, $field: ident) => {
         #[unsafe(no_mangle)]
         pub extern "C" fn $funcname() -> *const c_char {
-            match START.$field.is_empty() {
-                true => std::ptr::null_mut(),
+            ffi_guard(std::ptr::null(), || match START.$field.is_empty() {
+                true => std::ptr::null(),
                 false => START.$field.as_ptr().into(),
-            }
+            })
         }
     };
 }
@@ -161,21 +162,23 @@ start_c_func_str!(start_dtype_default, dtype_default);
 start_c_func_str!(start_local_map_default, local_map_default);
 #[unsafe(no_mangle)]
 pub extern "C" fn start_credits() -> i64 {
-    START.credits
+    ffi_guard(0, || START.credits)
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn start_date() -> NTimeC {
-    START.date.into()
+    ffi_guard(0, || START.date.into())
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn start_position(x: *mut f64, y: *mut f64) {
-    unsafe {
+    ffi_guard((), || unsafe {
         *x = START.pos_x;
         *y = START.pos_y;
-    }
+    })
 }
 #[unsafe(no_mangle)]
 pub extern "C" fn start_load() -> c_int {
-    let _ = START;
-    0
+    ffi_guard(-1, || {
+        let _ = START;
+        0
+    })
 }
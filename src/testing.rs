@@ -0,0 +1,71 @@
+//! Helpers for tests that need game data or an OpenGL context. The context needs a display and a
+//! driver, so the tests using it are marked `#[ignore]` and have to be run explicitly with
+//! `cargo test -- --ignored`.
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::sync::mpsc;
+use std::sync::{Mutex, Once, OnceLock};
+
+use crate::context::Context;
+
+type Job = Box<dyn FnOnce(&'static Context) + Send>;
+
+static JOBS: OnceLock<Mutex<mpsc::Sender<Job>>> = OnceLock::new();
+
+/// Mounts the `dat` directory of the source tree, followed by the test fixtures in `test/data`,
+/// as the data directories. Safe to call from any number of tests.
+pub fn init_data() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let root = std::path::Path::new(file!()).parent().unwrap().join("..");
+        let mount = |dir: &str| {
+            let path = root
+                .join(dir)
+                .canonicalize()
+                .unwrap_or_else(|_| panic!("unable to find the {dir} directory"));
+            let path = CString::new(path.to_string_lossy().into_owned()).unwrap();
+            assert!(unsafe { naevc::PHYSFS_mount(path.as_ptr(), std::ptr::null(), 1) } != 0);
+        };
+        assert!(unsafe { naevc::PHYSFS_init(std::ptr::null::<c_char>()) } != 0);
+        mount("dat");
+        mount("test/data");
+    });
+}
+
+/// Sets up the data and the context the same way the game does.
+fn init() -> &'static Context {
+    init_data();
+    unsafe {
+        naevc::nxml_init();
+        naevc::input_init();
+        naevc::conf_setDefaults();
+    }
+    let sdlctx = sdl2::init().expect("unable to initialize SDL");
+    let sdlvid = sdlctx.video().expect("unable to initialize SDL video");
+    Context::new(sdlvid).expect("unable to create the OpenGL context")
+}
+
+/// Runs `f` with the context. Contexts are bound to the thread that created them, so all the
+/// tests share a single context living on its own thread.
+pub fn with_context<R: Send + 'static>(
+    f: impl FnOnce(&'static Context) -> R + Send + 'static,
+) -> R {
+    let jobs = JOBS.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<Job>();
+        std::thread::spawn(move || {
+            let ctx = init();
+            for job in rx {
+                job(ctx);
+            }
+        });
+        Mutex::new(tx)
+    });
+    let (tx, rx) = mpsc::channel();
+    jobs.lock()
+        .unwrap()
+        .send(Box::new(move |ctx| {
+            let _ = tx.send(f(ctx));
+        }))
+        .unwrap();
+    rx.recv().expect("test panicked on the context thread")
+}
@@ -9,16 +9,81 @@ use std::boxed::Box;
 use std::ffi::{CStr, CString};
 use std::num::NonZero;
 use std::os::raw::{c_char, c_double, c_float, c_int, c_uint};
-use std::sync::{Arc, LazyLock, Mutex, MutexGuard, Weak};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, OnceLock, Weak};
 
 use crate::context::{Context, ContextWrapper};
+use crate::ffi::ffi_guard;
 use crate::log::warn_err;
-use crate::{buffer, context, gettext, ndata, render};
-use crate::{warn, warn_err};
+use crate::{assets, buffer, context, gettext, ndata, render};
+use crate::{debug, warn, warn_err};
 
 static TEXTURE_DATA: LazyLock<Mutex<Vec<Weak<TextureData>>>> =
     LazyLock::new(|| Mutex::new(Default::default()));
 
+/// Weak handles to the samplers of the live mipmapped textures, so the sampling settings can be
+/// updated on the fly.
+static SAMPLERS: LazyLock<Mutex<Vec<Weak<Sampler>>>> =
+    LazyLock::new(|| Mutex::new(Default::default()));
+
+/// Maximum anisotropy supported, queried along with the first texture.
+static MAX_ANISOTROPY: OnceLock<f32> = OnceLock::new();
+
+/// Gets the maximum anisotropy supported, or 1 if anisotropic filtering is not available.
+pub(crate) fn max_anisotropy(gl: &glow::Context) -> f32 {
+    *MAX_ANISOTROPY.get_or_init(|| {
+        let version = gl.version();
+        let exts = gl.supported_extensions();
+        if (version.major, version.minor) >= (4, 6)
+            || exts.contains("GL_ARB_texture_filter_anisotropic")
+            || exts.contains("GL_EXT_texture_filter_anisotropic")
+        {
+            unsafe { gl.get_parameter_f32(glow::MAX_TEXTURE_MAX_ANISOTROPY) }
+        } else {
+            1.0
+        }
+    })
+}
+
+/// Applies the filtering settings from the configuration to a sampler used with mipmaps.
+fn apply_sampler_defaults(gl: &glow::Context, sampler: glow::Sampler) {
+    let (anisotropy, trilinear) =
+        unsafe { (naevc::conf.anisotropy as f32, naevc::conf.trilinear != 0) };
+    unsafe {
+        let min_filter = gl.get_sampler_parameter_i32(sampler, glow::TEXTURE_MIN_FILTER) as u32;
+        if min_filter == glow::LINEAR_MIPMAP_LINEAR || min_filter == glow::LINEAR_MIPMAP_NEAREST {
+            let filter = match trilinear {
+                true => glow::LINEAR_MIPMAP_LINEAR,
+                false => glow::LINEAR_MIPMAP_NEAREST,
+            };
+            gl.sampler_parameter_i32(sampler, glow::TEXTURE_MIN_FILTER, filter as i32);
+        }
+        let max = max_anisotropy(gl);
+        if max > 1.0 {
+            gl.sampler_parameter_f32(
+                sampler,
+                glow::TEXTURE_MAX_ANISOTROPY,
+                anisotropy.clamp(1.0, max),
+            );
+        }
+    }
+}
+
+/// Reapplies the filtering settings from the configuration to all the live mipmapped textures.
+pub fn reapply_sampler_defaults(ctx: &context::Context) {
+    let gl = &ctx.gl;
+    SAMPLERS
+        .lock()
+        .unwrap()
+        .retain(|sampler| match sampler.upgrade() {
+            Some(sampler) => {
+                apply_sampler_defaults(gl, sampler.sampler);
+                true
+            }
+            None => false,
+        });
+}
+
 // Temporary hack until image-rs significantly increases performance...
 pub fn surface_to_image(sur: sdl::surface::Surface) -> Result<image::DynamicImage> {
     //let has_alpha = sur.pixel_format_enum().supports_alpha();
@@ -82,6 +147,308 @@ impl TextureFormat {
             Self::SRGB | Self::SRGBA => true,
         }
     }
+
+    /// Tries to find a compressed internal format the driver supports, along with a name for
+    /// logging purposes.
+    pub fn compressed(gl: &glow::Context, has_alpha: bool, is_srgb: bool) -> Option<(u32, &str)> {
+        let exts = gl.supported_extensions();
+        if exts.contains("GL_ARB_texture_compression_bptc") {
+            Some(match is_srgb {
+                true => (
+                    glow::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+                    "SRGB_ALPHA_BPTC_UNORM",
+                ),
+                false => (glow::COMPRESSED_RGBA_BPTC_UNORM, "RGBA_BPTC_UNORM"),
+            })
+        } else if exts.contains("GL_EXT_texture_compression_s3tc")
+            && (!is_srgb || exts.contains("GL_EXT_texture_sRGB"))
+        {
+            Some(match (is_srgb, has_alpha) {
+                (true, true) => (glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT, "SRGB_ALPHA_DXT5"),
+                (true, false) => (glow::COMPRESSED_SRGB_S3TC_DXT1_EXT, "SRGB_DXT1"),
+                (false, true) => (glow::COMPRESSED_RGBA_S3TC_DXT5_EXT, "RGBA_DXT5"),
+                (false, false) => (glow::COMPRESSED_RGB_S3TC_DXT1_EXT, "RGB_DXT1"),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Gets the block width, block height and bytes per block of a block-compressed internal format,
+/// or `None` if the format is not a known compressed format.
+pub fn compressed_block_info(format: u32) -> Option<(usize, usize, usize)> {
+    match format {
+        glow::COMPRESSED_RGB_S3TC_DXT1_EXT
+        | glow::COMPRESSED_RGBA_S3TC_DXT1_EXT
+        | glow::COMPRESSED_SRGB_S3TC_DXT1_EXT
+        | glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT
+        | glow::COMPRESSED_RED_RGTC1
+        | glow::COMPRESSED_SIGNED_RED_RGTC1
+        | glow::COMPRESSED_RGB8_ETC2
+        | glow::COMPRESSED_SRGB8_ETC2 => Some((4, 4, 8)),
+        glow::COMPRESSED_RGBA_S3TC_DXT3_EXT
+        | glow::COMPRESSED_RGBA_S3TC_DXT5_EXT
+        | glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT
+        | glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT
+        | glow::COMPRESSED_RG_RGTC2
+        | glow::COMPRESSED_SIGNED_RG_RGTC2
+        | glow::COMPRESSED_RGBA_BPTC_UNORM
+        | glow::COMPRESSED_SRGB_ALPHA_BPTC_UNORM
+        | glow::COMPRESSED_RGB_BPTC_SIGNED_FLOAT
+        | glow::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT
+        | glow::COMPRESSED_RGBA8_ETC2_EAC
+        | glow::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC => Some((4, 4, 16)),
+        _ => None,
+    }
+}
+
+/// Expected size in bytes of an image of `w`×`h` pixels in a block-compressed format. Partial
+/// blocks at the edges are stored as full blocks.
+pub fn compressed_image_size(format: u32, w: usize, h: usize) -> Option<usize> {
+    let (bw, bh, bytes) = compressed_block_info(format)?;
+    Some(w.div_ceil(bw) * h.div_ceil(bh) * bytes)
+}
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const DDS_HEADER_SIZE: usize = 128;
+const DDS_DX10_HEADER_SIZE: usize = 20;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const KTX2_HEADER_SIZE: usize = 80;
+const KTX2_LEVEL_SIZE: usize = 24;
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    match data.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_le_bytes(b.try_into()?)),
+        None => anyhow::bail!("compressed texture is truncated"),
+    }
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    match data.get(offset..offset + 8) {
+        Some(b) => Ok(u64::from_le_bytes(b.try_into()?)),
+        None => anyhow::bail!("compressed texture is truncated"),
+    }
+}
+
+/// Block-compressed image with its mipmap levels, as stored in DDS or KTX2 files. Only the BC1
+/// (DXT1), BC3 (DXT5) and BC7 (BPTC) formats are handled.
+#[derive(Debug, Clone)]
+pub struct CompressedImage {
+    /// OpenGL internal format of the data.
+    pub format: u32,
+    pub w: usize,
+    pub h: usize,
+    /// Data of each mipmap level, starting at the base level.
+    pub levels: Vec<Vec<u8>>,
+}
+impl CompressedImage {
+    /// Parses a DDS or KTX2 file. `srgb` is only used when the file does not say whether the data
+    /// is sRGB, which is the case for DDS files without the DX10 header.
+    pub fn from_memory(data: &[u8], srgb: bool) -> Result<Self> {
+        if data.starts_with(DDS_MAGIC) {
+            Self::from_dds(data, srgb)
+        } else if data.starts_with(&KTX2_IDENTIFIER) {
+            Self::from_ktx2(data)
+        } else {
+            anyhow::bail!("not a DDS or KTX2 file")
+        }
+    }
+
+    /// Loads the compressed image at `path` if it is a DDS or KTX2 file, returning `None` for
+    /// other files or on failure so they go through the image loader instead.
+    fn load(path: &str, srgb: bool) -> Option<Self> {
+        let ext = std::path::Path::new(path)
+            .extension()?
+            .to_string_lossy()
+            .to_lowercase();
+        if ext != "dds" && ext != "ktx2" {
+            return None;
+        }
+        let res = ndata::read(path).and_then(|data| Self::from_memory(&data, srgb));
+        match res {
+            Ok(img) => Some(img),
+            Err(e) => {
+                warn!(
+                    "unable to parse compressed texture '{}', falling back to the image loader: {}",
+                    path, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Checks the mipmap count read from a file header against the size of the image, so that
+    /// corrupt files can't make us allocate or shift out of bounds.
+    fn check_levels(w: usize, h: usize, count: usize) -> Result<usize> {
+        if w == 0 || h == 0 {
+            anyhow::bail!("invalid image size {w}x{h}");
+        }
+        // floor(log2(max(w,h))) + 1
+        let max = (usize::BITS - w.max(h).leading_zeros()) as usize;
+        if count > max {
+            anyhow::bail!("{count} mipmap levels is too many for a {w}x{h} image");
+        }
+        Ok(count)
+    }
+
+    fn from_dds(data: &[u8], srgb: bool) -> Result<Self> {
+        if read_u32(data, 4)? != 124 {
+            anyhow::bail!("invalid DDS header size");
+        }
+        let flags = read_u32(data, 8)?;
+        let h = read_u32(data, 12)? as usize;
+        let w = read_u32(data, 16)? as usize;
+        let count = match flags & DDSD_MIPMAPCOUNT {
+            0 => 1,
+            _ => read_u32(data, 28)?.max(1) as usize,
+        };
+        let count = Self::check_levels(w, h, count)?;
+        let pf_flags = read_u32(data, 80)?;
+        if pf_flags & DDPF_FOURCC == 0 {
+            anyhow::bail!("uncompressed DDS files are not supported");
+        }
+        let fourcc = data.get(84..88).unwrap_or_default();
+        let (format, offset) = match fourcc {
+            b"DXT1" => (
+                match (srgb, pf_flags & DDPF_ALPHAPIXELS != 0) {
+                    (true, true) => glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT,
+                    (true, false) => glow::COMPRESSED_SRGB_S3TC_DXT1_EXT,
+                    (false, true) => glow::COMPRESSED_RGBA_S3TC_DXT1_EXT,
+                    (false, false) => glow::COMPRESSED_RGB_S3TC_DXT1_EXT,
+                },
+                DDS_HEADER_SIZE,
+            ),
+            b"DXT5" => (
+                match srgb {
+                    true => glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT,
+                    false => glow::COMPRESSED_RGBA_S3TC_DXT5_EXT,
+                },
+                DDS_HEADER_SIZE,
+            ),
+            b"DX10" => (
+                // DXGI_FORMAT values
+                match read_u32(data, DDS_HEADER_SIZE)? {
+                    71 => glow::COMPRESSED_RGBA_S3TC_DXT1_EXT,
+                    72 => glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT,
+                    77 => glow::COMPRESSED_RGBA_S3TC_DXT5_EXT,
+                    78 => glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT,
+                    98 => glow::COMPRESSED_RGBA_BPTC_UNORM,
+                    99 => glow::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+                    f => anyhow::bail!("unsupported DXGI format '{f}'"),
+                },
+                DDS_HEADER_SIZE + DDS_DX10_HEADER_SIZE,
+            ),
+            f => anyhow::bail!("unsupported DDS format '{}'", String::from_utf8_lossy(f)),
+        };
+
+        let mut levels = Vec::new();
+        let mut pos = offset;
+        for level in 0..count {
+            let (lw, lh) = ((w >> level).max(1), (h >> level).max(1));
+            let size = compressed_image_size(format, lw, lh).unwrap_or_default();
+            match pos.checked_add(size).and_then(|end| data.get(pos..end)) {
+                Some(l) => levels.push(l.to_vec()),
+                None => anyhow::bail!("DDS file is truncated at mipmap level {level}"),
+            }
+            pos += size;
+        }
+        Ok(CompressedImage {
+            format,
+            w,
+            h,
+            levels,
+        })
+    }
+
+    fn from_ktx2(data: &[u8]) -> Result<Self> {
+        let vkformat = read_u32(data, 12)?;
+        let w = read_u32(data, 20)? as usize;
+        let h = read_u32(data, 24)? as usize;
+        let depth = read_u32(data, 28)?;
+        let layers = read_u32(data, 32)?;
+        let faces = read_u32(data, 36)?;
+        let count = Self::check_levels(w, h, read_u32(data, 40)?.max(1) as usize)?;
+        let supercompression = read_u32(data, 44)?;
+        if depth > 1 || layers > 1 || faces > 1 {
+            anyhow::bail!("only 2D KTX2 textures are supported");
+        }
+        if supercompression != 0 {
+            anyhow::bail!("supercompressed KTX2 textures are not supported");
+        }
+        // VkFormat values
+        let format = match vkformat {
+            131 => glow::COMPRESSED_RGB_S3TC_DXT1_EXT,
+            132 => glow::COMPRESSED_SRGB_S3TC_DXT1_EXT,
+            133 => glow::COMPRESSED_RGBA_S3TC_DXT1_EXT,
+            134 => glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT,
+            137 => glow::COMPRESSED_RGBA_S3TC_DXT5_EXT,
+            138 => glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT,
+            145 => glow::COMPRESSED_RGBA_BPTC_UNORM,
+            146 => glow::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+            f => anyhow::bail!("unsupported VkFormat '{f}'"),
+        };
+
+        let mut levels = Vec::new();
+        for level in 0..count {
+            let entry = KTX2_HEADER_SIZE + level * KTX2_LEVEL_SIZE;
+            let offset = read_u64(data, entry)? as usize;
+            let length = read_u64(data, entry + 8)? as usize;
+            match offset
+                .checked_add(length)
+                .and_then(|end| data.get(offset..end))
+            {
+                Some(l) => levels.push(l.to_vec()),
+                None => anyhow::bail!("KTX2 file is truncated at mipmap level {level}"),
+            }
+        }
+        Ok(CompressedImage {
+            format,
+            w,
+            h,
+            levels,
+        })
+    }
+
+    /// Whether the driver supports a compressed format.
+    pub fn is_supported(gl: &glow::Context, format: u32) -> bool {
+        let exts = gl.supported_extensions();
+        let version = gl.version();
+        match format {
+            glow::COMPRESSED_RGBA_BPTC_UNORM | glow::COMPRESSED_SRGB_ALPHA_BPTC_UNORM => {
+                (version.major, version.minor) >= (4, 2)
+                    || exts.contains("GL_ARB_texture_compression_bptc")
+            }
+            glow::COMPRESSED_RGB_S3TC_DXT1_EXT
+            | glow::COMPRESSED_RGBA_S3TC_DXT1_EXT
+            | glow::COMPRESSED_RGBA_S3TC_DXT5_EXT => {
+                exts.contains("GL_EXT_texture_compression_s3tc")
+            }
+            glow::COMPRESSED_SRGB_S3TC_DXT1_EXT
+            | glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT
+            | glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT => {
+                exts.contains("GL_EXT_texture_compression_s3tc")
+                    && exts.contains("GL_EXT_texture_sRGB")
+            }
+            _ => false,
+        }
+    }
+
+    fn is_srgb(format: u32) -> bool {
+        matches!(
+            format,
+            glow::COMPRESSED_SRGB_S3TC_DXT1_EXT
+                | glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT
+                | glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT
+                | glow::COMPRESSED_SRGB_ALPHA_BPTC_UNORM
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -93,6 +460,7 @@ pub struct TextureData {
     is_srgb: bool,
     is_sdf: bool,
     mipmaps: bool,
+    compressed: bool,
     vmax: f64, // For SDF
 }
 impl Drop for TextureData {
@@ -139,6 +507,7 @@ impl TextureData {
             is_srgb: format.is_srgb(),
             is_sdf: false,
             mipmaps: false,
+            compressed: false,
             vmax: 1.,
         })
     }
@@ -176,6 +545,7 @@ impl TextureData {
             is_srgb: true,
             is_sdf: false,
             mipmaps: false,
+            compressed: false,
             vmax: 1.,
         })
     }
@@ -187,6 +557,7 @@ impl TextureData {
         img: &image::DynamicImage,
         flipv: bool,
         srgb: bool,
+        compress: bool,
     ) -> Result<Self> {
         let gl = &ctx.gl;
         let texture = unsafe { gl.create_texture().map_err(|e| anyhow::anyhow!(e)) }?;
@@ -202,7 +573,26 @@ impl TextureData {
             false => img.to_rgb8().into_raw(),
         };
 
-        let internalformat = TextureFormat::auto(has_alpha, srgb);
+        let internalformat = match compress {
+            true => match TextureFormat::compressed(gl, has_alpha, srgb) {
+                Some((fmt, fmtname)) => {
+                    debug!(
+                        "compressing texture '{}' as {}",
+                        name.unwrap_or("unnamed"),
+                        fmtname
+                    );
+                    fmt as i32
+                }
+                None => {
+                    debug!(
+                        "no compressed texture format available for '{}'",
+                        name.unwrap_or("unnamed")
+                    );
+                    TextureFormat::auto(has_alpha, srgb)
+                }
+            },
+            false => TextureFormat::auto(has_alpha, srgb),
+        };
         unsafe {
             gl.bind_texture(glow::TEXTURE_2D, Some(texture));
             let gldata = glow::PixelUnpackData::Slice(Some(imgdata.as_slice()));
@@ -221,7 +611,7 @@ impl TextureData {
                 glow::UNSIGNED_BYTE,
                 gldata,
             );
-            gl.object_label(glow::TEXTURE, texture.0.into(), name);
+            context::set_label(gl, glow::TEXTURE, texture.0.into(), name);
             gl.bind_texture(glow::TEXTURE_2D, None);
         }
 
@@ -233,10 +623,112 @@ impl TextureData {
             is_srgb: srgb,
             is_sdf: false,
             mipmaps: false,
+            compressed: false,
             vmax: 1.,
         })
     }
 
+    /// Creates a new TextureData from block-compressed data, uploading all of its mipmap levels.
+    fn from_compressed(
+        ctx: &context::Context,
+        name: Option<&str>,
+        img: &CompressedImage,
+    ) -> Result<Self> {
+        let gl = &ctx.gl;
+        if !CompressedImage::is_supported(gl, img.format) {
+            anyhow::bail!(
+                "compressed texture format '0x{:x}' is not supported by the driver",
+                img.format
+            );
+        }
+        check_size(ctx, name, img.w, img.h)?;
+        let texture = unsafe { gl.create_texture().map_err(|e| anyhow::anyhow!(e)) }?;
+        let data = TextureData {
+            name: name.map(String::from),
+            w: img.w,
+            h: img.h,
+            texture,
+            is_srgb: CompressedImage::is_srgb(img.format),
+            is_sdf: false,
+            mipmaps: img.levels.len() > 1,
+            compressed: true,
+            vmax: 1.,
+        };
+        let levels: Vec<&[u8]> = img.levels.iter().map(|l| l.as_slice()).collect();
+        data.upload_compressed(gl, img.format, &levels)?;
+        context::set_label(gl, glow::TEXTURE, texture.0.into(), name);
+        Ok(data)
+    }
+
+    /// Uploads a chain of block-compressed mipmap levels, see
+    /// [`Texture::upload_compressed_levels`].
+    fn upload_compressed(&self, gl: &glow::Context, format: u32, levels: &[&[u8]]) -> Result<()> {
+        let tex = self;
+        let name = tex.name.as_deref().unwrap_or("unnamed");
+        if levels.is_empty() {
+            anyhow::bail!("no mipmap levels to upload for texture '{name}'");
+        }
+        if levels.len() > tex.mip_levels() as usize {
+            anyhow::bail!(
+                "texture '{}' has {} mipmap levels, but at most {} are possible for {}x{}",
+                name,
+                levels.len(),
+                tex.mip_levels(),
+                tex.w,
+                tex.h
+            );
+        }
+        for (level, data) in levels.iter().enumerate() {
+            let (w, h) = tex.mip_dimensions(level as u32);
+            let expected = match compressed_image_size(format, w, h) {
+                Some(size) => size,
+                None => anyhow::bail!(
+                    "unknown compressed texture format '{}' for texture '{}'",
+                    format!("0x{:x}", format),
+                    name
+                ),
+            };
+            if data.len() != expected {
+                anyhow::bail!(
+                    "mipmap level {} of texture '{}' expects {} bytes ({}x{}), got {}",
+                    level,
+                    name,
+                    expected,
+                    w,
+                    h,
+                    data.len()
+                );
+            }
+        }
+
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(tex.texture));
+            for (level, data) in levels.iter().enumerate() {
+                let (w, h) = tex.mip_dimensions(level as u32);
+                gl.compressed_tex_image_2d(
+                    glow::TEXTURE_2D,
+                    level as i32,
+                    format as i32,
+                    w as i32,
+                    h as i32,
+                    0,
+                    data.len() as i32,
+                    data,
+                );
+            }
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_BASE_LEVEL, 0);
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAX_LEVEL,
+                levels.len() as i32 - 1,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+        Ok(())
+    }
+
+    /// Generates the mipmap chain from the base level. The sRGB textures are stored as
+    /// `SRGB8_ALPHA8`, which drivers filter in linear space, so the smaller levels don't darken.
     fn generate_mipmap(&mut self, gl: &glow::Context) -> Result<()> {
         unsafe {
             gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
@@ -246,6 +738,29 @@ impl TextureData {
         self.mipmaps = true;
         Ok(())
     }
+
+    /// Number of mipmap levels a full chain would have for this texture.
+    pub fn mip_levels(&self) -> u32 {
+        self.w.max(self.h).max(1).ilog2() + 1
+    }
+
+    /// Gets the dimensions of a specific mipmap level.
+    pub fn mip_dimensions(&self, level: u32) -> (usize, usize) {
+        ((self.w >> level).max(1), (self.h >> level).max(1))
+    }
+
+    fn check_level(&self, level: u32) -> Result<()> {
+        let levels = self.mip_levels();
+        if level >= levels {
+            anyhow::bail!(
+                "mipmap level {} out of range for texture '{}' with {} levels",
+                level,
+                self.name.as_deref().unwrap_or("unnamed"),
+                levels
+            );
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -263,18 +778,10 @@ pub struct Texture {
 
     // Data
     pub texture: Arc<TextureData>,
-    pub sampler: glow::Sampler,
+    pub sampler: Arc<Sampler>,
     pub flipv: bool,
     pub mipmaps: bool,
 }
-impl Drop for Texture {
-    fn drop(&mut self) {
-        context::MESSAGE_QUEUE
-            .lock()
-            .unwrap()
-            .push(context::Message::DeleteSampler(self.sampler));
-    }
-}
 impl Texture {
     fn copy_sampler_params(gl: &glow::Context, dst: &glow::Sampler, src: &glow::Sampler) {
         for param in [
@@ -282,6 +789,8 @@ impl Texture {
             glow::TEXTURE_WRAP_T,
             glow::TEXTURE_MIN_FILTER,
             glow::TEXTURE_MAG_FILTER,
+            glow::TEXTURE_COMPARE_MODE,
+            glow::TEXTURE_COMPARE_FUNC,
         ] {
             unsafe {
                 let val = gl.get_sampler_parameter_i32(*src, param);
@@ -294,11 +803,18 @@ impl Texture {
 
     pub fn try_clone(&self) -> Result<Self> {
         let ctx = Context::get().unwrap();
-        let gl = &ctx.gl;
+        self.try_clone_gl(&ctx.gl)
+    }
+
+    /// Same as [`Texture::try_clone`], but with an explicit context, e.g. from a
+    /// [`ContextWrapper`] lock.
+    pub fn try_clone_gl(&self, gl: &glow::Context) -> Result<Self> {
         let sampler = unsafe { gl.create_sampler() }.map_err(|e| anyhow::anyhow!(e))?;
-        Self::copy_sampler_params(gl, &sampler, &self.sampler);
-        unsafe {
-            gl.object_label(glow::SAMPLER, sampler.0.into(), self.path.clone());
+        Self::copy_sampler_params(gl, &sampler, &self.sampler.sampler);
+        context::set_label(gl, glow::SAMPLER, sampler.0.into(), self.path.as_deref());
+        let sampler = Arc::new(Sampler { sampler });
+        if self.mipmaps {
+            SAMPLERS.lock().unwrap().push(Arc::downgrade(&sampler));
         }
 
         Ok(Texture {
@@ -317,6 +833,80 @@ impl Texture {
         })
     }
 
+    /// Creates a texture from raw RGBA pixels with 8 bits per channel, stored row by row from the
+    /// top.
+    pub fn from_rgba(
+        ctx: &context::Context,
+        width: usize,
+        height: usize,
+        data: &[u8],
+    ) -> Result<Self> {
+        let expected = width * height * 4;
+        if data.len() != expected {
+            anyhow::bail!(
+                "RGBA data for a {}x{} texture must be {} bytes, got {}",
+                width,
+                height,
+                expected,
+                data.len()
+            );
+        }
+        let img = match image::RgbaImage::from_raw(width as u32, height as u32, data.to_vec()) {
+            Some(img) => image::DynamicImage::ImageRgba8(img),
+            None => anyhow::bail!("unable to create {}x{} RGBA image", width, height),
+        };
+        TextureBuilder::new().image(&img).build(ctx)
+    }
+
+    /// Creates a texture from an encoded image in memory, e.g., PNG or WebP, decoded with
+    /// SDL_image like textures loaded from paths.
+    pub fn from_memory_encoded(ctx: &context::Context, data: &[u8]) -> Result<Self> {
+        let rw = sdl::rwops::RWops::from_bytes(data).map_err(|e| anyhow::anyhow!(e))?;
+        let sur = rw.load().map_err(|e| anyhow::anyhow!(e))?;
+        let img = surface_to_image(sur)?;
+        TextureBuilder::new().image(&img).build(ctx)
+    }
+
+    /// Sets the mipmap LOD bias of the texture's sampler, clamped to what the driver supports.
+    /// Negative values sharpen, positive values blur.
+    pub fn set_lod_bias(&self, gl: &glow::Context, bias: f32) {
+        unsafe {
+            let max = gl.get_parameter_f32(glow::MAX_TEXTURE_LOD_BIAS);
+            gl.sampler_parameter_f32(
+                self.sampler.sampler,
+                glow::TEXTURE_LOD_BIAS,
+                bias.clamp(-max, max),
+            );
+        }
+    }
+
+    /// Sets the anisotropy of the texture's sampler, clamped to what the hardware supports. The
+    /// texture then no longer follows the anisotropy setting from the configuration.
+    pub fn set_anisotropy(&self, gl: &glow::Context, anisotropy: f32) {
+        SAMPLERS
+            .lock()
+            .unwrap()
+            .retain(|s| !std::ptr::eq(s.as_ptr(), Arc::as_ptr(&self.sampler)));
+        let max = max_anisotropy(gl);
+        if max > 1.0 {
+            unsafe {
+                gl.sampler_parameter_f32(
+                    self.sampler.sampler,
+                    glow::TEXTURE_MAX_ANISOTROPY,
+                    anisotropy.clamp(1.0, max),
+                );
+            }
+        }
+    }
+
+    /// Gets the anisotropy the texture's sampler is actually using, 1 meaning it is disabled.
+    pub fn anisotropy(&self, gl: &glow::Context) -> f32 {
+        if max_anisotropy(gl) <= 1.0 {
+            return 1.0;
+        }
+        unsafe { gl.get_sampler_parameter_f32(self.sampler.sampler, glow::TEXTURE_MAX_ANISOTROPY) }
+    }
+
     pub fn scale(&self, ctx: &context::Context, w: usize, h: usize) -> Result<Self> {
         self.scale_wrap(&ctx.as_wrap(), w, h)
     }
@@ -358,12 +948,12 @@ impl Texture {
 
         Framebuffer::unbind_gl(gl);
         unsafe {
-            gl.viewport(0, 0, naevc::gl_screen.rw, naevc::gl_screen.rh);
+            naevc::gl_screenViewport();
         }
 
         // Have to copy the parameters over
         let tex = fbo.into_texture()?;
-        Self::copy_sampler_params(gl, &tex.sampler, &self.sampler);
+        Self::copy_sampler_params(gl, &tex.sampler.sampler, &self.sampler.sampler);
         Ok(tex)
     }
 
@@ -372,10 +962,20 @@ impl Texture {
     }
 
     pub fn bind_gl(&self, gl: &glow::Context, idx: u32) {
+        self.bind_sampler_gl(gl, idx, None)
+    }
+
+    /// Binds the texture, using the sampler instead of the texture's own if specified.
+    pub fn bind_sampler(&self, ctx: &context::Context, idx: u32, sampler: Option<&Sampler>) {
+        self.bind_sampler_gl(&ctx.gl, idx, sampler)
+    }
+
+    pub fn bind_sampler_gl(&self, gl: &glow::Context, idx: u32, sampler: Option<&Sampler>) {
+        let sampler = sampler.unwrap_or(&self.sampler).sampler;
         unsafe {
             gl.active_texture(glow::TEXTURE0 + idx);
             gl.bind_texture(glow::TEXTURE_2D, Some(self.texture.texture));
-            gl.bind_sampler(idx, Some(self.sampler));
+            gl.bind_sampler(idx, Some(sampler));
         }
     }
 
@@ -390,6 +990,90 @@ impl Texture {
         }
     }
 
+    /// Sets the lowest (most detailed) mipmap level that can be sampled.
+    pub fn set_base_level(&self, ctx: &context::Context, level: u32) -> Result<()> {
+        self.texture.check_level(level)?;
+        self.set_level_param(&ctx.gl, glow::TEXTURE_BASE_LEVEL, level);
+        Ok(())
+    }
+
+    /// Sets the highest (least detailed) mipmap level that can be sampled.
+    pub fn set_max_level(&self, ctx: &context::Context, level: u32) -> Result<()> {
+        self.texture.check_level(level)?;
+        self.set_level_param(&ctx.gl, glow::TEXTURE_MAX_LEVEL, level);
+        Ok(())
+    }
+
+    fn set_level_param(&self, gl: &glow::Context, param: u32, level: u32) {
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture.texture));
+            gl.tex_parameter_i32(glow::TEXTURE_2D, param, level as i32);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+    }
+
+    /// Uploads the data of a single mipmap level explicitly. The data is expected to be tightly
+    /// packed and match the dimensions of the level.
+    pub fn upload_level(
+        &self,
+        ctx: &context::Context,
+        level: u32,
+        format: TextureFormat,
+        data: &[u8],
+    ) -> Result<()> {
+        let tex = &self.texture;
+        tex.check_level(level)?;
+        let (w, h) = tex.mip_dimensions(level);
+        let channels = match format {
+            TextureFormat::RGB | TextureFormat::SRGB => 3,
+            TextureFormat::RGBA | TextureFormat::SRGBA => 4,
+            TextureFormat::Depth => anyhow::bail!("can not upload mipmap levels to depth textures"),
+        };
+        if data.len() != w * h * channels {
+            anyhow::bail!(
+                "mipmap level {} expects {} bytes ({}x{}), got {}",
+                level,
+                w * h * channels,
+                w,
+                h,
+                data.len()
+            );
+        }
+
+        let gl = &ctx.gl;
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(tex.texture));
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                level as i32,
+                format.to_sized_gl(),
+                w as i32,
+                h as i32,
+                0,
+                format.to_gl(),
+                glow::UNSIGNED_BYTE,
+                glow::PixelUnpackData::Slice(Some(data)),
+            );
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+        Ok(())
+    }
+
+    /// Uploads a chain of block-compressed mipmap levels, starting at the base level. Every level
+    /// is checked against the size expected for its dimensions before anything is uploaded, since
+    /// an incomplete or wrongly sized chain samples as black when mipmapping is enabled. The
+    /// maximum level is set to the last uploaded level, so partial chains are still complete.
+    pub fn upload_compressed_levels(
+        &self,
+        ctx: &context::Context,
+        format: u32,
+        levels: &[&[u8]],
+    ) -> Result<()> {
+        self.texture.upload_compressed(&ctx.gl, format, levels)
+    }
+
     pub fn draw(&self, ctx: &context::Context, x: f32, y: f32, w: f32, h: f32) -> Result<()> {
         let dims = ctx.dimensions.read().unwrap();
         #[rustfmt::skip]
@@ -406,9 +1090,25 @@ impl Texture {
     }
 
     pub fn draw_ex(&self, ctx: &context::Context, uniform: &render::TextureUniform) -> Result<()> {
+        self.draw_ex_sampler(ctx, uniform, None)
+    }
+
+    /// Draws the texture, optionally overriding the texture's sampling parameters.
+    pub fn draw_ex_sampler(
+        &self,
+        ctx: &context::Context,
+        uniform: &render::TextureUniform,
+        sampler: Option<&Sampler>,
+    ) -> Result<()> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "Texture::draw_ex_sampler called outside of the main thread"
+        );
+        let _scope = ctx.debug_scope("Texture");
         let gl = &ctx.gl;
         ctx.program_texture.use_program(gl);
-        self.bind(ctx, 0);
+        let default = ctx.default_sampler();
+        self.bind_sampler(ctx, 0, sampler.or((*default).as_ref()));
         ctx.vao_square.bind(ctx);
 
         ctx.buffer_texture
@@ -420,6 +1120,7 @@ impl Texture {
         Texture::unbind(ctx);
         buffer::VertexArray::unbind(ctx);
         ctx.buffer_texture.unbind(ctx);
+        ctx.check_errors();
 
         Ok(())
     }
@@ -460,11 +1161,21 @@ impl Texture {
         &self,
         wctx: &context::ContextWrapper,
         uniform: &render::TextureScaleUniform,
+    ) -> Result<()> {
+        self.draw_scale_ex_sampler_wrap(wctx, uniform, None)
+    }
+
+    /// Draws the texture scaled, optionally overriding the texture's sampling parameters.
+    pub fn draw_scale_ex_sampler_wrap(
+        &self,
+        wctx: &context::ContextWrapper,
+        uniform: &render::TextureScaleUniform,
+        sampler: Option<&Sampler>,
     ) -> Result<()> {
         let ctx = &wctx.lock();
         let gl = &ctx.gl;
         ctx.program_texture_scale.use_program(gl);
-        self.bind(ctx, 0);
+        self.bind_sampler(ctx, 0, sampler);
         ctx.vao_square.bind(ctx);
 
         ctx.buffer_texture_scale
@@ -476,6 +1187,7 @@ impl Texture {
         Texture::unbind(ctx);
         buffer::VertexArray::unbind(ctx);
         ctx.buffer_texture_scale.unbind(ctx);
+        ctx.check_errors();
         Ok(())
     }
 
@@ -519,32 +1231,269 @@ impl FilterMode {
     }
 }
 
-pub enum TextureSource {
-    Path(String),
-    Image(image::DynamicImage),
-    TextureData(Arc<TextureData>),
-    Raw(glow::NativeTexture),
-    Empty(TextureFormat),
+/// Comparison done by samplers of depth textures, e.g., for shadow maps, see
+/// `GL_TEXTURE_COMPARE_FUNC`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompareFunction {
+    Never,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+    Always,
+}
+impl CompareFunction {
+    pub fn to_gl(self) -> i32 {
+        (match self {
+            CompareFunction::Never => glow::NEVER,
+            CompareFunction::Less => glow::LESS,
+            CompareFunction::LessEqual => glow::LEQUAL,
+            CompareFunction::Equal => glow::EQUAL,
+            CompareFunction::NotEqual => glow::NOTEQUAL,
+            CompareFunction::GreaterEqual => glow::GEQUAL,
+            CompareFunction::Greater => glow::GREATER,
+            CompareFunction::Always => glow::ALWAYS,
+        }) as i32
+    }
 }
-impl TextureSource {
-    fn to_texture_data(
-        &self,
-        sctx: &ContextWrapper,
-        w: usize,
-        h: usize,
-        srgb: bool,
-        flipv: bool,
-        mipmaps: bool,
-        name: Option<&str>,
-    ) -> Result<Arc<TextureData>> {
-        if let TextureSource::TextureData(tex) = self {
-            // Purpose dropout, don't want to cache again here
-            return Ok(tex.clone());
-        };
-
-        let mut textures = TEXTURE_DATA.lock().unwrap();
 
-        // Try to load from name if possible
+/// Source of a texture channel when sampled, see `GL_TEXTURE_SWIZZLE_RGBA`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Swizzle {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Zero,
+    One,
+}
+impl Swizzle {
+    pub fn to_gl(self) -> i32 {
+        (match self {
+            Swizzle::Red => glow::RED,
+            Swizzle::Green => glow::GREEN,
+            Swizzle::Blue => glow::BLUE,
+            Swizzle::Alpha => glow::ALPHA,
+            Swizzle::Zero => glow::ZERO,
+            Swizzle::One => glow::ONE,
+        }) as i32
+    }
+
+    /// Converts a raw GL enum, failing on anything that is not a valid swizzle component.
+    pub fn from_gl(value: u32) -> Result<Self> {
+        Ok(match value {
+            glow::RED => Swizzle::Red,
+            glow::GREEN => Swizzle::Green,
+            glow::BLUE => Swizzle::Blue,
+            glow::ALPHA => Swizzle::Alpha,
+            glow::ZERO => Swizzle::Zero,
+            glow::ONE => Swizzle::One,
+            _ => anyhow::bail!("invalid texture swizzle component 0x{value:x}"),
+        })
+    }
+
+    /// Presents a single channel red texture as (1,1,1,r), useful for masks and fonts.
+    pub const RED_AS_ALPHA: [Swizzle; 4] = [Swizzle::One, Swizzle::One, Swizzle::One, Swizzle::Red];
+}
+
+/// A sampler object that can be shared between textures, overriding their own sampling
+/// parameters when bound.
+#[derive(Debug)]
+pub struct Sampler {
+    pub sampler: glow::Sampler,
+}
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        context::MESSAGE_QUEUE
+            .lock()
+            .unwrap()
+            .push(context::Message::DeleteSampler(self.sampler));
+    }
+}
+impl Sampler {
+    pub fn bind(&self, ctx: &context::Context, unit: u32) {
+        self.bind_gl(&ctx.gl, unit)
+    }
+
+    pub fn bind_gl(&self, gl: &glow::Context, unit: u32) {
+        unsafe {
+            gl.bind_sampler(unit, Some(self.sampler));
+        }
+    }
+
+    /// Creates a new sampler object with the same parameters.
+    pub fn try_clone(&self, ctx: &context::Context) -> Result<Self> {
+        let gl = &ctx.gl;
+        let sampler = unsafe { gl.create_sampler() }.map_err(|e| anyhow::anyhow!(e))?;
+        Texture::copy_sampler_params(gl, &sampler, &self.sampler);
+        if max_anisotropy(gl) > 1.0 {
+            unsafe {
+                let val = gl.get_sampler_parameter_f32(self.sampler, glow::TEXTURE_MAX_ANISOTROPY);
+                gl.sampler_parameter_f32(sampler, glow::TEXTURE_MAX_ANISOTROPY, val);
+            }
+        }
+        Ok(Sampler { sampler })
+    }
+
+    pub fn unbind(ctx: &context::Context, unit: u32) {
+        Self::unbind_gl(&ctx.gl, unit)
+    }
+
+    pub fn unbind_gl(gl: &glow::Context, unit: u32) {
+        unsafe {
+            gl.bind_sampler(unit, None);
+        }
+    }
+}
+
+pub struct SamplerBuilder {
+    name: Option<String>,
+    border_value: Option<Vector4<f32>>,
+    address_u: AddressMode,
+    address_v: AddressMode,
+    mag_filter: FilterMode,
+    min_filter: FilterMode,
+    anisotropy: f32,
+    compare: Option<CompareFunction>,
+}
+
+impl SamplerBuilder {
+    pub fn new(name: Option<&str>) -> Self {
+        SamplerBuilder {
+            name: name.map(String::from),
+            border_value: None,
+            address_u: AddressMode::Repeat,
+            address_v: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            anisotropy: 1.0,
+            compare: None,
+        }
+    }
+
+    pub fn address_mode(self, mode: AddressMode) -> Self {
+        self.address_mode_u(mode).address_mode_v(mode)
+    }
+
+    pub fn address_mode_u(mut self, mode: AddressMode) -> Self {
+        self.address_u = mode;
+        self
+    }
+
+    pub fn address_mode_v(mut self, mode: AddressMode) -> Self {
+        self.address_v = mode;
+        self
+    }
+
+    pub fn filter(self, mode: FilterMode) -> Self {
+        self.min_filter(mode).mag_filter(mode)
+    }
+
+    pub fn min_filter(mut self, mode: FilterMode) -> Self {
+        self.min_filter = mode;
+        self
+    }
+
+    pub fn mag_filter(mut self, mode: FilterMode) -> Self {
+        // Magnification can't use mipmaps
+        self.mag_filter = match mode {
+            FilterMode::MipmapLinear => FilterMode::Linear,
+            m => m,
+        };
+        self
+    }
+
+    pub fn border(mut self, border_value: Option<Vector4<f32>>) -> Self {
+        self.border_value = border_value;
+        match border_value {
+            Some(_) => self.address_mode(AddressMode::ClampToBorder),
+            None => self,
+        }
+    }
+
+    /// Sets the anisotropy level, gets clamped to what the hardware supports.
+    pub fn anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    /// Compares the sampled depth against the reference coordinate instead of returning it,
+    /// for use with `sampler2DShadow` and depth textures.
+    pub fn compare(mut self, func: Option<CompareFunction>) -> Self {
+        self.compare = func;
+        self
+    }
+
+    pub fn build(self, ctx: &context::Context) -> Result<Sampler> {
+        self.build_gl(&ctx.gl)
+    }
+
+    pub fn build_gl(self, gl: &glow::Context) -> Result<Sampler> {
+        let sampler = unsafe { gl.create_sampler() }.map_err(|e| anyhow::anyhow!(e))?;
+        unsafe {
+            gl.sampler_parameter_i32(sampler, glow::TEXTURE_MIN_FILTER, self.min_filter.to_gl());
+            gl.sampler_parameter_i32(sampler, glow::TEXTURE_MAG_FILTER, self.mag_filter.to_gl());
+            if let Some(border) = &self.border_value {
+                gl.sampler_parameter_f32_slice(
+                    sampler,
+                    glow::TEXTURE_BORDER_COLOR,
+                    border.as_slice(),
+                );
+            }
+            gl.sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_S, self.address_u.to_gl());
+            gl.sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_T, self.address_v.to_gl());
+            let max = max_anisotropy(gl);
+            if max > 1.0 && self.anisotropy > 1.0 {
+                gl.sampler_parameter_f32(
+                    sampler,
+                    glow::TEXTURE_MAX_ANISOTROPY,
+                    self.anisotropy.min(max),
+                );
+            }
+            if let Some(func) = self.compare {
+                gl.sampler_parameter_i32(
+                    sampler,
+                    glow::TEXTURE_COMPARE_MODE,
+                    glow::COMPARE_REF_TO_TEXTURE as i32,
+                );
+                gl.sampler_parameter_i32(sampler, glow::TEXTURE_COMPARE_FUNC, func.to_gl());
+            }
+            context::set_label(gl, glow::SAMPLER, sampler.0.into(), self.name.as_deref());
+        }
+        Ok(Sampler { sampler })
+    }
+}
+
+pub enum TextureSource {
+    Path(String),
+    Image(image::DynamicImage),
+    TextureData(Arc<TextureData>),
+    Raw(glow::NativeTexture),
+    Empty(TextureFormat),
+    Compressed(Arc<CompressedImage>),
+}
+impl TextureSource {
+    fn to_texture_data(
+        &self,
+        sctx: &ContextWrapper,
+        w: usize,
+        h: usize,
+        srgb: bool,
+        flipv: bool,
+        mipmaps: bool,
+        compress: bool,
+        name: Option<&str>,
+    ) -> Result<Arc<TextureData>> {
+        if let TextureSource::TextureData(tex) = self {
+            // Purpose dropout, don't want to cache again here
+            return Ok(tex.clone());
+        };
+
+        let mut textures = TEXTURE_DATA.lock().unwrap();
+
+        // Try to load from name if possible
         if let Some(name) = name {
             if let Some(t) = TextureData::exists_textures(name, &textures) {
                 // TODO we would actually have to make sure it has mipmaps if we want them...
@@ -555,7 +1504,20 @@ impl TextureSource {
         // Failed to find in cache, load a new
         let tex = Arc::new({
             let mut inner = match self {
-                TextureSource::Path(path) => {
+                TextureSource::Path(path) => 'path: {
+                    if let Some(img) = CompressedImage::load(path, srgb) {
+                        let ctx = &sctx.lock();
+                        match TextureData::from_compressed(ctx, name, &img) {
+                            Ok(data) => break 'path data,
+                            Err(e) => {
+                                warn!(
+                                    "unable to load compressed texture '{}', falling back to the image loader: {}",
+                                    path,
+                                    e
+                                );
+                            }
+                        }
+                    }
                     //let bytes = ndata::read(path.as_str())?;
                     //let img = image::load_from_memory(&bytes)?;
                     let cpath = ndata::simplify_path(path)?;
@@ -563,20 +1525,30 @@ impl TextureSource {
                     let sur = rw.load().map_err(|e| anyhow::anyhow!(e))?;
                     let img = surface_to_image(sur)?;
                     let ctx = &sctx.lock();
-                    TextureData::from_image(ctx, name, &img, flipv, srgb)?
+                    check_size(ctx, name, img.width() as usize, img.height() as usize)?;
+                    TextureData::from_image(ctx, name, &img, flipv, srgb, compress)?
                 }
                 TextureSource::Image(img) => {
                     let ctx = &sctx.lock();
-                    TextureData::from_image(ctx, name, img, flipv, srgb)?
+                    check_size(ctx, name, img.width() as usize, img.height() as usize)?;
+                    TextureData::from_image(ctx, name, img, flipv, srgb, compress)?
                 }
                 TextureSource::Raw(tex) => TextureData::from_raw(*tex, w, h)?,
                 TextureSource::Empty(fmt) => {
                     let ctx = &sctx.lock();
-                    TextureData::new(ctx, *fmt, w, h)?
+                    check_size(ctx, name, w, h)?;
+                    let data = TextureData::new(ctx, *fmt, w, h)?;
+                    context::set_label(&ctx.gl, glow::TEXTURE, data.texture.0.into(), name);
+                    data
+                }
+                TextureSource::Compressed(img) => {
+                    let ctx = &sctx.lock();
+                    TextureData::from_compressed(ctx, name, img)?
                 }
                 TextureSource::TextureData(tex) => unreachable!(),
             };
-            if mipmaps {
+            // Compressed textures can't have their mipmaps generated, they come with the file
+            if mipmaps && !inner.mipmaps && !inner.compressed {
                 let ctx = &sctx.lock();
                 inner.generate_mipmap(&ctx.gl)?;
             }
@@ -592,6 +1564,21 @@ impl TextureSource {
     }
 }
 
+/// Makes sure a texture fits in the maximum texture size of the context.
+fn check_size(ctx: &context::Context, name: Option<&str>, w: usize, h: usize) -> Result<()> {
+    let max = ctx.limits.max_texture_size as usize;
+    if w > max || h > max {
+        anyhow::bail!(
+            "texture '{}' is {}x{}, but the maximum texture size is {}",
+            name.unwrap_or("unnamed"),
+            w,
+            h,
+            max
+        );
+    }
+    Ok(())
+}
+
 pub struct TextureBuilder {
     name: Option<String>,
     source: TextureSource,
@@ -609,6 +1596,10 @@ pub struct TextureBuilder {
     mag_filter: FilterMode,
     min_filter: FilterMode,
     mipmaps: bool,
+    compress: bool,
+    swizzle: Option<[Swizzle; 4]>,
+    lod_bias: f32,
+    anisotropy: Option<f32>,
 }
 
 impl TextureBuilder {
@@ -630,6 +1621,10 @@ impl TextureBuilder {
             mag_filter: FilterMode::Linear,
             min_filter: FilterMode::Linear,
             mipmaps: false,
+            compress: false,
+            swizzle: None,
+            lod_bias: 0.0,
+            anisotropy: None,
         }
     }
 
@@ -664,6 +1659,13 @@ impl TextureBuilder {
         self
     }
 
+    /// Uses block-compressed data, including any mipmap levels, instead of decoded pixels. The
+    /// data is uploaded as is, so it is not flipped vertically.
+    pub fn compressed(mut self, img: CompressedImage) -> Self {
+        self.source = TextureSource::Compressed(Arc::new(img));
+        self
+    }
+
     pub fn srgb(mut self, enable: bool) -> Self {
         self.is_srgb = enable;
         self
@@ -740,6 +1742,41 @@ impl TextureBuilder {
         self
     }
 
+    /// Generates mipmaps after uploading and samples them with trilinear filtering, to avoid
+    /// shimmering when the texture is drawn scaled down. Non-power-of-two textures are fine.
+    pub fn generate_mipmaps(self, enable: bool) -> Self {
+        match enable {
+            true => self.mipmaps(true).min_filter(FilterMode::MipmapLinear),
+            false => self.mipmaps(false),
+        }
+    }
+
+    /// Lets the driver compress the texture on upload if a compressed format is available.
+    /// Quality depends on the driver, so it is best used for things like backgrounds.
+    pub fn compress(mut self, enable: bool) -> Self {
+        self.compress = enable;
+        self
+    }
+
+    /// Sets the mipmap LOD bias, clamped to what the driver supports.
+    pub fn lod_bias(mut self, bias: f32) -> Self {
+        self.lod_bias = bias;
+        self
+    }
+
+    /// Sets the anisotropy instead of using the one from the configuration.
+    pub fn anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = Some(anisotropy);
+        self
+    }
+
+    /// Remaps the channels when sampling, e.g. [`Swizzle::RED_AS_ALPHA`]. This is stored in the
+    /// texture data, so it is shared by all textures using the same data.
+    pub fn swizzle(mut self, swizzle: [Swizzle; 4]) -> Self {
+        self.swizzle = Some(swizzle);
+        self
+    }
+
     pub fn build(self, ctx: &context::Context) -> Result<Texture> {
         let wctx: ContextWrapper = ctx.into();
         self.build_wrap(&wctx)
@@ -754,9 +1791,24 @@ impl TextureBuilder {
             self.is_srgb,
             self.is_flipv,
             self.mipmaps,
+            self.compress,
             self.name.as_deref(),
         )?;
 
+        if let Some(swizzle) = &self.swizzle {
+            let ctx = &sctx.lock();
+            let gl = &ctx.gl;
+            unsafe {
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+                gl.tex_parameter_i32_slice(
+                    glow::TEXTURE_2D,
+                    glow::TEXTURE_SWIZZLE_RGBA,
+                    &swizzle.map(Swizzle::to_gl),
+                );
+                gl.bind_texture(glow::TEXTURE_2D, None);
+            }
+        }
+
         // Create the sampler
         let sampler = {
             let ctx = &sctx.lock();
@@ -782,7 +1834,12 @@ impl TextureBuilder {
                 }
                 gl.sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_S, self.address_u.to_gl());
                 gl.sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_T, self.address_v.to_gl());
-                gl.object_label(glow::SAMPLER, sampler.0.into(), self.name.clone());
+                context::set_label(gl, glow::SAMPLER, sampler.0.into(), self.name.as_deref());
+            }
+            let sampler = Arc::new(Sampler { sampler });
+            if self.mipmaps {
+                apply_sampler_defaults(gl, sampler.sampler);
+                SAMPLERS.lock().unwrap().push(Arc::downgrade(&sampler));
             }
             sampler
         };
@@ -794,7 +1851,7 @@ impl TextureBuilder {
         let srw = sw / (w as f64);
         let srh = sh / (h as f64);
 
-        Ok(Texture {
+        let tex = Texture {
             path: self.name.clone(),
             name: self.name.map(|s| CString::new(s.as_str()).unwrap()),
             sx,
@@ -807,7 +1864,320 @@ impl TextureBuilder {
             sampler,
             flipv: self.is_flipv,
             mipmaps: self.mipmaps,
-        })
+        };
+        if self.lod_bias != 0.0 || self.anisotropy.is_some() {
+            let ctx = &sctx.lock();
+            if self.lod_bias != 0.0 {
+                tex.set_lod_bias(&ctx.gl, self.lod_bias);
+            }
+            if let Some(anisotropy) = self.anisotropy {
+                tex.set_anisotropy(&ctx.gl, anisotropy);
+            }
+        }
+        Ok(tex)
+    }
+}
+
+/// Manifest of the textures to preload during the load screen.
+pub const PRELOAD_MANIFEST: &str = "gfx/preload.txt";
+
+/// Manifest of the textures to preload when entering a system with a nebula.
+pub const PRELOAD_NEBULA_MANIFEST: &str = "gfx/preload/nebula.txt";
+
+/// Gets the manifest of the textures to preload when entering the system `name`.
+pub fn preload_system_manifest(name: &str) -> String {
+    format!("gfx/preload/systems/{name}.txt")
+}
+
+/// Textures kept alive by [`preload_manifest`] for each manifest, as the texture cache only holds
+/// weak references.
+static PRELOADED: LazyLock<Mutex<std::collections::HashMap<String, Vec<Arc<TextureData>>>>> =
+    LazyLock::new(|| Mutex::new(Default::default()));
+
+/// Manifests preloaded for the current system by [`preload_system`].
+static PRELOADED_SYSTEM: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Keeps a preloaded texture alive, unless its manifest was released while it was loading.
+fn preload_keep(manifest: &str, tex: Arc<TextureData>) {
+    if let Some(textures) = PRELOADED.lock().unwrap().get_mut(manifest) {
+        textures.push(tex);
+    }
+}
+
+/// Outcome of a [`preload_manifest`] request, filled in as the textures get loaded.
+#[derive(Clone, Default)]
+pub struct PreloadReport {
+    total: usize,
+    succeeded: Arc<AtomicUsize>,
+    failed: Arc<AtomicUsize>,
+}
+impl PreloadReport {
+    /// Number of textures listed in the manifest.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn succeeded(&self) -> usize {
+        self.succeeded.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Whether all the textures have been either loaded or failed to load.
+    pub fn is_done(&self) -> bool {
+        self.succeeded() + self.failed() >= self.total
+    }
+}
+
+/// Warms up the texture cache with the textures listed in a manifest, so that they don't have to
+/// be loaded when first used during gameplay. The manifest is a text file with one texture path
+/// per line, where empty lines and lines starting with '#' are ignored.
+///
+/// Images are decoded by the asset loader workers and uploaded as the main thread drains it, so
+/// this should be called during the load screen or right when entering a system. The textures
+/// stay loaded until [`preload_release`] or [`preload_clear`] is called. Preloading a manifest
+/// that is already loaded does nothing.
+pub fn preload_manifest(path: &str) -> Result<PreloadReport> {
+    if PRELOADED.lock().unwrap().contains_key(path) {
+        return Ok(PreloadReport::default());
+    }
+    let data = ndata::read(path)?;
+    let manifest = std::str::from_utf8(&data)?;
+    let paths: Vec<String> = manifest
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect();
+
+    let report = PreloadReport {
+        total: paths.len(),
+        ..Default::default()
+    };
+    PRELOADED
+        .lock()
+        .unwrap()
+        .insert(String::from(path), Vec::new());
+    for texpath in paths {
+        // Already loaded, just make sure it stays that way
+        if let Some(tex) = TextureData::exists(&texpath) {
+            preload_keep(path, tex);
+            report.succeeded.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+        let manifest = String::from(path);
+        let succeeded = report.succeeded.clone();
+        let failed = report.failed.clone();
+        assets::loader().request(
+            assets::Priority::Normal,
+            Box::new(move || -> Result<Option<assets::Upload>> {
+                let img = (|| {
+                    let cpath = ndata::simplify_path(&texpath)?;
+                    let rw = ndata::rwops(&cpath).map_err(|e| anyhow::anyhow!(e))?;
+                    let sur = rw.load().map_err(|e| anyhow::anyhow!(e))?;
+                    surface_to_image(sur)
+                })();
+                let img = match img {
+                    Ok(img) => img,
+                    Err(e) => {
+                        failed.fetch_add(1, Ordering::Relaxed);
+                        return Err(e.context(format!("unable to preload texture '{texpath}'")));
+                    }
+                };
+                let upload: assets::Upload = Box::new(move |ctx: &Context| {
+                    match TextureBuilder::new()
+                        .name(Some(&texpath))
+                        .image(&img)
+                        .build(ctx)
+                    {
+                        Ok(tex) => {
+                            preload_keep(&manifest, tex.texture.clone());
+                            succeeded.fetch_add(1, Ordering::Relaxed);
+                            Ok(())
+                        }
+                        Err(e) => {
+                            failed.fetch_add(1, Ordering::Relaxed);
+                            Err(e.context(format!("unable to preload texture '{texpath}'")))
+                        }
+                    }
+                });
+                Ok(Some(upload))
+            }),
+        );
+    }
+    Ok(report)
+}
+
+/// Releases the textures kept alive by [`preload_manifest`] for the manifest at `path`. Those
+/// still in use stay cached.
+pub fn preload_release(path: &str) {
+    PRELOADED.lock().unwrap().remove(path);
+}
+
+/// Releases the textures kept alive by [`preload_manifest`] for all the manifests. Those still in
+/// use stay cached.
+pub fn preload_clear() {
+    PRELOADED.lock().unwrap().clear();
+    PRELOADED_SYSTEM.lock().unwrap().clear();
+}
+
+/// Preloads the textures for the system `name`, and those for nebulas if it has one. The
+/// manifests of the previous system that are not needed anymore are released.
+pub fn preload_system(name: &str, nebula: bool) {
+    let mut manifests = vec![preload_system_manifest(name)];
+    if nebula {
+        manifests.push(String::from(PRELOAD_NEBULA_MANIFEST));
+    }
+    manifests.retain(|m| ndata::exists(m));
+
+    let mut current = PRELOADED_SYSTEM.lock().unwrap();
+    for old in current.iter().filter(|m| !manifests.contains(m)) {
+        preload_release(old);
+    }
+    for manifest in &manifests {
+        if let Err(e) = preload_manifest(manifest) {
+            warn_err(e.context(format!("unable to preload textures from '{manifest}'")));
+        }
+    }
+    *current = manifests;
+}
+
+/// Releases the textures preloaded by [`preload_system`].
+pub fn preload_system_clear() {
+    for manifest in PRELOADED_SYSTEM.lock().unwrap().drain(..) {
+        preload_release(&manifest);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn tex_preloadSystem(name: *const c_char, nebula: c_int) {
+    ffi_guard((), || {
+        let name = unsafe { CStr::from_ptr(name) };
+        preload_system(&name.to_string_lossy(), nebula != 0);
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn tex_preloadSystemClear() {
+    ffi_guard((), preload_system_clear)
+}
+
+/// Handle of a texture requested from a [`TextureLoader`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TextureHandle(u64);
+
+/// Image decoded by a worker thread, waiting to be uploaded.
+type Decoded = (TextureHandle, String, Result<image::DynamicImage>);
+
+/// Loads textures without blocking the main thread. The images are decoded on the asset worker
+/// threads without touching OpenGL, and only uploaded when [`TextureLoader::poll`] is called
+/// from the main thread.
+///
+/// ```ignore
+/// let mut loader = TextureLoader::new();
+/// let handle = loader.request("gfx/ship/llama/llama.webp");
+/// // Every frame
+/// for (h, tex) in loader.poll(ctx) {
+///     ...
+/// }
+/// ```
+pub struct TextureLoader {
+    next: u64,
+    pending: usize,
+    ready: Arc<Mutex<Vec<Decoded>>>,
+    cancelled: Arc<AtomicBool>,
+}
+impl Default for TextureLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Drop for TextureLoader {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+impl TextureLoader {
+    pub fn new() -> Self {
+        TextureLoader {
+            next: 0,
+            pending: 0,
+            ready: Arc::new(Mutex::new(Vec::new())),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Requests a texture to be loaded from `path`.
+    pub fn request(&mut self, path: &str) -> TextureHandle {
+        let handle = TextureHandle(self.next);
+        self.next += 1;
+        self.pending += 1;
+
+        let path = String::from(path);
+        let ready = self.ready.clone();
+        let cancelled = self.cancelled.clone();
+        assets::loader().request(
+            assets::Priority::Normal,
+            Box::new(move || -> Result<Option<assets::Upload>> {
+                if cancelled.load(Ordering::Relaxed) {
+                    return Ok(None);
+                }
+                let img = (|| {
+                    let cpath = ndata::simplify_path(&path)?;
+                    let rw = ndata::rwops(&cpath).map_err(|e| anyhow::anyhow!(e))?;
+                    let sur = rw.load().map_err(|e| anyhow::anyhow!(e))?;
+                    surface_to_image(sur)
+                })();
+                if !cancelled.load(Ordering::Relaxed) {
+                    ready.lock().unwrap().push((handle, path, img));
+                }
+                Ok(None)
+            }),
+        );
+        handle
+    }
+
+    /// Uploads the images that finished decoding, returning their textures. Has to be called from
+    /// the main thread.
+    pub fn poll(&mut self, ctx: &Context) -> Vec<(TextureHandle, Result<Texture>)> {
+        debug_assert!(
+            ctx.is_main_thread(),
+            "TextureLoader::poll called outside of the main thread"
+        );
+        // Don't hold the lock while uploading, so workers can keep pushing
+        let ready = std::mem::take(&mut *self.ready.lock().unwrap());
+        self.pending -= ready.len();
+        ready
+            .into_iter()
+            .map(|(handle, path, img)| {
+                let tex = img.and_then(|img| {
+                    TextureBuilder::new()
+                        .name(Some(&path))
+                        .image(&img)
+                        .build(ctx)
+                });
+                let tex = tex.map_err(|e| e.context(format!("unable to load texture '{path}'")));
+                (handle, tex)
+            })
+            .collect()
+    }
+
+    /// Number of requested textures that haven't been returned by [`TextureLoader::poll`] yet.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Drops all the requests in flight. Requests that haven't started decoding are skipped,
+    /// and the ones being decoded are discarded when done. Also done when the loader is dropped.
+    pub fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.ready.lock().unwrap().clear();
+        self.pending = 0;
+        // New requests get a fresh flag, so they aren't cancelled too
+        self.cancelled = Arc::new(AtomicBool::new(false));
+        self.ready = Arc::new(Mutex::new(Vec::new()));
     }
 }
 
@@ -866,11 +2236,18 @@ pub struct Framebuffer {
     pub h: usize,
     pub texture: Option<Texture>,
     pub depth: Option<Texture>,
+    /// Depth and stencil attachment that can't be sampled from.
+    pub depth_stencil: Option<glow::Renderbuffer>,
+    /// Configuration used to create the framebuffer again when resizing.
+    builder: FramebufferBuilder,
 }
 impl Drop for Framebuffer {
     fn drop(&mut self) {
-        let ctx = Context::get().unwrap();
-        unsafe { ctx.gl.delete_framebuffer(self.framebuffer) };
+        let mut queue = context::MESSAGE_QUEUE.lock().unwrap();
+        queue.push(context::Message::DeleteFramebuffer(self.framebuffer));
+        if let Some(rb) = self.depth_stencil {
+            queue.push(context::Message::DeleteRenderbuffer(rb));
+        }
     }
 }
 impl Framebuffer {
@@ -902,14 +2279,50 @@ impl Framebuffer {
             None => anyhow::bail!("unable to remove texture from framebuffer"),
         }
     }
+
+    /// Recreates the attachments with a new size, e.g., when the window is resized. The contents
+    /// are lost. Does nothing if the size doesn't change.
+    pub fn resize(&mut self, ctx: &context::Context, w: usize, h: usize) -> Result<()> {
+        if self.w == w && self.h == h {
+            return Ok(());
+        }
+        *self = self.builder.clone().width(w).height(h).build(ctx)?;
+        Ok(())
+    }
+
+    /// Copies the colour attachment to the drawing area of the screen, scaling it if needed.
+    pub fn blit_to_screen(&self, ctx: &context::Context) {
+        let gl = &ctx.gl;
+        let [x, y, w, h] = ctx.dimensions.read().unwrap().viewport;
+        unsafe {
+            let screen = NonZero::new(naevc::gl_screen.current_fbo).map(glow::NativeFramebuffer);
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(self.framebuffer));
+            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, screen);
+            gl.blit_framebuffer(
+                0,
+                0,
+                self.w as i32,
+                self.h as i32,
+                x,
+                y,
+                x + w,
+                y + h,
+                glow::COLOR_BUFFER_BIT,
+                glow::LINEAR,
+            );
+            gl.bind_framebuffer(glow::FRAMEBUFFER, screen);
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct FramebufferBuilder {
     name: Option<String>,
     w: usize,
     h: usize,
     texture: bool,
     depth: bool,
+    depth_stencil: bool,
     filter: FilterMode,
     address_mode: AddressMode,
 }
@@ -922,6 +2335,7 @@ impl FramebufferBuilder {
             h: 0,
             texture: true,
             depth: false,
+            depth_stencil: false,
             filter: FilterMode::Linear,
             address_mode: AddressMode::ClampToBorder,
         }
@@ -947,6 +2361,13 @@ impl FramebufferBuilder {
         self
     }
 
+    /// Adds a depth and stencil renderbuffer. Unlike [`FramebufferBuilder::depth`], it can't be
+    /// sampled, so both can't be used at the same time.
+    pub fn depth_stencil(mut self, enable: bool) -> Self {
+        self.depth_stencil = enable;
+        self
+    }
+
     pub fn filter(mut self, mode: FilterMode) -> Self {
         self.filter = mode;
         self
@@ -967,7 +2388,23 @@ impl FramebufferBuilder {
         self.build_wrap(&wctx)
     }
 
+    /// Same as [`FramebufferBuilder::build`], but gives back `old` instead if it already has the
+    /// requested size. Useful for render targets that follow the screen size.
+    pub fn build_reusing(
+        self,
+        ctx: &context::Context,
+        old: Option<Framebuffer>,
+    ) -> Result<Framebuffer> {
+        match old {
+            Some(fb) if fb.w == self.w && fb.h == self.h => Ok(fb),
+            _ => self.build(ctx),
+        }
+    }
+
     pub fn build_wrap(self, ctx: &context::ContextWrapper) -> Result<Framebuffer> {
+        if self.depth && self.depth_stencil {
+            anyhow::bail!("framebuffer can't have both a depth texture and depth stencil buffer");
+        }
         let texture = if self.texture {
             let name = self.name.as_ref().map(|name| format!("{name}-Texture"));
             let texture = TextureBuilder::new()
@@ -1003,7 +2440,12 @@ impl FramebufferBuilder {
         let framebuffer = unsafe { gl.create_framebuffer().map_err(|e| anyhow::anyhow!(e)) }?;
         unsafe {
             gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
-            gl.object_label(glow::FRAMEBUFFER, framebuffer.0.into(), self.name);
+            context::set_label(
+                gl,
+                glow::FRAMEBUFFER,
+                framebuffer.0.into(),
+                self.name.as_deref(),
+            );
         }
 
         if let Some(ref texture) = texture {
@@ -1032,9 +2474,47 @@ impl FramebufferBuilder {
             }
         };
 
+        let depth_stencil = if self.depth_stencil {
+            unsafe {
+                let rb = gl.create_renderbuffer().map_err(|e| anyhow::anyhow!(e))?;
+                gl.bind_renderbuffer(glow::RENDERBUFFER, Some(rb));
+                let name = self
+                    .name
+                    .as_ref()
+                    .map(|name| format!("{name}-DepthStencil"));
+                context::set_label(gl, glow::RENDERBUFFER, rb.0.into(), name.as_deref());
+                gl.renderbuffer_storage(
+                    glow::RENDERBUFFER,
+                    glow::DEPTH24_STENCIL8,
+                    self.w as i32,
+                    self.h as i32,
+                );
+                gl.framebuffer_renderbuffer(
+                    glow::FRAMEBUFFER,
+                    glow::DEPTH_STENCIL_ATTACHMENT,
+                    glow::RENDERBUFFER,
+                    Some(rb),
+                );
+                gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+                Some(rb)
+            }
+        } else {
+            None
+        };
+
         let status = unsafe { gl.check_framebuffer_status(glow::FRAMEBUFFER) };
         if status != glow::FRAMEBUFFER_COMPLETE {
-            anyhow::bail!("error setting up framebuffer");
+            unsafe {
+                gl.delete_framebuffer(framebuffer);
+                if let Some(rb) = depth_stencil {
+                    gl.delete_renderbuffer(rb);
+                }
+                gl.bind_framebuffer(
+                    glow::FRAMEBUFFER,
+                    NonZero::new(naevc::gl_screen.current_fbo).map(glow::NativeFramebuffer),
+                );
+            }
+            anyhow::bail!("error setting up framebuffer: status 0x{status:x}");
         }
 
         unsafe {
@@ -1051,10 +2531,150 @@ impl FramebufferBuilder {
             h: self.h,
             texture,
             depth,
+            depth_stencil,
+            builder: self,
         })
     }
 }
 
+/// Many small images packed into a single texture, so that they can be drawn without switching
+/// textures. Entries are looked up by the name they were added with.
+///
+/// ```ignore
+/// let atlas = AtlasBuilder::new(Some("UI Icons"))
+///     .padding(2)
+///     .image("close", &close_img)
+///     .image("open", &open_img)
+///     .build(ctx)?;
+/// let [u, v, w, h] = atlas.uv("close").unwrap();
+/// ```
+pub struct Atlas {
+    texture: Texture,
+    entries: std::collections::HashMap<String, [f32; 4]>,
+}
+impl Atlas {
+    /// Gets the rectangle of an entry in texture coordinates as `[x, y, w, h]`.
+    pub fn uv(&self, name: &str) -> Option<[f32; 4]> {
+        self.entries.get(name).copied()
+    }
+
+    /// Texture holding all the entries.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+pub struct AtlasBuilder {
+    name: Option<String>,
+    padding: u32,
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(name: Option<&str>) -> Self {
+        AtlasBuilder {
+            name: name.map(String::from),
+            padding: 1,
+            images: Vec::new(),
+        }
+    }
+
+    /// Empty pixels left around every entry, so that filtering doesn't bleed between them.
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Adds an image, replacing any previous one with the same name.
+    pub fn image(mut self, name: &str, img: &image::DynamicImage) -> Self {
+        self.images.retain(|(n, _)| n != name);
+        self.images.push((String::from(name), img.to_rgba8()));
+        self
+    }
+
+    /// Places the images in shelves, tallest first, in an atlas `width` pixels wide. Returns the
+    /// position of every image, in the order they were added, and the height used.
+    fn pack(&self, width: u32) -> Option<(Vec<(u32, u32)>, u32)> {
+        let pad = self.padding;
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|i| std::cmp::Reverse(self.images[*i].1.height()));
+
+        let mut pos = vec![(0, 0); self.images.len()];
+        let (mut x, mut y, mut shelf) = (pad, pad, 0);
+        for i in order {
+            let (w, h) = self.images[i].1.dimensions();
+            if w + 2 * pad > width {
+                return None;
+            }
+            if x + w + pad > width {
+                x = pad;
+                y += shelf + pad;
+                shelf = 0;
+            }
+            pos[i] = (x, y);
+            x += w + pad;
+            shelf = shelf.max(h);
+        }
+        Some((pos, y + shelf + pad))
+    }
+
+    pub fn build(self, ctx: &context::Context) -> Result<Atlas> {
+        let max = ctx.limits.max_texture_size;
+        let name = self.name.as_deref().unwrap_or("unnamed");
+        if self.images.is_empty() {
+            anyhow::bail!("atlas '{name}' has no images");
+        }
+
+        // Start with a square guess and widen until it fits
+        let pad = self.padding as u64;
+        let area: u64 = self
+            .images
+            .iter()
+            .map(|(_, img)| (img.width() as u64 + pad) * (img.height() as u64 + pad))
+            .sum();
+        let mut width = ((area as f64).sqrt() as u32).next_power_of_two().min(max);
+        let (pos, height) = loop {
+            if let Some((pos, height)) = self.pack(width) {
+                if height <= max {
+                    break (pos, height);
+                }
+            }
+            if width >= max {
+                anyhow::bail!("images do not fit in atlas '{name}' of at most {max}x{max}");
+            }
+            width = (width * 2).min(max);
+        };
+
+        let mut atlas = image::RgbaImage::new(width, height);
+        let mut entries = std::collections::HashMap::new();
+        let (fw, fh) = (width as f32, height as f32);
+        for ((entry, img), (x, y)) in self.images.iter().zip(pos) {
+            image::imageops::replace(&mut atlas, img, x as i64, y as i64);
+            // The texture is flipped vertically on upload
+            let (w, h) = (img.width() as f32, img.height() as f32);
+            entries.insert(
+                entry.clone(),
+                [x as f32 / fw, 1.0 - (y as f32 + h) / fh, w / fw, h / fh],
+            );
+        }
+
+        // Not named, as named textures are cached and the atlas may be rebuilt with other images
+        let texture = TextureBuilder::new()
+            .image(&image::DynamicImage::ImageRgba8(atlas))
+            .address_mode(AddressMode::ClampToEdge)
+            .build(ctx)?;
+        Ok(Atlas { texture, entries })
+    }
+}
+
 // BELOW THIS IS THE C API CODE
 
 struct Flags {
@@ -1082,8 +2702,10 @@ macro_rules! capi_tex {
     ($funcname: ident, $field: tt) => {
         #[unsafe(no_mangle)]
         pub extern "C" fn $funcname(ctex: *mut Texture) -> c_double {
-            let tex = unsafe { &*ctex };
-            tex.texture.$field as f64
+            ffi_guard(0.0, || {
+                let tex = unsafe { &*ctex };
+                tex.texture.$field as f64
+            })
         }
     };
 }
@@ -1091,8 +2713,10 @@ macro_rules! capi {
     ($funcname: ident, $field: tt) => {
         #[unsafe(no_mangle)]
         pub extern "C" fn $funcname(ctex: *mut Texture) -> c_double {
-            let tex = unsafe { &*ctex };
-            tex.$field as f64
+            ffi_guard(0.0, || {
+                let tex = unsafe { &*ctex };
+                tex.$field as f64
+            })
         }
     };
 }
@@ -1115,48 +2739,57 @@ pub extern "C" fn gl_texExistsOrCreate(
     sy: c_int,
     created: *mut c_int,
 ) -> *mut Texture {
-    let ctx = Context::get().unwrap(); /* Lock early. */
+    ffi_guard(std::ptr::null_mut(), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return std::ptr::null_mut();
+            }
+        }; /* Lock early. */
 
-    unsafe {
-        naevc::gl_contextSet();
-    }
+        unsafe {
+            naevc::gl_contextSet();
+        }
 
-    let path = unsafe { CStr::from_ptr(cpath) };
-    let flags = Flags::from(cflags);
-    let mut builder = TextureBuilder::new()
-        .sx(sx as usize)
-        .sy(sy as usize)
-        .srgb(!flags.notsrgb)
-        .mipmaps(flags.mipmaps);
+        let path = unsafe { CStr::from_ptr(cpath) };
+        let flags = Flags::from(cflags);
+        let mut builder = TextureBuilder::new()
+            .sx(sx as usize)
+            .sy(sy as usize)
+            .srgb(!flags.notsrgb)
+            .mipmaps(flags.mipmaps);
 
-    if flags.clamp_alpha {
-        builder = builder.border(Some(Vector4::<f32>::new(0., 0., 0., 0.)));
-    }
+        if flags.clamp_alpha {
+            builder = builder.border(Some(Vector4::<f32>::new(0., 0., 0., 0.)));
+        }
 
-    let pathname = path.to_str().unwrap();
-    builder = match TextureData::exists(pathname) {
-        Some(tex) => {
-            unsafe {
-                *created = 0;
+        let pathname = path.to_string_lossy();
+        let pathname = &*pathname;
+        builder = match TextureData::exists(pathname) {
+            Some(tex) => {
+                unsafe {
+                    *created = 0;
+                }
+                builder.texture_data(&tex)
             }
-            builder.texture_data(&tex)
-        }
-        None => {
-            unsafe {
-                *created = 1;
+            None => {
+                unsafe {
+                    *created = 1;
+                }
+                builder.path(pathname)
             }
-            builder.path(pathname)
-        }
-    };
+        };
 
-    let out = match builder.build(ctx) {
-        Ok(tex) => tex.into_ptr(),
-        _ => std::ptr::null_mut(),
-    };
-    unsafe {
-        naevc::gl_contextUnset();
-    }
-    out
+        let out = match builder.build(ctx) {
+            Ok(tex) => tex.into_ptr(),
+            _ => std::ptr::null_mut(),
+        };
+        unsafe {
+            naevc::gl_contextUnset();
+        }
+        out
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -1169,57 +2802,65 @@ pub extern "C" fn gl_loadImageData(
     cname: *const c_char,
     cflags: c_uint,
 ) -> *mut Texture {
-    let ctx = Context::get().unwrap(); /* Lock early. */
-    let name = unsafe { CStr::from_ptr(cname) };
-    let flags = Flags::from(cflags);
+    ffi_guard(std::ptr::null_mut(), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return std::ptr::null_mut();
+            }
+        }; /* Lock early. */
+        let name = unsafe { CStr::from_ptr(cname) };
+        let flags = Flags::from(cflags);
 
-    unsafe {
-        naevc::gl_contextSet();
-    }
-
-    let mut builder = TextureBuilder::new()
-        .name(Some(name.to_str().unwrap()))
-        .sx(sx as usize)
-        .sy(sy as usize)
-        .width(w as usize)
-        .height(h as usize);
-
-    if !data.is_null() {
-        let rawdata = unsafe { std::slice::from_raw_parts(data, (w * h * 4) as usize) };
-        let buf = match image::ImageBuffer::<image::Rgba<f32>, Vec<f32>>::from_raw(
-            w as u32,
-            h as u32,
-            rawdata.to_vec(),
-        ) {
-            Some(val) => val,
-            None => {
-                warn!("unable to load image");
-                unsafe {
-                    naevc::gl_contextUnset();
+        unsafe {
+            naevc::gl_contextSet();
+        }
+
+        let mut builder = TextureBuilder::new()
+            .name(Some(&name.to_string_lossy()))
+            .sx(sx as usize)
+            .sy(sy as usize)
+            .width(w as usize)
+            .height(h as usize);
+
+        if !data.is_null() {
+            let rawdata = unsafe { std::slice::from_raw_parts(data, (w * h * 4) as usize) };
+            let buf = match image::ImageBuffer::<image::Rgba<f32>, Vec<f32>>::from_raw(
+                w as u32,
+                h as u32,
+                rawdata.to_vec(),
+            ) {
+                Some(val) => val,
+                None => {
+                    warn!("unable to load image");
+                    unsafe {
+                        naevc::gl_contextUnset();
+                    }
+                    return std::ptr::null_mut();
                 }
-                return std::ptr::null_mut();
+            };
+            let img = image::DynamicImage::ImageRgba32F(buf);
+            builder = builder.image(&img);
+        }
+
+        let out = match builder.build(ctx) {
+            Ok(tex) => tex.into_ptr(),
+            Err(e) => {
+                warn_err!(e);
+                std::ptr::null_mut()
             }
         };
-        let img = image::DynamicImage::ImageRgba32F(buf);
-        builder = builder.image(&img);
-    }
-
-    let out = match builder.build(ctx) {
-        Ok(tex) => tex.into_ptr(),
-        Err(e) => {
-            warn_err!(e);
-            std::ptr::null_mut()
+        unsafe {
+            naevc::gl_contextUnset();
         }
-    };
-    unsafe {
-        naevc::gl_contextUnset();
-    }
-    out
+        out
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gl_newImage(cpath: *const c_char, flags: c_uint) -> *mut Texture {
-    gl_newSprite(cpath, 1, 1, flags)
+    ffi_guard(std::ptr::null_mut(), || gl_newSprite(cpath, 1, 1, flags))
 }
 
 #[unsafe(no_mangle)]
@@ -1229,36 +2870,44 @@ pub extern "C" fn gl_newSprite(
     sy: c_int,
     cflags: c_uint,
 ) -> *mut Texture {
-    let ctx = Context::get().unwrap(); /* Lock early. */
-    let path = unsafe { CStr::from_ptr(cpath) };
-    let flags = Flags::from(cflags);
+    ffi_guard(std::ptr::null_mut(), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return std::ptr::null_mut();
+            }
+        }; /* Lock early. */
+        let path = unsafe { CStr::from_ptr(cpath) };
+        let flags = Flags::from(cflags);
 
-    unsafe {
-        naevc::gl_contextSet();
-    }
+        unsafe {
+            naevc::gl_contextSet();
+        }
 
-    let mut builder = TextureBuilder::new()
-        .path(path.to_str().unwrap())
-        .sx(sx as usize)
-        .sy(sy as usize)
-        .srgb(!flags.notsrgb)
-        .mipmaps(flags.mipmaps);
+        let mut builder = TextureBuilder::new()
+            .path(&path.to_string_lossy())
+            .sx(sx as usize)
+            .sy(sy as usize)
+            .srgb(!flags.notsrgb)
+            .mipmaps(flags.mipmaps);
 
-    if flags.clamp_alpha {
-        builder = builder.border(Some(Vector4::<f32>::new(0., 0., 0., 0.)));
-    }
+        if flags.clamp_alpha {
+            builder = builder.border(Some(Vector4::<f32>::new(0., 0., 0., 0.)));
+        }
 
-    let out = match builder.build(ctx) {
-        Ok(tex) => tex.into_ptr(),
-        Err(e) => {
-            warn_err(e.context("unable to build texture for new sprite"));
-            std::ptr::null_mut()
+        let out = match builder.build(ctx) {
+            Ok(tex) => tex.into_ptr(),
+            Err(e) => {
+                warn_err(e.context("unable to build texture for new sprite"));
+                std::ptr::null_mut()
+            }
+        };
+        unsafe {
+            naevc::gl_contextUnset();
         }
-    };
-    unsafe {
-        naevc::gl_contextUnset();
-    }
-    out
+        out
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -1269,79 +2918,110 @@ pub extern "C" fn gl_newSpriteRWops(
     sy: c_int,
     cflags: c_uint,
 ) -> *mut Texture {
-    let ctx = Context::get().unwrap(); /* Lock early. */
-    let path = unsafe { CStr::from_ptr(cpath) };
-    let flags = Flags::from(cflags);
-    unsafe {
-        naevc::gl_contextSet();
-    }
-
-    let mut builder = TextureBuilder::new()
-        .sx(sx as usize)
-        .sy(sy as usize)
-        .srgb(!flags.notsrgb)
-        .mipmaps(flags.mipmaps);
-
-    if flags.mipmaps {
-        builder = builder.min_filter(FilterMode::MipmapLinear);
-    }
-    if flags.clamp_alpha {
-        builder = builder.border(Some(Vector4::<f32>::new(0., 0., 0., 0.)));
-    }
-
-    let pathname = path.to_str().unwrap();
-    builder = match TextureData::exists(pathname) {
-        Some(tex) => builder.texture_data(&tex),
-        None => {
-            let rw = unsafe { sdl::rwops::RWops::from_ll(rw as *mut sdl::sys::SDL_RWops) };
-            /* TODO support image when it's faster...
-            let img = image::ImageReader::new(std::io::BufReader::new(rw))
-                .with_guessed_format()
-                .unwrap()
-                .decode()
-                .unwrap();
-            */
-            let img = match rw.load() {
-                Ok(sur) => surface_to_image(sur).unwrap(),
-                Err(e) => {
-                    // SDL2 uses strings as errors...
-                    //warn_err!(e, "unable to load image '{}'", pathname);
-                    warn!("unable to load image '{}': {}", pathname, e);
-                    return std::ptr::null_mut();
-                }
-            };
-            builder.image(&img)
+    ffi_guard(std::ptr::null_mut(), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return std::ptr::null_mut();
+            }
+        }; /* Lock early. */
+        let path = unsafe { CStr::from_ptr(cpath) };
+        let flags = Flags::from(cflags);
+        unsafe {
+            naevc::gl_contextSet();
         }
-    };
 
-    let out = match builder.build(ctx) {
-        Ok(tex) => tex.into_ptr(),
-        Err(e) => {
-            warn_err(e.context("unable to build texture for new sprite from rwops"));
-            std::ptr::null_mut()
+        let mut builder = TextureBuilder::new()
+            .sx(sx as usize)
+            .sy(sy as usize)
+            .srgb(!flags.notsrgb)
+            .mipmaps(flags.mipmaps);
+
+        if flags.mipmaps {
+            builder = builder.min_filter(FilterMode::MipmapLinear);
         }
-    };
-    unsafe {
-        naevc::gl_contextUnset();
-    }
-    out
+        if flags.clamp_alpha {
+            builder = builder.border(Some(Vector4::<f32>::new(0., 0., 0., 0.)));
+        }
+
+        let pathname = path.to_string_lossy();
+        let pathname = &*pathname;
+        builder = match TextureData::exists(pathname) {
+            Some(tex) => builder.texture_data(&tex),
+            None => {
+                let rw = unsafe { sdl::rwops::RWops::from_ll(rw as *mut sdl::sys::SDL_RWops) };
+                /* TODO support image when it's faster...
+                let img = image::ImageReader::new(std::io::BufReader::new(rw))
+                    .with_guessed_format()
+                    .unwrap()
+                    .decode()
+                    .unwrap();
+                */
+                let img = match rw.load() {
+                    Ok(sur) => match surface_to_image(sur) {
+                        Ok(img) => img,
+                        Err(e) => {
+                            warn_err(e.context(format!("unable to convert image '{pathname}'")));
+                            unsafe {
+                                naevc::gl_contextUnset();
+                            }
+                            return std::ptr::null_mut();
+                        }
+                    },
+                    Err(e) => {
+                        // SDL2 uses strings as errors...
+                        //warn_err!(e, "unable to load image '{}'", pathname);
+                        warn!("unable to load image '{}': {}", pathname, e);
+                        unsafe {
+                            naevc::gl_contextUnset();
+                        }
+                        return std::ptr::null_mut();
+                    }
+                };
+                builder.image(&img)
+            }
+        };
+
+        let out = match builder.build(ctx) {
+            Ok(tex) => tex.into_ptr(),
+            Err(e) => {
+                warn_err(e.context("unable to build texture for new sprite from rwops"));
+                std::ptr::null_mut()
+            }
+        };
+        unsafe {
+            naevc::gl_contextUnset();
+        }
+        out
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gl_dupTexture(ctex: *mut Texture) -> *mut Texture {
-    if ctex.is_null() {
-        return ctex;
-    }
-    unsafe {
-        naevc::gl_contextSet();
-    }
-    let tex = unsafe { &*ctex };
-    unsafe { Arc::increment_strong_count(Arc::into_raw(tex.texture.clone())) }
-    let out = Box::into_raw(Box::new(tex.try_clone().unwrap()));
-    unsafe {
-        naevc::gl_contextUnset();
-    }
-    out
+    ffi_guard(std::ptr::null_mut(), || {
+        if ctex.is_null() {
+            return ctex;
+        }
+        unsafe {
+            naevc::gl_contextSet();
+        }
+        let tex = unsafe { &*ctex };
+        let out = match tex.try_clone() {
+            Ok(dup) => {
+                unsafe { Arc::increment_strong_count(Arc::into_raw(tex.texture.clone())) }
+                Box::into_raw(Box::new(dup))
+            }
+            Err(e) => {
+                warn_err(e.context("unable to duplicate texture"));
+                std::ptr::null_mut()
+            }
+        };
+        unsafe {
+            naevc::gl_contextUnset();
+        }
+        out
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -1351,102 +3031,147 @@ pub extern "C" fn gl_rawTexture(
     w: c_double,
     h: c_double,
 ) -> *mut Texture {
-    let ctx = Context::get().unwrap(); /* Lock early. */
-    unsafe {
-        naevc::gl_contextSet();
-    }
-    let pathname: Option<&str> = {
-        if cpath.is_null() {
-            None
-        } else {
-            let path = unsafe { CStr::from_ptr(cpath) };
-            Some(path.to_str().unwrap())
+    ffi_guard(std::ptr::null_mut(), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return std::ptr::null_mut();
+            }
+        }; /* Lock early. */
+        unsafe {
+            naevc::gl_contextSet();
         }
-    };
-    let mut builder = TextureBuilder::new()
-        .width(w as usize)
-        .height(h as usize)
-        .name(pathname);
+        let pathname = match cpath.is_null() {
+            true => None,
+            false => Some(unsafe { CStr::from_ptr(cpath) }.to_string_lossy()),
+        };
+        let pathname = pathname.as_deref();
+        let mut builder = TextureBuilder::new()
+            .width(w as usize)
+            .height(h as usize)
+            .name(pathname);
+
+        builder = match (
+            pathname.and_then(TextureData::exists),
+            NonZero::new(tex).map(glow::NativeTexture),
+        ) {
+            (Some(data), _) => builder.texture_data(&data),
+            (None, Some(ntex)) => builder.native_texture(ntex),
+            (None, None) => {
+                warn!(
+                    "unable to create raw texture '{}': invalid texture",
+                    pathname.unwrap_or("(null)")
+                );
+                unsafe {
+                    naevc::gl_contextUnset();
+                }
+                return std::ptr::null_mut();
+            }
+        };
 
-    builder = match pathname {
-        Some(pathname) => match TextureData::exists(pathname) {
-            Some(tex) => builder.texture_data(&tex),
-            None => {
-                let tex = glow::NativeTexture(NonZero::new(tex).unwrap());
-                builder.native_texture(tex)
+        let out = match builder.build(ctx) {
+            Ok(tex) => tex.into_ptr(),
+            Err(e) => {
+                warn_err(e.context("unable to build texture for raw texture"));
+                std::ptr::null_mut()
             }
-        },
-        None => {
-            let tex = glow::NativeTexture(NonZero::new(tex).unwrap());
-            builder.native_texture(tex)
+        };
+        unsafe {
+            naevc::gl_contextUnset();
         }
-    };
+        out
+    })
+}
 
-    let out = match builder.build(ctx) {
-        Ok(tex) => tex.into_ptr(),
-        Err(e) => {
-            warn_err(e.context("unable to build texture for raw texture"));
-            std::ptr::null_mut()
+#[unsafe(no_mangle)]
+pub extern "C" fn gl_freeTexture(ctex: *mut Texture) {
+    ffi_guard((), || {
+        if !ctex.is_null() {
+            let _ = unsafe { Box::from_raw(ctex) };
         }
-    };
-    unsafe {
-        naevc::gl_contextUnset();
-    }
-    out
+        // The texture should get dropped now
+    })
 }
 
 #[unsafe(no_mangle)]
-pub extern "C" fn gl_freeTexture(ctex: *mut Texture) {
-    if !ctex.is_null() {
-        let _ = unsafe { Box::from_raw(ctex) };
-    }
-    // The texture should get dropped now
+pub extern "C" fn gl_reapplySamplerDefaults() {
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        reapply_sampler_defaults(ctx);
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn tex_tex(ctex: *mut Texture) -> naevc::GLuint {
-    let tex = unsafe { &*ctex };
-    tex.texture.texture.0.into()
+    ffi_guard(0, || {
+        let tex = unsafe { &*ctex };
+        tex.texture.texture.0.into()
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn tex_sampler(ctex: *mut Texture) -> naevc::GLuint {
-    let tex = unsafe { &*ctex };
-    tex.sampler.0.into()
+    ffi_guard(0, || {
+        let tex = unsafe { &*ctex };
+        tex.sampler.sampler.0.into()
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn tex_name(ctex: *mut Texture) -> *const c_char {
-    let tex = unsafe { &*ctex };
-    match &tex.name {
-        Some(name) => name.as_ptr(),
-        None => std::ptr::null(),
-    }
+    ffi_guard(std::ptr::null(), || {
+        let tex = unsafe { &*ctex };
+        match &tex.name {
+            Some(name) => name.as_ptr(),
+            None => std::ptr::null(),
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn tex_isSDF(ctex: *mut Texture) -> c_int {
-    let tex = unsafe { &*ctex };
-    tex.texture.is_sdf as i32
+    ffi_guard(0, || {
+        let tex = unsafe { &*ctex };
+        tex.texture.is_sdf as i32
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn gl_isTrans(ctex: *mut Texture, x: c_int, y: c_int) -> c_int {
-    // TODO
-    0
+    ffi_guard(0, || {
+        // TODO
+        0
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn tex_hasTrans(ctex: *mut Texture) -> c_int {
-    // TODO
-    0
+    ffi_guard(0, || {
+        // TODO
+        0
+    })
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn tex_setTex(ctex: *mut Texture, texture: naevc::GLuint) {
-    let tex = unsafe { &mut *ctex };
-    let ntex = glow::NativeTexture(std::num::NonZero::new(texture).unwrap());
-    tex.texture = Arc::new(TextureData::from_raw(ntex, tex.texture.w, tex.texture.h).unwrap());
+    ffi_guard((), || {
+        let tex = unsafe { &mut *ctex };
+        let Some(ntex) = NonZero::new(texture).map(glow::NativeTexture) else {
+            warn!("unable to set texture: invalid texture");
+            return;
+        };
+        match TextureData::from_raw(ntex, tex.texture.w, tex.texture.h) {
+            Ok(data) => tex.texture = Arc::new(data),
+            Err(e) => warn_err(e.context("unable to set texture")),
+        }
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -1463,55 +3188,63 @@ pub extern "C" fn gl_renderTexture(
     c: *mut Vector4<f32>,
     angle: c_double,
 ) {
-    let ctx = Context::get().unwrap();
-    let colour = match c.is_null() {
-        true => Vector4::<f32>::from([1.0, 1.0, 1.0, 1.0]),
-        false => unsafe { *c },
-    };
-    let dims = ctx.dimensions.read().unwrap();
-    #[rustfmt::skip]
-    let transform: Matrix3<f32> = dims.projection * {
-        if angle.abs() > 1e-5 {
-            let hw = 0.5 * w as f32;
-            let hh = 0.5 * h as f32;
-            let c = angle.cos() as f32;
-            let s = angle.sin() as f32;
-            Matrix3::new(
-                1.0, 0.0, x as f32 + hw,
-                0.0, 1.0, y as f32 + hh,
-                0.0, 0.0, 1.0,
-            ) * Matrix3::new(
-                 c,  -s,  0.0,
-                 s,   c,  0.0,
-                0.0, 0.0, 1.0,
-            ) * Matrix3::new(
-                w as f32, 0.0,      -hw,
-                0.0,      h as f32, -hh,
-                0.0,      0.0,      1.0,
-            )
-        } else {
-            Matrix3::new(
-                w as f32, 0.0,      x as f32,
-                0.0,      h as f32, y as f32,
-                0.0,      0.0,      1.0,
-            )
-        }
-    };
-    // Our coordinate system rust-side is inverted with respect to Lua and textures
-    #[rustfmt::skip]
-    let texture: Matrix3<f32> = Matrix3::new(
-        tw as f32, 0.0,       tx as f32,
-        0.0,      th as f32, ty as f32,
-        0.0,       0.0,       1.0,
-    );
-    let data = render::TextureUniform {
-        texture,
-        transform,
-        colour,
-    };
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let colour = match c.is_null() {
+            true => Vector4::<f32>::from([1.0, 1.0, 1.0, 1.0]),
+            false => unsafe { *c },
+        };
+        let dims = ctx.dimensions.read().unwrap();
+        #[rustfmt::skip]
+        let transform: Matrix3<f32> = dims.projection * {
+            if angle.abs() > 1e-5 {
+                let hw = 0.5 * w as f32;
+                let hh = 0.5 * h as f32;
+                let c = angle.cos() as f32;
+                let s = angle.sin() as f32;
+                Matrix3::new(
+                    1.0, 0.0, x as f32 + hw,
+                    0.0, 1.0, y as f32 + hh,
+                    0.0, 0.0, 1.0,
+                ) * Matrix3::new(
+                     c,  -s,  0.0,
+                     s,   c,  0.0,
+                    0.0, 0.0, 1.0,
+                ) * Matrix3::new(
+                    w as f32, 0.0,      -hw,
+                    0.0,      h as f32, -hh,
+                    0.0,      0.0,      1.0,
+                )
+            } else {
+                Matrix3::new(
+                    w as f32, 0.0,      x as f32,
+                    0.0,      h as f32, y as f32,
+                    0.0,      0.0,      1.0,
+                )
+            }
+        };
+        // Our coordinate system rust-side is inverted with respect to Lua and textures
+        #[rustfmt::skip]
+        let texture: Matrix3<f32> = Matrix3::new(
+            tw as f32, 0.0,       tx as f32,
+            0.0,      th as f32, ty as f32,
+            0.0,       0.0,       1.0,
+        );
+        let data = render::TextureUniform {
+            texture,
+            transform,
+            colour,
+        };
 
-    let tex = unsafe { &*ctex };
-    let _ = tex.draw_ex(ctx, &data);
+        let tex = unsafe { &*ctex };
+        let _ = tex.draw_ex(ctx, &data);
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -1522,18 +3255,26 @@ pub extern "C" fn gl_renderScaleAspectMagic(
     bw: c_double,
     bh: c_double,
 ) {
-    let ctx = Context::get().unwrap();
-    let dims = ctx.dimensions.read().unwrap();
-    let tex = unsafe { &*ctex };
-    let tw = tex.texture.w as f32;
-    let th = tex.texture.h as f32;
-    let x = bx as f32;
-    let y = by as f32;
-    let w = bw as f32;
-    let h = bw as f32;
-    let scale = (w / tw).min(h / th);
-    let nw = scale * tw;
-    let nh = scale * th;
-
-    let _ = tex.draw_scale(ctx, x, y, nw, nh, scale);
+    ffi_guard((), || {
+        let ctx = match Context::get() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn_err(e);
+                return;
+            }
+        };
+        let dims = ctx.dimensions.read().unwrap();
+        let tex = unsafe { &*ctex };
+        let tw = tex.texture.w as f32;
+        let th = tex.texture.h as f32;
+        let x = bx as f32;
+        let y = by as f32;
+        let w = bw as f32;
+        let h = bw as f32;
+        let scale = (w / tw).min(h / th);
+        let nw = scale * tw;
+        let nh = scale * th;
+
+        let _ = tex.draw_scale(ctx, x, y, nw, nh, scale);
+    })
 }
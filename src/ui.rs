@@ -0,0 +1,19 @@
+use sdl2::messagebox::{show_simple_message_box, MessageBoxFlag};
+
+use crate::context::CONTEXT;
+
+/// Shows an error message box before exiting, so that fatal errors are not silent. Falls back to
+/// printing to stderr if there is no video subsystem or the message box can't be shown.
+pub fn fatal_error(title: &str, message: &str) {
+    let has_video = unsafe { sdl2::sys::SDL_WasInit(sdl2::sys::SDL_INIT_VIDEO) != 0 };
+    if has_video {
+        // SDL2 wants the parent window as an option, while SDL3 takes a raw window pointer that
+        // can be null, so keep this the only place that has to care.
+        let window = CONTEXT.get().map(|ctx| &ctx.window);
+        match show_simple_message_box(MessageBoxFlag::ERROR, title, message, window) {
+            Ok(()) => return,
+            Err(e) => eprintln!("unable to show error message box: {e}"),
+        }
+    }
+    eprintln!("{title}: {message}");
+}
@@ -2,6 +2,7 @@ use mlua::{Either, FromLua, Lua, MetaMethod, UserData, UserDataMethods, Value};
 use nalgebra::Vector2;
 use std::os::raw::c_void;
 
+use crate::ffi::ffi_guard;
 use crate::gettext::gettext;
 use crate::warn_err;
 
@@ -459,15 +460,17 @@ use std::os::raw::{c_char, c_int};
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn nlua_loadVector(env: *mut LuaEnv) -> c_int {
-    let lua = NLUA.lock().unwrap();
-    let env = unsafe { &*env };
-    match open_vec2(&lua.lua, env) {
-        Err(e) => {
-            warn_err!(e);
-            -1
+    ffi_guard(-1, || {
+        let lua = NLUA.lock().unwrap();
+        let env = unsafe { &*env };
+        match open_vec2(&lua.lua, env) {
+            Err(e) => {
+                warn_err!(e);
+                -1
+            }
+            _ => 0,
         }
-        _ => 0,
-    }
+    })
 }
 
 #[allow(non_snake_case)]
@@ -5,6 +5,8 @@ use std::ffi::CStr;
 use std::os::raw::{c_char, c_int};
 use std::sync::LazyLock;
 
+use crate::ffi::ffi_guard;
+
 pub static VERSION: LazyLock<semver::Version> =
     LazyLock::new(|| semver::Version::parse(config::PACKAGE_VERSION).unwrap());
 pub static VERSION_HUMAN: LazyLock<String> = LazyLock::new(|| {
@@ -46,11 +48,13 @@ fn parse_cstr(ver: *const c_char) -> Result<semver::Version> {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn naev_versionCompare(version: *const c_char) -> c_int {
-    let ver = match parse_cstr(version) {
-        Ok(v) => v,
-        _ => return 0,
-    };
-    compare_versions(&VERSION, &ver)
+    ffi_guard(0, || {
+        let ver = match parse_cstr(version) {
+            Ok(v) => v,
+            _ => return 0,
+        };
+        compare_versions(&VERSION, &ver)
+    })
 }
 
 #[unsafe(no_mangle)]
@@ -58,13 +62,15 @@ pub extern "C" fn naev_versionCompareTarget(
     version: *const c_char,
     target: *const c_char,
 ) -> c_int {
-    let vera = match parse_cstr(version) {
-        Ok(v) => v,
-        _ => return 0,
-    };
-    let verb = match parse_cstr(target) {
-        Ok(v) => v,
-        _ => return 0,
-    };
-    compare_versions(&vera, &verb)
+    ffi_guard(0, || {
+        let vera = match parse_cstr(version) {
+            Ok(v) => v,
+            _ => return 0,
+        };
+        let verb = match parse_cstr(target) {
+            Ok(v) => v,
+            _ => return 0,
+        };
+        compare_versions(&vera, &verb)
+    })
 }
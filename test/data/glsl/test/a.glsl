@@ -0,0 +1,2 @@
+#include "common.glsl"
+const float A = COMMON;
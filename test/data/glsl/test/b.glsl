@@ -0,0 +1,2 @@
+#include "/glsl/test/common.glsl"
+const float B = COMMON;
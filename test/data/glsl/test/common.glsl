@@ -0,0 +1,2 @@
+#pragma once
+const float COMMON = 1.0;